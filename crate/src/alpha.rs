@@ -0,0 +1,207 @@
+//! Control how operations treat the alpha channel of transparent images.
+//!
+//! By default, each operation treats the alpha channel its own way: most only change the
+//! colour of each pixel, but some, such as the 3x3 convolution filters in `conv`, make the
+//! image opaque. Setting the alpha mode to `AlphaMode::Preserve` makes every operation
+//! which would otherwise change the alpha channel leave it exactly as it was:
+//!
+//! * the 3x3 filters, box blur and Gaussian blur in `conv`,
+//! * the HSL, LCh and HSV adjustments in `colour_spaces`, and the filters in `filters`
+//!   which are built on them,
+//! * `selective_hue_rotate`, `selective_lighten`, `selective_desaturate` and
+//!   `selective_saturate` in `channels`,
+//! * `grayscale_shades` in `monochrome`,
+//! * `horizontal_strips`, `vertical_strips`, `tilt_shift`, `depth_of_field`, `zoom_blur`
+//!   and `spin_blur` in `effects`.
+//!
+//! Operations whose purpose is to change the alpha channel, such as `effects::chroma_key`,
+//! ignore the alpha mode. Any other operation can be made to preserve the alpha channel by
+//! running it with `with_alpha_mode`.
+//!
+//! Regardless of the mode, `conv::box_blur`, `conv::gaussian_blur`, `transform::resize`
+//! and `PhotonImage::thumbnail` work on premultiplied alpha, so that the colour of fully
+//! transparent pixels (usually black) can't bleed into visible ones, which would otherwise
+//! give transparent logos a dark halo.
+//!
+//! # Example
+//! ```
+//! use photon_rs::alpha::{set_alpha_mode, AlphaMode};
+//! use photon_rs::conv::sharpen;
+//! use photon_rs::PhotonImage;
+//!
+//! let mut img = PhotonImage::new(vec![200, 100, 50, 128].repeat(8 * 8), 8, 8);
+//! set_alpha_mode(AlphaMode::Preserve);
+//! sharpen(&mut img);
+//! assert_eq!(img.get_raw_pixels()[3], 128);
+//! ```
+
+use crate::PhotonImage;
+use std::cell::Cell;
+use wasm_bindgen::prelude::*;
+
+/// How operations treat the alpha channel.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    /// Each operation treats the alpha channel its own way, as it always has.
+    Default,
+    /// Operations which would otherwise change the alpha channel leave it unchanged, and
+    /// only change colours. See the module documentation for which operations these are.
+    Preserve,
+}
+
+thread_local! {
+    static ALPHA_MODE: Cell<AlphaMode> = const { Cell::new(AlphaMode::Default) };
+}
+
+/// Set how operations on the current thread treat the alpha channel.
+///
+/// # Arguments
+/// * `mode` - The alpha mode to use from now on.
+#[wasm_bindgen]
+pub fn set_alpha_mode(mode: AlphaMode) {
+    ALPHA_MODE.with(|current| current.set(mode));
+}
+
+/// Get how operations on the current thread treat the alpha channel.
+#[wasm_bindgen]
+pub fn get_alpha_mode() -> AlphaMode {
+    ALPHA_MODE.with(|current| current.get())
+}
+
+/// Run a single operation with a different alpha mode to the one set by `set_alpha_mode`.
+///
+/// With `AlphaMode::Preserve`, the image's alpha channel is restored after the operation,
+/// even if it doesn't honour the alpha mode itself, as long as it doesn't change the size of
+/// the image.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `mode` - The alpha mode to use for this operation.
+/// * `op` - The operation to apply to the image.
+/// # Example
+///
+/// ```
+/// use photon_rs::alpha::{with_alpha_mode, AlphaMode};
+/// use photon_rs::conv::box_blur;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("logo.png");
+/// with_alpha_mode(&mut img, AlphaMode::Preserve, box_blur);
+/// ```
+pub fn with_alpha_mode<R, F: FnOnce(&mut PhotonImage) -> R>(
+    photon_image: &mut PhotonImage,
+    mode: AlphaMode,
+    op: F,
+) -> R {
+    let previous = get_alpha_mode();
+    set_alpha_mode(mode);
+    let result = apply_alpha_mode(photon_image, op);
+    set_alpha_mode(previous);
+    result
+}
+
+/// Run an operation, restoring the image's alpha channel afterwards if the current alpha
+/// mode is `AlphaMode::Preserve`.
+pub(crate) fn apply_alpha_mode<R, F: FnOnce(&mut PhotonImage) -> R>(
+    photon_image: &mut PhotonImage,
+    op: F,
+) -> R {
+    if get_alpha_mode() == AlphaMode::Default {
+        return op(photon_image);
+    }
+
+    let alpha: Vec<u8> = photon_image
+        .raw_pixels
+        .iter()
+        .skip(3)
+        .step_by(4)
+        .cloned()
+        .collect();
+    let (width, height) = (photon_image.width, photon_image.height);
+    let result = op(photon_image);
+    if photon_image.width == width && photon_image.height == height {
+        for (px, a) in photon_image.raw_pixels.chunks_exact_mut(4).zip(alpha) {
+            px[3] = a;
+        }
+    }
+    result
+}
+
+/// Multiply the colour of each pixel by its alpha value.
+///
+/// Some graphics APIs expect premultiplied pixels. Photon's own operations expect straight
+/// (not premultiplied) alpha, so undo this with `unpremultiply` before processing the image
+/// further.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+#[wasm_bindgen]
+pub fn premultiply(photon_image: &mut PhotonImage) {
+    premultiply_pixels(&mut photon_image.raw_pixels);
+}
+
+/// Divide the colour of each pixel by its alpha value, undoing `premultiply`.
+///
+/// # Arguments
+/// * `img` - A PhotonImage with premultiplied alpha.
+#[wasm_bindgen]
+pub fn unpremultiply(photon_image: &mut PhotonImage) {
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        let alpha = px[3];
+        unpremultiply_pixel(px, alpha);
+    }
+}
+
+fn premultiply_pixels(pixels: &mut [u8]) {
+    for px in pixels.chunks_exact_mut(4) {
+        let alpha = px[3] as u32;
+        for value in px[..3].iter_mut() {
+            *value = ((*value as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+fn unpremultiply_pixel(px: &mut [u8], alpha: u8) {
+    for value in px[..3].iter_mut() {
+        *value = if alpha == 0 {
+            0
+        } else {
+            ((*value as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8
+        };
+    }
+}
+
+/// Apply a filter which only changes the colour channels of straight RGBA pixels, such as a
+/// blur or resize, to premultiplied colours, and to the alpha channel.
+///
+/// The filter is run twice: once on the premultiplied colours, and once on a copy of the
+/// image whose colour channels hold the alpha values. It may change the number of pixels,
+/// eg: when resizing. Opaque images are filtered directly, which gives the same result.
+pub(crate) fn filter_premultiplied<E, F>(
+    pixels: &mut Vec<u8>,
+    mut filter: F,
+) -> Result<(), E>
+where
+    F: FnMut(&mut Vec<u8>) -> Result<(), E>,
+{
+    if pixels.chunks_exact(4).all(|px| px[3] == 255) {
+        return filter(pixels);
+    }
+
+    let mut colours = pixels.clone();
+    premultiply_pixels(&mut colours);
+    let mut alphas: Vec<u8> = pixels
+        .chunks_exact(4)
+        .flat_map(|px| vec![px[3], px[3], px[3], 255])
+        .collect();
+    filter(&mut colours)?;
+    filter(&mut alphas)?;
+
+    for (px, alpha) in colours.chunks_exact_mut(4).zip(alphas.chunks_exact(4)) {
+        unpremultiply_pixel(px, alpha[0]);
+        px[3] = alpha[0];
+    }
+    *pixels = colours;
+    Ok(())
+}
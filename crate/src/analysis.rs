@@ -0,0 +1,1386 @@
+//! Measure and analyse images, eg: for automated quality checks.
+
+use crate::features;
+use crate::helpers::{self, luma};
+use crate::{Connectivity, PhotonImage, Point, Rect, Rgb};
+use image::{imageops, FilterType, GrayImage, ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use wasm_bindgen::prelude::*;
+
+/// Statistics of the values of a single channel of an image.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ChannelStats {
+    mean: f64,
+    std_dev: f64,
+    min: u8,
+    max: u8,
+    entropy: f64,
+}
+
+#[wasm_bindgen]
+impl ChannelStats {
+    /// Get the mean value, between 0 and 255.
+    pub fn get_mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Get the standard deviation of the values.
+    pub fn get_std_dev(&self) -> f64 {
+        self.std_dev
+    }
+
+    /// Get the smallest value.
+    pub fn get_min(&self) -> u8 {
+        self.min
+    }
+
+    /// Get the largest value.
+    pub fn get_max(&self) -> u8 {
+        self.max
+    }
+
+    /// Get the Shannon entropy of the values' histogram, in bits, between 0 (every value is
+    /// the same) and 8 (every value occurs equally often).
+    pub fn get_entropy(&self) -> f64 {
+        self.entropy
+    }
+}
+
+/// Per-channel statistics of an image, as returned by `stats`.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ImageStats {
+    red: ChannelStats,
+    green: ChannelStats,
+    blue: ChannelStats,
+    alpha: ChannelStats,
+    luma: ChannelStats,
+}
+
+#[wasm_bindgen]
+impl ImageStats {
+    /// Get the statistics of the red channel.
+    pub fn get_red(&self) -> ChannelStats {
+        self.red
+    }
+
+    /// Get the statistics of the green channel.
+    pub fn get_green(&self) -> ChannelStats {
+        self.green
+    }
+
+    /// Get the statistics of the blue channel.
+    pub fn get_blue(&self) -> ChannelStats {
+        self.blue
+    }
+
+    /// Get the statistics of the alpha channel.
+    pub fn get_alpha(&self) -> ChannelStats {
+        self.alpha
+    }
+
+    /// Get the statistics of the image's luma (perceived brightness), using Rec. 601 weights.
+    pub fn get_luma(&self) -> ChannelStats {
+        self.luma
+    }
+}
+
+/// Calculate the mean, standard deviation, minimum, maximum and entropy of each channel of
+/// an image, and of its luma.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// // For example, to reject images which are mostly black or white:
+/// use photon_rs::analysis::stats;
+/// use photon_rs::native::open_image;
+///
+/// let img = open_image("img.jpg");
+/// let luma = stats(&img).get_luma();
+/// let well_exposed = luma.get_mean() > 30.0 && luma.get_mean() < 225.0;
+/// ```
+#[wasm_bindgen]
+pub fn stats(photon_image: &PhotonImage) -> ImageStats {
+    let mut histograms = [[0u64; 256]; 5];
+    for px in photon_image.raw_pixels.chunks_exact(4) {
+        for c in 0..4 {
+            histograms[c][px[c] as usize] += 1;
+        }
+        histograms[4][luma(px) as usize] += 1;
+    }
+
+    ImageStats {
+        red: channel_stats(&histograms[0]),
+        green: channel_stats(&histograms[1]),
+        blue: channel_stats(&histograms[2]),
+        alpha: channel_stats(&histograms[3]),
+        luma: channel_stats(&histograms[4]),
+    }
+}
+
+fn channel_stats(histogram: &[u64; 256]) -> ChannelStats {
+    let count: u64 = histogram.iter().sum();
+    if count == 0 {
+        return ChannelStats {
+            mean: 0.0,
+            std_dev: 0.0,
+            min: 0,
+            max: 0,
+            entropy: 0.0,
+        };
+    }
+    let n = count as f64;
+
+    let mean = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &c)| value as f64 * c as f64)
+        .sum::<f64>()
+        / n;
+    let variance = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &c)| (value as f64 - mean).powi(2) * c as f64)
+        .sum::<f64>()
+        / n;
+    let entropy = histogram
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / n;
+            -p * p.log2()
+        })
+        .sum::<f64>();
+
+    ChannelStats {
+        mean,
+        std_dev: variance.sqrt(),
+        min: histogram.iter().position(|&c| c > 0).unwrap_or(0) as u8,
+        max: histogram.iter().rposition(|&c| c > 0).unwrap_or(0) as u8,
+        entropy,
+    }
+}
+
+/// Calculate the structural similarity (SSIM) of two images, which measures how similar
+/// they look, based on their local luminance, contrast and structure.
+///
+/// The result is between -1 and 1, where 1 means that the images are identical. The
+/// comparison uses the images' luma, with an 11x11 gaussian window (σ = 1.5).
+///
+/// # Arguments
+/// * `img_a` - A PhotonImage.
+/// * `img_b` - A PhotonImage of the same size as `img_a`.
+/// # Example
+///
+/// ```
+/// // For example, to check that a compressed image is still close to the original:
+/// use photon_rs::analysis::ssim;
+/// use photon_rs::native::open_image;
+///
+/// let original = open_image("img.jpg");
+/// let compressed = open_image("img_compressed.jpg");
+/// assert!(ssim(&original, &compressed) > 0.95);
+/// ```
+#[wasm_bindgen]
+pub fn ssim(img_a: &PhotonImage, img_b: &PhotonImage) -> f64 {
+    check_same_size(img_a, img_b);
+    let (width, height) = (img_a.width as usize, img_a.height as usize);
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+
+    let luma_values = |img: &PhotonImage| -> Vec<f64> {
+        img.raw_pixels
+            .chunks_exact(4)
+            .map(|px| luma(px) as f64)
+            .collect()
+    };
+    let a = luma_values(img_a);
+    let b = luma_values(img_b);
+    let product = |x: &[f64], y: &[f64]| -> Vec<f64> {
+        x.iter().zip(y).map(|(x, y)| x * y).collect()
+    };
+
+    let mu_a = gaussian_window(&a, width, height, 1.5);
+    let mu_b = gaussian_window(&b, width, height, 1.5);
+    let mean_aa = gaussian_window(&product(&a, &a), width, height, 1.5);
+    let mean_bb = gaussian_window(&product(&b, &b), width, height, 1.5);
+    let mean_ab = gaussian_window(&product(&a, &b), width, height, 1.5);
+
+    let c1 = (0.01 * 255.0_f64).powi(2);
+    let c2 = (0.03 * 255.0_f64).powi(2);
+    let total: f64 = (0..width * height)
+        .map(|i| {
+            let var_a = mean_aa[i] - mu_a[i] * mu_a[i];
+            let var_b = mean_bb[i] - mu_b[i] * mu_b[i];
+            let covariance = mean_ab[i] - mu_a[i] * mu_b[i];
+            ((2.0 * mu_a[i] * mu_b[i] + c1) * (2.0 * covariance + c2))
+                / ((mu_a[i] * mu_a[i] + mu_b[i] * mu_b[i] + c1) * (var_a + var_b + c2))
+        })
+        .sum();
+    total / (width * height) as f64
+}
+
+/// Calculate the peak signal-to-noise ratio (PSNR) between two images, in decibels.
+///
+/// Higher values mean the images are more similar: above 40dB differences are hard to see,
+/// while below 20dB they are obvious. Identical images have a PSNR of infinity. The red,
+/// green and blue channels are compared, and the alpha channel is ignored.
+///
+/// # Arguments
+/// * `img_a` - A PhotonImage.
+/// * `img_b` - A PhotonImage of the same size as `img_a`.
+/// # Example
+///
+/// ```
+/// use photon_rs::analysis::psnr;
+/// use photon_rs::native::open_image;
+///
+/// let original = open_image("img.jpg");
+/// let compressed = open_image("img_compressed.jpg");
+/// println!("PSNR: {}dB", psnr(&original, &compressed));
+/// ```
+#[wasm_bindgen]
+pub fn psnr(img_a: &PhotonImage, img_b: &PhotonImage) -> f64 {
+    check_same_size(img_a, img_b);
+    let mut squared_error = 0.0;
+    let mut count = 0;
+    for (px_a, px_b) in img_a
+        .raw_pixels
+        .chunks_exact(4)
+        .zip(img_b.raw_pixels.chunks_exact(4))
+    {
+        for c in 0..3 {
+            squared_error += (px_a[c] as f64 - px_b[c] as f64).powi(2);
+        }
+        count += 3;
+    }
+
+    if squared_error == 0.0 {
+        return f64::INFINITY;
+    }
+    let mse = squared_error / count as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Calculate the average colour of an image.
+/// Pixels are weighted by their opacity, so transparent areas are ignored.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// // For example, to find a placeholder colour to show while an image loads:
+/// use photon_rs::analysis::average_color;
+/// use photon_rs::native::open_image;
+///
+/// let img = open_image("img.jpg");
+/// let placeholder = average_color(&img);
+/// ```
+#[wasm_bindgen]
+pub fn average_color(photon_image: &PhotonImage) -> Rgb {
+    let mut sums = [0u64; 3];
+    let mut weight = 0u64;
+    for px in photon_image.raw_pixels.chunks_exact(4) {
+        let alpha = px[3] as u64;
+        for c in 0..3 {
+            sums[c] += px[c] as u64 * alpha;
+        }
+        weight += alpha;
+    }
+
+    // A fully transparent image has no weighted colour, so fall back to the plain average.
+    if weight == 0 {
+        for px in photon_image.raw_pixels.chunks_exact(4) {
+            for c in 0..3 {
+                sums[c] += px[c] as u64;
+            }
+            weight += 1;
+        }
+    }
+    if weight == 0 {
+        return Rgb::new(0, 0, 0);
+    }
+
+    let mean = |sum: u64| ((sum + weight / 2) / weight) as u8;
+    Rgb::new(mean(sums[0]), mean(sums[1]), mean(sums[2]))
+}
+
+/// Find the dominant (most common) colour of an image.
+///
+/// Colours are grouped into a coarse palette of 16 levels per channel, and the average
+/// colour of the most common group is returned. Pixels which are mostly transparent are
+/// ignored.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// // For example, to theme a UI around an album cover:
+/// use photon_rs::analysis::dominant_color;
+/// use photon_rs::native::open_image;
+///
+/// let img = open_image("img.jpg");
+/// let accent = dominant_color(&img);
+/// ```
+#[wasm_bindgen]
+pub fn dominant_color(photon_image: &PhotonImage) -> Rgb {
+    let bin = |px: &[u8]| {
+        ((px[0] as usize >> 4) << 8)
+            | ((px[1] as usize >> 4) << 4)
+            | (px[2] as usize >> 4)
+    };
+
+    let mut counts = vec![0u32; 16 * 16 * 16];
+    for px in photon_image.raw_pixels.chunks_exact(4) {
+        if px[3] >= 128 {
+            counts[bin(px)] += 1;
+        }
+    }
+    let (dominant, &count) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|&(i, count)| (count, std::cmp::Reverse(i)))
+        .unwrap();
+    if count == 0 {
+        return average_color(photon_image);
+    }
+
+    let mut sums = [0u64; 3];
+    for px in photon_image.raw_pixels.chunks_exact(4) {
+        if px[3] >= 128 && bin(px) == dominant {
+            for c in 0..3 {
+                sums[c] += px[c] as u64;
+            }
+        }
+    }
+    let mean = |sum: u64| ((sum + count as u64 / 2) / count as u64) as u8;
+    Rgb::new(mean(sums[0]), mean(sums[1]), mean(sums[2]))
+}
+
+/// Measure how sharp (in focus) an image is, as the variance of the Laplacian of its luma.
+///
+/// Edges in sharp images give a strong Laplacian response, so the variance is high, while
+/// blurry images have a low variance. The value depends on the content of the image, so a
+/// threshold for "blurry" should be chosen for the kind of images being checked. For
+/// typical photos, values below around 100 are usually blurry.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// // For example, to flag blurry uploads:
+/// use photon_rs::analysis::sharpness;
+/// use photon_rs::native::open_image;
+///
+/// let img = open_image("img.jpg");
+/// let is_blurry = sharpness(&img) < 100.0;
+/// ```
+#[wasm_bindgen]
+pub fn sharpness(photon_image: &PhotonImage) -> f64 {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let luma_values: Vec<f64> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| luma(px) as f64)
+        .collect();
+
+    // Apply the 3x3 Laplacian kernel to every pixel which isn't on the edge of the image.
+    let mut sum = 0.0;
+    let mut sum_sq = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let i = y * width + x;
+            let laplacian = luma_values[i - width]
+                + luma_values[i - 1]
+                + luma_values[i + 1]
+                + luma_values[i + width]
+                - 4.0 * luma_values[i];
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+        }
+    }
+    let n = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / n;
+    sum_sq / n - mean * mean
+}
+
+/// Generate a saliency map of an image, highlighting the regions most likely to draw the
+/// eye, eg: to choose where to crop an image or where to place a watermark.
+///
+/// The map is a grayscale image of the same size as the original, where brighter pixels
+/// are more salient. It is calculated with the spectral residual method (Hou & Zhang,
+/// 2007): the parts of the image's frequency spectrum which differ from a smoothed version
+/// of the spectrum are kept, and transformed back to find where those unexpected features
+/// lie. For speed, this is done on a 64x64 copy of the image.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// use photon_rs::analysis::saliency_map;
+/// use photon_rs::native::open_image;
+///
+/// let img = open_image("img.jpg");
+/// let saliency = saliency_map(&img);
+/// ```
+#[wasm_bindgen]
+pub fn saliency_map(photon_image: &PhotonImage) -> PhotonImage {
+    const SIZE: usize = 64;
+    let (width, height) = (photon_image.width, photon_image.height);
+    if width == 0 || height == 0 {
+        return photon_image.clone();
+    }
+
+    let luma_img: GrayImage = ImageBuffer::from_fn(width, height, |x, y| {
+        let i = ((y * width + x) * 4) as usize;
+        Luma([luma(&photon_image.raw_pixels[i..i + 4])])
+    });
+    let small =
+        imageops::resize(&luma_img, SIZE as u32, SIZE as u32, FilterType::Triangle);
+    let mut spectrum: Vec<(f64, f64)> = small
+        .pixels()
+        .map(|px| (px[0] as f64 / 255.0, 0.0))
+        .collect();
+    fft_2d(&mut spectrum, SIZE, false);
+
+    // The spectral residual is the log amplitude spectrum, minus its 3x3 local average.
+    let amplitudes: Vec<f64> = spectrum
+        .iter()
+        .map(|(re, im)| (re * re + im * im).sqrt())
+        .collect();
+    let log_amplitudes: Vec<f64> = amplitudes.iter().map(|a| (a + 1e-9).ln()).collect();
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let mut local_mean = 0.0;
+            for dy in [SIZE - 1, 0, 1].iter() {
+                for dx in [SIZE - 1, 0, 1].iter() {
+                    let i = (y + dy) % SIZE * SIZE + (x + dx) % SIZE;
+                    local_mean += log_amplitudes[i] / 9.0;
+                }
+            }
+            let i = y * SIZE + x;
+            // Keep the phase, but replace the amplitude with the residual's.
+            let scale = if amplitudes[i] > 0.0 {
+                (log_amplitudes[i] - local_mean).exp() / amplitudes[i]
+            } else {
+                0.0
+            };
+            spectrum[i] = (spectrum[i].0 * scale, spectrum[i].1 * scale);
+        }
+    }
+    fft_2d(&mut spectrum, SIZE, true);
+
+    let energy: Vec<f64> = spectrum.iter().map(|(re, im)| re * re + im * im).collect();
+    let smoothed = gaussian_window(&energy, SIZE, SIZE, 2.5);
+    let min = smoothed.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = smoothed.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let small_map: GrayImage = ImageBuffer::from_fn(SIZE as u32, SIZE as u32, |x, y| {
+        let value = (smoothed[y as usize * SIZE + x as usize] - min) / range;
+        Luma([(value * 255.0).round() as u8])
+    });
+
+    let map = imageops::resize(&small_map, width, height, FilterType::Triangle);
+    let mut raw_pixels = Vec::with_capacity((width * height * 4) as usize);
+    for px in map.pixels() {
+        raw_pixels.extend_from_slice(&[px[0], px[0], px[0], 255]);
+    }
+    PhotonImage::new(raw_pixels, width, height)
+}
+
+/// Select the contiguous region of similar colour around a pixel, like the magic wand
+/// tool of a paint program.
+///
+/// Returns a grayscale mask of the same size as the image: white where a pixel is
+/// selected, and black elsewhere. A pixel is selected if each of its channels is within
+/// `tolerance` of the starting pixel's, and it's connected to the starting pixel through
+/// other selected pixels above, below, left or right of it.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `x` - x-coordinate of the starting pixel.
+/// * `y` - y-coordinate of the starting pixel.
+/// * `tolerance` - How much each channel may differ from the starting pixel's.
+///
+/// # Panics
+/// Panics if the starting pixel is outside of the image.
+/// # Example
+///
+/// ```
+/// // For example, to select the sky, starting from the top-left corner:
+/// use photon_rs::analysis::select_similar;
+/// use photon_rs::native::open_image;
+///
+/// let img = open_image("img.jpg");
+/// let mask = select_similar(&img, 0, 0, 24);
+/// ```
+#[wasm_bindgen]
+pub fn select_similar(
+    photon_image: &PhotonImage,
+    x: u32,
+    y: u32,
+    tolerance: u8,
+) -> PhotonImage {
+    let region =
+        helpers::similar_region(photon_image, x, y, tolerance, Connectivity::Four);
+    let raw_pixels = region
+        .iter()
+        .flat_map(|&selected| {
+            let v = if selected { 255 } else { 0 };
+            vec![v, v, v, 255]
+        })
+        .collect();
+    PhotonImage::new(raw_pixels, photon_image.width, photon_image.height)
+}
+
+/// The label of each pixel of an image, as found by `connected_components`: 0 for the
+/// background, and from 1 upwards for the pixels of each component.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelMap {
+    width: u32,
+    height: u32,
+    labels: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl LabelMap {
+    /// Get the width of the labelled image.
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height of the labelled image.
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Get the label of a pixel.
+    pub fn get_label(&self, x: u32, y: u32) -> u32 {
+        self.labels[(y * self.width + x) as usize]
+    }
+
+    /// Get the labels of every pixel, row by row.
+    pub fn get_labels(&self) -> Vec<u32> {
+        self.labels.clone()
+    }
+}
+
+impl LabelMap {
+    /// Get the labels of every pixel, row by row, without copying them.
+    pub fn labels(&self) -> &[u32] {
+        &self.labels
+    }
+}
+
+/// Measurements of a single component found by `connected_components`.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ComponentStats {
+    label: u32,
+    area: u32,
+    bounding_box: Rect,
+    centroid_x: f64,
+    centroid_y: f64,
+}
+
+#[wasm_bindgen]
+impl ComponentStats {
+    /// Get the component's label in the `LabelMap`.
+    pub fn get_label(&self) -> u32 {
+        self.label
+    }
+
+    /// Get the number of pixels in the component.
+    pub fn get_area(&self) -> u32 {
+        self.area
+    }
+
+    /// Get the smallest rectangle which contains the component.
+    pub fn get_bounding_box(&self) -> Rect {
+        self.bounding_box
+    }
+
+    /// Get the x-coordinate of the component's centroid: the mean position of its pixels.
+    pub fn get_centroid_x(&self) -> f64 {
+        self.centroid_x
+    }
+
+    /// Get the y-coordinate of the component's centroid.
+    pub fn get_centroid_y(&self) -> f64 {
+        self.centroid_y
+    }
+}
+
+/// Find the separate objects in a binary image, eg: a thresholded scan, by labelling each
+/// group of connected foreground pixels, and measuring them.
+///
+/// Pixels with a luma of 128 or more are foreground, and the rest are background.
+/// Components are labelled from 1 upwards, in the order of their first pixel from the
+/// top-left of the image, and their stats are returned in the same order.
+///
+/// # Arguments
+/// * `binary_img` - A PhotonImage, eg: from `monochrome::threshold`.
+/// * `connectivity` - Whether diagonally adjacent pixels are connected.
+/// # Example
+///
+/// ```
+/// // For example, to count the objects larger than 20 pixels in a scan:
+/// use photon_rs::analysis::connected_components;
+/// use photon_rs::monochrome::threshold;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Connectivity;
+///
+/// let mut img = open_image("scan.png");
+/// threshold(&mut img, 128);
+/// let (_, components) = connected_components(&img, Connectivity::Eight);
+/// let objects = components.iter().filter(|c| c.get_area() > 20).count();
+/// ```
+pub fn connected_components(
+    binary_img: &PhotonImage,
+    connectivity: Connectivity,
+) -> (LabelMap, Vec<ComponentStats>) {
+    let (width, height) = (binary_img.width as usize, binary_img.height as usize);
+    let foreground: Vec<bool> = binary_img
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| luma(px) >= 128)
+        .collect();
+    let offsets: &[(isize, isize)] = match connectivity {
+        Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+        Connectivity::Eight => &[
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ],
+    };
+
+    let mut labels = vec![0u32; width * height];
+    let mut components = vec![];
+    let mut stack = vec![];
+    for start in 0..width * height {
+        if !foreground[start] || labels[start] != 0 {
+            continue;
+        }
+        let label = components.len() as u32 + 1;
+        labels[start] = label;
+        stack.push(start);
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+        let (mut area, mut sum_x, mut sum_y) = (0u32, 0.0, 0.0);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            area += 1;
+            sum_x += x as f64;
+            sum_y += y as f64;
+
+            for &(dx, dy) in offsets {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                    continue;
+                }
+                let n = ny as usize * width + nx as usize;
+                if foreground[n] && labels[n] == 0 {
+                    labels[n] = label;
+                    stack.push(n);
+                }
+            }
+        }
+
+        components.push(ComponentStats {
+            label,
+            area,
+            bounding_box: Rect::new(
+                min_x as u32,
+                min_y as u32,
+                (max_x - min_x + 1) as u32,
+                (max_y - min_y + 1) as u32,
+            ),
+            centroid_x: sum_x / area as f64,
+            centroid_y: sum_y / area as f64,
+        });
+    }
+
+    let label_map = LabelMap {
+        width: binary_img.width,
+        height: binary_img.height,
+        labels,
+    };
+    (label_map, components)
+}
+
+/// Trace the borders of the shapes in a binary image, eg: a thresholded scan, with the
+/// border following algorithm of Suzuki and Abe.
+///
+/// Pixels with a luma of 128 or more are foreground, and the rest are background. Each
+/// contour is the sequence of foreground pixels along one border, in order, with 8-way
+/// connectivity; the last pixel is a neighbour of the first. Both the outer border of each
+/// shape and the borders of any holes in it are returned, in the order that they're found
+/// from the top-left of the image. A shape of a single pixel has a contour of one point.
+///
+/// # Arguments
+/// * `binary_img` - A PhotonImage, eg: from `monochrome::threshold`.
+/// # Example
+///
+/// ```
+/// // For example, to outline the shapes in a scan:
+/// use photon_rs::analysis::find_contours;
+/// use photon_rs::drawing::draw_contours;
+/// use photon_rs::monochrome::threshold;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("scan.png");
+/// threshold(&mut img, 128);
+/// let contours = find_contours(&img);
+/// draw_contours(&mut img, &contours, &Rgba::new(255, 0, 0, 255), 2.0);
+/// ```
+pub fn find_contours(binary_img: &PhotonImage) -> Vec<Vec<Point>> {
+    // The 8 neighbours of a pixel as (row, column) offsets, anticlockwise from the east.
+    const NEIGHBOURS: [(isize, isize); 8] = [
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+        (1, 0),
+        (1, 1),
+    ];
+    let direction = |from: (isize, isize), to: (isize, isize)| {
+        let offset = (to.0 - from.0, to.1 - from.1);
+        NEIGHBOURS
+            .iter()
+            .position(|&n| n == offset)
+            .expect("the pixels are neighbours")
+    };
+
+    // Pad the image with a border of background, so that every pixel has 8 neighbours.
+    // Foreground pixels start as 1, and are relabelled as their borders are followed.
+    let (width, height) = (binary_img.width as usize, binary_img.height as usize);
+    let stride = width + 2;
+    let mut f = vec![0i32; stride * (height + 2)];
+    for (i, px) in binary_img.raw_pixels.chunks_exact(4).enumerate() {
+        if luma(px) >= 128 {
+            f[(i / width + 1) * stride + i % width + 1] = 1;
+        }
+    }
+    let at = |(r, c): (isize, isize)| r as usize * stride + c as usize;
+
+    let mut contours = vec![];
+    let mut nbd = 1;
+    for r in 1..=height as isize {
+        for c in 1..=width as isize {
+            let value = f[at((r, c))];
+            let previous = if value == 1 && f[at((r, c - 1))] == 0 {
+                (r, c - 1)
+            } else if value >= 1 && f[at((r, c + 1))] == 0 {
+                (r, c + 1)
+            } else {
+                continue;
+            };
+            nbd += 1;
+            let start = (r, c);
+            let mut contour = vec![];
+
+            // Find the first foreground neighbour, clockwise from the background pixel.
+            let from = direction(start, previous);
+            let first = (0..8)
+                .map(|k| NEIGHBOURS[(from + 8 - k) % 8])
+                .map(|(dr, dc)| (r + dr, c + dc))
+                .find(|&p| f[at(p)] != 0);
+            let first = match first {
+                Some(first) => first,
+                None => {
+                    f[at(start)] = -nbd;
+                    contours.push(vec![Point::new(c as u32 - 1, r as u32 - 1)]);
+                    continue;
+                }
+            };
+
+            // Follow the border anticlockwise, until it returns to the start.
+            let (mut previous, mut current) = (first, start);
+            loop {
+                let from = direction(current, previous);
+                let mut east_is_background = false;
+                let mut next = current;
+                for k in 1..=8 {
+                    let d = (from + k) % 8;
+                    let p = (current.0 + NEIGHBOURS[d].0, current.1 + NEIGHBOURS[d].1);
+                    if f[at(p)] != 0 {
+                        next = p;
+                        break;
+                    }
+                    if d == 0 {
+                        east_is_background = true;
+                    }
+                }
+
+                if east_is_background {
+                    f[at(current)] = -nbd;
+                } else if f[at(current)] == 1 {
+                    f[at(current)] = nbd;
+                }
+                contour.push(Point::new(current.1 as u32 - 1, current.0 as u32 - 1));
+
+                if next == start && current == first {
+                    break;
+                }
+                previous = current;
+                current = next;
+            }
+            contours.push(contour);
+        }
+    }
+    contours
+}
+
+/// A straight line found by `hough_lines`, in normal form: the points `(x, y)` for which
+/// `x * cos(theta) + y * sin(theta) = rho`.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Line {
+    rho: f32,
+    theta: f32,
+    votes: u32,
+}
+
+#[wasm_bindgen]
+impl Line {
+    /// Get the signed distance of the line from the top-left corner of the image, in
+    /// pixels.
+    pub fn get_rho(&self) -> f32 {
+        self.rho
+    }
+
+    /// Get the angle of the line's normal, in radians, between 0 and π. Horizontal lines
+    /// have an angle of π/2.
+    pub fn get_theta(&self) -> f32 {
+        self.theta
+    }
+
+    /// Get the number of edge pixels which lie on the line.
+    pub fn get_votes(&self) -> u32 {
+        self.votes
+    }
+}
+
+/// A circle found by `hough_circles`.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Circle {
+    x: u32,
+    y: u32,
+    radius: u32,
+    votes: u32,
+}
+
+#[wasm_bindgen]
+impl Circle {
+    /// Get the x-coordinate of the centre.
+    pub fn get_x(&self) -> u32 {
+        self.x
+    }
+
+    /// Get the y-coordinate of the centre.
+    pub fn get_y(&self) -> u32 {
+        self.y
+    }
+
+    /// Get the radius, in pixels.
+    pub fn get_radius(&self) -> u32 {
+        self.radius
+    }
+
+    /// Get the number of edge pixels which lie on the circle.
+    pub fn get_votes(&self) -> u32 {
+        self.votes
+    }
+}
+
+/// Find the straight lines in an edge image with the Hough transform, eg: to level a
+/// horizon, or to deskew a scanned document.
+///
+/// Pixels with a luma of 128 or more are edges. Each edge pixel votes for every line
+/// through it, at a resolution of 1 degree and 1 pixel; lines with at least `threshold`
+/// votes, which have more votes than the similar lines around them, are returned, with
+/// the most votes first.
+///
+/// # Arguments
+/// * `edge_img` - A PhotonImage, eg: from `conv::edge_detection` and
+///   `monochrome::threshold`.
+/// * `threshold` - The fewest edge pixels which a line must pass through.
+/// # Example
+///
+/// ```
+/// // For example, to find the angle of the strongest line in an image:
+/// use photon_rs::analysis::hough_lines;
+/// use photon_rs::conv::edge_detection;
+/// use photon_rs::monochrome::threshold;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// edge_detection(&mut img);
+/// threshold(&mut img, 128);
+/// let lines = hough_lines(&img, 100);
+/// if let Some(line) = lines.first() {
+///     let degrees = line.get_theta().to_degrees() - 90.0;
+/// }
+/// ```
+pub fn hough_lines(edge_img: &PhotonImage, threshold: u32) -> Vec<Line> {
+    const ANGLES: usize = 180;
+    let (width, height) = (edge_img.width as usize, edge_img.height as usize);
+    let max_rho = ((width * width + height * height) as f32).sqrt().ceil() as isize;
+    let rhos = (2 * max_rho + 1) as usize;
+    let trig: Vec<(f32, f32)> = (0..ANGLES)
+        .map(|a| (a as f32).to_radians().sin_cos())
+        .map(|(sin, cos)| (cos, sin))
+        .collect();
+
+    let mut accumulator = vec![0u32; ANGLES * rhos];
+    for (i, px) in edge_img.raw_pixels.chunks_exact(4).enumerate() {
+        if luma(px) < 128 {
+            continue;
+        }
+        let (x, y) = ((i % width) as f32, (i / width) as f32);
+        for (a, (cos, sin)) in trig.iter().enumerate() {
+            let rho = (x * cos + y * sin).round() as isize + max_rho;
+            accumulator[a * rhos + rho as usize] += 1;
+        }
+    }
+
+    let mut lines = vec![];
+    for a in 0..ANGLES {
+        for r in 0..rhos {
+            let votes = accumulator[a * rhos + r];
+            if votes < threshold.max(1) {
+                continue;
+            }
+            // Ties between neighbours go to the first, so each line is only found once.
+            let is_peak = (0..9).filter(|&n| n != 4).all(|n| {
+                let na = a as isize + n as isize / 3 - 1;
+                let mut nr = r as isize + n as isize % 3 - 1;
+                // A line at 180° is the line at 0° with its rho negated, so neighbours
+                // across the wrap are mirrored.
+                if na < 0 || na >= ANGLES as isize {
+                    nr = 2 * max_rho - nr;
+                }
+                if nr < 0 || nr >= rhos as isize {
+                    return true;
+                }
+                let na = na.rem_euclid(ANGLES as isize) as usize;
+                let other = accumulator[na * rhos + nr as usize];
+                if n < 4 {
+                    votes > other
+                } else {
+                    votes >= other
+                }
+            });
+            if is_peak {
+                lines.push(Line {
+                    rho: (r as isize - max_rho) as f32,
+                    theta: (a as f32).to_radians(),
+                    votes,
+                });
+            }
+        }
+    }
+    lines.sort_by_key(|line| Reverse(line.votes));
+    lines
+}
+
+/// Find the circles in an edge image with the Hough transform, eg: to detect coins, or
+/// round markers.
+///
+/// Pixels with a luma of 128 or more are edges. Each edge pixel votes for the centres of
+/// every circle through it, with each radius from `min_radius` to `max_radius`. Circles
+/// whose outlines are at least `threshold` edge pixels, as a fraction of their
+/// circumference, and which have more votes than the similar circles around them, are
+/// returned, with the most votes first.
+///
+/// # Arguments
+/// * `edge_img` - A PhotonImage, eg: from `conv::edge_detection` and
+///   `monochrome::threshold`.
+/// * `min_radius` - The radius of the smallest circles to find, in pixels.
+/// * `max_radius` - The radius of the largest circles to find, in pixels.
+/// * `threshold` - The fraction of a circle's outline, between 0 and 1, which must be
+///   edges for it to be found, eg: 0.5.
+/// # Example
+///
+/// ```
+/// use photon_rs::analysis::hough_circles;
+/// use photon_rs::conv::edge_detection;
+/// use photon_rs::monochrome::threshold;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("coins.jpg");
+/// edge_detection(&mut img);
+/// threshold(&mut img, 128);
+/// let coins = hough_circles(&img, 10, 40, 0.5).len();
+/// ```
+pub fn hough_circles(
+    edge_img: &PhotonImage,
+    min_radius: u32,
+    max_radius: u32,
+    threshold: f32,
+) -> Vec<Circle> {
+    let (width, height) = (edge_img.width as isize, edge_img.height as isize);
+    let min_radius = min_radius.max(1);
+    if max_radius < min_radius {
+        return vec![];
+    }
+    let radii = (max_radius - min_radius + 1) as usize;
+    let edges: Vec<(isize, isize)> = edge_img
+        .raw_pixels
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(_, px)| luma(px) >= 128)
+        .map(|(i, _)| (i as isize % width, i as isize / width))
+        .collect();
+
+    // For each radius, the distinct pixel offsets which lie on a circle of that radius.
+    let outlines: Vec<Vec<(isize, isize)>> = (min_radius..=max_radius)
+        .map(|radius| {
+            let steps = (8.0 * radius as f32).ceil() as usize;
+            let mut outline: Vec<(isize, isize)> = (0..steps)
+                .map(|step| {
+                    let angle = step as f32 / steps as f32 * std::f32::consts::PI * 2.0;
+                    let (sin, cos) = angle.sin_cos();
+                    (
+                        (radius as f32 * cos).round() as isize,
+                        (radius as f32 * sin).round() as isize,
+                    )
+                })
+                .collect();
+            outline.sort_unstable();
+            outline.dedup();
+            outline
+        })
+        .collect();
+
+    let plane = (width * height) as usize;
+    let mut accumulator = vec![0u32; radii * plane];
+    for (r, outline) in outlines.iter().enumerate() {
+        for &(x, y) in edges.iter() {
+            for &(dx, dy) in outline.iter() {
+                let (cx, cy) = (x - dx, y - dy);
+                if cx >= 0 && cy >= 0 && cx < width && cy < height {
+                    accumulator[r * plane + (cy * width + cx) as usize] += 1;
+                }
+            }
+        }
+    }
+
+    let mut circles = vec![];
+    for r in 0..radii {
+        let needed = (threshold * outlines[r].len() as f32).ceil().max(1.0) as u32;
+        for cy in 0..height {
+            for cx in 0..width {
+                let votes = accumulator[r * plane + (cy * width + cx) as usize];
+                if votes < needed {
+                    continue;
+                }
+                let mut is_peak = true;
+                'neighbours: for dr in -1..=1isize {
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let (nr, ny, nx) = (r as isize + dr, cy + dy, cx + dx);
+                            if (dr, dy, dx) == (0, 0, 0)
+                                || nr < 0
+                                || nr >= radii as isize
+                                || ny < 0
+                                || ny >= height
+                                || nx < 0
+                                || nx >= width
+                            {
+                                continue;
+                            }
+                            let other = accumulator
+                                [nr as usize * plane + (ny * width + nx) as usize];
+                            // Ties go to the first in the accumulator's order.
+                            let before = (dr, dy, dx) < (0, 0, 0);
+                            if other > votes || (before && other == votes) {
+                                is_peak = false;
+                                break 'neighbours;
+                            }
+                        }
+                    }
+                }
+                if is_peak {
+                    circles.push(Circle {
+                        x: cx as u32,
+                        y: cy as u32,
+                        radius: min_radius + r as u32,
+                        votes,
+                    });
+                }
+            }
+        }
+    }
+    circles.sort_by_key(|circle| Reverse(circle.votes));
+    circles
+}
+
+/// The corner detector used by `corners`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CornerMethod {
+    /// The Harris detector, which measures how much the image changes when shifted in any
+    /// direction. It is robust to noise, and finds corners at any angle.
+    Harris = 1,
+    /// The FAST detector, which compares each pixel to a circle of 16 around it. It is
+    /// much quicker than Harris, but more sensitive to noise.
+    Fast = 2,
+}
+
+/// Find the corners in an image, eg: to track features between frames, or align images.
+///
+/// Corners are returned with the strongest first. With non-max suppression, only the
+/// strongest pixel of each corner is returned; without it, every pixel whose response
+/// passes the threshold is.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `method` - The corner detector to use.
+/// * `threshold` - For `CornerMethod::Harris`, the weakest response to keep, as a
+///   fraction of the strongest, eg: 0.01. For `CornerMethod::Fast`, how much brighter or
+///   darker than a pixel the circle around it must be, between 0 and 255, eg: 20.
+/// * `non_max_suppression` - Whether to keep only the strongest pixel of each corner.
+/// # Example
+///
+/// ```
+/// use photon_rs::analysis::{corners, CornerMethod};
+/// use photon_rs::native::open_image;
+///
+/// let img = open_image("img.jpg");
+/// let points = corners(&img, CornerMethod::Fast, 20.0, true);
+/// ```
+pub fn corners(
+    photon_image: &PhotonImage,
+    method: CornerMethod,
+    threshold: f32,
+    non_max_suppression: bool,
+) -> Vec<Point> {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    let gray = features::gray(photon_image);
+    let (response, min_response, border) = match method {
+        CornerMethod::Harris => {
+            let response = features::harris_response(&gray, width, height);
+            let strongest = response.iter().cloned().fold(0.0, f32::max);
+            (response, threshold * strongest, 1)
+        }
+        CornerMethod::Fast => {
+            let response = features::fast_response(&gray, width, height, threshold);
+            (response, 0.0, 3)
+        }
+    };
+    features::peaks(
+        &response,
+        width,
+        height,
+        min_response,
+        border,
+        non_max_suppression,
+    )
+    .into_iter()
+    .map(|(x, y)| Point::new(x as u32, y as u32))
+    .collect()
+}
+
+/// The result of comparing two images with `diff`.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ImageDiff {
+    image: PhotonImage,
+    changed_pixels: u64,
+}
+
+#[wasm_bindgen]
+impl ImageDiff {
+    /// Get the difference image, where changed pixels are highlighted in red over a faded,
+    /// grayscale copy of the first image.
+    pub fn get_image(&self) -> PhotonImage {
+        self.image.clone()
+    }
+
+    /// Get the number of pixels which differ by more than the threshold.
+    pub fn get_changed_pixels(&self) -> u64 {
+        self.changed_pixels
+    }
+}
+
+impl ImageDiff {
+    /// Split the result into the difference image and the number of changed pixels.
+    pub fn into_parts(self) -> (PhotonImage, u64) {
+        (self.image, self.changed_pixels)
+    }
+}
+
+/// Compare two images pixel by pixel, producing an image which highlights where they
+/// differ, and a count of the pixels which changed. This is useful for visual regression
+/// testing, eg: to check that a rendering pipeline's output hasn't changed.
+///
+/// A pixel counts as changed if any of its channels, including alpha, differs by more than
+/// `threshold`. In the difference image, changed pixels are bright red, and unchanged
+/// pixels show a faded, grayscale copy of `img_a` for context.
+///
+/// # Arguments
+/// * `img_a` - A PhotonImage.
+/// * `img_b` - A PhotonImage of the same size as `img_a`.
+/// * `threshold` - The largest difference in any channel which is ignored, eg: 0 to
+/// highlight every change, or a few levels to ignore rounding differences.
+/// # Example
+///
+/// ```
+/// use photon_rs::analysis::diff;
+/// use photon_rs::native::{open_image, save_image};
+///
+/// let expected = open_image("expected.png");
+/// let actual = open_image("actual.png");
+/// let (image, changed_pixels) = diff(&expected, &actual, 2).into_parts();
+/// if changed_pixels > 0 {
+///     save_image(image, "diff.png");
+/// }
+/// ```
+#[wasm_bindgen]
+pub fn diff(img_a: &PhotonImage, img_b: &PhotonImage, threshold: u8) -> ImageDiff {
+    check_same_size(img_a, img_b);
+    let mut raw_pixels = Vec::with_capacity(img_a.raw_pixels.len());
+    let mut changed_pixels = 0;
+    for (px_a, px_b) in img_a
+        .raw_pixels
+        .chunks_exact(4)
+        .zip(img_b.raw_pixels.chunks_exact(4))
+    {
+        let changed = px_a
+            .iter()
+            .zip(px_b)
+            .any(|(a, b)| (*a as i16 - *b as i16).abs() > threshold as i16);
+        if changed {
+            changed_pixels += 1;
+            raw_pixels.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // Blend the luma towards white, so that the highlighted pixels stand out.
+            let faded = 255 - (255 - luma(px_a) as u16) * px_a[3] as u16 / 255 / 4;
+            let faded = faded as u8;
+            raw_pixels.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    ImageDiff {
+        image: PhotonImage::new(raw_pixels, img_a.width, img_a.height),
+        changed_pixels,
+    }
+}
+
+fn check_same_size(img_a: &PhotonImage, img_b: &PhotonImage) {
+    if img_a.width != img_b.width || img_a.height != img_b.height {
+        panic!("Images must be the same size to be compared.");
+    }
+}
+
+/// Weighted mean of the values around each pixel, using a gaussian window with a standard
+/// deviation of `sigma`, extending 3 standard deviations either side of the pixel (ie: 11x11
+/// for a `sigma` of 1.5). Near the edges, the window is cropped and renormalized.
+fn gaussian_window(values: &[f64], width: usize, height: usize, sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    let pass = |src: &[f64], len: usize, runs: usize, step: usize, stride: usize| {
+        let mut dst = vec![0.0; src.len()];
+        for run in 0..runs {
+            for i in 0..len as isize {
+                let (mut sum, mut weight_sum) = (0.0, 0.0);
+                for (k, weight) in weights.iter().enumerate() {
+                    let j = i + k as isize - radius;
+                    if j >= 0 && j < len as isize {
+                        sum += src[run * stride + j as usize * step] * weight;
+                        weight_sum += weight;
+                    }
+                }
+                dst[run * stride + i as usize * step] = sum / weight_sum;
+            }
+        }
+        dst
+    };
+
+    let horizontal = pass(values, width, height, 1, width);
+    pass(&horizontal, height, width, width, 1)
+}
+
+/// In-place 2D discrete Fourier transform of a `size` x `size` grid, where `size` is a
+/// power of two. The inverse transform is scaled, so that it undoes the forward transform.
+fn fft_2d(values: &mut [(f64, f64)], size: usize, inverse: bool) {
+    let mut line = vec![(0.0, 0.0); size];
+    for y in 0..size {
+        line.copy_from_slice(&values[y * size..(y + 1) * size]);
+        fft(&mut line, inverse);
+        values[y * size..(y + 1) * size].copy_from_slice(&line);
+    }
+    for x in 0..size {
+        for (y, value) in line.iter_mut().enumerate() {
+            *value = values[y * size + x];
+        }
+        fft(&mut line, inverse);
+        for (y, value) in line.iter().enumerate() {
+            values[y * size + x] = *value;
+        }
+    }
+}
+
+/// In-place radix-2 fast Fourier transform, whose length must be a power of two.
+fn fft(values: &mut [(f64, f64)], inverse: bool) {
+    let n = values.len();
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (values[start + k], values[start + k + len / 2]);
+                let twiddled = (b.0 * cos - b.1 * sin, b.0 * sin + b.1 * cos);
+                values[start + k] = (a.0 + twiddled.0, a.1 + twiddled.1);
+                values[start + k + len / 2] = (a.0 - twiddled.0, a.1 - twiddled.1);
+            }
+        }
+        len *= 2;
+    }
+
+    if inverse {
+        for value in values.iter_mut() {
+            *value = (value.0 / n as f64, value.1 / n as f64);
+        }
+    }
+}
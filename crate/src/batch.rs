@@ -0,0 +1,259 @@
+//! Process many image files at once, eg: from the `photon` command-line tool.
+//!
+//! Inputs are given as glob patterns, and the path each result is saved to is built from
+//! an output template, in which these placeholders are replaced:
+//!
+//! - `{dir}`: the directory of the input file.
+//! - `{name}`: the file name of the input, eg: `beach.jpg`.
+//! - `{stem}`: the file name without its extension, eg: `beach`.
+//! - `{ext}`: the extension of the input, eg: `jpg`.
+//! - `{n}`: the position of the input in the batch, starting from 1.
+//!
+//! The format of each output file is chosen from its extension.
+//!
+//! With the `watch` feature, `watch` processes images as they're added to a directory, eg:
+//! by a tethered camera.
+//!
+//! # Example
+//! ```no_run
+//! use photon_rs::batch::process_all;
+//! use photon_rs::pipeline::{Operation, Pipeline};
+//!
+//! let pipeline = Pipeline::new().then(Operation::Resize { w: Some(800), h: None });
+//! let patterns = vec!["photos/*.jpg".to_string()];
+//! for (input, result) in process_all(&patterns, "thumbs/{stem}.png", &pipeline).unwrap() {
+//!     if let Err(err) = result {
+//!         eprintln!("{}: {}", input.display(), err);
+//!     }
+//! }
+//! ```
+
+#[cfg(feature = "watch")]
+use crate::cancel::CancellationToken;
+use crate::error::{PhotonError, Result};
+use crate::pipeline::Pipeline;
+use crate::PhotonImage;
+use image::{GenericImageView, ImageBuffer};
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "watch")]
+use std::time::{Duration, Instant};
+
+/// The extensions of the image files which `watch` processes.
+#[cfg(feature = "watch")]
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "bmp", "gif", "ico", "jpeg", "jpg", "png", "pnm", "tga", "tif", "tiff", "webp",
+];
+
+/// How long a file must go unchanged before `watch` processes it, so that files which
+/// are still being copied or written aren't read half-finished.
+#[cfg(feature = "watch")]
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// The output template used when none is given: the input's directory and name, with
+/// `-photon` added before the extension.
+pub const DEFAULT_TEMPLATE: &str = "{dir}/{stem}-photon.{ext}";
+
+/// Expand glob patterns into the files they match, in order, without duplicates.
+///
+/// A pattern which isn't a valid glob, or which matches no files, is an error, so that
+/// typos aren't silently ignored.
+pub fn expand_globs(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for pattern in patterns {
+        let matches = glob::glob(pattern).map_err(|err| {
+            PhotonError::Io(format!("invalid glob {}: {}", pattern, err))
+        })?;
+        let before = paths.len();
+        for entry in matches {
+            let path = entry.map_err(|err| PhotonError::Io(err.to_string()))?;
+            if path.is_file() && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        if paths.len() == before {
+            return Err(PhotonError::Io(format!("no files match {}", pattern)));
+        }
+    }
+    Ok(paths)
+}
+
+/// Build the path to save the result for `input` to, by filling in an output template.
+///
+/// # Arguments
+/// * `template` - An output template, eg: `out/{stem}.png`.
+/// * `input` - The path of the input file.
+/// * `index` - The position of the input in the batch, starting from 1.
+pub fn output_path(template: &str, input: &Path, index: usize) -> PathBuf {
+    let dir = match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy(),
+        _ => ".".into(),
+    };
+    let part = |part: Option<&std::ffi::OsStr>| {
+        part.map(|part| part.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let path = template
+        .replace("{dir}", &dir)
+        .replace("{name}", &part(input.file_name()))
+        .replace("{stem}", &part(input.file_stem()))
+        .replace("{ext}", &part(input.extension()))
+        .replace("{n}", &index.to_string());
+    PathBuf::from(path)
+}
+
+/// Open an image file.
+///
+/// Unlike `native::open_image`, an error is returned rather than panicking if the file
+/// can't be read.
+pub fn open(path: &Path) -> Result<PhotonImage> {
+    let img = image::open(path)
+        .map_err(|err| PhotonError::Io(format!("{}: {}", path.display(), err)))?;
+    let (width, height) = img.dimensions();
+    Ok(PhotonImage::new(img.to_rgba().to_vec(), width, height))
+}
+
+/// Save an image to a file, creating its directory if needed. The format is chosen from
+/// the extension of the path.
+pub fn save(photon_image: &PhotonImage, path: &Path) -> Result<()> {
+    let io_error =
+        |err: std::io::Error| PhotonError::Io(format!("{}: {}", path.display(), err));
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let buffer = ImageBuffer::from_vec(
+        photon_image.width,
+        photon_image.height,
+        photon_image.raw_pixels.clone(),
+    )
+    .expect("raw pixels match the dimensions");
+    image::ImageRgba8(buffer).save(path).map_err(io_error)
+}
+
+/// Open an image file, apply a pipeline to it, and save the result.
+pub fn process_file(input: &Path, output: &Path, pipeline: &Pipeline) -> Result<()> {
+    let mut photon_image = open(input)?;
+    pipeline.apply(&mut photon_image)?;
+    save(&photon_image, output)
+}
+
+/// Apply a pipeline to every file matched by some glob patterns, saving each result to the
+/// path built from an output template.
+///
+/// An error is returned if the patterns can't be expanded. Otherwise, each input is
+/// returned with the path it was saved to, or the error which stopped it from being
+/// processed; a failure doesn't stop the rest of the batch.
+pub fn process_all(
+    patterns: &[String],
+    template: &str,
+    pipeline: &Pipeline,
+) -> Result<Vec<(PathBuf, Result<PathBuf>)>> {
+    let inputs = expand_globs(patterns)?;
+    let results = inputs
+        .into_iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let output = output_path(template, &input, i + 1);
+            let result = process_file(&input, &output, pipeline).map(|_| output);
+            (input, result)
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Watch a directory, and apply a pipeline to every image file added to it, saving each
+/// result to the path built from an output template.
+///
+/// Files already in the directory are left alone. A new file is processed once it has
+/// stopped changing for half a second, and files written by the watch itself are ignored,
+/// so the output template may point back into the watched directory. After each file,
+/// `on_processed` is called with its path and the path it was saved to, or the error which
+/// stopped it from being processed.
+///
+/// This blocks until `token` is cancelled, eg: from another thread, and only returns an
+/// error if the directory can't be watched.
+///
+/// # Arguments
+/// * `dir` - The directory to watch. Subdirectories aren't watched.
+/// * `template` - An output template, eg: `edited/{stem}.jpg`.
+/// * `pipeline` - The operations to apply to each new image.
+/// * `token` - Cancel this to stop watching.
+/// * `on_processed` - Called after each file is processed.
+#[cfg(feature = "watch")]
+pub fn watch<F>(
+    dir: &Path,
+    template: &str,
+    pipeline: &Pipeline,
+    token: &CancellationToken,
+    mut on_processed: F,
+) -> Result<()>
+where
+    F: FnMut(&Path, Result<PathBuf>),
+{
+    use notify::{EventKind, RecursiveMode, Watcher};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::mpsc;
+
+    let watch_error =
+        |err: notify::Error| PhotonError::Io(format!("{}: {}", dir.display(), err));
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+
+    // New files, with the time they last changed.
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut written = HashSet::new();
+    let mut count = 0;
+    while !token.is_cancelled() {
+        match receiver.recv_timeout(SETTLE_TIME / 5) {
+            Ok(Ok(event))
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) =>
+            {
+                for path in event.paths {
+                    let is_output = fs::canonicalize(&path)
+                        .is_ok_and(|path| written.contains(&path));
+                    if is_image(&path) && !is_output {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => return Err(watch_error(err)),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for input in settled {
+            pending.remove(&input);
+            if !input.is_file() {
+                continue;
+            }
+            count += 1;
+            let output = output_path(template, &input, count);
+            let result = process_file(&input, &output, pipeline).map(|_| output);
+            // Events for the output are only received after this, so it can be ignored.
+            if let Some(output) =
+                result.as_ref().ok().and_then(|o| fs::canonicalize(o).ok())
+            {
+                written.insert(output);
+            }
+            on_processed(&input, result);
+        }
+    }
+    Ok(())
+}
+
+/// Check whether a path has the extension of an image file.
+#[cfg(feature = "watch")]
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
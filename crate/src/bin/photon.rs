@@ -0,0 +1,225 @@
+//! The `photon` command-line tool, for using Photon from shell scripts.
+//!
+//! Build it with `cargo install photon-rs --features cli`, then run `photon help`.
+
+extern crate photon_rs;
+use photon_rs::batch;
+use photon_rs::pipeline::{Operation, Pipeline};
+use photon_rs::preset::Preset;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+Usage: photon <command> [arguments] <input>... [-o <template>]
+
+Commands:
+    resize [--width <w>] [--height <h>]   Resize, keeping the aspect ratio if only one size is given
+    crop <x> <y> <w> <h>                  Crop to a rectangle
+    blur <sigma>                          Gaussian blur
+    sharpen                               Sharpen
+    grayscale                             Convert to grayscale
+    sepia                                 Apply a sepia tone
+    invert                                Invert the colours
+    auto-enhance                          Correct colour balance, contrast and sharpness
+    filter <name>                         Apply a preset filter, eg: vintage
+    pipeline <spec.json>                  Apply a JSON array of pipeline operations
+    preset <preset.json>                  Apply a saved preset
+    watch <dir> <spec.json>               Apply a pipeline to each image added to a directory
+    help                                  Show this message
+
+Inputs may be glob patterns, eg: 'photos/*.jpg'. The output template may contain
+{dir}, {name}, {stem}, {ext} and {n}, and defaults to {dir}/{stem}-photon.{ext}.";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(msg) = run(args) {
+        eprintln!("photon: {}", msg);
+        process::exit(2);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => return Err(format!("missing command\n\n{}", USAGE)),
+    };
+    if command == "help" || command == "--help" || command == "-h" {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let mut template = batch::DEFAULT_TEMPLATE.to_string();
+    let mut options = vec![];
+    let mut positional = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" | "--width" | "--height" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                if arg == "-o" || arg == "--output" {
+                    template = value.clone();
+                } else {
+                    options.push((arg.as_str(), value.as_str()));
+                }
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+    if !options.is_empty() && command != "resize" {
+        return Err(format!("{} does not take --width or --height", command));
+    }
+
+    if command == "watch" {
+        return watch(&positional, &template);
+    }
+
+    let (pipeline, inputs) = parse_command(command, &options, &positional)?;
+    if inputs.is_empty() {
+        return Err(format!("missing input files\n\n{}", USAGE));
+    }
+
+    let results = batch::process_all(inputs, &template, &pipeline)
+        .map_err(|err| err.to_string())?;
+    let mut failed = 0;
+    for (input, result) in results {
+        match result {
+            Ok(output) => println!("{} -> {}", input.display(), output.display()),
+            Err(err) => {
+                eprintln!("photon: {}: {}", input.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} file(s) could not be processed", failed));
+    }
+    Ok(())
+}
+
+/// Process the images added to a directory until the process is stopped, eg: with Ctrl-C.
+#[cfg(feature = "watch")]
+fn watch(args: &[String], template: &str) -> Result<(), String> {
+    use photon_rs::cancel::CancellationToken;
+    use std::path::Path;
+
+    let args = take("watch", args, 2)?;
+    let pipeline =
+        Pipeline::from_json(&read(&args[1])?).map_err(|err| err.to_string())?;
+    println!("Watching {} for new images...", args[0]);
+    let token = CancellationToken::new();
+    batch::watch(
+        Path::new(&args[0]),
+        template,
+        &pipeline,
+        &token,
+        |input, result| match result {
+            Ok(output) => println!("{} -> {}", input.display(), output.display()),
+            Err(err) => eprintln!("photon: {}: {}", input.display(), err),
+        },
+    )
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "watch"))]
+fn watch(_args: &[String], _template: &str) -> Result<(), String> {
+    Err("watch needs photon to be built with the watch feature".to_string())
+}
+
+/// Build the pipeline for a command, and return it with the remaining arguments, which
+/// are the inputs.
+fn parse_command<'a>(
+    command: &str,
+    options: &[(&str, &str)],
+    positional: &'a [String],
+) -> Result<(Pipeline, &'a [String]), String> {
+    let operation = match command {
+        "resize" => {
+            let mut size = (None, None);
+            for (option, value) in options {
+                let value = Some(parse_number(option, value)?);
+                if *option == "--width" {
+                    size.0 = value;
+                } else {
+                    size.1 = value;
+                }
+            }
+            Operation::Resize {
+                w: size.0,
+                h: size.1,
+            }
+        }
+        "crop" => {
+            let values = take(command, positional, 4)?;
+            let numbers = values
+                .iter()
+                .map(|value| parse_number(command, value))
+                .collect::<Result<Vec<u32>, String>>()?;
+            let inputs = &positional[4..];
+            let crop = Operation::Crop {
+                x: numbers[0],
+                y: numbers[1],
+                w: numbers[2],
+                h: numbers[3],
+            };
+            return Ok((Pipeline::new().then(crop), inputs));
+        }
+        "blur" => {
+            let sigma = parse_number(command, &take(command, positional, 1)?[0])?;
+            return Ok((
+                Pipeline::new().then(Operation::GaussianBlur { sigma }),
+                &positional[1..],
+            ));
+        }
+        "sharpen" => Operation::Sharpen,
+        "grayscale" => Operation::Grayscale,
+        "sepia" => Operation::Sepia,
+        "invert" => Operation::Invert,
+        "auto-enhance" => Operation::AutoEnhance,
+        "filter" => {
+            let name = take(command, positional, 1)?[0].clone();
+            return Ok((
+                Pipeline::new().then(Operation::Filter { name }),
+                &positional[1..],
+            ));
+        }
+        "pipeline" => {
+            let spec = read(&take(command, positional, 1)?[0])?;
+            let pipeline = Pipeline::from_json(&spec).map_err(|err| err.to_string())?;
+            return Ok((pipeline, &positional[1..]));
+        }
+        "preset" => {
+            let json = read(&take(command, positional, 1)?[0])?;
+            let preset = Preset::from_json(&json).map_err(|err| err.to_string())?;
+            return Ok((preset.pipeline().clone(), &positional[1..]));
+        }
+        _ => return Err(format!("unknown command {}\n\n{}", command, USAGE)),
+    };
+    Ok((Pipeline::new().then(operation), positional))
+}
+
+/// Get the first `count` arguments of a command.
+fn take<'a>(
+    command: &str,
+    args: &'a [String],
+    count: usize,
+) -> Result<&'a [String], String> {
+    if args.len() < count {
+        return Err(format!(
+            "{} needs {} argument(s)\n\n{}",
+            command, count, USAGE
+        ));
+    }
+    Ok(&args[..count])
+}
+
+fn parse_number<T: std::str::FromStr>(what: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{}: {} is not a valid number", what, value))
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))
+}
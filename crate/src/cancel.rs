@@ -0,0 +1,78 @@
+//! Cooperative cancellation of long-running operations.
+//!
+//! Heavy operations have `_cancellable` variants which accept a `CancellationToken`.
+//! These poll the token periodically while they run, and if it has been cancelled, they
+//! stop early and return `PhotonError::Cancelled`, leaving the image untouched.
+//!
+//! # Example
+//! ```
+//! use photon_rs::cancel::CancellationToken;
+//! use photon_rs::colour_spaces::hsl_cancellable;
+//! use photon_rs::PhotonImage;
+//!
+//! let mut img = PhotonImage::new(vec![200; 64 * 64 * 4], 64, 64);
+//! let token = CancellationToken::new();
+//!
+//! // Typically called from elsewhere, eg: when the user picks another filter.
+//! token.cancel();
+//!
+//! assert!(hsl_cancellable(&mut img, "saturate", 0.1, &token).is_err());
+//! ```
+
+use crate::error::{PhotonError, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+/// A token which can be used to cancel a long-running operation.
+///
+/// Cloning a token produces a handle to the same cancellation state, so one clone can be
+/// handed to the operation while the other is kept to cancel it.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[wasm_bindgen]
+impl CancellationToken {
+    #[wasm_bindgen(constructor)]
+    /// Create a new, uncancelled token.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Request cancellation of any operation polling this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Check whether cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Clear a previous cancellation request, so the token can be reused.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+}
+
+impl CancellationToken {
+    /// Return `Err(PhotonError::Cancelled)` if cancellation has been requested.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(PhotonError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Poll an optional token; operations called without a token can never be cancelled.
+pub(crate) fn check(token: Option<&CancellationToken>) -> Result<()> {
+    match token {
+        Some(token) => token.check(),
+        None => Ok(()),
+    }
+}
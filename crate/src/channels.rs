@@ -5,7 +5,10 @@ extern crate image;
 use image::{GenericImage, GenericImageView};
 
 extern crate wasm_bindgen;
+use crate::alpha;
+use crate::gamma;
 use crate::helpers;
+use crate::simd;
 use crate::{PhotonImage, Rgb};
 extern crate palette;
 use crate::channels::palette::Hue;
@@ -57,10 +60,9 @@ pub fn alter_channel(img: &mut PhotonImage, channel: usize, amt: i16) {
     }
     let end = img.raw_pixels.len() - 4;
 
-    for i in (channel..end).step_by(4) {
-        let inc_val: i16 = img.raw_pixels[i] as i16 + amt as i16;
-        img.raw_pixels[i] = num::clamp(inc_val, 0, 255) as u8;
-    }
+    let mut amounts = [0_i16; 3];
+    amounts[channel] = amt;
+    simd::add_rgb(&mut img.raw_pixels[..end], amounts);
 }
 
 /// Increment or decrement every pixel's Red channel by a constant.
@@ -167,13 +169,10 @@ pub fn alter_two_channels(
     }
     let end = img.raw_pixels.len() - 4;
 
-    for i in (0..end).step_by(4) {
-        let inc_val1: i16 = img.raw_pixels[i + channel1] as i16 + amt1 as i16;
-        let inc_val2: i16 = img.raw_pixels[i + channel2] as i16 + amt2 as i16;
-
-        img.raw_pixels[i + channel1] = num::clamp(inc_val1, 0, 255) as u8;
-        img.raw_pixels[i + channel2] = num::clamp(inc_val2, 0, 255) as u8;
-    }
+    let mut amounts = [0_i16; 3];
+    amounts[channel1] = amt1;
+    amounts[channel2] = amt2;
+    simd::add_rgb(&mut img.raw_pixels[..end], amounts);
 }
 
 /// Increment all 3 channels' values by adding an amt to each channel per pixel.
@@ -208,15 +207,7 @@ pub fn alter_channels(img: &mut PhotonImage, r_amt: i16, g_amt: i16, b_amt: i16)
     }
     let end = img.raw_pixels.len() - 4;
 
-    for i in (0..end).step_by(4) {
-        let r_val: i16 = img.raw_pixels[i] as i16 + r_amt as i16;
-        let g_val: i16 = img.raw_pixels[i + 1] as i16 + g_amt as i16;
-        let b_val: i16 = img.raw_pixels[i + 2] as i16 + b_amt as i16;
-
-        img.raw_pixels[i] = num::clamp(r_val, 0, 255) as u8;
-        img.raw_pixels[i + 1] = num::clamp(g_val, 0, 255) as u8;
-        img.raw_pixels[i + 2] = num::clamp(b_val, 0, 255) as u8;
-    }
+    simd::add_rgb(&mut img.raw_pixels[..end], [r_amt, g_amt, b_amt]);
 }
 
 /// Set a certain channel to zero, thus removing the channel's influence in the pixels' final rendered colour.
@@ -380,46 +371,48 @@ pub fn selective_hue_rotate(
     ref_color: Rgb,
     degrees: f32,
 ) {
-    let img = helpers::dyn_image_from_raw(&photon_image);
-    let (width, height) = img.dimensions();
-
-    let mut img = img.to_rgba();
-    for (x, y) in ImageIterator::new(width, height) {
-        let px = img.get_pixel(x, y);
-
-        // Reference colour to compare the current pixel's colour to
-        let lab: Lab = Srgb::new(
-            ref_color.r as f32 / 255.0,
-            ref_color.g as f32 / 255.0,
-            ref_color.b as f32 / 255.0,
-        )
-        .into();
-
-        // Convert the current pixel's colour to the l*a*b colour space
-        let r_val: f32 = px.data[0] as f32 / 255.0;
-        let g_val: f32 = px.data[1] as f32 / 255.0;
-        let b_val: f32 = px.data[2] as f32 / 255.0;
-
-        let px_lab: Lab = Srgb::new(r_val, g_val, b_val).into();
-
-        let sim = color_sim(lab, px_lab);
-        if sim > 0 && sim < 40 {
-            let px_data = img.get_pixel(x, y).data;
-            let color = Srgba::from_raw(&px_data).into_format();
-
-            let hue_rotated_color = Lch::from(color).shift_hue(degrees);
-            img.put_pixel(
-                x,
-                y,
-                image::Rgba {
-                    data: Srgba::from_linear(hue_rotated_color.into())
-                        .into_format()
-                        .into_raw(),
-                },
-            );
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        let img = helpers::dyn_image_from_raw(&photon_image);
+        let (width, height) = img.dimensions();
+
+        let mut img = img.to_rgba();
+        for (x, y) in ImageIterator::new(width, height) {
+            let px = img.get_pixel(x, y);
+
+            // Reference colour to compare the current pixel's colour to
+            let lab: Lab = Srgb::new(
+                ref_color.r as f32 / 255.0,
+                ref_color.g as f32 / 255.0,
+                ref_color.b as f32 / 255.0,
+            )
+            .into();
+
+            // Convert the current pixel's colour to the l*a*b colour space
+            let r_val: f32 = px.data[0] as f32 / 255.0;
+            let g_val: f32 = px.data[1] as f32 / 255.0;
+            let b_val: f32 = px.data[2] as f32 / 255.0;
+
+            let px_lab: Lab = Srgb::new(r_val, g_val, b_val).into();
+
+            let sim = color_sim(lab, px_lab);
+            if sim > 0 && sim < 40 {
+                let px_data = img.get_pixel(x, y).data;
+                let color = Srgba::from_raw(&px_data).into_format();
+
+                let hue_rotated_color = Lch::from(color).shift_hue(degrees);
+                img.put_pixel(
+                    x,
+                    y,
+                    image::Rgba {
+                        data: Srgba::from_linear(hue_rotated_color.into())
+                            .into_format()
+                            .into_raw(),
+                    },
+                );
+            }
         }
-    }
-    photon_image.raw_pixels = img.to_vec();
+        photon_image.raw_pixels = img.to_vec();
+    })
 }
 
 /// Invert RGB value of an image.
@@ -437,16 +430,123 @@ pub fn selective_hue_rotate(
 /// ```
 #[wasm_bindgen]
 pub fn invert(photon_image: &mut PhotonImage) {
-    let end = photon_image.get_raw_pixels().len() - 4;
+    let end = photon_image.raw_pixels.len() - 4;
 
-    for i in (0..end).step_by(4) {
-        let r_val = photon_image.raw_pixels[i];
-        let g_val = photon_image.raw_pixels[i + 1];
-        let b_val = photon_image.raw_pixels[i + 2];
+    simd::invert_channels(&mut photon_image.raw_pixels[..end], [true, true, true]);
+}
 
-        photon_image.raw_pixels[i] = 255 - r_val;
-        photon_image.raw_pixels[i + 1] = 255 - g_val;
-        photon_image.raw_pixels[i + 2] = 255 - b_val;
+/// Invert only some of the R, G and B channels of an image, leaving the others
+/// unchanged.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `r` - Whether to invert the red channel.
+/// * `g` - Whether to invert the green channel.
+/// * `b` - Whether to invert the blue channel.
+/// # Example
+///
+/// ```
+/// // For example, to invert only the blue channel:
+/// use photon_rs::channels::invert_channels;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// invert_channels(&mut img, false, false, true);
+/// ```
+#[wasm_bindgen]
+pub fn invert_channels(photon_image: &mut PhotonImage, r: bool, g: bool, b: bool) {
+    simd::invert_channels(&mut photon_image.raw_pixels, [r, g, b]);
+}
+
+/// Partially invert an image, moving each of its R, G and B values towards its negative
+/// along a straight tone curve, whose slope falls from 1 to -1 as `amount` goes from 0
+/// to 1. At 0.5, every value meets in the middle, giving a flat grey.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `amount` - How far to invert the image, from 0 (unchanged) to 1 (a full negative).
+/// # Example
+///
+/// ```
+/// use photon_rs::channels::partial_invert;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// partial_invert(&mut img, 0.3);
+/// ```
+#[wasm_bindgen]
+pub fn partial_invert(photon_image: &mut PhotonImage, amount: f32) {
+    let amount = amount.clamp(0.0, 1.0);
+    let curve: Vec<u8> = (0..=255)
+        .map(|v| {
+            let v = v as f32;
+            (v + amount * (255.0 - 2.0 * v)).round() as u8
+        })
+        .collect();
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        for value in &mut px[..3] {
+            *value = curve[*value as usize];
+        }
+    }
+}
+
+/// Convert a scan of a colour negative film to a positive image.
+///
+/// A plain `invert` leaves a strong blue cast, from the orange mask built into colour
+/// negative film. Instead, each channel's density is measured against the film base (the
+/// unexposed film between frames, or at its edge), which removes the mask, and is then
+/// stretched to the full range, ignoring the darkest and brightest 0.1% of pixels.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `film_base` - The colour of the unexposed film base in the scan.
+/// # Example
+///
+/// ```
+/// use photon_rs::channels::convert_negative;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("negative.tiff");
+/// convert_negative(&mut img, Rgb::new(215, 140, 95));
+/// ```
+#[wasm_bindgen]
+pub fn convert_negative(photon_image: &mut PhotonImage, film_base: Rgb) {
+    let base = [film_base.r, film_base.g, film_base.b];
+    for (c, &base) in base.iter().enumerate() {
+        // The density of each value, relative to the film base, in linear light.
+        let base = gamma::decode(base.max(1));
+        let density: Vec<f32> = (0..=255u8)
+            .map(|v| (base / gamma::decode(v.max(1))).log10().max(0.0))
+            .collect();
+
+        let mut histogram = [0usize; 256];
+        for px in photon_image.raw_pixels.chunks_exact(4) {
+            histogram[px[c] as usize] += 1;
+        }
+        // Denser (darker) values on the film become brighter values in the print.
+        let clip = photon_image.raw_pixels.len() / 4 / 1000;
+        let percentile = |from_bright: bool| {
+            let mut seen = 0;
+            (0..256)
+                .map(|i| if from_bright { 255 - i } else { i })
+                .find(|&v| {
+                    seen += histogram[v];
+                    seen > clip
+                })
+                .unwrap_or(0)
+        };
+        let black = density[percentile(true)];
+        let white = density[percentile(false)];
+        let range = (white - black).max(f32::EPSILON);
+
+        let curve: Vec<u8> = density
+            .iter()
+            .map(|d| ((d - black) / range * 255.0).round().clamp(0.0, 255.0) as u8)
+            .collect();
+        for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+            px[c] = curve[px[c] as usize];
+        }
     }
 }
 
@@ -487,7 +587,7 @@ pub fn color_sim(lab1: Lab, lab2: Lab) -> i64 {
 /// ```
 #[wasm_bindgen]
 pub fn selective_lighten(img: &mut PhotonImage, ref_color: Rgb, amt: f32) {
-    selective(img, "lighten", ref_color, amt)
+    alpha::apply_alpha_mode(img, |img| selective(img, "lighten", ref_color, amt));
 }
 
 /// Selectively desaturate pixel colours which are similar to the reference colour provided.
@@ -514,7 +614,7 @@ pub fn selective_lighten(img: &mut PhotonImage, ref_color: Rgb, amt: f32) {
 /// ```
 #[wasm_bindgen]
 pub fn selective_desaturate(img: &mut PhotonImage, ref_color: Rgb, amt: f32) {
-    selective(img, "desaturate", ref_color, amt)
+    alpha::apply_alpha_mode(img, |img| selective(img, "desaturate", ref_color, amt));
 }
 
 /// Selectively saturate pixel colours which are similar to the reference colour provided.
@@ -541,7 +641,7 @@ pub fn selective_desaturate(img: &mut PhotonImage, ref_color: Rgb, amt: f32) {
 /// ```
 #[wasm_bindgen]
 pub fn selective_saturate(img: &mut PhotonImage, ref_color: Rgb, amt: f32) {
-    selective(img, "saturate", ref_color, amt);
+    alpha::apply_alpha_mode(img, |img| selective(img, "saturate", ref_color, amt));
 }
 
 fn selective(
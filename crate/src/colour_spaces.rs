@@ -1,13 +1,17 @@
 //! Image manipulation effects in HSL, LCh and HSV.
 
 extern crate image;
-extern crate rand;
+use crate::alpha;
+use crate::cancel::{self, CancellationToken};
+use crate::error::PhotonError;
+use crate::gamma;
+use crate::monochrome;
 use crate::{helpers, PhotonImage, Rgb};
 use image::GenericImageView;
 use palette::{Hsl, Hsv, Hue, Lch, Pixel, Saturate, Shade, Srgba};
 extern crate wasm_bindgen;
 use wasm_bindgen::prelude::*;
-use crate::iter::ImageIterator;
+use crate::iter::{par_rows, ImageIterator};
 
 /// Apply gamma correction.
 // #[wasm_bindgen]
@@ -75,12 +79,49 @@ use crate::iter::ImageIterator;
 /// lch(&mut img, "saturate", 0.1_f32);
 /// ```
 #[wasm_bindgen]
-pub fn lch(mut photon_image: &mut PhotonImage, mode: &str, amt: f32) {
+pub fn lch(photon_image: &mut PhotonImage, mode: &str, amt: f32) {
+    // Without a token, the effect can never be cancelled.
+    let _ = alpha::apply_alpha_mode(photon_image, |photon_image| {
+        lch_inner(photon_image, mode, amt, None)
+    });
+}
+
+/// Image manipulation in the LCh colour space, which can be cancelled part-way through with a `CancellationToken`.
+///
+/// Accepts the same modes as [`lch`](fn.lch.html). If the token is cancelled before the effect completes,
+/// `PhotonError::Cancelled` is returned and the image is left unmodified.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `mode` - The effect desired to be applied. Choose from: `saturate`, `desaturate`, `shift_hue`, `darken`, `lighten`
+/// * `amt` - A float value from 0 to 1 which represents the amount the effect should be increased by.
+/// * `token` - The token to poll for cancellation.
+#[wasm_bindgen]
+pub fn lch_cancellable(
+    photon_image: &mut PhotonImage,
+    mode: &str,
+    amt: f32,
+    token: &CancellationToken,
+) -> Result<(), PhotonError> {
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        lch_inner(photon_image, mode, amt, Some(token))
+    })
+}
+
+fn lch_inner(
+    photon_image: &mut PhotonImage,
+    mode: &str,
+    amt: f32,
+    token: Option<&CancellationToken>,
+) -> Result<(), PhotonError> {
     let img = helpers::dyn_image_from_raw(&photon_image);
     let (width, height) = img.dimensions();
     let mut img = img.to_rgba();
 
     for (x, y) in ImageIterator::new(width, height) {
+        if y == 0 {
+            cancel::check(token)?;
+        }
         let px_data = img.get_pixel(x, y).data;
         let lch_colour: Lch =
             Srgba::from_raw(&px_data).into_format().into_linear().into();
@@ -106,6 +147,7 @@ pub fn lch(mut photon_image: &mut PhotonImage, mode: &str, amt: f32) {
         );
     }
     photon_image.raw_pixels = img.to_vec();
+    Ok(())
 }
 
 /// Image manipulation effects in the HSL colour space.
@@ -132,11 +174,48 @@ pub fn lch(mut photon_image: &mut PhotonImage, mode: &str, amt: f32) {
 /// hsl(&mut img, "saturate", 0.1_f32);
 /// ```
 #[wasm_bindgen]
-pub fn hsl(mut photon_image: &mut PhotonImage, mode: &str, amt: f32) {
+pub fn hsl(photon_image: &mut PhotonImage, mode: &str, amt: f32) {
+    // Without a token, the effect can never be cancelled.
+    let _ = alpha::apply_alpha_mode(photon_image, |photon_image| {
+        hsl_inner(photon_image, mode, amt, None)
+    });
+}
+
+/// Image manipulation in the HSL colour space, which can be cancelled part-way through with a `CancellationToken`.
+///
+/// Accepts the same modes as [`hsl`](fn.hsl.html). If the token is cancelled before the effect completes,
+/// `PhotonError::Cancelled` is returned and the image is left unmodified.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `mode` - The effect desired to be applied. Choose from: `saturate`, `desaturate`, `shift_hue`, `darken`, `lighten`
+/// * `amt` - A float value from 0 to 1 which represents the amount the effect should be increased by.
+/// * `token` - The token to poll for cancellation.
+#[wasm_bindgen]
+pub fn hsl_cancellable(
+    photon_image: &mut PhotonImage,
+    mode: &str,
+    amt: f32,
+    token: &CancellationToken,
+) -> Result<(), PhotonError> {
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        hsl_inner(photon_image, mode, amt, Some(token))
+    })
+}
+
+fn hsl_inner(
+    photon_image: &mut PhotonImage,
+    mode: &str,
+    amt: f32,
+    token: Option<&CancellationToken>,
+) -> Result<(), PhotonError> {
     // The function logic is kept separate from other colour spaces for now,
     // since other HSL-specific logic may be implemented here, which isn't available in other colour spaces
     let mut img = helpers::dyn_image_from_raw(&photon_image).to_rgba();
     for (x, y) in ImageIterator::with_dimension(&img.dimensions()) {
+        if y == 0 {
+            cancel::check(token)?;
+        }
         let px_data = img.get_pixel(x, y).data;
 
         let colour = Srgba::from_raw(&px_data).into_format();
@@ -165,6 +244,7 @@ pub fn hsl(mut photon_image: &mut PhotonImage, mode: &str, amt: f32) {
     }
 
     photon_image.raw_pixels = img.to_vec();
+    Ok(())
 }
 
 /// Image manipulation in the HSV colour space.
@@ -193,11 +273,48 @@ pub fn hsl(mut photon_image: &mut PhotonImage, mode: &str, amt: f32) {
 /// ```
 #[wasm_bindgen]
 pub fn hsv(photon_image: &mut PhotonImage, mode: &str, amt: f32) {
+    // Without a token, the effect can never be cancelled.
+    let _ = alpha::apply_alpha_mode(photon_image, |photon_image| {
+        hsv_inner(photon_image, mode, amt, None)
+    });
+}
+
+/// Image manipulation in the HSV colour space, which can be cancelled part-way through with a `CancellationToken`.
+///
+/// Accepts the same modes as [`hsv`](fn.hsv.html). If the token is cancelled before the effect completes,
+/// `PhotonError::Cancelled` is returned and the image is left unmodified.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `mode` - The effect desired to be applied. Choose from: `saturate`, `desaturate`, `shift_hue`, `darken`, `lighten`
+/// * `amt` - A float value from 0 to 1 which represents the amount the effect should be increased by.
+/// * `token` - The token to poll for cancellation.
+#[wasm_bindgen]
+pub fn hsv_cancellable(
+    photon_image: &mut PhotonImage,
+    mode: &str,
+    amt: f32,
+    token: &CancellationToken,
+) -> Result<(), PhotonError> {
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        hsv_inner(photon_image, mode, amt, Some(token))
+    })
+}
+
+fn hsv_inner(
+    photon_image: &mut PhotonImage,
+    mode: &str,
+    amt: f32,
+    token: Option<&CancellationToken>,
+) -> Result<(), PhotonError> {
     let img = helpers::dyn_image_from_raw(&photon_image);
     let (width, height) = img.dimensions();
     let mut img = img.to_rgba();
 
     for (x, y) in ImageIterator::new(width, height) {
+        if y == 0 {
+            cancel::check(token)?;
+        }
         let px_data = img.get_pixel(x, y).data;
 
         let color = Srgba::from_raw(&px_data).into_format();
@@ -225,6 +342,7 @@ pub fn hsv(photon_image: &mut PhotonImage, mode: &str, amt: f32) {
         );
     }
     photon_image.raw_pixels = img.to_vec();
+    Ok(())
 }
 
 /// Shift hue by a specified number of degrees in the HSL colour space.
@@ -610,6 +728,281 @@ pub fn mix_with_colour(photon_image: &mut PhotonImage, mix_colour: Rgb, opacity:
     photon_image.raw_pixels = img.to_vec();
 }
 
+/// Remove a colour cast from an image by picking a point which should be a neutral grey,
+/// like the eyedropper of a white balance tool.
+///
+/// The red, green and blue channels are each scaled, in linear light, so that the
+/// average colour of the 5x5 pixels around the point becomes a grey of the same
+/// luminance, and the same scaling is applied across the whole image.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `x` - The x-coordinate of a point which should be neutral, eg: a grey card, a white
+/// wall or a cloud.
+/// * `y` - The y-coordinate of the point.
+/// # Panics
+/// Panics if the point is outside the image.
+/// # Example
+///
+/// ```
+/// use photon_rs::colour_spaces::white_balance_from_point;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// white_balance_from_point(&mut img, 120, 45);
+/// ```
+#[wasm_bindgen]
+pub fn white_balance_from_point(photon_image: &mut PhotonImage, x: u32, y: u32) {
+    let (width, height) = (photon_image.width, photon_image.height);
+    if x >= width || y >= height {
+        panic!(
+            "Point ({}, {}) is outside the {}x{} image.",
+            x, y, width, height
+        );
+    }
+
+    let mut picked = [0.0f32; 3];
+    let mut count = 0.0;
+    for sy in y.saturating_sub(2)..(y + 3).min(height) {
+        for sx in x.saturating_sub(2)..(x + 3).min(width) {
+            let i = ((sy * width + sx) * 4) as usize;
+            for (c, total) in picked.iter_mut().enumerate() {
+                *total += gamma::decode(photon_image.raw_pixels[i + c]);
+            }
+            count += 1.0;
+        }
+    }
+    let picked = picked.map(|total| total / count);
+    let luminance = 0.2126 * picked[0] + 0.7152 * picked[1] + 0.0722 * picked[2];
+    if luminance <= 0.0 {
+        return;
+    }
+    let gains = picked.map(|c| (luminance / c.max(f32::EPSILON)).clamp(0.25, 4.0));
+    map_linear(photon_image, |rgb| {
+        [rgb[0] * gains[0], rgb[1] * gains[1], rgb[2] * gains[2]]
+    });
+}
+
+/// Kinds of colour vision deficiency (colour blindness), for `simulate_cvd` and
+/// `daltonize`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cvd {
+    /// No working red (long-wavelength) cones, so reds look dark and are confused with
+    /// greens.
+    Protanopia,
+    /// No working green (medium-wavelength) cones, the most common kind, so reds and
+    /// greens are confused.
+    Deuteranopia,
+    /// No working blue (short-wavelength) cones, so blues are confused with greens, and
+    /// yellows with pinks.
+    Tritanopia,
+}
+
+impl Cvd {
+    /// The matrix which simulates the deficiency in linear RGB, from Machado, Oliveira
+    /// and Fernandes (2009), at full severity.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Cvd::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            Cvd::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            Cvd::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+        }
+    }
+}
+
+/// Simulate how an image looks to someone with a colour vision deficiency, to check that
+/// charts, maps and interfaces can be read by everyone.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `kind` - The kind of colour vision deficiency to simulate.
+/// # Example
+///
+/// ```
+/// use photon_rs::colour_spaces::{simulate_cvd, Cvd};
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("chart.png");
+/// simulate_cvd(&mut img, Cvd::Deuteranopia);
+/// ```
+#[wasm_bindgen]
+pub fn simulate_cvd(photon_image: &mut PhotonImage, kind: Cvd) {
+    let matrix = kind.matrix();
+    map_linear(photon_image, |rgb| transform(&matrix, rgb));
+}
+
+/// Correct an image for someone with a colour vision deficiency (daltonize it), by
+/// shifting the colour differences they can't see into ones they can, so that eg: red
+/// and green areas become distinguishable.
+///
+/// The colours lost in a simulation of the deficiency are added back to the channels
+/// it leaves visible, as described by Fidaner, Lin and Ozguven (2005).
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `kind` - The kind of colour vision deficiency to correct for.
+/// # Example
+///
+/// ```
+/// use photon_rs::colour_spaces::{daltonize, Cvd};
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("chart.png");
+/// daltonize(&mut img, Cvd::Protanopia);
+/// ```
+#[wasm_bindgen]
+pub fn daltonize(photon_image: &mut PhotonImage, kind: Cvd) {
+    let matrix = kind.matrix();
+    let shift = match kind {
+        Cvd::Protanopia | Cvd::Deuteranopia => {
+            [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]]
+        }
+        Cvd::Tritanopia => [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]],
+    };
+    map_linear(photon_image, |rgb| {
+        let simulated = transform(&matrix, rgb);
+        let lost = [
+            rgb[0] - simulated[0],
+            rgb[1] - simulated[1],
+            rgb[2] - simulated[2],
+        ];
+        let correction = transform(&shift, lost);
+        [
+            rgb[0] + correction[0],
+            rgb[1] + correction[1],
+            rgb[2] + correction[2],
+        ]
+    });
+}
+
+/// Multiply a colour by a 3x3 matrix.
+fn transform(matrix: &[[f32; 3]; 3], rgb: [f32; 3]) -> [f32; 3] {
+    let row = |r: &[f32; 3]| r[0] * rgb[0] + r[1] * rgb[1] + r[2] * rgb[2];
+    [row(&matrix[0]), row(&matrix[1]), row(&matrix[2])]
+}
+
+/// Map the colour of each pixel in linear light, leaving alpha unchanged.
+fn map_linear<F>(photon_image: &mut PhotonImage, f: F)
+where
+    F: Fn([f32; 3]) -> [f32; 3] + Send + Sync,
+{
+    let table: Vec<f32> = (0..=255).map(gamma::decode).collect();
+    par_rows(photon_image, |_, row| {
+        for px in row.chunks_exact_mut(4) {
+            let rgb = f([
+                table[px[0] as usize],
+                table[px[1] as usize],
+                table[px[2] as usize],
+            ]);
+            for (channel, value) in px.iter_mut().zip(rgb) {
+                *channel = gamma::encode(value);
+            }
+        }
+    });
+}
+
+/// A colormap, for showing grayscale data such as heights, temperatures or densities in
+/// false colour with `apply_colormap`.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct Colormap {
+    stops: Vec<Rgb>,
+}
+
+#[wasm_bindgen]
+impl Colormap {
+    /// Matplotlib's default colormap, from dark purple through teal to yellow. It's
+    /// perceptually uniform, so equal steps in the data look like equal steps in colour,
+    /// and remains readable in grayscale and to colour-blind viewers.
+    pub fn viridis() -> Colormap {
+        Colormap::from_hex(&[
+            0x440154, 0x472d7b, 0x3b528b, 0x2c728e, 0x21908c, 0x27ad81, 0x5dc863,
+            0xaadc32, 0xfde725,
+        ])
+    }
+
+    /// A perceptually uniform colormap from black through purple and orange to pale
+    /// yellow, like a thermal camera.
+    pub fn inferno() -> Colormap {
+        Colormap::from_hex(&[
+            0x000004, 0x1b0c42, 0x4b0c6b, 0x781c6d, 0xa52c60, 0xcf4446, 0xed6925,
+            0xfb9a06, 0xf7d03c, 0xfcffa4,
+        ])
+    }
+
+    /// The classic rainbow colormap, from dark blue through cyan, yellow and red to dark
+    /// red. It isn't perceptually uniform, so shows false edges in smooth data.
+    pub fn jet() -> Colormap {
+        Colormap::from_hex(&[
+            0x000080, 0x0000ff, 0x0080ff, 0x00ffff, 0x80ff80, 0xffff00, 0xff8000,
+            0xff0000, 0x800000,
+        ])
+    }
+
+    /// A colormap through evenly spaced colours, the first for black and the last for
+    /// white.
+    ///
+    /// # Arguments
+    /// * `colours` - The colours, as consecutive red, green and blue values.
+    /// # Panics
+    /// Panics if there are fewer than two colours, or `colours` isn't a whole number of
+    /// them.
+    pub fn custom(colours: &[u8]) -> Colormap {
+        if colours.len() < 6 || !colours.len().is_multiple_of(3) {
+            panic!("A colormap needs at least two RGB colours.");
+        }
+        let stops = colours
+            .chunks_exact(3)
+            .map(|rgb| Rgb::new(rgb[0], rgb[1], rgb[2]))
+            .collect();
+        Colormap { stops }
+    }
+}
+
+impl Colormap {
+    fn from_hex(colours: &[u32]) -> Colormap {
+        let stops = colours
+            .iter()
+            .map(|&hex| Rgb::new((hex >> 16) as u8, (hex >> 8) as u8, hex as u8))
+            .collect();
+        Colormap { stops }
+    }
+}
+
+/// Replace the colour of each pixel with its luminance's colour in a colormap, to show
+/// grayscale data, such as a depth map or heatmap, in false colour. Alpha is left
+/// unchanged.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `map` - The colormap, eg: `Colormap::viridis()`.
+/// # Example
+///
+/// ```
+/// use photon_rs::colour_spaces::{apply_colormap, Colormap};
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("depth.png");
+/// apply_colormap(&mut img, &Colormap::inferno());
+/// ```
+#[wasm_bindgen]
+pub fn apply_colormap(photon_image: &mut PhotonImage, map: &Colormap) {
+    monochrome::tone_map(photon_image, &map.stops);
+}
+
 // #[wasm_bindgen]
 // pub fn selective_color_convert(mut photon_image: &mut PhotonImage, ref_color:Rgb, new_color:Rgb, fraction: f32) {
 //     let img = helpers::dyn_image_from_raw(&photon_image);
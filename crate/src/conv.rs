@@ -1,15 +1,26 @@
 //! Convolution effects such as sharpening, blurs, sobel filters, etc.,
 
 extern crate image;
+use crate::alpha;
+use crate::cancel::{self, CancellationToken};
+use crate::error::PhotonError;
+use crate::gamma;
 use crate::helpers;
 use crate::PhotonImage;
 use wasm_bindgen::prelude::*;
 
-fn conv(mut photon_image: &mut PhotonImage, kernel: Vec<f32>) {
-    let mut img = helpers::dyn_image_from_raw(&photon_image);
+fn conv(photon_image: &mut PhotonImage, kernel: Vec<f32>) {
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        filter3x3(photon_image, &kernel)
+    })
+}
+
+/// Filter the colours of an image with a 3x3 kernel, making it opaque.
+fn filter3x3(photon_image: &mut PhotonImage, kernel: &[f32]) {
+    let mut img = helpers::dyn_image_from_raw(photon_image);
     img = image::ImageRgb8(img.to_rgb());
 
-    let mut filtered_img = img.filter3x3(&kernel);
+    let mut filtered_img = img.filter3x3(kernel);
     filtered_img = image::ImageRgba8(filtered_img.to_rgba());
 
     photon_image.raw_pixels = filtered_img.raw_pixels();
@@ -59,6 +70,34 @@ pub fn sharpen(photon_image: &mut PhotonImage) {
     conv(photon_image, kernel)
 }
 
+/// Sharpen an image by a variable amount.
+///
+/// The sharpening kernel's contribution is scaled continuously, so an `amount` of 1 is the
+/// same as `sharpen`, 0.5 sharpens half as much, and 2 sharpens about as much as calling
+/// `sharpen` twice, without the noise and haloes that repeated sharpening builds up.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `amount` - How much to sharpen the image. 0 doesn't sharpen it, and negative values
+/// soften it slightly.
+///
+/// # Example
+///
+/// ```
+/// // For example, to sharpen an image gently:
+/// use photon_rs::conv::sharpen_amount;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// sharpen_amount(&mut img, 0.4);
+/// ```
+#[wasm_bindgen]
+pub fn sharpen_amount(photon_image: &mut PhotonImage, amount: f32) {
+    let a = amount;
+    let kernel = vec![0.0f32, -a, 0.0, -a, 1.0 + 4.0 * a, -a, 0.0, -a, 0.0];
+    conv(photon_image, kernel)
+}
+
 /// Apply edge detection to an image, to create a dark version with its edges highlighted.
 ///
 /// # Arguments
@@ -118,8 +157,17 @@ pub fn identity(photon_image: &mut PhotonImage) {
 /// ```
 #[wasm_bindgen]
 pub fn box_blur(photon_image: &mut PhotonImage) {
-    let kernel = vec![1.0f32, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
-    conv(photon_image, kernel)
+    let kernel = [1.0f32, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+    let (width, height) = (photon_image.width, photon_image.height);
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        let _: Result<(), ()> =
+            alpha::filter_premultiplied(&mut photon_image.raw_pixels, |pixels| {
+                let mut img = PhotonImage::new(std::mem::take(pixels), width, height);
+                filter3x3(&mut img, &kernel);
+                *pixels = img.raw_pixels;
+                Ok(())
+            });
+    })
 }
 
 /// Gaussian blur in linear time.
@@ -140,21 +188,278 @@ pub fn box_blur(photon_image: &mut PhotonImage) {
 /// ```
 #[wasm_bindgen]
 pub fn gaussian_blur(photon_image: &mut PhotonImage, radius: i32) {
-    // construct pixel data
-    let img = helpers::dyn_image_from_raw(&photon_image);
-    let mut src = img.raw_pixels();
+    // Without a token, the blur can never be cancelled.
+    let _ = gaussian_blur_inner(photon_image, radius, None);
+}
 
+/// Gaussian blur which can be cancelled part-way through with a `CancellationToken`.
+///
+/// If the token is cancelled before the blur completes, `PhotonError::Cancelled` is
+/// returned and the image is left unmodified.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage
+/// * `radius` - blur radius
+/// * `token` - The token to poll for cancellation.
+/// # Example
+///
+/// ```
+/// use photon_rs::cancel::CancellationToken;
+/// use photon_rs::conv::gaussian_blur_cancellable;
+/// use photon_rs::PhotonImage;
+///
+/// let mut img = PhotonImage::new(vec![120; 16 * 16 * 4], 16, 16);
+/// let token = CancellationToken::new();
+/// gaussian_blur_cancellable(&mut img, 3_i32, &token).unwrap();
+/// ```
+#[wasm_bindgen]
+pub fn gaussian_blur_cancellable(
+    photon_image: &mut PhotonImage,
+    radius: i32,
+    token: &CancellationToken,
+) -> Result<(), PhotonError> {
+    gaussian_blur_inner(photon_image, radius, Some(token))
+}
+
+fn gaussian_blur_inner(
+    photon_image: &mut PhotonImage,
+    radius: i32,
+    token: Option<&CancellationToken>,
+) -> Result<(), PhotonError> {
     let width = photon_image.get_width();
     let height = photon_image.get_height();
-    let mut target: Vec<u8> = src.clone();
-
     let bxs = boxes_for_gauss(radius as f32, 3);
-    box_blur_inner(&mut src, &mut target, width, height, (bxs[0] - 1) / 2);
-    box_blur_inner(&mut target, &mut src, width, height, (bxs[1] - 1) / 2);
-    box_blur_inner(&mut src, &mut target, width, height, (bxs[2] - 1) / 2);
 
-    // manipulate back
-    photon_image.raw_pixels = target;
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        let mut pixels = photon_image.raw_pixels.clone();
+        if gamma::get_linear_light() {
+            gamma::filter_linear(&mut pixels, |src| {
+                box_blurs(src, width, height, &bxs, token)
+            })?;
+        } else {
+            alpha::filter_premultiplied(&mut pixels, |src| {
+                box_blurs(src, width, height, &bxs, token)
+            })?;
+        }
+
+        // manipulate back
+        photon_image.raw_pixels = pixels;
+        Ok(())
+    })
+}
+
+/// Reduce noise in a photo, smoothing colour noise more strongly than brightness noise,
+/// as the denoisers in photo editors do.
+///
+/// The image is split into luma (brightness) and chroma (colour), as YCbCr. The eye is
+/// much less sensitive to fine detail in colour than in brightness, so chroma is blurred
+/// broadly, removing the coloured blotches of high-ISO noise, while luma is smoothed
+/// with an edge-preserving bilateral filter, which keeps detail sharp.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `luma_strength` - How strongly to smooth brightness noise, from 0 (not at all)
+/// to 1.
+/// * `chroma_strength` - How strongly to smooth colour noise, from 0 (not at all) to 1.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::conv::denoise;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// denoise(&mut img, 0.3, 0.8);
+/// ```
+#[wasm_bindgen]
+pub fn denoise(
+    photon_image: &mut PhotonImage,
+    luma_strength: f32,
+    chroma_strength: f32,
+) {
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    let luma_strength = luma_strength.clamp(0.0, 1.0);
+    let chroma_strength = chroma_strength.clamp(0.0, 1.0);
+
+    let ycbcr: Vec<(f32, f32, f32)> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(helpers::to_ycbcr)
+        .collect();
+
+    let luma: Vec<f32> = if luma_strength > 0.0 {
+        let spatial = 1.0 + luma_strength;
+        let range = 40.0 / 255.0 * luma_strength;
+        let radius = (2.0 * spatial).ceil() as isize;
+        let mut luma = Vec::with_capacity(ycbcr.len());
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let centre = ycbcr[y as usize * width + x as usize].0;
+                let (mut sum, mut total) = (0.0, 0.0);
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        let (sx, sy) = (x + dx, y + dy);
+                        if sx < 0
+                            || sy < 0
+                            || sx >= width as isize
+                            || sy >= height as isize
+                        {
+                            continue;
+                        }
+                        let value = ycbcr[sy as usize * width + sx as usize].0;
+                        let distance = (dx * dx + dy * dy) as f32 / (spatial * spatial);
+                        let difference = (value - centre) / range;
+                        let weight = (-0.5 * (distance + difference * difference)).exp();
+                        sum += value * weight;
+                        total += weight;
+                    }
+                }
+                luma.push(sum / total);
+            }
+        }
+        luma
+    } else {
+        ycbcr.iter().map(|p| p.0).collect()
+    };
+
+    // Blur the chroma with the same box blurs as `gaussian_blur`, on 16-bit values.
+    let to_u16 = |c: f32| ((c + 0.5) * 65535.0).round() as u16;
+    let mut chroma: Vec<u16> = ycbcr
+        .iter()
+        .flat_map(|p| vec![to_u16(p.1), to_u16(p.2), 0, 0])
+        .collect();
+    if chroma_strength > 0.0 {
+        let sizes = boxes_for_gauss(8.0 * chroma_strength, 3);
+        let _ = box_blurs(&mut chroma, width as u32, height as u32, &sizes, None);
+    }
+
+    for ((px, y), c) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(4)
+        .zip(luma)
+        .zip(chroma.chunks_exact(4))
+    {
+        let cb = c[0] as f32 / 65535.0 - 0.5;
+        let cr = c[1] as f32 / 65535.0 - 0.5;
+        px[..3].copy_from_slice(&helpers::from_ycbcr(y, cb, cr));
+    }
+}
+
+/// The radius of the patches compared by `nl_means`.
+const NL_MEANS_PATCH_RADIUS: isize = 1;
+
+/// The radius of the window searched for similar patches by `nl_means`.
+const NL_MEANS_SEARCH_RADIUS: isize = 5;
+
+/// The number of rows above and below a pixel which its result from `nl_means` depends on.
+#[cfg(feature = "pipeline")]
+pub(crate) const NL_MEANS_REACH: u32 =
+    (NL_MEANS_PATCH_RADIUS + NL_MEANS_SEARCH_RADIUS) as u32;
+
+/// Reduce noise with non-local means, which keeps fine texture and edges better than a
+/// blur or `denoise`, but is much slower.
+///
+/// Each pixel becomes a weighted average of the pixels in an 11x11 window around it, each
+/// weighted by how similar the 3x3 patch around it is to the patch around the pixel
+/// itself, so that only pixels in similar surroundings are averaged together. The alpha
+/// channel is left unchanged.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `strength` - How strongly to smooth, from 0 (not at all) to 1.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::conv::nl_means;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// nl_means(&mut img, 0.5);
+/// ```
+#[wasm_bindgen]
+pub fn nl_means(photon_image: &mut PhotonImage, strength: f32) {
+    let strength = strength.clamp(0.0, 1.0);
+    let width = photon_image.width as isize;
+    let height = photon_image.height as isize;
+    if strength == 0.0 || width == 0 || height == 0 {
+        return;
+    }
+    // The patch distance at which a pixel's weight falls to 1/e.
+    let h = 30.0 * strength;
+    let h2 = h * h;
+    let patch_len = (2 * NL_MEANS_PATCH_RADIUS + 1).pow(2) as f32 * 3.0;
+
+    let src = photon_image.raw_pixels.clone();
+    let index = |x: isize, y: isize| {
+        let x = x.clamp(0, width - 1);
+        let y = y.clamp(0, height - 1);
+        (y * width + x) as usize * 4
+    };
+    let patch_distance = |ax: isize, ay: isize, bx: isize, by: isize| {
+        let mut sum = 0.0;
+        for dy in -NL_MEANS_PATCH_RADIUS..=NL_MEANS_PATCH_RADIUS {
+            for dx in -NL_MEANS_PATCH_RADIUS..=NL_MEANS_PATCH_RADIUS {
+                let a = index(ax + dx, ay + dy);
+                let b = index(bx + dx, by + dy);
+                for c in 0..3 {
+                    let d = src[a + c] as f32 - src[b + c] as f32;
+                    sum += d * d;
+                }
+            }
+        }
+        sum / patch_len
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let (mut sums, mut total) = ([0.0f32; 3], 0.0);
+            let rows = (y - NL_MEANS_SEARCH_RADIUS).max(0)
+                ..=(y + NL_MEANS_SEARCH_RADIUS).min(height - 1);
+            for sy in rows {
+                let columns = (x - NL_MEANS_SEARCH_RADIUS).max(0)
+                    ..=(x + NL_MEANS_SEARCH_RADIUS).min(width - 1);
+                for sx in columns {
+                    let weight = (-patch_distance(x, y, sx, sy) / h2).exp();
+                    let i = index(sx, sy);
+                    for (sum, &c) in sums.iter_mut().zip(&src[i..i + 3]) {
+                        *sum += c as f32 * weight;
+                    }
+                    total += weight;
+                }
+            }
+            let i = index(x, y);
+            for (c, sum) in photon_image.raw_pixels[i..i + 3].iter_mut().zip(&sums) {
+                *c = (sum / total).round() as u8;
+            }
+        }
+    }
+}
+
+/// Apply a box blur of each of the given sizes in turn, which approximates a gaussian blur.
+fn box_blurs<T: Sample>(
+    src: &mut Vec<T>,
+    width: u32,
+    height: u32,
+    sizes: &[i32],
+    token: Option<&CancellationToken>,
+) -> Result<(), PhotonError> {
+    if width == 0 || height == 0 {
+        return Ok(());
+    }
+    // A box wider than the image would read past the ends of its rows or columns.
+    let max_radius = (width.min(height) as i32 - 1) / 2;
+    let radius = |size: i32| ((size - 1) / 2).min(max_radius);
+    let mut target = src.clone();
+    cancel::check(token)?;
+    box_blur_inner(src, &mut target, width, height, radius(sizes[0]));
+    cancel::check(token)?;
+    box_blur_inner(&mut target, src, width, height, radius(sizes[1]));
+    cancel::check(token)?;
+    box_blur_inner(src, &mut target, width, height, radius(sizes[2]));
+    *src = target;
+    Ok(())
 }
 
 fn boxes_for_gauss(sigma: f32, n: usize) -> Vec<i32> {
@@ -191,9 +496,35 @@ fn boxes_for_gauss(sigma: f32, n: usize) -> Vec<i32> {
     sizes
 }
 
-fn box_blur_inner(
-    src: &mut Vec<u8>,
-    target: &mut Vec<u8>,
+/// A channel value which can be blurred: 8-bit sRGB, or 16-bit linear light.
+trait Sample: Copy {
+    fn value(self) -> i32;
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Sample for u8 {
+    fn value(self) -> i32 {
+        self as i32
+    }
+
+    fn from_f32(value: f32) -> u8 {
+        num::clamp(value, 0.0, 255.0) as u8
+    }
+}
+
+impl Sample for u16 {
+    fn value(self) -> i32 {
+        self as i32
+    }
+
+    fn from_f32(value: f32) -> u16 {
+        num::clamp(value, 0.0, 65535.0) as u16
+    }
+}
+
+fn box_blur_inner<T: Sample>(
+    src: &mut [T],
+    target: &mut [T],
     width: u32,
     height: u32,
     radius: i32,
@@ -204,9 +535,9 @@ fn box_blur_inner(
     box_blur_vertical(src, target, width, height, radius);
 }
 
-fn box_blur_horizontal(
-    src: &[u8],
-    target: &mut Vec<u8>,
+fn box_blur_horizontal<T: Sample>(
+    src: &[T],
+    target: &mut [T],
     width: u32,
     height: u32,
     radius: i32,
@@ -217,9 +548,9 @@ fn box_blur_horizontal(
         let mut li: usize = ti;
         let mut ri: usize = ti + radius as usize * 4;
 
-        let fv_r = src[ti] as i32;
-        let fv_g = src[ti + 1] as i32;
-        let fv_b = src[ti + 2] as i32;
+        let fv_r = src[ti].value();
+        let fv_g = src[ti + 1].value();
+        let fv_b = src[ti + 2].value();
 
         let lv_r = src[ti + (width - 1) as usize * 4];
         let lv_g = src[ti + (width - 1) as usize * 4 + 1];
@@ -230,53 +561,53 @@ fn box_blur_horizontal(
         let mut val_b = (radius + 1) * fv_b;
 
         for j in 0..radius {
-            val_r += src[ti + j as usize * 4] as i32;
-            val_g += src[ti + j as usize * 4 + 1] as i32;
-            val_b += src[ti + j as usize * 4 + 2] as i32;
+            val_r += src[ti + j as usize * 4].value();
+            val_g += src[ti + j as usize * 4 + 1].value();
+            val_b += src[ti + j as usize * 4 + 2].value();
         }
 
         for _ in 0..radius + 1 {
-            val_r += src[ri] as i32 - fv_r;
-            val_g += src[ri + 1] as i32 - fv_g;
-            val_b += src[ri + 2] as i32 - fv_b;
+            val_r += src[ri].value() - fv_r;
+            val_g += src[ri + 1].value() - fv_g;
+            val_b += src[ri + 2].value() - fv_b;
             ri += 4;
 
-            target[ti] = num::clamp(val_r as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 1] = num::clamp(val_g as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 2] = num::clamp(val_b as f32 * iarr, 0.0, 255.0) as u8;
+            target[ti] = T::from_f32(val_r as f32 * iarr);
+            target[ti + 1] = T::from_f32(val_g as f32 * iarr);
+            target[ti + 2] = T::from_f32(val_b as f32 * iarr);
             ti += 4;
         }
 
         for _ in (radius + 1)..(width as i32 - radius) {
-            val_r += src[ri] as i32 - src[li] as i32;
-            val_g += src[ri + 1] as i32 - src[li + 1] as i32;
-            val_b += src[ri + 2] as i32 - src[li + 2] as i32;
+            val_r += src[ri].value() - src[li].value();
+            val_g += src[ri + 1].value() - src[li + 1].value();
+            val_b += src[ri + 2].value() - src[li + 2].value();
             ri += 4;
             li += 4;
 
-            target[ti] = num::clamp(val_r as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 1] = num::clamp(val_g as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 2] = num::clamp(val_b as f32 * iarr, 0.0, 255.0) as u8;
+            target[ti] = T::from_f32(val_r as f32 * iarr);
+            target[ti + 1] = T::from_f32(val_g as f32 * iarr);
+            target[ti + 2] = T::from_f32(val_b as f32 * iarr);
             ti += 4;
         }
 
         for _ in (width as i32 - radius)..width as i32 {
-            val_r += lv_r as i32 - src[li] as i32;
-            val_g += lv_g as i32 - src[li + 1] as i32;
-            val_b += lv_b as i32 - src[li + 2] as i32;
+            val_r += lv_r.value() - src[li].value();
+            val_g += lv_g.value() - src[li + 1].value();
+            val_b += lv_b.value() - src[li + 2].value();
             li += 4;
 
-            target[ti] = num::clamp(val_r as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 1] = num::clamp(val_g as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 2] = num::clamp(val_b as f32 * iarr, 0.0, 255.0) as u8;
+            target[ti] = T::from_f32(val_r as f32 * iarr);
+            target[ti + 1] = T::from_f32(val_g as f32 * iarr);
+            target[ti + 2] = T::from_f32(val_b as f32 * iarr);
             ti += 4;
         }
     }
 }
 
-fn box_blur_vertical(
-    src: &[u8],
-    target: &mut Vec<u8>,
+fn box_blur_vertical<T: Sample>(
+    src: &[T],
+    target: &mut [T],
     width: u32,
     height: u32,
     radius: i32,
@@ -288,9 +619,9 @@ fn box_blur_vertical(
         let mut li: usize = ti;
         let mut ri: usize = ti + (radius * width as i32) as usize * 4;
 
-        let fv_r = src[ti] as i32;
-        let fv_g = src[ti + 1] as i32;
-        let fv_b = src[ti + 2] as i32;
+        let fv_r = src[ti].value();
+        let fv_g = src[ti + 1].value();
+        let fv_b = src[ti + 2].value();
 
         let lv_r = src[ti + ((height - 1) * width) as usize * 4];
         let lv_g = src[ti + ((height - 1) * width) as usize * 4 + 1];
@@ -301,45 +632,45 @@ fn box_blur_vertical(
         let mut val_b = (radius + 1) * fv_b;
 
         for j in 0..radius {
-            val_r += src[ti + (j * width as i32) as usize * 4] as i32;
-            val_g += src[ti + (j * width as i32) as usize * 4 + 1] as i32;
-            val_b += src[ti + (j * width as i32) as usize * 4 + 2] as i32;
+            val_r += src[ti + (j * width as i32) as usize * 4].value();
+            val_g += src[ti + (j * width as i32) as usize * 4 + 1].value();
+            val_b += src[ti + (j * width as i32) as usize * 4 + 2].value();
         }
 
         for _ in 0..radius + 1 {
-            val_r += src[ri] as i32 - fv_r;
-            val_g += src[ri + 1] as i32 - fv_g;
-            val_b += src[ri + 2] as i32 - fv_b;
+            val_r += src[ri].value() - fv_r;
+            val_g += src[ri + 1].value() - fv_g;
+            val_b += src[ri + 2].value() - fv_b;
             ri += width as usize * 4;
 
-            target[ti] = num::clamp(val_r as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 1] = num::clamp(val_g as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 2] = num::clamp(val_b as f32 * iarr, 0.0, 255.0) as u8;
+            target[ti] = T::from_f32(val_r as f32 * iarr);
+            target[ti + 1] = T::from_f32(val_g as f32 * iarr);
+            target[ti + 2] = T::from_f32(val_b as f32 * iarr);
             ti += width as usize * 4;
         }
 
         for _ in (radius + 1)..(height as i32 - radius) {
-            val_r += src[ri] as i32 - src[li] as i32;
-            val_g += src[ri + 1] as i32 - src[li + 1] as i32;
-            val_b += src[ri + 2] as i32 - src[li + 2] as i32;
+            val_r += src[ri].value() - src[li].value();
+            val_g += src[ri + 1].value() - src[li + 1].value();
+            val_b += src[ri + 2].value() - src[li + 2].value();
             ri += width as usize * 4;
             li += width as usize * 4;
 
-            target[ti] = num::clamp(val_r as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 1] = num::clamp(val_g as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 2] = num::clamp(val_b as f32 * iarr, 0.0, 255.0) as u8;
+            target[ti] = T::from_f32(val_r as f32 * iarr);
+            target[ti + 1] = T::from_f32(val_g as f32 * iarr);
+            target[ti + 2] = T::from_f32(val_b as f32 * iarr);
             ti += width as usize * 4;
         }
 
         for _ in (height as i32 - radius)..height as i32 {
-            val_r += lv_r as i32 - src[li] as i32;
-            val_g += lv_g as i32 - src[li + 1] as i32;
-            val_b += lv_b as i32 - src[li + 2] as i32;
+            val_r += lv_r.value() - src[li].value();
+            val_g += lv_g.value() - src[li + 1].value();
+            val_b += lv_b.value() - src[li + 2].value();
             li += width as usize * 4;
 
-            target[ti] = num::clamp(val_r as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 1] = num::clamp(val_g as f32 * iarr, 0.0, 255.0) as u8;
-            target[ti + 2] = num::clamp(val_b as f32 * iarr, 0.0, 255.0) as u8;
+            target[ti] = T::from_f32(val_r as f32 * iarr);
+            target[ti + 1] = T::from_f32(val_g as f32 * iarr);
+            target[ti + 2] = T::from_f32(val_b as f32 * iarr);
             ti += width as usize * 4;
         }
     }
@@ -551,3 +882,241 @@ pub fn sobel_vertical(photon_image: &mut PhotonImage) {
     let kernel = vec![-1.0f32, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0];
     conv(photon_image, kernel)
 }
+
+/// Partially recover the detail of a blurred image by Richardson–Lucy deconvolution, given
+/// the point spread function (PSF) of the blur: the image of a single point of light.
+///
+/// Each iteration blurs the current estimate with the PSF, compares it with the image, and
+/// corrects the estimate by the ratio between them. More iterations recover more detail,
+/// but also amplify noise and ringing, so 10 to 30 usually suit mildly out-of-focus or
+/// motion-blurred photos.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `psf` - The point spread function, row by row, eg: a disc for defocus blur, or a line
+/// for motion blur. It's normalised, so only the relative weights matter.
+/// * `psf_width` - The width of the point spread function; its height is
+/// `psf.len() / psf_width`.
+/// * `iterations` - The number of iterations to run.
+///
+/// # Panics
+/// Panics if the PSF's length isn't a multiple of its width, or its weights don't sum to
+/// more than 0.
+///
+/// # Example
+///
+/// ```
+/// // For example, to undo a horizontal motion blur of 5 pixels:
+/// use photon_rs::conv::deconvolve_rl;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// deconvolve_rl(&mut img, &[1.0; 5], 5_u32, 20_u32);
+/// ```
+#[wasm_bindgen]
+pub fn deconvolve_rl(
+    photon_image: &mut PhotonImage,
+    psf: &[f32],
+    psf_width: u32,
+    iterations: u32,
+) {
+    let psf_width = psf_width as usize;
+    if psf_width == 0 || psf.is_empty() || !psf.len().is_multiple_of(psf_width) {
+        panic!(
+            "A PSF of {} weights can't be {} weights wide.",
+            psf.len(),
+            psf_width
+        );
+    }
+    let total: f32 = psf.iter().sum();
+    if total <= 0.0 {
+        panic!("The weights of the PSF must sum to more than 0.");
+    }
+    let psf: Vec<f32> = psf.iter().map(|w| w / total).collect();
+    let flipped: Vec<f32> = psf.iter().rev().cloned().collect();
+    let psf_height = psf.len() / psf_width;
+    let centre = (psf_width / 2, psf_height / 2);
+    let flipped_centre = (psf_width - 1 - centre.0, psf_height - 1 - centre.1);
+
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    // Offset the values from 0, so that the ratios are always defined.
+    let observed: Vec<f32> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .flat_map(|px| px[..3].iter().map(|&v| v as f32 + 1.0))
+        .collect();
+
+    let mut estimate = observed.clone();
+    for _ in 0..iterations {
+        let blurred = convolve_rgb(&estimate, width, height, &psf, psf_width, centre);
+        let ratio: Vec<f32> = observed
+            .iter()
+            .zip(&blurred)
+            .map(|(o, b)| o / b.max(1e-3))
+            .collect();
+        let correction =
+            convolve_rgb(&ratio, width, height, &flipped, psf_width, flipped_centre);
+        for (e, c) in estimate.iter_mut().zip(&correction) {
+            *e *= c;
+        }
+    }
+
+    for (px, rgb) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(4)
+        .zip(estimate.chunks_exact(3))
+    {
+        for (channel, value) in px.iter_mut().zip(rgb) {
+            *channel = (value - 1.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// The shape of the aperture of a lens, which gives the shape of out-of-focus highlights.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApertureShape {
+    /// A circular aperture, as in lenses with rounded aperture blades, or used wide open.
+    Disc,
+    /// A hexagonal aperture, as in lenses with six straight aperture blades.
+    Hexagon,
+}
+
+/// Blur an image like an out-of-focus lens, spreading each point into the shape of the
+/// lens' aperture.
+///
+/// Unlike a gaussian blur, which fades points out smoothly, a lens spreads each point
+/// evenly over a disc or polygon with a crisp edge, so small bright highlights, such as
+/// street lights, become distinct shapes, known as bokeh. Brighter pixels are given more
+/// weight, so that highlights aren't dimmed by averaging them with their darker
+/// surroundings, as they wouldn't be by a lens.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `radius` - The radius of the aperture's shape, in pixels, eg: 8.0.
+/// * `shape` - The shape of the aperture.
+/// * `highlight_boost` - How much more weight to give bright pixels, from 0 (none) upwards,
+/// eg: 4.0.
+/// # Example
+///
+/// ```
+/// use photon_rs::conv::{bokeh_blur, ApertureShape};
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("night.jpg");
+/// bokeh_blur(&mut img, 8.0_f32, ApertureShape::Hexagon, 4.0_f32);
+/// ```
+#[wasm_bindgen]
+pub fn bokeh_blur(
+    photon_image: &mut PhotonImage,
+    radius: f32,
+    shape: ApertureShape,
+    highlight_boost: f32,
+) {
+    if radius < 0.5 {
+        return;
+    }
+    let width = photon_image.width as isize;
+    let height = photon_image.height as isize;
+    let reach = radius.ceil() as isize;
+
+    // The coverage of each pixel of the kernel by the shape, with antialiased edges.
+    let inradius = radius * 3f32.sqrt() / 2.0;
+    let mut kernel = vec![];
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            let (x, y) = (dx as f32, dy as f32);
+            let coverage = match shape {
+                ApertureShape::Disc => radius + 0.5 - (x * x + y * y).sqrt(),
+                ApertureShape::Hexagon => {
+                    // The distance from the centre along the normals of the three pairs of
+                    // sides, of a hexagon with a flat top and bottom.
+                    let along = |angle: f32| (x * angle.cos() + y * angle.sin()).abs();
+                    let furthest = along(30f32.to_radians())
+                        .max(along(90f32.to_radians()))
+                        .max(along(150f32.to_radians()));
+                    inradius + 0.5 - furthest
+                }
+            };
+            if coverage > 0.0 {
+                kernel.push((dx, dy, coverage.min(1.0)));
+            }
+        }
+    }
+
+    let src = &photon_image.raw_pixels;
+    let boost = highlight_boost.max(0.0);
+    let weights: Vec<f32> = src
+        .chunks_exact(4)
+        .map(|px| {
+            let brightness = helpers::luma(px) as f32 / 255.0;
+            (1.0 + boost * brightness.powi(4)) * px[3] as f32 / 255.0
+        })
+        .collect();
+
+    let mut out = vec![0; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0.0f32; 3];
+            let (mut weight_sum, mut alpha_sum, mut coverage_sum) = (0.0, 0.0, 0.0);
+            for &(dx, dy, coverage) in &kernel {
+                let (sx, sy) = (x + dx, y + dy);
+                if sx < 0 || sy < 0 || sx >= width || sy >= height {
+                    continue;
+                }
+                let j = (sy * width + sx) as usize;
+                let weight = coverage * weights[j];
+                for (c, s) in sum.iter_mut().enumerate() {
+                    *s += weight * src[j * 4 + c] as f32;
+                }
+                weight_sum += weight;
+                alpha_sum += coverage * src[j * 4 + 3] as f32;
+                coverage_sum += coverage;
+            }
+            let i = (y * width + x) as usize * 4;
+            if weight_sum > 0.0 {
+                for (c, s) in sum.iter().enumerate() {
+                    out[i + c] = (s / weight_sum).round().min(255.0) as u8;
+                }
+            }
+            out[i + 3] = (alpha_sum / coverage_sum).round().min(255.0) as u8;
+        }
+    }
+    photon_image.raw_pixels = out;
+}
+
+/// Convolve interleaved RGB values with a kernel, with the kernel's `centre` over each
+/// pixel, extending the edges of the image outwards.
+fn convolve_rgb(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    kernel: &[f32],
+    kernel_width: usize,
+    centre: (usize, usize),
+) -> Vec<f32> {
+    let (cx, cy) = (centre.0 as isize, centre.1 as isize);
+    let mut out = vec![0.0; values.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0.0f32; 3];
+            for (k, weight) in kernel.iter().enumerate() {
+                if *weight == 0.0 {
+                    continue;
+                }
+                let sx = (x as isize + (k % kernel_width) as isize - cx)
+                    .max(0)
+                    .min(width as isize - 1) as usize;
+                let sy = (y as isize + (k / kernel_width) as isize - cy)
+                    .max(0)
+                    .min(height as isize - 1) as usize;
+                let i = (sy * width + sx) * 3;
+                for (c, s) in sum.iter_mut().enumerate() {
+                    *s += weight * values[i + c];
+                }
+            }
+            out[(y * width + x) * 3..(y * width + x) * 3 + 3].copy_from_slice(&sum);
+        }
+    }
+    out
+}
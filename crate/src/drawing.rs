@@ -0,0 +1,864 @@
+//! Draw anti-aliased lines, shapes and curves onto images.
+//!
+//! Coordinates are in pixels and may be fractional, with `(0, 0)` being the top-left corner
+//! of the image. Shapes are drawn with the alpha of their colour, so semi-transparent
+//! colours can be used for overlays and highlights.
+
+use crate::multiple::blend_colours;
+use crate::{gamma, helpers};
+use crate::{Connectivity, PhotonImage, Point, Rect, Rgba};
+use wasm_bindgen::prelude::*;
+
+/// Draw a straight line between two points.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `x0` - x-coordinate of the start of the line.
+/// * `y0` - y-coordinate of the start of the line.
+/// * `x1` - x-coordinate of the end of the line.
+/// * `y1` - y-coordinate of the end of the line.
+/// * `colour` - The colour of the line.
+/// * `width` - The thickness of the line, in pixels.
+///
+/// # Example
+///
+/// ```
+/// // For example, to draw a 3px-wide red diagonal line across an image:
+/// use photon_rs::drawing::draw_line;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// let (width, height) = (img.get_width() as f32, img.get_height() as f32);
+/// draw_line(&mut img, 0.0, 0.0, width, height, &Rgba::new(255, 0, 0, 255), 3.0);
+/// ```
+#[wasm_bindgen]
+pub fn draw_line(
+    photon_image: &mut PhotonImage,
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    colour: &Rgba,
+    width: f32,
+) {
+    stroke_path(photon_image, &[(x0, y0), (x1, y1)], false, colour, width);
+}
+
+/// Draw the outline of a rectangle.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `x` - x-coordinate of the top-left corner of the rectangle.
+/// * `y` - y-coordinate of the top-left corner of the rectangle.
+/// * `width` - The width of the rectangle.
+/// * `height` - The height of the rectangle.
+/// * `colour` - The colour of the outline.
+/// * `stroke_width` - The thickness of the outline, which is centred on the rectangle's edges.
+///
+/// # Example
+///
+/// ```
+/// // For example, to outline a 100x50 region at (10, 10) in green:
+/// use photon_rs::drawing::draw_rect;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// draw_rect(&mut img, 10.0, 10.0, 100.0, 50.0, &Rgba::new(0, 255, 0, 255), 2.0);
+/// ```
+#[wasm_bindgen]
+pub fn draw_rect(
+    photon_image: &mut PhotonImage,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    colour: &Rgba,
+    stroke_width: f32,
+) {
+    let points = [
+        (x, y),
+        (x + width, y),
+        (x + width, y + height),
+        (x, y + height),
+    ];
+    stroke_path(photon_image, &points, true, colour, stroke_width);
+}
+
+/// Fill a rectangle.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `x` - x-coordinate of the top-left corner of the rectangle.
+/// * `y` - y-coordinate of the top-left corner of the rectangle.
+/// * `width` - The width of the rectangle.
+/// * `height` - The height of the rectangle.
+/// * `colour` - The colour to fill the rectangle with.
+///
+/// # Example
+///
+/// ```
+/// // For example, to darken a strip along the top of an image:
+/// use photon_rs::drawing::fill_rect;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// let width = img.get_width() as f32;
+/// fill_rect(&mut img, 0.0, 0.0, width, 40.0, &Rgba::new(0, 0, 0, 128));
+/// ```
+#[wasm_bindgen]
+pub fn fill_rect(
+    photon_image: &mut PhotonImage,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    colour: &Rgba,
+) {
+    let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+    let (hw, hh) = (width.abs() / 2.0, height.abs() / 2.0);
+    let mut mask = Mask::new(photon_image, cx - hw, cy - hh, cx + hw, cy + hh);
+    mask.cover(cx - hw, cy - hh, cx + hw, cy + hh, |px, py| {
+        let dx = (px - cx).abs() - hw;
+        let dy = (py - cy).abs() - hh;
+        let outside = (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt();
+        outside + dx.max(dy).min(0.0)
+    });
+    mask.paint(photon_image, colour);
+}
+
+/// Draw the outline of a circle.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `cx` - x-coordinate of the centre of the circle.
+/// * `cy` - y-coordinate of the centre of the circle.
+/// * `radius` - The radius of the circle.
+/// * `colour` - The colour of the outline.
+/// * `stroke_width` - The thickness of the outline, which is centred on the circle's edge.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::drawing::draw_circle;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// draw_circle(&mut img, 100.0, 100.0, 40.0, &Rgba::new(255, 255, 0, 255), 4.0);
+/// ```
+#[wasm_bindgen]
+pub fn draw_circle(
+    photon_image: &mut PhotonImage,
+    cx: f32,
+    cy: f32,
+    radius: f32,
+    colour: &Rgba,
+    stroke_width: f32,
+) {
+    draw_ellipse(photon_image, cx, cy, radius, radius, colour, stroke_width);
+}
+
+/// Fill a circle.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `cx` - x-coordinate of the centre of the circle.
+/// * `cy` - y-coordinate of the centre of the circle.
+/// * `radius` - The radius of the circle.
+/// * `colour` - The colour to fill the circle with.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::drawing::fill_circle;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// fill_circle(&mut img, 100.0, 100.0, 40.0, &Rgba::new(255, 255, 255, 200));
+/// ```
+#[wasm_bindgen]
+pub fn fill_circle(
+    photon_image: &mut PhotonImage,
+    cx: f32,
+    cy: f32,
+    radius: f32,
+    colour: &Rgba,
+) {
+    fill_ellipse(photon_image, cx, cy, radius, radius, colour);
+}
+
+/// Draw the outline of an axis-aligned ellipse.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `cx` - x-coordinate of the centre of the ellipse.
+/// * `cy` - y-coordinate of the centre of the ellipse.
+/// * `rx` - The horizontal radius of the ellipse.
+/// * `ry` - The vertical radius of the ellipse.
+/// * `colour` - The colour of the outline.
+/// * `stroke_width` - The thickness of the outline, which is centred on the ellipse's edge.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::drawing::draw_ellipse;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// draw_ellipse(&mut img, 150.0, 100.0, 80.0, 40.0, &Rgba::new(255, 0, 0, 255), 3.0);
+/// ```
+#[wasm_bindgen]
+pub fn draw_ellipse(
+    photon_image: &mut PhotonImage,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    colour: &Rgba,
+    stroke_width: f32,
+) {
+    let half = stroke_width / 2.0;
+    let (rx, ry) = (rx.abs(), ry.abs());
+    let (x0, y0) = (cx - rx - half, cy - ry - half);
+    let (x1, y1) = (cx + rx + half, cy + ry + half);
+    let mut mask = Mask::new(photon_image, x0, y0, x1, y1);
+    mask.cover(x0, y0, x1, y1, |px, py| {
+        ellipse_distance(px - cx, py - cy, rx, ry).abs() - half
+    });
+    mask.paint(photon_image, colour);
+}
+
+/// Fill an axis-aligned ellipse.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `cx` - x-coordinate of the centre of the ellipse.
+/// * `cy` - y-coordinate of the centre of the ellipse.
+/// * `rx` - The horizontal radius of the ellipse.
+/// * `ry` - The vertical radius of the ellipse.
+/// * `colour` - The colour to fill the ellipse with.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::drawing::fill_ellipse;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// fill_ellipse(&mut img, 150.0, 100.0, 80.0, 40.0, &Rgba::new(0, 0, 255, 100));
+/// ```
+#[wasm_bindgen]
+pub fn fill_ellipse(
+    photon_image: &mut PhotonImage,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    colour: &Rgba,
+) {
+    let (rx, ry) = (rx.abs(), ry.abs());
+    let (x0, y0, x1, y1) = (cx - rx, cy - ry, cx + rx, cy + ry);
+    let mut mask = Mask::new(photon_image, x0, y0, x1, y1);
+    mask.cover(x0, y0, x1, y1, |px, py| {
+        ellipse_distance(px - cx, py - cy, rx, ry)
+    });
+    mask.paint(photon_image, colour);
+}
+
+/// Draw the outline of a polygon.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `points` - The coordinates of the polygon's vertices, as `[x0, y0, x1, y1, ...]`.
+/// The last vertex is joined back to the first.
+/// * `colour` - The colour of the outline.
+/// * `stroke_width` - The thickness of the outline, which is centred on the polygon's edges.
+///
+/// # Example
+///
+/// ```
+/// // For example, to outline a triangle:
+/// use photon_rs::drawing::draw_polygon;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// let points = [50.0, 10.0, 90.0, 80.0, 10.0, 80.0];
+/// draw_polygon(&mut img, &points, &Rgba::new(255, 0, 0, 255), 2.0);
+/// ```
+#[wasm_bindgen]
+pub fn draw_polygon(
+    photon_image: &mut PhotonImage,
+    points: &[f32],
+    colour: &Rgba,
+    stroke_width: f32,
+) {
+    let points = pairs(points);
+    stroke_path(photon_image, &points, true, colour, stroke_width);
+}
+
+/// Draw a series of connected straight lines.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `points` - The coordinates of the points to join, as `[x0, y0, x1, y1, ...]`.
+/// * `colour` - The colour of the lines.
+/// * `width` - The thickness of the lines, in pixels.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::drawing::draw_polyline;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// let points = [10.0, 90.0, 40.0, 30.0, 70.0, 60.0, 100.0, 10.0];
+/// draw_polyline(&mut img, &points, &Rgba::new(0, 255, 0, 255), 2.0);
+/// ```
+#[wasm_bindgen]
+pub fn draw_polyline(
+    photon_image: &mut PhotonImage,
+    points: &[f32],
+    colour: &Rgba,
+    width: f32,
+) {
+    let points = pairs(points);
+    stroke_path(photon_image, &points, false, colour, width);
+}
+
+/// Fill a polygon. Self-intersecting polygons are filled using the even-odd rule.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `points` - The coordinates of the polygon's vertices, as `[x0, y0, x1, y1, ...]`.
+/// * `colour` - The colour to fill the polygon with.
+///
+/// # Example
+///
+/// ```
+/// // For example, to fill a triangle:
+/// use photon_rs::drawing::fill_polygon;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// let points = [50.0, 10.0, 90.0, 80.0, 10.0, 80.0];
+/// fill_polygon(&mut img, &points, &Rgba::new(255, 0, 0, 255));
+/// ```
+#[wasm_bindgen]
+pub fn fill_polygon(photon_image: &mut PhotonImage, points: &[f32], colour: &Rgba) {
+    let points = pairs(points);
+    if points.len() < 3 {
+        return;
+    }
+    let (x0, y0, x1, y1) = bounds(&points, 0.0);
+    let mut mask = Mask::new(photon_image, x0, y0, x1, y1);
+    mask.cover(x0, y0, x1, y1, |px, py| {
+        let mut distance = f32::MAX;
+        let mut inside = false;
+        for i in 0..points.len() {
+            let a = points[i];
+            let b = points[(i + 1) % points.len()];
+            distance = distance.min(segment_distance(px, py, a, b));
+            if (a.1 > py) != (b.1 > py)
+                && px < a.0 + (py - a.1) / (b.1 - a.1) * (b.0 - a.0)
+            {
+                inside = !inside;
+            }
+        }
+        if inside {
+            -distance
+        } else {
+            distance
+        }
+    });
+    mask.paint(photon_image, colour);
+}
+
+/// Draw a cubic Bézier curve, or a path of several curves joined end to end.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `points` - The coordinates of the curve's control points, as `[x0, y0, x1, y1, ...]`.
+/// The first curve is defined by the first four points (start, two control points, end), and
+/// each further curve by the end of the previous curve followed by the next three points.
+/// * `colour` - The colour of the curve.
+/// * `width` - The thickness of the curve, in pixels.
+///
+/// # Example
+///
+/// ```
+/// // For example, to draw an S-shaped curve:
+/// use photon_rs::drawing::draw_cubic_bezier;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let mut img = open_image("img.jpg");
+/// let points = [10.0, 10.0, 200.0, 10.0, 10.0, 200.0, 200.0, 200.0];
+/// draw_cubic_bezier(&mut img, &points, &Rgba::new(255, 255, 255, 255), 3.0);
+/// ```
+#[wasm_bindgen]
+pub fn draw_cubic_bezier(
+    photon_image: &mut PhotonImage,
+    points: &[f32],
+    colour: &Rgba,
+    width: f32,
+) {
+    let controls = pairs(points);
+    if controls.len() < 4 {
+        return;
+    }
+
+    let mut path = vec![controls[0]];
+    for curve in controls[1..].chunks_exact(3) {
+        let p0 = *path.last().unwrap();
+        let (p1, p2, p3) = (curve[0], curve[1], curve[2]);
+
+        // Flatten the curve into enough segments that each is at most a few pixels long.
+        let length = distance(p0, p1) + distance(p1, p2) + distance(p2, p3);
+        let steps = num::clamp((length / 4.0).ceil() as usize, 1, 1000);
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            path.push((
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            ));
+        }
+    }
+    stroke_path(photon_image, &path, false, colour, width);
+}
+
+/// A linear or radial gradient, made up of colour stops.
+///
+/// The positions of gradients are given in pixels, relative to the top-left corner of the
+/// region they are drawn in. Before the first stop and after the last stop, the colour of
+/// the nearest stop is used.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    shape: GradientShape,
+    stops: Vec<(f32, Rgba)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum GradientShape {
+    Linear { x0: f32, y0: f32, x1: f32, y1: f32 },
+    Radial { cx: f32, cy: f32, radius: f32 },
+}
+
+#[wasm_bindgen]
+impl Gradient {
+    /// Create a gradient which changes colour along the line from `(x0, y0)` to `(x1, y1)`.
+    pub fn linear(x0: f32, y0: f32, x1: f32, y1: f32) -> Gradient {
+        Gradient {
+            shape: GradientShape::Linear { x0, y0, x1, y1 },
+            stops: vec![],
+        }
+    }
+
+    /// Create a gradient which changes colour outwards from `(cx, cy)`, to `radius` pixels.
+    pub fn radial(cx: f32, cy: f32, radius: f32) -> Gradient {
+        Gradient {
+            shape: GradientShape::Radial { cx, cy, radius },
+            stops: vec![],
+        }
+    }
+
+    /// Add a colour stop to the gradient.
+    ///
+    /// # Arguments
+    /// * `offset` - The position of the stop, from 0 (the start of the gradient) to 1 (the end).
+    /// * `colour` - The colour at the stop.
+    pub fn add_stop(&mut self, offset: f32, colour: &Rgba) {
+        let offset = num::clamp(offset, 0.0, 1.0);
+        let i = self.stops.iter().take_while(|(o, _)| *o <= offset).count();
+        self.stops.insert(i, (offset, *colour));
+    }
+}
+
+impl Gradient {
+    /// The colour of the gradient at `(x, y)`, relative to the top-left of the region.
+    fn colour_at(&self, x: f32, y: f32) -> [f32; 4] {
+        let t = match self.shape {
+            GradientShape::Linear { x0, y0, x1, y1 } => {
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length_sq = dx * dx + dy * dy;
+                if length_sq > 0.0 {
+                    ((x - x0) * dx + (y - y0) * dy) / length_sq
+                } else {
+                    0.0
+                }
+            }
+            GradientShape::Radial { cx, cy, radius } => {
+                if radius > 0.0 {
+                    distance((x, y), (cx, cy)) / radius
+                } else {
+                    1.0
+                }
+            }
+        };
+        let t = num::clamp(t, 0.0, 1.0);
+
+        let to_f32 = |c: &Rgba| [c.r as f32, c.g as f32, c.b as f32, c.a as f32];
+        let after = self.stops.iter().position(|(offset, _)| *offset > t);
+        match after {
+            Some(0) => to_f32(&self.stops[0].1),
+            None => to_f32(&self.stops[self.stops.len() - 1].1),
+            Some(i) => {
+                let (o0, c0) = &self.stops[i - 1];
+                let (o1, c1) = &self.stops[i];
+                let f = (t - o0) / (o1 - o0);
+                let (c0, c1) = (to_f32(c0), to_f32(c1));
+                let mut colour = [0.0; 4];
+                for c in 0..4 {
+                    colour[c] = c0[c] + (c1[c] - c0[c]) * f;
+                }
+                colour
+            }
+        }
+    }
+}
+
+/// Paint a gradient onto a region of an image.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `region` - The region of the image to paint. Parts outside of the image are ignored.
+/// * `gradient` - The gradient to paint.
+/// * `blend_mode` - How to blend the gradient with the image. This can be any of the blend
+/// modes accepted by `multiple::blend`, eg: "over", "multiply" or "overlay".
+/// * `opacity` - The opacity of the gradient, from 0 (invisible) to 1.
+///
+/// # Example
+///
+/// ```
+/// // For example, to darken the bottom 200 pixels of an image, so text stands out against it:
+/// use photon_rs::drawing::{fill_gradient, Gradient};
+/// use photon_rs::native::open_image;
+/// use photon_rs::{Rect, Rgba};
+///
+/// let mut img = open_image("img.jpg");
+/// let (width, height) = (img.get_width(), img.get_height());
+/// let mut gradient = Gradient::linear(0.0, 0.0, 0.0, 200.0);
+/// gradient.add_stop(0.0, &Rgba::new(0, 0, 0, 0));
+/// gradient.add_stop(1.0, &Rgba::new(0, 0, 0, 200));
+/// let region = Rect::new(0, height.saturating_sub(200), width, 200);
+/// fill_gradient(&mut img, &region, &gradient, "over", 1.0);
+/// ```
+#[wasm_bindgen]
+pub fn fill_gradient(
+    photon_image: &mut PhotonImage,
+    region: &Rect,
+    gradient: &Gradient,
+    blend_mode: &str,
+    opacity: f32,
+) {
+    if gradient.stops.is_empty() {
+        return;
+    }
+    let opacity = num::clamp(opacity, 0.0, 1.0);
+    let width = photon_image.width;
+    let (x0, y0, x1, y1) = region.clip(width, photon_image.height);
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let rx = (x - region.x) as f32 + 0.5;
+            let ry = (y - region.y) as f32 + 0.5;
+            let colour = gradient.colour_at(rx, ry);
+            let raw = [
+                colour[0].round() as u8,
+                colour[1].round() as u8,
+                colour[2].round() as u8,
+                colour[3].round() as u8,
+            ];
+
+            let idx = ((y * width + x) * 4) as usize;
+            let px = &mut photon_image.raw_pixels[idx..idx + 4];
+            let base = gamma::blendable([px[0], px[1], px[2], px[3]]);
+            let top = gamma::blendable(raw);
+            let blended = gamma::from_blendable(blend_colours(top, base, blend_mode));
+            for c in 0..4 {
+                let value = px[c] as f32 + (blended[c] as f32 - px[c] as f32) * opacity;
+                px[c] = value.round() as u8;
+            }
+        }
+    }
+}
+
+/// Draw contours, such as those found by `analysis::find_contours`, as closed outlines
+/// through the centres of their pixels.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `contours` - The contours to draw, each a sequence of pixels.
+/// * `colour` - The colour of the outlines.
+/// * `stroke_width` - The thickness of the outlines, in pixels.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::drawing::draw_contours;
+/// use photon_rs::native::open_image;
+/// use photon_rs::{Point, Rgba};
+///
+/// let mut img = open_image("img.jpg");
+/// let triangle = vec![Point::new(10, 10), Point::new(20, 10), Point::new(20, 20)];
+/// draw_contours(&mut img, &[triangle], &Rgba::new(0, 255, 0, 255), 1.0);
+/// ```
+pub fn draw_contours(
+    photon_image: &mut PhotonImage,
+    contours: &[Vec<Point>],
+    colour: &Rgba,
+    stroke_width: f32,
+) {
+    for contour in contours {
+        let mut points: Vec<(f32, f32)> = contour
+            .iter()
+            .map(|p| (p.x as f32 + 0.5, p.y as f32 + 0.5))
+            .collect();
+        // Draw a contour of a single pixel as a dot.
+        if points.len() == 1 {
+            points.push(points[0]);
+        }
+        stroke_path(photon_image, &points, true, colour, stroke_width);
+    }
+}
+
+/// Fill the contiguous region of similar pixels around a point with a colour, like the
+/// bucket tool of a paint program.
+///
+/// Pixels whose channels are all within `tolerance` of the starting pixel's are filled,
+/// as long as they are connected to it through other such pixels. The colour is drawn
+/// with its alpha, so a semi-transparent colour tints the region.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `x` - x-coordinate of the starting pixel.
+/// * `y` - y-coordinate of the starting pixel.
+/// * `fill_color` - The colour to fill the region with.
+/// * `tolerance` - How much each channel may differ from the starting pixel's: 0 only
+/// fills pixels of exactly the same colour.
+/// * `connectivity` - Whether the region can spread diagonally between pixels.
+///
+/// # Panics
+/// Panics if the starting pixel is outside of the image.
+///
+/// # Example
+///
+/// ```
+/// // For example, to fill the background around the top-left corner with white:
+/// use photon_rs::drawing::flood_fill;
+/// use photon_rs::native::open_image;
+/// use photon_rs::{Connectivity, Rgba};
+///
+/// let mut img = open_image("img.jpg");
+/// let white = Rgba::new(255, 255, 255, 255);
+/// flood_fill(&mut img, 0, 0, &white, 16, Connectivity::Four);
+/// ```
+#[wasm_bindgen]
+pub fn flood_fill(
+    photon_image: &mut PhotonImage,
+    x: u32,
+    y: u32,
+    fill_color: &Rgba,
+    tolerance: u8,
+    connectivity: Connectivity,
+) {
+    let region = helpers::similar_region(photon_image, x, y, tolerance, connectivity);
+    for (px, &inside) in photon_image.raw_pixels.chunks_exact_mut(4).zip(&region) {
+        if inside {
+            blend(px, fill_color, 1.0);
+        }
+    }
+}
+
+/// Draw lines of the given width between consecutive points, joining the last point back
+/// to the first if `closed` is set. Overlapping segments are only painted once, so
+/// semi-transparent paths don't darken at their joins.
+fn stroke_path(
+    photon_image: &mut PhotonImage,
+    points: &[(f32, f32)],
+    closed: bool,
+    colour: &Rgba,
+    width: f32,
+) {
+    if points.len() < 2 {
+        return;
+    }
+    let half = width / 2.0;
+    let (x0, y0, x1, y1) = bounds(points, half);
+    let mut mask = Mask::new(photon_image, x0, y0, x1, y1);
+
+    let count = if closed {
+        points.len()
+    } else {
+        points.len() - 1
+    };
+    for i in 0..count {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        let (sx0, sy0, sx1, sy1) = bounds(&[a, b], half);
+        mask.cover(sx0, sy0, sx1, sy1, |px, py| {
+            segment_distance(px, py, a, b) - half
+        });
+    }
+    mask.paint(photon_image, colour);
+}
+
+/// The coverage of shape by each pixel within a rectangular region of an image.
+struct Mask {
+    left: i32,
+    top: i32,
+    width: i32,
+    height: i32,
+    values: Vec<f32>,
+}
+
+impl Mask {
+    /// Create an empty mask covering the pixels between `(x0, y0)` and `(x1, y1)`,
+    /// clipped to the bounds of the image.
+    fn new(photon_image: &PhotonImage, x0: f32, y0: f32, x1: f32, y1: f32) -> Mask {
+        let (left, top, right, bottom) = clip(photon_image, x0, y0, x1, y1);
+        let (width, height) = ((right - left).max(0), (bottom - top).max(0));
+        Mask {
+            left,
+            top,
+            width,
+            height,
+            values: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    /// Cover the pixels between `(x0, y0)` and `(x1, y1)` with a shape, given the signed
+    /// distance from the centre of a pixel to the edge of the shape (negative inside).
+    fn cover<F: Fn(f32, f32) -> f32>(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        f: F,
+    ) {
+        let x_range = (x0.floor() as i32 - 1).max(self.left)
+            ..(x1.ceil() as i32 + 1).min(self.left + self.width);
+        let y_range = (y0.floor() as i32 - 1).max(self.top)
+            ..(y1.ceil() as i32 + 1).min(self.top + self.height);
+
+        for y in y_range {
+            for x in x_range.clone() {
+                // A pixel is fully covered once it lies half a pixel inside the shape.
+                let coverage =
+                    num::clamp(0.5 - f(x as f32 + 0.5, y as f32 + 0.5), 0.0, 1.0);
+                let i = ((y - self.top) * self.width + (x - self.left)) as usize;
+                self.values[i] = self.values[i].max(coverage);
+            }
+        }
+    }
+
+    /// Blend `colour` over the image, weighted by the coverage of each pixel.
+    fn paint(&self, photon_image: &mut PhotonImage, colour: &Rgba) {
+        let image_width = photon_image.width as i32;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let coverage = self.values[(y * self.width + x) as usize];
+                if coverage <= 0.0 {
+                    continue;
+                }
+                let idx = ((y + self.top) * image_width + x + self.left) as usize * 4;
+                blend(&mut photon_image.raw_pixels[idx..idx + 4], colour, coverage);
+            }
+        }
+    }
+}
+
+/// Composite `colour` over an RGBA pixel, weighted by how much of the pixel is covered.
+fn blend(px: &mut [u8], colour: &Rgba, coverage: f32) {
+    let alpha = coverage * colour.a as f32 / 255.0;
+    let rgb = [colour.r, colour.g, colour.b];
+    for c in 0..3 {
+        px[c] = (rgb[c] as f32 * alpha + px[c] as f32 * (1.0 - alpha)).round() as u8;
+    }
+    px[3] = (255.0 * alpha + px[3] as f32 * (1.0 - alpha)).round() as u8;
+}
+
+/// Clip a region to the bounds of an image, returning whole pixel coordinates.
+fn clip(
+    photon_image: &PhotonImage,
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+) -> (i32, i32, i32, i32) {
+    let (width, height) = (photon_image.width as i32, photon_image.height as i32);
+    (
+        num::clamp(x0.min(x1).floor() as i32 - 1, 0, width),
+        num::clamp(y0.min(y1).floor() as i32 - 1, 0, height),
+        num::clamp(x0.max(x1).ceil() as i32 + 1, 0, width),
+        num::clamp(y0.max(y1).ceil() as i32 + 1, 0, height),
+    )
+}
+
+/// The bounding box of a set of points, grown by `margin` on every side.
+fn bounds(points: &[(f32, f32)], margin: f32) -> (f32, f32, f32, f32) {
+    let mut bounds = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for &(x, y) in points {
+        bounds = (
+            bounds.0.min(x),
+            bounds.1.min(y),
+            bounds.2.max(x),
+            bounds.3.max(y),
+        );
+    }
+    (
+        bounds.0 - margin,
+        bounds.1 - margin,
+        bounds.2 + margin,
+        bounds.3 + margin,
+    )
+}
+
+/// Group a flat list of coordinates into `(x, y)` points. A trailing x-coordinate without
+/// a y-coordinate is ignored.
+fn pairs(points: &[f32]) -> Vec<(f32, f32)> {
+    points.chunks_exact(2).map(|p| (p[0], p[1])).collect()
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+/// The distance from a point to the closest point on the line segment from `a` to `b`.
+fn segment_distance(px: f32, py: f32, a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 {
+        num::clamp(((px - a.0) * dx + (py - a.1) * dy) / length_sq, 0.0, 1.0)
+    } else {
+        0.0
+    };
+    distance((px, py), (a.0 + t * dx, a.1 + t * dy))
+}
+
+/// Approximate signed distance from a point (relative to the centre of an ellipse) to the
+/// ellipse's edge, which is accurate close to the edge.
+fn ellipse_distance(x: f32, y: f32, rx: f32, ry: f32) -> f32 {
+    if rx <= 0.0 || ry <= 0.0 {
+        return x.abs().max(y.abs());
+    }
+    let f = (x / rx).powi(2) + (y / ry).powi(2) - 1.0;
+    let gradient = 2.0 * ((x / (rx * rx)).powi(2) + (y / (ry * ry)).powi(2)).sqrt();
+    if gradient > 0.0 {
+        f / gradient
+    } else {
+        -rx.min(ry)
+    }
+}
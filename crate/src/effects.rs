@@ -6,15 +6,21 @@ use std::f64;
 extern crate imageproc;
 use imageproc::drawing::draw_filled_rect_mut;
 use imageproc::rect::Rect;
-extern crate rusttype;
-use crate::helpers;
+use crate::alpha::{self, with_alpha_mode, AlphaMode};
+use crate::conv::sharpen_amount;
+use crate::{helpers, simd};
 use crate::{PhotonImage, Rgb};
 use image::Rgba;
 use wasm_bindgen::prelude::*;
 use crate::iter::ImageIterator;
+use palette::white_point::D65;
+use palette::{Laba, Limited, Pixel, Srgba};
 
 /// Adds an offset to the image by a certain number of pixels.
 ///
+/// A 10px border at the right and bottom edges is left unchanged. To move a channel in
+/// either direction, along a single axis, or over the whole image, see `offset_with_direction`.
+///
 /// # Arguments
 /// * `img` - A PhotonImage that contains a view into the image.
 /// * `offset` - The offset is added to the pixels in the image.  
@@ -52,6 +58,68 @@ pub fn offset(photon_image: &mut PhotonImage, channel_index: usize, offset: u32)
     photon_image.raw_pixels = raw_pixels;
 }
 
+/// The direction in which `offset_with_direction` moves a channel.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OffsetDirection {
+    /// Positive offsets move the channel right, negative offsets move it left.
+    Horizontal,
+    /// Positive offsets move the channel down, negative offsets move it up.
+    Vertical,
+    /// Positive offsets move the channel down and right, negative offsets up and left.
+    Diagonal,
+}
+
+/// Moves a single channel of the image by a signed number of pixels, along an axis or
+/// diagonally, eg: for a channel-shift glitch effect.
+///
+/// Unlike `offset`, every pixel is shifted. Where the shifted channel would have to be
+/// sampled from outside the image, the nearest edge pixel is used instead.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `channel_index` - The index of the channel to move: 0 for red, 1 for green or 2 for blue.
+/// * `offset` - The number of pixels to move the channel by, which may be negative.
+/// * `direction` - The direction to move the channel in.
+/// # Example
+///
+/// ```
+/// // For example, to move the red channel 30 pixels to the left:
+/// use photon_rs::effects::{offset_with_direction, OffsetDirection};
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// offset_with_direction(&mut img, 0_usize, -30_i32, OffsetDirection::Horizontal);
+/// ```
+#[wasm_bindgen]
+pub fn offset_with_direction(
+    photon_image: &mut PhotonImage,
+    channel_index: usize,
+    offset: i32,
+    direction: OffsetDirection,
+) {
+    if channel_index > 2 {
+        panic!("Invalid channel index passed. Channel1 must be equal to 0, 1, or 2.");
+    }
+    let (dx, dy) = match direction {
+        OffsetDirection::Horizontal => (offset, 0),
+        OffsetDirection::Vertical => (0, offset),
+        OffsetDirection::Diagonal => (offset, offset),
+    };
+    let (width, height) = (photon_image.width as i32, photon_image.height as i32);
+    let original = photon_image.raw_pixels.clone();
+
+    for y in 0..height {
+        let src_y = num::clamp(y - dy, 0, height - 1);
+        for x in 0..width {
+            let src_x = num::clamp(x - dx, 0, width - 1);
+            let src = (src_y * width + src_x) as usize * 4;
+            let dst = (y * width + x) as usize * 4;
+            photon_image.raw_pixels[dst + channel_index] = original[src + channel_index];
+        }
+    }
+}
+
 /// Adds an offset to the red channel by a certain number of pixels.
 ///
 /// # Arguments
@@ -384,55 +452,6 @@ pub fn colorize(mut photon_image: &mut PhotonImage) {
     photon_image.raw_pixels = raw_pixels;
 }
 
-// #[wasm_bindgen]
-// pub fn inc_luminosity(mut photon_image: PhotonImage) -> PhotonImage {
-//     let mut img = helpers::dyn_image_from_raw(&photon_image);
-//     let (width, height) = img.dimensions();
-//     let mut min_intensity = 255;
-//     let mut max_intensity = 0;
-
-//     // find the max and min intensities in the image
-//     for x in 0..width {
-//         for y in 0..height {
-//             let px = img.get_pixel(x, y);
-//             let intensity = (px.data[0] as u32 + px.data[1] as u32 + px.data[2] as u32) / 3;
-//             if intensity > 0{
-//                 min_intensity = cmp::min(min_intensity, intensity);
-//                 max_intensity = cmp::max(max_intensity, intensity);
-//             }
-
-//         }
-//     }
-
-//     for x in 0..width {
-//         for y in 0..height {
-//             let mut px = img.get_pixel(x, y);
-//             // let px_as_rgb = Rgb{r: px.data[0], g: px.data[1], b: px.data[2]};
-
-//             let mut r = px.data[0] as f32;
-//             let mut g = px.data[1] as f32;
-//             let mut b = px.data[2] as f32;
-
-//             let lum = (r + g + b) / 3.0;
-
-//             let new_lum = 255.0 * (lum - min_intensity as f32) / (max_intensity / min_intensity) as f32;
-
-//             r = r * new_lum / lum;
-//             g = g * new_lum / lum;
-//             b = b * new_lum / lum;
-
-//             px.data[0] = r as u8;
-//             px.data[1] = g as u8;
-//             px.data[2] = b as u8;
-
-//             img.put_pixel(x, y, px);
-//         }
-//     }
-//     let mut raw_pixels = img.raw_pixels();
-//     photon_image.raw_pixels = raw_pixels;
-//     return photon_image;
-// }
-
 /// Applies a solarizing effect to an image.
 ///
 /// # Arguments
@@ -497,7 +516,7 @@ pub fn solarize_retimg(photon_image: &PhotonImage) -> PhotonImage {
 ///
 /// # Arguments
 /// * `img` - A PhotonImage that contains a view into the image.
-/// * `brightness` - A u8 to add to the brightness.
+/// * `brightness` - A u8 to add to the brightness. To darken an image, see `adjust_brightness`.
 /// # Example
 ///
 /// ```
@@ -509,30 +528,28 @@ pub fn solarize_retimg(photon_image: &PhotonImage) -> PhotonImage {
 /// ```
 #[wasm_bindgen]
 pub fn inc_brightness(photon_image: &mut PhotonImage, brightness: u8) {
-    let end = photon_image.get_raw_pixels().len() - 4;
-
-    for i in (0..end).step_by(4) {
-        let r_val = photon_image.raw_pixels[i];
-        let g_val = photon_image.raw_pixels[i + 1];
-        let b_val = photon_image.raw_pixels[i + 2];
-
-        if r_val <= 255 - brightness {
-            photon_image.raw_pixels[i] += brightness;
-        } else {
-            photon_image.raw_pixels[i] = 255;
-        }
-        if g_val <= 255 - brightness {
-            photon_image.raw_pixels[i + 1] += brightness;
-        } else {
-            photon_image.raw_pixels[1] = 255
-        }
+    adjust_brightness(photon_image, brightness as i16);
+}
 
-        if b_val <= 255 - brightness {
-            photon_image.raw_pixels[i + 2] += brightness;
-        } else {
-            photon_image.raw_pixels[i + 2] = 255
-        }
-    }
+/// Brighten or darken an image by adding a signed amount to its red, green and blue
+/// channels. Values saturate at 0 and 255, rather than wrapping around.
+///
+/// # Arguments
+/// * `img` - A PhotonImage that contains a view into the image.
+/// * `amount` - The amount to add to each channel, between -255 (darker) and 255 (brighter).
+/// # Example
+///
+/// ```
+/// // For example, to darken an image:
+/// use photon_rs::effects::adjust_brightness;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// adjust_brightness(&mut img, -20_i16);
+/// ```
+#[wasm_bindgen]
+pub fn adjust_brightness(photon_image: &mut PhotonImage, amount: i16) {
+    simd::add_rgb(&mut photon_image.raw_pixels, [amount; 3]);
 }
 
 /// Adjust the contrast of an image by a factor.
@@ -598,157 +615,2312 @@ pub fn adjust_contrast(mut photon_image: &mut PhotonImage, contrast: f32) {
 ///
 #[wasm_bindgen]
 pub fn tint(
-    mut photon_image: &mut PhotonImage,
+    photon_image: &mut PhotonImage,
     r_offset: u32,
     g_offset: u32,
     b_offset: u32,
 ) {
-    let mut img = helpers::dyn_image_from_raw(&photon_image);
+    let clamp = |offset: u32| offset.min(255) as i32;
+    tint_signed(
+        photon_image,
+        clamp(r_offset),
+        clamp(g_offset),
+        clamp(b_offset),
+    );
+}
 
-    for (x, y) in ImageIterator::with_dimension(&img.dimensions()) {
-        let mut px = img.get_pixel(x, y);
-        let (r_val, g_val, b_val) =
-            (px.data[0] as u32, px.data[1] as u32, px.data[2] as u32);
+/// Tint an image by adding a signed offset to each of its RGB channels.
+///
+/// Negative offsets remove colour, eg: to correct a colour cast. Values saturate at 0 and
+/// 255, rather than wrapping around.
+///
+/// # Arguments
+/// * `img` - A PhotonImage that contains a view into the image.
+/// * `r_offset` - The amount to add to the R channel, between -255 and 255.
+/// * `g_offset` - The amount to add to the G channel, between -255 and 255.
+/// * `b_offset` - The amount to add to the B channel, between -255 and 255.
+/// # Example
+///
+/// ```
+/// // For example, to remove a blue cast from an image:
+/// use photon_rs::effects::tint_signed;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// tint_signed(&mut img, 5_i32, 0_i32, -20_i32);
+/// ```
+#[wasm_bindgen]
+pub fn tint_signed(
+    photon_image: &mut PhotonImage,
+    r_offset: i32,
+    g_offset: i32,
+    b_offset: i32,
+) {
+    let clamp = |offset: i32| num::clamp(offset, -255, 255) as i16;
+    simd::add_rgb(
+        &mut photon_image.raw_pixels,
+        [clamp(r_offset), clamp(g_offset), clamp(b_offset)],
+    );
+}
 
-        px.data[0] = if r_val as u32 + r_offset < 255 {
-            r_val as u8 + r_offset as u8
-        } else {
-            255
-        };
-        px.data[1] = if g_val as u32 + g_offset < 255 {
-            g_val as u8 + g_offset as u8
-        } else {
-            255
+/// Horizontal strips. Divide an image into a series of equal-height strips, for an artistic effect.
+#[wasm_bindgen]
+pub fn horizontal_strips(photon_image: &mut PhotonImage, num_strips: u8) {
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        let (width, height) = photon_image.dimensions();
+
+        let total_strips = (num_strips * 2) - 1;
+        let height_strip = height / total_strips as u32;
+        let background_color = Rgb {
+            r: 255,
+            g: 255,
+            b: 255,
         };
-        px.data[2] = if b_val as u32 + b_offset < 255 {
-            b_val as u8 + b_offset as u8
-        } else {
-            255
+        let mut y_pos: u32 = 0;
+        for i in 1..num_strips {
+            draw_filled_rect_mut(
+                photon_image,
+                Rect::at(0, (y_pos + height_strip) as i32).of_size(width, height_strip),
+                Rgba([
+                    background_color.r,
+                    background_color.g,
+                    background_color.b,
+                    255u8,
+                ]),
+            );
+            y_pos = i as u32 * (height_strip * 2);
+        }
+    })
+}
+
+/// Vertical strips. Divide an image into a series of equal-width strips, for an artistic effect.
+#[wasm_bindgen]
+pub fn vertical_strips(photon_image: &mut PhotonImage, num_strips: u8) {
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        let (width, height) = photon_image.dimensions();
+
+        let total_strips = (num_strips * 2) - 1;
+        let width_strip = width / total_strips as u32;
+        let background_color = Rgb {
+            r: 255,
+            g: 255,
+            b: 255,
         };
+        let mut x_pos: u32 = 0;
+        for i in 1..num_strips {
+            draw_filled_rect_mut(
+                photon_image,
+                Rect::at((x_pos + width_strip) as i32, 0).of_size(width_strip, height),
+                Rgba([
+                    background_color.r,
+                    background_color.g,
+                    background_color.b,
+                    255u8,
+                ]),
+            );
+            x_pos = i as u32 * (width_strip * 2);
+        }
+    })
+}
 
-        img.put_pixel(x, y, px);
-    }
-    let raw_pixels = img.raw_pixels();
-    photon_image.raw_pixels = raw_pixels;
+/// Add a solid border around an image, expanding the canvas by `width` pixels on every side.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `width` - The width of the border, in pixels.
+/// * `colour` - The colour of the border.
+/// # Example
+///
+/// ```
+/// // For example, to frame an image with a 20px white border:
+/// use photon_rs::effects::add_border;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("img.jpg");
+/// add_border(&mut img, 20_u32, Rgb::new(255, 255, 255));
+/// ```
+#[wasm_bindgen]
+pub fn add_border(photon_image: &mut PhotonImage, width: u32, colour: Rgb) {
+    expand_canvas(photon_image, width, [colour.r, colour.g, colour.b, 255]);
 }
 
-/// Horizontal strips. Divide an image into a series of equal-height strips, for an artistic effect.
+/// Add a double-line border around an image, expanding the canvas by `width` pixels on
+/// every side. The border is split into thirds: an outer line, a gap, and an inner line.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `width` - The total width of the border, in pixels.
+/// * `line_colour` - The colour of the two lines.
+/// * `gap_colour` - The colour of the gap between the lines.
+/// # Example
+///
+/// ```
+/// // For example, to add a black double line on a white mat:
+/// use photon_rs::effects::add_double_border;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("img.jpg");
+/// add_double_border(&mut img, 30_u32, Rgb::new(0, 0, 0), Rgb::new(255, 255, 255));
+/// ```
 #[wasm_bindgen]
-pub fn horizontal_strips(mut photon_image: &mut PhotonImage, num_strips: u8) {
-    let mut img = helpers::dyn_image_from_raw(&photon_image);
-    let (width, height) = img.dimensions();
+pub fn add_double_border(
+    photon_image: &mut PhotonImage,
+    width: u32,
+    line_colour: Rgb,
+    gap_colour: Rgb,
+) {
+    let line = [line_colour.r, line_colour.g, line_colour.b, 255];
+    let gap = [gap_colour.r, gap_colour.g, gap_colour.b, 255];
+    let inner = width / 3;
+    let middle = (width - inner) / 2;
+    let outer = width - inner - middle;
+
+    expand_canvas(photon_image, inner, line);
+    expand_canvas(photon_image, middle, gap);
+    expand_canvas(photon_image, outer, line);
+}
 
-    let total_strips = (num_strips * 2) - 1;
-    let height_strip = height / total_strips as u32;
-    let background_color = Rgb {
-        r: 255,
-        g: 255,
-        b: 255,
-    };
-    let mut y_pos: u32 = 0;
-    for i in 1..num_strips {
-        draw_filled_rect_mut(
-            &mut img,
-            Rect::at(0, (y_pos + height_strip) as i32).of_size(width, height_strip),
-            Rgba([
-                background_color.r,
-                background_color.g,
-                background_color.b,
-                255u8,
-            ]),
-        );
-        y_pos = i as u32 * (height_strip * 2);
+/// Add a border around an image which casts a soft shadow inwards over the image's edges,
+/// making the image look recessed into the frame. The canvas is expanded by `width` pixels
+/// on every side.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `width` - The width of the border, in pixels.
+/// * `colour` - The colour of the border.
+/// * `shadow_size` - How far the shadow extends over the image, in pixels.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::add_inset_border;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("img.jpg");
+/// add_inset_border(&mut img, 25_u32, Rgb::new(240, 236, 228), 12_u32);
+/// ```
+#[wasm_bindgen]
+pub fn add_inset_border(
+    photon_image: &mut PhotonImage,
+    width: u32,
+    colour: Rgb,
+    shadow_size: u32,
+) {
+    let (img_width, img_height) = (photon_image.width, photon_image.height);
+    let size = shadow_size as f32;
+
+    if shadow_size > 0 {
+        for y in 0..img_height {
+            for x in 0..img_width {
+                // Distance from the pixel to the nearest edge of the image.
+                let edge =
+                    x.min(y).min(img_width - 1 - x).min(img_height - 1 - y) as f32;
+                if edge >= size {
+                    continue;
+                }
+                // Darken by up to 60% at the edge, easing out towards the shadow's extent.
+                let t = 1.0 - (edge + 0.5) / size;
+                let darken = 1.0 - 0.6 * t * t;
+                let idx = ((y * img_width + x) * 4) as usize;
+                for c in 0..3 {
+                    let value = photon_image.raw_pixels[idx + c] as f32 * darken;
+                    photon_image.raw_pixels[idx + c] = value.round() as u8;
+                }
+            }
+        }
     }
 
-    let raw_pixels = img.raw_pixels();
-    photon_image.raw_pixels = raw_pixels;
+    expand_canvas(photon_image, width, [colour.r, colour.g, colour.b, 255]);
 }
 
-/// Vertical strips. Divide an image into a series of equal-width strips, for an artistic effect.
+/// Add a border around an image, and round the image's corners where it meets the border.
+/// The canvas is expanded by `width` pixels on every side.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `width` - The width of the border, in pixels.
+/// * `colour` - The colour of the border.
+/// * `radius` - The radius of the image's corners, in pixels.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::add_rounded_border;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("img.jpg");
+/// add_rounded_border(&mut img, 20_u32, Rgb::new(255, 255, 255), 16_u32);
+/// ```
 #[wasm_bindgen]
-pub fn vertical_strips(mut photon_image: &mut PhotonImage, num_strips: u8) {
-    let mut img = helpers::dyn_image_from_raw(&photon_image);
-    let (width, height) = img.dimensions();
-
-    let total_strips = (num_strips * 2) - 1;
-    let width_strip = width / total_strips as u32;
-    let background_color = Rgb {
-        r: 255,
-        g: 255,
-        b: 255,
+pub fn add_rounded_border(
+    photon_image: &mut PhotonImage,
+    width: u32,
+    colour: Rgb,
+    radius: u32,
+) {
+    let colour = [colour.r, colour.g, colour.b, 255];
+    let (img_width, img_height) = (photon_image.width, photon_image.height);
+    let radius = radius.min(img_width.min(img_height) / 2) as f32;
+
+    // Blend the corners of the image into the border colour, anti-aliasing the curve.
+    let corner = |p: f32, len: f32| {
+        if p < radius {
+            Some(radius)
+        } else if p > len - radius {
+            Some(len - radius)
+        } else {
+            None
+        }
     };
-    let mut x_pos: u32 = 0;
-    for i in 1..num_strips {
-        draw_filled_rect_mut(
-            &mut img,
-            Rect::at((x_pos + width_strip) as i32, 0).of_size(width_strip, height),
-            Rgba([
-                background_color.r,
-                background_color.g,
-                background_color.b,
-                255u8,
-            ]),
-        );
-        x_pos = i as u32 * (width_strip * 2);
+    for y in 0..img_height {
+        for x in 0..img_width {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let (cx, cy) =
+                match (corner(px, img_width as f32), corner(py, img_height as f32)) {
+                    (Some(cx), Some(cy)) => (cx, cy),
+                    _ => continue,
+                };
+            let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+            let coverage = num::clamp(radius - distance + 0.5, 0.0, 1.0);
+            let idx = ((y * img_width + x) * 4) as usize;
+            for (c, &border) in colour.iter().enumerate() {
+                let value = photon_image.raw_pixels[idx + c] as f32 * coverage
+                    + border as f32 * (1.0 - coverage);
+                photon_image.raw_pixels[idx + c] = value.round() as u8;
+            }
+        }
     }
 
-    let raw_pixels = img.raw_pixels();
-    photon_image.raw_pixels = raw_pixels;
+    expand_canvas(photon_image, width, colour);
 }
 
-// pub fn create_gradient_map(color_a : Rgb, color_b: Rgb) -> Vec<Rgb> {
-//     println!("hi");
-//     println!("{}", color_a.get_red());
-//     let mut gradient_map = vec![];
-
-//     let max_val = 255;
-//     let mut r_val = 0;
+/// Grow the canvas by `border` pixels on every side, filling the new area with `colour`.
+fn expand_canvas(photon_image: &mut PhotonImage, border: u32, colour: [u8; 4]) {
+    if border == 0 {
+        return;
+    }
+    let (width, height) = (photon_image.width, photon_image.height);
+    let new_width = width + 2 * border;
+    let new_height = height + 2 * border;
+
+    let mut raw_pixels = colour.repeat((new_width * new_height) as usize);
+    for y in 0..height {
+        let src = (y * width * 4) as usize;
+        let dst = (((y + border) * new_width + border) * 4) as usize;
+        let row = (width * 4) as usize;
+        raw_pixels[dst..dst + row]
+            .copy_from_slice(&photon_image.raw_pixels[src..src + row]);
+    }
 
-//     let end: i32 = 256 * 4;
+    photon_image.raw_pixels = raw_pixels;
+    photon_image.width = new_width;
+    photon_image.height = new_height;
+}
 
-//     for i in (0..end).step_by(4){
-//         let i: u8 = i as u8;
-//         let intensity_b = max_val - i;
+/// Equalize the histogram of an image's lightness, spreading its tones evenly from black
+/// to white. This fixes flat, low-contrast images (such as faded scans) without shifting
+/// their colours, as only the L channel of the Lab colour space is changed.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::equalize;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// equalize(&mut img);
+/// ```
+#[wasm_bindgen]
+pub fn equalize(photon_image: &mut PhotonImage) {
+    let mut pixels = to_lab(photon_image);
+    let histogram = lightness_histogram(pixels.iter());
+    let lut = equalization_lut(&histogram);
+    for px in pixels.iter_mut() {
+        px.l = lut[lightness_bin(px.l)];
+    }
+    from_lab(photon_image, &pixels);
+}
 
-//         let res1 = (i * color_a.get_red() + intensity_b * color_b.get_red());
-//         let res2 = res1 / max_val;
-//         println!("res 1 {}", res1);
-//         println!("res 2 {}", res2);
+/// Contrast Limited Adaptive Histogram Equalization (CLAHE) of an image's lightness.
+///
+/// Unlike `equalize`, the image is equalized in tiles, so local contrast is enhanced in
+/// both dark and bright areas of the image. Limiting the contrast of each tile stops noise
+/// in flat areas from being amplified, and tiles are blended smoothly into each other.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `tile_size` - The width and height of each tile, in pixels. Eg: 64.
+/// * `clip_limit` - The maximum contrast increase, as a multiple of the tile's average
+/// histogram count. Values between 2 and 4 work well. A value of 1 or less leaves the
+/// image unchanged, and large values approach plain histogram equalization of each tile.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::clahe;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// clahe(&mut img, 64_u32, 3.0_f32);
+/// ```
+#[wasm_bindgen]
+pub fn clahe(photon_image: &mut PhotonImage, tile_size: u32, clip_limit: f32) {
+    let (width, height) = (photon_image.width, photon_image.height);
+    let tile_size = tile_size.max(1);
+    let tiles_x = width.div_ceil(tile_size).max(1);
+    let tiles_y = height.div_ceil(tile_size).max(1);
+    let mut pixels = to_lab(photon_image);
+
+    // Build a clipped equalization lookup table for each tile.
+    let mut luts = Vec::with_capacity((tiles_x * tiles_y) as usize);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let (x0, y0) = (tx * tile_size, ty * tile_size);
+            let (x1, y1) = ((x0 + tile_size).min(width), (y0 + tile_size).min(height));
+            let tile = (y0..y1).flat_map(|y| {
+                let row = (y * width) as usize;
+                pixels[row + x0 as usize..row + x1 as usize].iter()
+            });
+            let mut histogram = lightness_histogram(tile);
+            clip_histogram(&mut histogram, clip_limit);
+            luts.push(equalization_lut(&histogram));
+        }
+    }
 
-//         r_val = (i * color_a.get_red() + intensity_b * color_b.get_red()) / max_val;
-//         println!("r_val {}", r_val);
-//         gradient_map.push(Rgb {
-//             r: (256 - (i / 4) * color_a.get_red() + (i / 4) * color_b.r) / 256 ,
-//             g: (i * color_a.get_green() + intensity_b * color_b.get_green()) / max_val ,
-//             b: (i * color_a.get_blue() + intensity_b * color_b.get_blue()) / max_val
-//         });
+    // Interpolate between the lookup tables of the four nearest tile centres.
+    let tile_pos = |p: u32, tiles: u32| {
+        let t = (p as f32 + 0.5) / tile_size as f32 - 0.5;
+        let t = num::clamp(t, 0.0, (tiles - 1) as f32);
+        let t0 = t.floor() as u32;
+        (t0, (t0 + 1).min(tiles - 1), t - t0 as f32)
+    };
+    for y in 0..height {
+        let (ty0, ty1, fy) = tile_pos(y, tiles_y);
+        for x in 0..width {
+            let (tx0, tx1, fx) = tile_pos(x, tiles_x);
+            let px = &mut pixels[(y * width + x) as usize];
+            let bin = lightness_bin(px.l);
+            let lut = |tx: u32, ty: u32| luts[(ty * tiles_x + tx) as usize][bin];
+            let top = lut(tx0, ty0) * (1.0 - fx) + lut(tx1, ty0) * fx;
+            let bottom = lut(tx0, ty1) * (1.0 - fx) + lut(tx1, ty1) * fx;
+            px.l = top * (1.0 - fy) + bottom * fy;
+        }
+    }
+    from_lab(photon_image, &pixels);
+}
 
-//     }
-//     println!("{:?}", gradient_map);
+/// Convert every pixel of an image to the Lab colour space.
+fn to_lab(photon_image: &PhotonImage) -> Vec<Laba<D65>> {
+    photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| {
+            Srgba::from_raw(&[px[0], px[1], px[2], px[3]])
+                .into_format()
+                .into_linear()
+                .into()
+        })
+        .collect()
+}
 
-//     return gradient_map;
-// }
+/// Replace the pixels of an image with Lab colours, clamping them to the sRGB gamut.
+fn from_lab(photon_image: &mut PhotonImage, pixels: &[Laba<D65>]) {
+    for (px, lab) in photon_image.raw_pixels.chunks_exact_mut(4).zip(pixels) {
+        let rgba: [u8; 4] = Srgba::from_linear((*lab).into())
+            .clamp()
+            .into_format()
+            .into_raw();
+        px.copy_from_slice(&rgba);
+    }
+}
 
-// pub fn duotone(mut img: DynamicImage, color_a : Rgb, color_b : Rgb) -> DynamicImage {
-//     let (width, height) = img.dimensions();
-//     let gradient_map = create_gradient_map(color_a, color_b);
-//     println!("entering for loop");
+/// The histogram bin of a lightness value, from 0 to 255.
+fn lightness_bin(l: f32) -> usize {
+    num::clamp((l / 100.0 * 255.0).round(), 0.0, 255.0) as usize
+}
 
-//     for x in 0..width {
-//         for y in 0..height {
+fn lightness_histogram<'a, I>(pixels: I) -> [f32; 256]
+where
+    I: Iterator<Item = &'a Laba<D65>>,
+{
+    let mut histogram = [0.0; 256];
+    for px in pixels {
+        histogram[lightness_bin(px.l)] += 1.0;
+    }
+    histogram
+}
 
-//             let mut px = img.get_pixel(x, y);
+/// Limit each bin of a histogram to `clip_limit` times the average bin count,
+/// redistributing the excess evenly across all of the bins.
+fn clip_histogram(histogram: &mut [f32; 256], clip_limit: f32) {
+    let total: f32 = histogram.iter().sum();
+    let limit = (clip_limit * total / 256.0).max(total / 256.0);
+    let mut excess = 0.0;
+    for count in histogram.iter_mut() {
+        if *count > limit {
+            excess += *count - limit;
+            *count = limit;
+        }
+    }
+    for count in histogram.iter_mut() {
+        *count += excess / 256.0;
+    }
+}
 
-//             let r = px.data[0];
-//             let g = px.data[1];
-//             let b = px.data[2];
+/// Map each lightness bin to a new lightness, so that the histogram becomes flat.
+fn equalization_lut(histogram: &[f32; 256]) -> [f32; 256] {
+    let total: f32 = histogram.iter().sum();
+    let first = histogram
+        .iter()
+        .copied()
+        .find(|&count| count > 0.0)
+        .unwrap_or(0.0);
+    let mut lut = [0.0; 256];
+    let mut cumulative = 0.0;
+    for (bin, count) in histogram.iter().enumerate() {
+        cumulative += count;
+        lut[bin] = if total > first {
+            num::clamp((cumulative - first) / (total - first), 0.0, 1.0) * 100.0
+        } else {
+            bin as f32 / 255.0 * 100.0
+        };
+    }
+    lut
+}
+
+/// Automatically correct the exposure of an image, brightening dark images and darkening
+/// overexposed ones.
+///
+/// The mean luma of the image is measured, and a gamma curve is applied to every channel
+/// so that the mean moves to a mid-tone. Unlike adding a constant brightness, this keeps
+/// the black and white points in place, so shadows and highlights aren't clipped.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// // For example, to fix an underexposed phone photo:
+/// use photon_rs::effects::auto_exposure;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// auto_exposure(&mut img);
+/// ```
+#[wasm_bindgen]
+pub fn auto_exposure(photon_image: &mut PhotonImage) {
+    // The mean luma which well-exposed images are corrected towards, as a fraction.
+    const TARGET_MEAN: f64 = 0.46;
+
+    let pixels = photon_image.raw_pixels.chunks_exact(4);
+    let count = pixels.len();
+    if count == 0 {
+        return;
+    }
+    let total: u64 = pixels.map(|px| helpers::luma(px) as u64).sum();
+    let mean = num::clamp(total as f64 / count as f64 / 255.0, 0.01, 0.99);
+
+    // Solve mean ^ gamma = target, limiting the correction to avoid extreme results on
+    // images which are almost entirely black or white.
+    let gamma = num::clamp(TARGET_MEAN.ln() / mean.ln(), 0.4, 2.5);
+    let mut lut = [0u8; 256];
+    for (i, value) in lut.iter_mut().enumerate() {
+        *value = (255.0 * (i as f64 / 255.0).powf(gamma)).round() as u8;
+    }
+
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        for c in 0..3 {
+            px[c] = lut[px[c] as usize];
+        }
+    }
+}
+
+/// Automatically stretch the contrast of an image, so that its tones span the full range
+/// from black to white.
+///
+/// The darkest and brightest `clip_percent` percent of pixels (by luma) are clipped to
+/// black and white, and the tones in between are stretched linearly. The same stretch is
+/// applied to every channel, so the colours of the image aren't changed. Ignoring a small
+/// percentage of outliers stops a few stray black or white pixels from preventing any
+/// correction.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `clip_percent` - The percentage of pixels to clip at each end of the histogram,
+/// eg: 0.5. Must be between 0 and 50.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::auto_contrast;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// auto_contrast(&mut img, 0.5_f32);
+/// ```
+#[wasm_bindgen]
+pub fn auto_contrast(photon_image: &mut PhotonImage, clip_percent: f32) {
+    let mut histogram = [0u32; 256];
+    for px in photon_image.raw_pixels.chunks_exact(4) {
+        histogram[helpers::luma(px) as usize] += 1;
+    }
+    let (low, high) = clipped_range(&histogram, clip_percent);
+    let lut = stretch_lut(low, high);
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        for c in 0..3 {
+            px[c] = lut[px[c] as usize];
+        }
+    }
+}
+
+/// Automatically stretch each of the red, green and blue channels of an image separately,
+/// so that each spans the full range from 0 to 255 (also known as "auto levels").
+///
+/// As well as increasing contrast, this neutralises colour casts, since a channel which
+/// never reaches 0 or 255 is stretched more than the others.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `clip_percent` - The percentage of pixels to clip at each end of each channel's
+/// histogram, eg: 0.5. Must be between 0 and 50.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::auto_contrast_per_channel;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// auto_contrast_per_channel(&mut img, 0.5_f32);
+/// ```
+#[wasm_bindgen]
+pub fn auto_contrast_per_channel(photon_image: &mut PhotonImage, clip_percent: f32) {
+    let mut histograms = [[0u32; 256]; 3];
+    for px in photon_image.raw_pixels.chunks_exact(4) {
+        for c in 0..3 {
+            histograms[c][px[c] as usize] += 1;
+        }
+    }
+    let luts: Vec<[u8; 256]> = histograms
+        .iter()
+        .map(|histogram| {
+            let (low, high) = clipped_range(histogram, clip_percent);
+            stretch_lut(low, high)
+        })
+        .collect();
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        for c in 0..3 {
+            px[c] = luts[c][px[c] as usize];
+        }
+    }
+}
+
+/// Find the values below and above which `clip_percent` percent of a histogram lies.
+fn clipped_range(histogram: &[u32; 256], clip_percent: f32) -> (u8, u8) {
+    if !(0.0..=50.0).contains(&clip_percent) {
+        panic!("Clip percentage must be between 0 and 50.");
+    }
+    let total: u64 = histogram.iter().map(|&count| count as u64).sum();
+    let clip = (total as f64 * clip_percent as f64 / 100.0).floor() as u64;
+
+    let mut seen = 0;
+    let mut low = 0;
+    for (value, &count) in histogram.iter().enumerate() {
+        seen += count as u64;
+        if seen > clip {
+            low = value;
+            break;
+        }
+    }
+    seen = 0;
+    let mut high = 255;
+    for (value, &count) in histogram.iter().enumerate().rev() {
+        seen += count as u64;
+        if seen > clip {
+            high = value;
+            break;
+        }
+    }
+    (low as u8, high as u8)
+}
+
+/// A lookup table mapping `low` to 0 and `high` to 255, clamping values outside of them.
+fn stretch_lut(low: u8, high: u8) -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    for (value, out) in lut.iter_mut().enumerate() {
+        *out = if high <= low {
+            value as u8
+        } else {
+            let stretched = (value as f32 - low as f32) * 255.0 / (high - low) as f32;
+            num::clamp(stretched.round(), 0.0, 255.0) as u8
+        };
+    }
+    lut
+}
+
+/// Automatically remove a colour cast from an image, eg: the orange tint of indoor lighting.
+///
+/// This uses the "grey world" assumption: the average colour of a scene is taken to be a
+/// neutral grey, so the red, green and blue channels are each scaled until their means are
+/// equal. The scaling is limited, so images which really are dominated by one colour, such
+/// as a sunset, are only partly corrected.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::auto_white_balance;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// auto_white_balance(&mut img);
+/// ```
+#[wasm_bindgen]
+pub fn auto_white_balance(photon_image: &mut PhotonImage) {
+    let mut totals = [0u64; 3];
+    for px in photon_image.raw_pixels.chunks_exact(4) {
+        for c in 0..3 {
+            totals[c] += px[c] as u64;
+        }
+    }
+    let grey = totals.iter().sum::<u64>() as f32 / 3.0;
+    if grey == 0.0 {
+        return;
+    }
+
+    let mut luts = [[0u8; 256]; 3];
+    for (lut, &total) in luts.iter_mut().zip(totals.iter()) {
+        let gain = num::clamp(grey / total.max(1) as f32, 0.5, 2.0);
+        for (value, out) in lut.iter_mut().enumerate() {
+            *out = num::clamp((value as f32 * gain).round(), 0.0, 255.0) as u8;
+        }
+    }
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        for c in 0..3 {
+            px[c] = luts[c][px[c] as usize];
+        }
+    }
+}
+
+/// Increase (or decrease) the saturation of an image, mostly affecting its muted colours.
+///
+/// Unlike adjusting the saturation directly, colours which are already vivid are barely
+/// changed, so they aren't clipped, and skin tones stay natural.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `amount` - The strength of the adjustment, between -1 and 1. Negative values mute
+/// the colours instead.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::vibrance;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// vibrance(&mut img, 0.3_f32);
+/// ```
+#[wasm_bindgen]
+pub fn vibrance(photon_image: &mut PhotonImage, amount: f32) {
+    let amount = num::clamp(amount, -1.0, 1.0);
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        let max = px[0].max(px[1]).max(px[2]);
+        let min = px[0].min(px[1]).min(px[2]);
+        let saturation = (max - min) as f32 / 255.0;
+        let scale = 1.0 + amount * (1.0 - saturation);
+        let luma = helpers::luma(px) as f32;
+        for c in 0..3 {
+            let value = luma + (px[c] as f32 - luma) * scale;
+            px[c] = num::clamp(value.round(), 0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Automatically improve an image, with a single call: the "make it look better" button.
+///
+/// Removes any colour cast, stretches the contrast, slightly boosts muted colours, then
+/// gently sharpens the image. Each step uses defaults which suit most photos; to tune
+/// them, call `auto_white_balance`, `auto_contrast` and `vibrance` directly.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::auto_enhance;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// auto_enhance(&mut img);
+/// ```
+#[wasm_bindgen]
+pub fn auto_enhance(photon_image: &mut PhotonImage) {
+    auto_white_balance(photon_image);
+    auto_contrast(photon_image, 0.5);
+    vibrance(photon_image, 0.15);
+    // Leave the alpha channel alone, which the convolution would otherwise make opaque.
+    with_alpha_mode(photon_image, AlphaMode::Preserve, |img| {
+        sharpen_amount(img, 0.075)
+    });
+}
+
+/// Brighten the dark corners caused by the light falloff of a lens (vignetting).
+///
+/// Each pixel's light is multiplied by `1 + strength * r²`, where `r` is its distance from
+/// the centre of the image, as a fraction of half of the image's diagonal. The gain is
+/// applied to linear light, as the falloff is, so the colours of the corners are kept.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `strength` - How much to brighten the corners: 0.5 brightens the very corners by
+/// half. Negative values darken them instead, adding a vignette.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::devignette;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// devignette(&mut img, 0.4_f32);
+/// ```
+#[wasm_bindgen]
+pub fn devignette(photon_image: &mut PhotonImage, strength: f32) {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    let to_linear: Vec<f32> = (0..256).map(|v| (v as f32 / 255.0).powf(2.2)).collect();
+    for (i, px) in photon_image.raw_pixels.chunks_exact_mut(4).enumerate() {
+        let r = vignette_radius(i % width, i / width, width, height);
+        let gain = (1.0 + strength * r * r).max(0.0);
+        for c in 0..3 {
+            let value = (to_linear[px[c] as usize] * gain).min(1.0).powf(1.0 / 2.2);
+            px[c] = (value * 255.0).round() as u8;
+        }
+    }
+}
+
+/// Brighten the dark corners caused by the light falloff of a lens, estimating how much
+/// they need brightening by comparing their brightness with the centre of the image.
+///
+/// The estimate assumes that the scene itself is evenly lit, eg: a sky, a wall or a
+/// scanned document; for other photos, choose the strength with `devignette` instead.
+/// Returns the strength which was estimated and applied.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::devignette_auto;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// let strength = devignette_auto(&mut img);
+/// ```
+#[wasm_bindgen]
+pub fn devignette_auto(photon_image: &mut PhotonImage) -> f32 {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    let patch = (width.min(height) / 8).max(1);
+
+    // The mean linear luma of a square patch, and its distance from the centre.
+    let brightness = |x0: usize, y0: usize| {
+        let mut total = 0.0;
+        for y in y0..y0 + patch {
+            for x in x0..x0 + patch {
+                let px = &photon_image.raw_pixels[(y * width + x) * 4..];
+                total += (helpers::luma(px) as f32 / 255.0).powf(2.2);
+            }
+        }
+        total / (patch * patch) as f32
+    };
+    let centre = brightness((width - patch) / 2, (height - patch) / 2);
+    let (right, bottom) = (width - patch, height - patch);
+    let corners = [(0, 0), (right, 0), (0, bottom), (right, bottom)]
+        .iter()
+        .map(|&(x, y)| brightness(x, y))
+        .sum::<f32>()
+        / 4.0;
+    let r = vignette_radius(patch / 2, patch / 2, width, height);
+
+    let strength = if corners > 0.0 && r > 0.0 {
+        num::clamp((centre / corners - 1.0) / (r * r), 0.0, 2.0)
+    } else {
+        0.0
+    };
+    devignette(photon_image, strength);
+    strength
+}
+
+/// The distance of a pixel from the centre of an image, as a fraction of half of the
+/// image's diagonal.
+fn vignette_radius(x: usize, y: usize, width: usize, height: usize) -> f32 {
+    let dx = x as f32 + 0.5 - width as f32 / 2.0;
+    let dy = y as f32 + 0.5 - height as f32 / 2.0;
+    let half_diagonal = ((width * width + height * height) as f32).sqrt() / 2.0;
+    (dx * dx + dy * dy).sqrt() / half_diagonal
+}
+
+/// Remove a coloured background, such as a green screen, making it transparent, so that
+/// the foreground can be composited over another image.
+///
+/// Pixels are compared with the key colour by their chroma (their colour, regardless of
+/// their brightness), so shadows and creases in the screen are removed too. Pixels within
+/// `tolerance` of the key colour are made fully transparent, and those up to `feather`
+/// further away partially transparent, for soft edges. Near the key colour, its colour is
+/// also removed from the pixels which are kept, so that light reflected from the screen
+/// doesn't leave a coloured fringe (spill suppression).
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `key_color` - The colour of the background, eg: `Rgb::new(0, 177, 64)` for a green
+/// screen.
+/// * `tolerance` - How far from the key colour pixels are removed, between 0 and 1:
+/// 0.1 to 0.2 suits an evenly lit screen.
+/// * `feather` - The width of the soft edge beyond the tolerance, between 0 and 1.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::chroma_key;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("green_screen.jpg");
+/// chroma_key(&mut img, Rgb::new(0, 177, 64), 0.15_f32, 0.1_f32);
+/// ```
+#[wasm_bindgen]
+pub fn chroma_key(
+    photon_image: &mut PhotonImage,
+    key_color: Rgb,
+    tolerance: f32,
+    feather: f32,
+) {
+    let tolerance = tolerance.max(0.0);
+    let feather = feather.max(0.0);
+    let (_, key_cb, key_cr) =
+        helpers::to_ycbcr(&[key_color.r, key_color.g, key_color.b]);
+    let key_chroma = (key_cb * key_cb + key_cr * key_cr).sqrt();
+
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        let (y, mut cb, mut cr) = helpers::to_ycbcr(px);
+        let distance = ((cb - key_cb).powi(2) + (cr - key_cr).powi(2)).sqrt();
+
+        let opacity = if distance <= tolerance {
+            0.0
+        } else if distance >= tolerance + feather {
+            1.0
+        } else {
+            let t = (distance - tolerance) / feather;
+            t * t * (3.0 - 2.0 * t)
+        };
+        px[3] = (px[3] as f32 * opacity).round() as u8;
+
+        // Remove the part of the chroma in the direction of the key colour, fading out
+        // with the distance from it.
+        if key_chroma > 1e-3 && opacity > 0.0 {
+            let spill = (cb * key_cb + cr * key_cr) / key_chroma;
+            let weight = num::clamp(
+                1.0 - (distance - tolerance) / (tolerance + feather).max(1e-3),
+                0.0,
+                1.0,
+            );
+            if spill > 0.0 {
+                cb -= key_cb / key_chroma * spill * weight;
+                cr -= key_cr / key_chroma * spill * weight;
+                px[..3].copy_from_slice(&helpers::from_ycbcr(y, cb, cr));
+            }
+        }
+    }
+}
+
+// The states of the pixels in `inpaint`: known, in the band being marched inwards, or
+// still to be filled.
+const KNOWN: u8 = 0;
+const BAND: u8 = 1;
+const INSIDE: u8 = 2;
+
+/// Fill the masked regions of an image from their surroundings, eg: to remove blemishes,
+/// scratches or small objects.
+///
+/// This follows Telea's fast marching method: the masked region is filled inwards from its
+/// boundary, each pixel becoming a weighted average of the already-known pixels within
+/// `radius` of it, favouring those which are close, and lie along the direction the
+/// boundary is moving in.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `mask` - A mask of the same size as the image, in which white pixels (with a luma of
+/// at least 128) are filled, and the others are left unchanged.
+/// * `radius` - How far from each pixel to look for known pixels, eg: 5. Larger regions
+/// need larger radii.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::inpaint;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// let mask = open_image("mask.png");
+/// inpaint(&mut img, &mask, 5_u32);
+/// ```
+#[wasm_bindgen]
+pub fn inpaint(photon_image: &mut PhotonImage, mask: &PhotonImage, radius: u32) {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    if mask.width != photon_image.width || mask.height != photon_image.height {
+        panic!("The mask must be the same size as the image.");
+    }
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    let radius = radius.max(1) as i64;
+
+    let mut flags: Vec<u8> = mask
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| {
+            if helpers::luma(px) >= 128 {
+                INSIDE
+            } else {
+                KNOWN
+            }
+        })
+        .collect();
+    let mut dist = vec![f32::INFINITY; width * height];
+    let neighbours = |i: usize| {
+        let (x, y) = (i % width, i / width);
+        let mut out = Vec::with_capacity(4);
+        if x > 0 {
+            out.push(i - 1);
+        }
+        if x + 1 < width {
+            out.push(i + 1);
+        }
+        if y > 0 {
+            out.push(i - width);
+        }
+        if y + 1 < height {
+            out.push(i + width);
+        }
+        out
+    };
+
+    // The band starts as the known pixels bordering the masked region.
+    let mut heap = BinaryHeap::new();
+    for i in 0..width * height {
+        if flags[i] == KNOWN {
+            dist[i] = 0.0;
+            if neighbours(i).iter().any(|&n| flags[n] == INSIDE) {
+                flags[i] = BAND;
+                heap.push(Reverse(MarchingPixel(0.0, i)));
+            }
+        }
+    }
+
+    while let Some(Reverse(MarchingPixel(_, i))) = heap.pop() {
+        if flags[i] == KNOWN {
+            continue;
+        }
+        flags[i] = KNOWN;
+        for n in neighbours(i) {
+            if flags[n] != INSIDE {
+                continue;
+            }
+            dist[n] = solve_eikonal(n, width, height, &flags, &dist);
+            inpaint_pixel(photon_image, n, radius, &flags, &dist);
+            flags[n] = BAND;
+            heap.push(Reverse(MarchingPixel(dist[n], n)));
+        }
+    }
+}
+
+/// A pixel in the narrow band of `inpaint`, ordered by its distance from the boundary.
+#[derive(PartialEq)]
+struct MarchingPixel(f32, usize);
+
+impl Eq for MarchingPixel {}
+
+impl PartialOrd for MarchingPixel {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MarchingPixel {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// The distance of pixel `i` from the boundary of the masked region, from the distances of
+/// its known neighbours.
+fn solve_eikonal(
+    i: usize,
+    width: usize,
+    height: usize,
+    flags: &[u8],
+    dist: &[f32],
+) -> f32 {
+    let (x, y) = (i % width, i / width);
+    let known = |j: usize| {
+        if flags[j] != INSIDE {
+            dist[j]
+        } else {
+            f32::INFINITY
+        }
+    };
+    let mut dx = f32::INFINITY;
+    if x > 0 {
+        dx = dx.min(known(i - 1));
+    }
+    if x + 1 < width {
+        dx = dx.min(known(i + 1));
+    }
+    let mut dy = f32::INFINITY;
+    if y > 0 {
+        dy = dy.min(known(i - width));
+    }
+    if y + 1 < height {
+        dy = dy.min(known(i + width));
+    }
+    if (dx - dy).abs() < 1.0 {
+        (dx + dy + (2.0 - (dx - dy).powi(2)).sqrt()) / 2.0
+    } else {
+        dx.min(dy) + 1.0
+    }
+}
+
+/// Set pixel `i` to a weighted average of the known pixels within `radius` of it.
+fn inpaint_pixel(
+    photon_image: &mut PhotonImage,
+    i: usize,
+    radius: i64,
+    flags: &[u8],
+    dist: &[f32],
+) {
+    let width = photon_image.width as i64;
+    let height = photon_image.height as i64;
+    let (x, y) = ((i as i64) % width, (i as i64) / width);
+    let at = |x: i64, y: i64| -> Option<f32> {
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return None;
+        }
+        let j = (y * width + x) as usize;
+        if flags[j] != INSIDE {
+            Some(dist[j])
+        } else {
+            None
+        }
+    };
+    // The gradient of the distance, which points away from the boundary.
+    let gradient = |prev: Option<f32>, next: Option<f32>| match (prev, next) {
+        (Some(prev), Some(next)) => (next - prev) / 2.0,
+        (None, Some(next)) => next - dist[i],
+        (Some(prev), None) => dist[i] - prev,
+        (None, None) => 0.0,
+    };
+    let gx = gradient(at(x - 1, y), at(x + 1, y));
+    let gy = gradient(at(x, y - 1), at(x, y + 1));
+    let has_gradient = gx != 0.0 || gy != 0.0;
+
+    let mut total = [0.0f32; 4];
+    let mut total_weight = 0.0f32;
+    for ny in (y - radius).max(0)..=(y + radius).min(height - 1) {
+        for nx in (x - radius).max(0)..=(x + radius).min(width - 1) {
+            let (rx, ry) = ((x - nx) as f32, (y - ny) as f32);
+            let dist_sq = rx * rx + ry * ry;
+            if dist_sq == 0.0 || dist_sq > (radius * radius) as f32 {
+                continue;
+            }
+            let neighbour_dist = match at(nx, ny) {
+                Some(d) => d,
+                None => continue,
+            };
+            let direction = if has_gradient {
+                ((rx * gx + ry * gy) / dist_sq.sqrt()).abs().max(0.01)
+            } else {
+                1.0
+            };
+            let level = 1.0 / (1.0 + (neighbour_dist - dist[i]).abs());
+            let weight = direction * level / dist_sq;
+            let j = (ny * width + nx) as usize * 4;
+            for c in 0..4 {
+                total[c] += weight * photon_image.raw_pixels[j + c] as f32;
+            }
+            total_weight += weight;
+        }
+    }
+    if total_weight > 0.0 {
+        for c in 0..4 {
+            photon_image.raw_pixels[i * 4 + c] = (total[c] / total_weight).round() as u8;
+        }
+    }
+}
+
+// pub fn create_gradient_map(color_a : Rgb, color_b: Rgb) -> Vec<Rgb> {
+//     println!("hi");
+//     println!("{}", color_a.get_red());
+//     let mut gradient_map = vec![];
+
+//     let max_val = 255;
+//     let mut r_val = 0;
+
+//     let end: i32 = 256 * 4;
+
+//     for i in (0..end).step_by(4){
+//         let i: u8 = i as u8;
+//         let intensity_b = max_val - i;
+
+//         let res1 = (i * color_a.get_red() + intensity_b * color_b.get_red());
+//         let res2 = res1 / max_val;
+//         println!("res 1 {}", res1);
+//         println!("res 2 {}", res2);
+
+//         r_val = (i * color_a.get_red() + intensity_b * color_b.get_red()) / max_val;
+//         println!("r_val {}", r_val);
+//         gradient_map.push(Rgb {
+//             r: (256 - (i / 4) * color_a.get_red() + (i / 4) * color_b.r) / 256 ,
+//             g: (i * color_a.get_green() + intensity_b * color_b.get_green()) / max_val ,
+//             b: (i * color_a.get_blue() + intensity_b * color_b.get_blue()) / max_val
+//         });
+
+//     }
+//     println!("{:?}", gradient_map);
+
+//     return gradient_map;
+// }
+
+// pub fn duotone(mut img: DynamicImage, color_a : Rgb, color_b : Rgb) -> DynamicImage {
+//     let (width, height) = img.dimensions();
+//     let gradient_map = create_gradient_map(color_a, color_b);
+//     println!("entering for loop");
+
+//     for x in 0..width {
+//         for y in 0..height {
+
+//             let mut px = img.get_pixel(x, y);
+
+//             let r = px.data[0];
+//             let g = px.data[1];
+//             let b = px.data[2];
 
 //             px.data[0] = gradient_map[r as usize].r as u8;
 //             px.data[1] = gradient_map[g as usize].g as u8;
 //             px.data[2] = gradient_map[b as usize].b as u8;
 
-//             img.put_pixel(x, y, px);
-//         }
-//     }
-//     return img;
-// }
+//             img.put_pixel(x, y, px);
+//         }
+//     }
+//     return img;
+// }
+
+/// Smooth an image with the classic Kuwahara filter, which flattens regions of similar
+/// colour while keeping the edges between them sharp, giving a painted look.
+///
+/// This is `generalized_kuwahara` with four sectors and no anisotropy.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `radius` - The radius of the neighbourhood around each pixel, eg: 5.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::kuwahara;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// kuwahara(&mut img, 5_u32);
+/// ```
+#[wasm_bindgen]
+pub fn kuwahara(photon_image: &mut PhotonImage, radius: u32) {
+    generalized_kuwahara(photon_image, radius, 4, 0.0);
+}
+
+/// Smooth an image with the generalized Kuwahara filter of Papari et al., or its
+/// anisotropic variant by Kyprianidis et al.
+///
+/// The disc around each pixel is divided into overlapping sectors, with smooth,
+/// Gaussian-weighted edges. Each pixel becomes the average colour of the sectors, weighted
+/// towards those with the least variance, so that sectors which straddle an edge
+/// contribute little. With anisotropy, the disc is stretched into an ellipse along the
+/// local direction of the image's edges, found from its structure tensor, so that strokes
+/// follow the shapes in the image. Pixels beyond the edges of the image are treated as
+/// copies of the nearest edge pixel.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `radius` - The radius of the neighbourhood around each pixel, eg: 5.
+/// * `sectors` - The number of sectors, at least 2, eg: 8.
+/// * `anisotropy` - How far to stretch the neighbourhood along edges, from 0 (a disc) to
+/// 1 (up to twice as long as it's wide, where the edges are strongest).
+/// # Panics
+/// Panics if `sectors` is less than 2.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::generalized_kuwahara;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// generalized_kuwahara(&mut img, 6_u32, 8_u32, 1.0_f32);
+/// ```
+#[wasm_bindgen]
+pub fn generalized_kuwahara(
+    photon_image: &mut PhotonImage,
+    radius: u32,
+    sectors: u32,
+    anisotropy: f32,
+) {
+    if sectors < 2 {
+        panic!(
+            "The Kuwahara filter needs at least 2 sectors, not {}.",
+            sectors
+        );
+    }
+    if radius == 0 {
+        return;
+    }
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    let anisotropy = anisotropy.clamp(0.0, 1.0) as f64;
+    let weights = SectorWeights::new(radius as i64, sectors as usize);
+    let orientations = if anisotropy > 0.0 {
+        structure_orientations(photon_image)
+    } else {
+        vec![(0.0, 0.0); width * height]
+    };
+
+    let src = photon_image.raw_pixels.clone();
+    let pixel = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as usize;
+        let y = y.clamp(0, height as i64 - 1) as usize;
+        &src[(y * width + x) * 4..(y * width + x) * 4 + 3]
+    };
+    let r = radius as f64;
+    let sectors = sectors as usize;
+    let mut sums = vec![[0.0f64; 7]; sectors];
+
+    for y in 0..height {
+        for x in 0..width {
+            let (angle, strength) = orientations[y * width + x];
+            let stretch = 1.0 + anisotropy * strength;
+            let (sin, cos) = angle.sin_cos();
+            let reach = (r * stretch).ceil() as i64;
+            for sum in sums.iter_mut() {
+                *sum = [0.0; 7];
+            }
+
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    // Map the offset from the ellipse onto the disc of sector weights.
+                    let (fx, fy) = (dx as f64, dy as f64);
+                    let u = (fx * cos + fy * sin) / stretch;
+                    let v = (-fx * sin + fy * cos) * stretch;
+                    if u * u + v * v > r * r {
+                        continue;
+                    }
+                    let px = pixel(x as i64 + dx, y as i64 + dy);
+                    let sector_weights = weights.at(u.round() as i64, v.round() as i64);
+                    for (sum, &w) in sums.iter_mut().zip(sector_weights) {
+                        if w == 0.0 {
+                            continue;
+                        }
+                        sum[0] += w;
+                        for c in 0..3 {
+                            let value = px[c] as f64;
+                            sum[1 + c] += w * value;
+                            sum[4 + c] += w * value * value;
+                        }
+                    }
+                }
+            }
+
+            let mut total_weight = 0.0;
+            let mut colour = [0.0; 3];
+            for sum in &sums {
+                if sum[0] == 0.0 {
+                    continue;
+                }
+                let mut variance = 0.0;
+                let mut mean = [0.0; 3];
+                for c in 0..3 {
+                    mean[c] = sum[1 + c] / sum[0];
+                    variance += (sum[4 + c] / sum[0] - mean[c] * mean[c]).max(0.0);
+                }
+                // Weight sectors by the inverse of the 8th power of their deviation.
+                let weight = 1.0 / (1.0 + variance.powi(4));
+                total_weight += weight;
+                for c in 0..3 {
+                    colour[c] += weight * mean[c];
+                }
+            }
+            let i = (y * width + x) * 4;
+            for (channel, value) in
+                photon_image.raw_pixels[i..i + 3].iter_mut().zip(&colour)
+            {
+                *channel = (value / total_weight).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+/// The weight of each sector of `generalized_kuwahara` at each offset within a disc.
+struct SectorWeights {
+    radius: i64,
+    sectors: usize,
+    weights: Vec<f64>,
+}
+
+impl SectorWeights {
+    fn new(radius: i64, sectors: usize) -> SectorWeights {
+        let side = (2 * radius + 1) as usize;
+        let sigma = radius as f64 / 2.0;
+        let half_width = std::f64::consts::PI / sectors as f64;
+        let mut weights = vec![0.0; side * side * sectors];
+        for v in -radius..=radius {
+            for u in -radius..=radius {
+                let start =
+                    (((v + radius) * (2 * radius + 1) + u + radius) as usize) * sectors;
+                let at = &mut weights[start..start + sectors];
+                if u == 0 && v == 0 {
+                    // The centre is shared equally between the sectors.
+                    at.iter_mut().for_each(|w| *w = 1.0 / sectors as f64);
+                    continue;
+                }
+                let distance_sq = (u * u + v * v) as f64;
+                if distance_sq > (radius * radius) as f64 {
+                    continue;
+                }
+                let radial = (-distance_sq / (2.0 * sigma * sigma)).exp();
+                let angle = (v as f64).atan2(u as f64);
+                for (k, w) in at.iter_mut().enumerate() {
+                    // Each sector fades out towards the centres of its neighbours, so the
+                    // sectors' weights always sum to 1.
+                    let centre = (2 * k + 1) as f64 * half_width;
+                    let mut offset =
+                        (angle - centre).rem_euclid(2.0 * std::f64::consts::PI);
+                    if offset > std::f64::consts::PI {
+                        offset -= 2.0 * std::f64::consts::PI;
+                    }
+                    if offset.abs() < 2.0 * half_width {
+                        *w = radial
+                            * (offset * std::f64::consts::PI / (4.0 * half_width))
+                                .cos()
+                                .powi(2);
+                    }
+                }
+            }
+        }
+        SectorWeights {
+            radius,
+            sectors,
+            weights,
+        }
+    }
+
+    /// The weight of each sector at an offset, which must lie within the disc.
+    fn at(&self, u: i64, v: i64) -> &[f64] {
+        let u = u.clamp(-self.radius, self.radius);
+        let v = v.clamp(-self.radius, self.radius);
+        let side = 2 * self.radius + 1;
+        let start =
+            (((v + self.radius) * side + u + self.radius) as usize) * self.sectors;
+        &self.weights[start..start + self.sectors]
+    }
+}
+
+/// The direction of the edges around each pixel, as an angle in radians, and how strongly
+/// directional they are, from 0 to 1, from the smoothed structure tensor of its luma.
+fn structure_orientations(photon_image: &PhotonImage) -> Vec<(f64, f64)> {
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    let luma: Vec<f64> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f64)
+        .collect();
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as usize;
+        let y = y.clamp(0, height as i64 - 1) as usize;
+        luma[y * width + x]
+    };
+
+    let mut tensor = vec![[0.0; 3]; width * height];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let gx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+            let gy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+            tensor[y as usize * width + x as usize] = [gx * gx, gx * gy, gy * gy];
+        }
+    }
+
+    // Smooth the tensor over a 5x5 neighbourhood, so that the orientation is consistent
+    // along an edge.
+    let smoothed = |x: i64, y: i64| {
+        let mut sum = [0.0; 3];
+        for dy in -2..=2 {
+            for dx in -2..=2 {
+                let tx = (x + dx).clamp(0, width as i64 - 1) as usize;
+                let ty = (y + dy).clamp(0, height as i64 - 1) as usize;
+                for (s, t) in sum.iter_mut().zip(&tensor[ty * width + tx]) {
+                    *s += t;
+                }
+            }
+        }
+        sum
+    };
+
+    let mut orientations = Vec::with_capacity(width * height);
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let [e, f, g] = smoothed(x, y);
+            let root = ((e - g) * (e - g) + 4.0 * f * f).sqrt();
+            let (major, minor) = ((e + g + root) / 2.0, (e + g - root) / 2.0);
+            // The edges run at right angles to the dominant gradient.
+            let angle = 0.5 * (2.0 * f).atan2(e - g) + std::f64::consts::FRAC_PI_2;
+            let strength = if major + minor > 1e-9 {
+                (major - minor) / (major + minor)
+            } else {
+                0.0
+            };
+            orientations.push((angle, strength));
+        }
+    }
+    orientations
+}
+
+/// Apply a tilt-shift effect, which keeps a horizontal band of the image sharp and blurs
+/// it progressively more above and below, so that a scene looks like a miniature model.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `focus_y` - The centre of the sharp band, as a fraction of the image's height, from 0
+/// (the top) to 1 (the bottom), eg: 0.6.
+/// * `focus_height` - The height of the sharp band, as a fraction of the image's height,
+/// eg: 0.2.
+/// * `max_blur` - The amount of blur at the top and bottom of the image, as the standard
+/// deviation of a Gaussian blur in pixels, eg: 8.0. The blur increases linearly from the
+/// edges of the band to the edges of the image.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::tilt_shift;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("city.jpg");
+/// tilt_shift(&mut img, 0.6_f32, 0.2_f32, 8.0_f32);
+/// ```
+#[wasm_bindgen]
+pub fn tilt_shift(
+    photon_image: &mut PhotonImage,
+    focus_y: f32,
+    focus_height: f32,
+    max_blur: f32,
+) {
+    let width = photon_image.width as usize;
+    let height = photon_image.height as f32;
+    let top = (focus_y - focus_height.max(0.0) / 2.0).clamp(0.0, 1.0) * height;
+    let bottom = (focus_y + focus_height.max(0.0) / 2.0).clamp(0.0, 1.0) * height;
+    let max_blur = max_blur.max(0.0);
+
+    let mut sigmas = Vec::with_capacity(photon_image.raw_pixels.len() / 4);
+    for y in 0..photon_image.height {
+        let centre = y as f32 + 0.5;
+        let ramp = if centre < top {
+            (top - centre) / top
+        } else if centre > bottom {
+            (centre - bottom) / (height - bottom)
+        } else {
+            0.0
+        };
+        sigmas.resize(sigmas.len() + width, max_blur * ramp.min(1.0));
+    }
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        variable_blur(photon_image, &sigmas)
+    });
+}
+
+/// Blur an image according to a depth map, simulating the shallow depth of field of a
+/// camera with a wide aperture, eg: to blur the background of a portrait taken with a
+/// phone which records depth.
+///
+/// Each pixel is blurred in proportion to how far its depth is from the focal depth, so
+/// that the subject stays sharp, and everything in front of or behind it is increasingly
+/// blurred.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `depth_map` - A grayscale depth map of the same size as the image, whose brightness
+/// gives the depth of each pixel, either nearer or further away the brighter it is.
+/// * `focal_depth` - The depth which is in focus, from 0 (black in the depth map) to 1
+/// (white).
+/// * `aperture` - The amount of blur at a depth 1 away from the focal depth, as the
+/// standard deviation of a Gaussian blur in pixels, eg: 12.0.
+/// # Panics
+/// Panics if the depth map isn't the same size as the image.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::depth_of_field;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("portrait.jpg");
+/// let depth_map = open_image("portrait_depth.png");
+/// depth_of_field(&mut img, &depth_map, 0.9_f32, 12.0_f32);
+/// ```
+#[wasm_bindgen]
+pub fn depth_of_field(
+    photon_image: &mut PhotonImage,
+    depth_map: &PhotonImage,
+    focal_depth: f32,
+    aperture: f32,
+) {
+    if depth_map.width != photon_image.width || depth_map.height != photon_image.height {
+        panic!("The depth map must be the same size as the image.");
+    }
+    let sigmas: Vec<f32> = depth_map
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| {
+            let depth = helpers::luma(px) as f32 / 255.0;
+            aperture.max(0.0) * (depth - focal_depth).abs()
+        })
+        .collect();
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        variable_blur(photon_image, &sigmas)
+    });
+}
+
+/// Blur each pixel of an image by its own amount, given as the standard deviation of an
+/// approximately Gaussian blur, in pixels.
+///
+/// Three passes of box blurs are used, each of whose averages comes from a summed-area
+/// table, so the cost doesn't depend on the amount of blur. Colours are premultiplied by
+/// alpha while they're averaged.
+fn variable_blur(photon_image: &mut PhotonImage, sigmas: &[f32]) {
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    // Three boxes of width `w` have a variance of `(w² - 1) / 4`.
+    let radii: Vec<f32> = sigmas
+        .iter()
+        .map(|&sigma| (((4.0 * sigma * sigma + 1.0).sqrt() - 1.0) / 2.0).max(0.0))
+        .collect();
+
+    let mut values: Vec<f32> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .flat_map(|px| {
+            let alpha = px[3] as f32 / 255.0;
+            vec![
+                px[0] as f32 * alpha,
+                px[1] as f32 * alpha,
+                px[2] as f32 * alpha,
+                px[3] as f32,
+            ]
+        })
+        .collect();
+
+    let stride = width + 1;
+    let mut table = vec![0.0f64; stride * (height + 1) * 4];
+    for _ in 0..3 {
+        for y in 0..height {
+            let mut row = [0.0f64; 4];
+            for x in 0..width {
+                let i = (y * width + x) * 4;
+                let above = (y * stride + x + 1) * 4;
+                let here = ((y + 1) * stride + x + 1) * 4;
+                for c in 0..4 {
+                    row[c] += values[i + c] as f64;
+                    table[here + c] = table[above + c] + row[c];
+                }
+            }
+        }
+
+        let box_mean = |x: usize, y: usize, radius: usize| {
+            let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+            let (x1, y1) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+            let area = ((x1 - x0) * (y1 - y0)) as f64;
+            let mut mean = [0.0; 4];
+            for (c, value) in mean.iter_mut().enumerate() {
+                let sum = table[(y1 * stride + x1) * 4 + c]
+                    - table[(y0 * stride + x1) * 4 + c]
+                    - table[(y1 * stride + x0) * 4 + c]
+                    + table[(y0 * stride + x0) * 4 + c];
+                *value = sum / area;
+            }
+            mean
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let radius = radii[y * width + x];
+                if radius <= 0.0 {
+                    continue;
+                }
+                let lower = radius.floor() as usize;
+                let fraction = (radius - lower as f32) as f64;
+                let (small, large) = (box_mean(x, y, lower), box_mean(x, y, lower + 1));
+                let i = (y * width + x) * 4;
+                for c in 0..4 {
+                    values[i + c] =
+                        (small[c] * (1.0 - fraction) + large[c] * fraction) as f32;
+                }
+            }
+        }
+    }
+
+    for (px, value) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(4)
+        .zip(values.chunks_exact(4))
+    {
+        let alpha = value[3];
+        if alpha <= 0.0 {
+            px.copy_from_slice(&[0, 0, 0, 0]);
+            continue;
+        }
+        for c in 0..3 {
+            px[c] = (value[c] * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+        }
+        px[3] = alpha.round().clamp(0.0, 255.0) as u8;
+    }
+}
+
+/// Blur an image along rays from a centre point, as if zooming the lens during the
+/// exposure, for a sense of speed or impact.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `cx` - The x-coordinate of the centre of the zoom.
+/// * `cy` - The y-coordinate of the centre of the zoom.
+/// * `strength` - The length of the blur, as a fraction of each pixel's distance from the
+/// centre, from 0 upwards, eg: 0.2. The centre itself stays sharp.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::zoom_blur;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// let (cx, cy) = (img.get_width() as f32 / 2.0, img.get_height() as f32 / 2.0);
+/// zoom_blur(&mut img, cx, cy, 0.2_f32);
+/// ```
+#[wasm_bindgen]
+pub fn zoom_blur(photon_image: &mut PhotonImage, cx: f32, cy: f32, strength: f32) {
+    let strength = strength.max(0.0) as f64;
+    let (cx, cy) = (cx as f64, cy as f64);
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        path_blur(photon_image, |x, y, t| {
+            let scale = 1.0 + strength * t;
+            (cx + (x - cx) * scale, cy + (y - cy) * scale)
+        })
+    });
+}
+
+/// Blur an image along circular arcs around a centre point, as if the camera or subject
+/// were spinning during the exposure.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `cx` - The x-coordinate of the centre of the spin.
+/// * `cy` - The y-coordinate of the centre of the spin.
+/// * `angle` - The angle which each arc covers, in degrees, eg: 10.0. The further a pixel
+/// is from the centre, the longer its arc, so the more it's blurred.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::spin_blur;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("wheel.jpg");
+/// let (cx, cy) = (img.get_width() as f32 / 2.0, img.get_height() as f32 / 2.0);
+/// spin_blur(&mut img, cx, cy, 10.0_f32);
+/// ```
+#[wasm_bindgen]
+pub fn spin_blur(photon_image: &mut PhotonImage, cx: f32, cy: f32, angle: f32) {
+    let angle = (angle.abs() as f64).min(360.0).to_radians();
+    let (cx, cy) = (cx as f64, cy as f64);
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        path_blur(photon_image, |x, y, t| {
+            let (sin, cos) = (angle * t).sin_cos();
+            let (dx, dy) = (x - cx, y - cy);
+            (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+        })
+    });
+}
+
+/// Average each pixel with samples of the image taken along a path through it, where
+/// `path(x, y, t)` gives the position at `t`, from -0.5 to 0.5, along the path through the
+/// centre of the pixel at `(x, y)`. Positions outside the image are moved to its edges.
+fn path_blur<F: Fn(f64, f64, f64) -> (f64, f64)>(
+    photon_image: &mut PhotonImage,
+    path: F,
+) {
+    if photon_image.width == 0 || photon_image.height == 0 {
+        return;
+    }
+    let src = photon_image.clone();
+    let max_x = (src.width - 1) as f64;
+    let max_y = (src.height - 1) as f64;
+    for y in 0..src.height {
+        for x in 0..src.width {
+            let (px, py) = (x as f64, y as f64);
+            // Take about one sample per pixel of the path's length.
+            let (x0, y0) = path(px, py, -0.5);
+            let (x1, y1) = path(px, py, 0.5);
+            let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+            let samples = (length.ceil() as usize).clamp(1, MAX_PATH_SAMPLES);
+            if samples == 1 {
+                continue;
+            }
+
+            let mut sum = [0.0; 4];
+            for s in 0..samples {
+                let t = s as f64 / (samples - 1) as f64 - 0.5;
+                let (sx, sy) = path(px, py, t);
+                let sample = helpers::sample_bilinear(
+                    &src,
+                    sx.clamp(0.0, max_x),
+                    sy.clamp(0.0, max_y),
+                );
+                let alpha = sample[3] as f64;
+                for c in 0..3 {
+                    sum[c] += sample[c] as f64 * alpha;
+                }
+                sum[3] += alpha;
+            }
+            let i = ((y * src.width + x) * 4) as usize;
+            let px = &mut photon_image.raw_pixels[i..i + 4];
+            if sum[3] == 0.0 {
+                px.copy_from_slice(&[0, 0, 0, 0]);
+                continue;
+            }
+            for c in 0..3 {
+                px[c] = (sum[c] / sum[3]).round() as u8;
+            }
+            px[3] = (sum[3] / samples as f64).round() as u8;
+        }
+    }
+}
+
+/// The most samples `path_blur` takes for each pixel.
+const MAX_PATH_SAMPLES: usize = 64;
+
+/// Degrade an image with the artifacts of JPEG compression, by compressing it repeatedly
+/// at a low quality, as happens to images which are shared and re-shared online.
+///
+/// Each generation is compressed at a slightly different quality from the last, so that
+/// it adds new artifacts, rather than reproducing the previous generation's. The alpha
+/// channel is kept, although JPEG doesn't store one.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `quality` - The JPEG quality, from 1 (smallest, most degraded) to 100, eg: 10.
+/// * `generations` - How many times to compress the image, eg: 5.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::jpegify;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// jpegify(&mut img, 10_u8, 5_u32);
+/// ```
+#[wasm_bindgen]
+pub fn jpegify(photon_image: &mut PhotonImage, quality: u8, generations: u32) {
+    let quality = quality.clamp(1, 100);
+    let (width, height) = (photon_image.width, photon_image.height);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let mut rgb: Vec<u8> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .flat_map(|px| px[..3].to_vec())
+        .collect();
+
+    for generation in 0..generations {
+        let offset = [0, 2, 4][generation as usize % 3];
+        let mut encoded = vec![];
+        image::jpeg::JPEGEncoder::new_with_quality(
+            &mut encoded,
+            quality.saturating_add(offset).min(100),
+        )
+        .encode(&rgb, width, height, image::RGB(8))
+        .expect("encoding to memory can't fail");
+        rgb = image::load_from_memory_with_format(&encoded, image::JPEG)
+            .expect("the encoder produces valid JPEGs")
+            .to_rgb()
+            .into_raw();
+    }
+
+    for (px, decoded) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(4)
+        .zip(rgb.chunks(3))
+    {
+        px[..3].copy_from_slice(decoded);
+    }
+}
+
+/// Give an image the oversaturated, overcontrasted and heavily compressed look of a
+/// "deep-fried" meme.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `intensity` - How deep-fried the image is, from 0 (barely) to 1 (thoroughly).
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::deep_fry;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// deep_fry(&mut img, 0.8_f32);
+/// ```
+#[wasm_bindgen]
+pub fn deep_fry(photon_image: &mut PhotonImage, intensity: f32) {
+    let intensity = intensity.clamp(0.0, 1.0);
+    crate::colour_spaces::saturate_hsl(photon_image, 0.5 * intensity);
+    adjust_contrast(photon_image, 80.0 * intensity);
+    let quality = (30.0 - 25.0 * intensity).round() as u8;
+    let generations = 1 + (6.0 * intensity).round() as u32;
+    jpegify(photon_image, quality, generations);
+}
+
+/// Options for the `vhs` effect. Each part of the effect can be turned off by setting
+/// its amount to 0.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct VhsOptions {
+    jitter: f32,
+    chroma_bleed: u32,
+    scanlines: f32,
+    noise: f32,
+    tracking: f32,
+    seed: u64,
+}
+
+#[wasm_bindgen]
+impl VhsOptions {
+    #[wasm_bindgen(constructor)]
+    /// Create options for a moderately worn tape.
+    pub fn new() -> VhsOptions {
+        VhsOptions {
+            jitter: 1.5,
+            chroma_bleed: 4,
+            scanlines: 0.2,
+            noise: 0.06,
+            tracking: 0.5,
+            seed: 0,
+        }
+    }
+
+    /// Set the most that each row is shifted sideways, in pixels, as the tape wobbles.
+    pub fn set_jitter(&mut self, jitter: f32) {
+        self.jitter = jitter.max(0.0);
+    }
+
+    /// Set how far colour smears to the right of where it belongs, in pixels, as VHS
+    /// records colour at a much lower resolution than brightness.
+    pub fn set_chroma_bleed(&mut self, chroma_bleed: u32) {
+        self.chroma_bleed = chroma_bleed;
+    }
+
+    /// Set how much every other row is darkened, from 0 to 1.
+    pub fn set_scanlines(&mut self, scanlines: f32) {
+        self.scanlines = scanlines.clamp(0.0, 1.0);
+    }
+
+    /// Set the strength of the static, from 0 to 1.
+    pub fn set_noise(&mut self, noise: f32) {
+        self.noise = noise.clamp(0.0, 1.0);
+    }
+
+    /// Set the strength of the tracking error: a band near the bottom of the picture
+    /// which is torn sideways and full of static, from 0 to 1.
+    pub fn set_tracking(&mut self, tracking: f32) {
+        self.tracking = tracking.clamp(0.0, 1.0);
+    }
+
+    /// Set the seed for the random jitter, static and position of the tracking error, so
+    /// that successive frames of a video can differ.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+impl Default for VhsOptions {
+    fn default() -> VhsOptions {
+        VhsOptions::new()
+    }
+}
+
+/// Make an image look like a frame of a worn VHS tape, with colour bleeding, wobbling
+/// rows, scanlines, static and a tracking error.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `options` - The strength of each part of the effect.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::{vhs, VhsOptions};
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// let mut options = VhsOptions::new();
+/// options.set_tracking(0.8);
+/// vhs(&mut img, &options);
+/// ```
+#[wasm_bindgen]
+pub fn vhs(photon_image: &mut PhotonImage, options: &VhsOptions) {
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    if width == 0 || height == 0 {
+        return;
+    }
+    let seed = options.seed;
+
+    // Convert to YUV, smearing the colour (U and V) to the right with a trailing
+    // average.
+    let mut yuv: Vec<[f32; 3]> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| {
+            let (r, g, b) = (px[0] as f32, px[1] as f32, px[2] as f32);
+            let y = 0.299 * r + 0.587 * g + 0.114 * b;
+            [y, 0.492 * (b - y), 0.877 * (r - y)]
+        })
+        .collect();
+    let bleed = options.chroma_bleed as usize;
+    if bleed > 0 {
+        for row in yuv.chunks_exact_mut(width) {
+            let chroma: Vec<[f32; 2]> = row.iter().map(|p| [p[1], p[2]]).collect();
+            let mut sum = [0.0; 2];
+            for x in 0..width {
+                sum[0] += chroma[x][0];
+                sum[1] += chroma[x][1];
+                if x > bleed {
+                    sum[0] -= chroma[x - bleed - 1][0];
+                    sum[1] -= chroma[x - bleed - 1][1];
+                }
+                let count = (x.min(bleed) + 1) as f32;
+                row[x][1] = sum[0] / count;
+                row[x][2] = sum[1] / count;
+            }
+        }
+    }
+
+    // The tracking error is a band across the lower part of the picture.
+    let band_height = (height as f32 * 0.08).max(1.0);
+    let band_top = height as f32 * (0.7 + 0.2 * random(seed, 0, 0).abs());
+
+    let src = yuv.clone();
+    for y in 0..height {
+        let in_band = options.tracking > 0.0
+            && (y as f32) >= band_top
+            && (y as f32) < band_top + band_height;
+        let mut shift = options.jitter * random(seed, 1, y as u64);
+        if in_band {
+            shift += options.tracking
+                * width as f32
+                * 0.05
+                * (1.0 + random(seed, 2, y as u64));
+        }
+        let shift = shift.round() as isize;
+        let scanline = if y % 2 == 1 {
+            1.0 - options.scanlines
+        } else {
+            1.0
+        };
+
+        for x in 0..width {
+            let sx = (x as isize - shift).clamp(0, width as isize - 1) as usize;
+            let [mut luma, u, v] = src[y * width + sx];
+            let mut static_amount = options.noise;
+            if in_band {
+                static_amount += options.tracking * 0.5;
+            }
+            luma += static_amount * 255.0 * random(seed, 3, (y * width + x) as u64);
+            luma *= scanline;
+            yuv[y * width + x] = [luma, u, v];
+        }
+    }
+
+    for (px, [y, u, v]) in photon_image.raw_pixels.chunks_exact_mut(4).zip(yuv) {
+        let clamp = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+        px[0] = clamp(y + 1.140 * v);
+        px[1] = clamp(y - 0.395 * u - 0.581 * v);
+        px[2] = clamp(y + 2.032 * u);
+    }
+}
+
+/// A pseudo-random number from -1 to 1, which is the same for the same seed, stream and
+/// index.
+fn random(seed: u64, stream: u64, index: u64) -> f32 {
+    // SplitMix64's finaliser, which scrambles every bit of its input.
+    let mut z = seed
+        .wrapping_add(stream.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add(index.wrapping_mul(0xbf58_476d_1ce4_e5b9));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+/// Remove atmospheric haze from a photo, such as a landscape, restoring the contrast and
+/// colour of distant areas, with the dark channel prior of He, Sun and Tang (2009).
+///
+/// In a clear photo, almost every small patch has some pixel which is very dark in at
+/// least one channel, so the brightness of a patch's darkest channel estimates how much
+/// haze is in front of it. The haze's colour is taken from the haziest part of the
+/// image, and that much of it is subtracted from each pixel, with the estimate smoothed
+/// along the edges of the image by a guided filter.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `strength` - How much of the haze to remove, from 0 (none) to 1 (almost all of it).
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::dehaze;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("landscape.jpg");
+/// dehaze(&mut img, 0.8);
+/// ```
+#[wasm_bindgen]
+pub fn dehaze(photon_image: &mut PhotonImage, strength: f32) {
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    let strength = strength.clamp(0.0, 1.0);
+    if width == 0 || height == 0 || strength == 0.0 {
+        return;
+    }
+    let patch = (width.min(height) / 40).clamp(1, 7);
+    let pixels: Vec<[f32; 3]> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| {
+            [
+                px[0] as f32 / 255.0,
+                px[1] as f32 / 255.0,
+                px[2] as f32 / 255.0,
+            ]
+        })
+        .collect();
+
+    // The colour of the haze: the brightest of the pixels whose dark channel is in the
+    // top 0.1%, which are the haziest.
+    let dark = dark_channel(&pixels, [1.0; 3], width, height, patch);
+    let mut order: Vec<usize> = (0..pixels.len()).collect();
+    order.sort_unstable_by(|&a, &b| dark[b].total_cmp(&dark[a]));
+    let haziest = &order[..(pixels.len() / 1000).max(1)];
+    let brightest = haziest
+        .iter()
+        .max_by(|&&a, &&b| {
+            let sum = |i: usize| pixels[i][0] + pixels[i][1] + pixels[i][2];
+            sum(a).total_cmp(&sum(b))
+        })
+        .unwrap();
+    let haze = pixels[*brightest].map(|c| c.max(0.05));
+
+    // Keep a little haze even at full strength, so that distant areas still look
+    // distant.
+    let omega = 0.95 * strength;
+    let transmission: Vec<f32> = dark_channel(&pixels, haze, width, height, patch)
+        .iter()
+        .map(|d| 1.0 - omega * d)
+        .collect();
+    let guide: Vec<f32> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f32 / 255.0)
+        .collect();
+    let transmission =
+        guided_filter(&guide, &transmission, width, height, patch * 4, 1e-3);
+
+    for (px, (rgb, t)) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(4)
+        .zip(pixels.iter().zip(transmission))
+    {
+        let t = t.max(0.1);
+        for c in 0..3 {
+            let value = (rgb[c] - haze[c]) / t + haze[c];
+            px[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// The darkest channel of each pixel, after dividing by `scale`, over the square patch
+/// of the given radius around it.
+fn dark_channel(
+    pixels: &[[f32; 3]],
+    scale: [f32; 3],
+    width: usize,
+    height: usize,
+    radius: usize,
+) -> Vec<f32> {
+    let darkest: Vec<f32> = pixels
+        .iter()
+        .map(|px| {
+            (px[0] / scale[0])
+                .min(px[1] / scale[1])
+                .min(px[2] / scale[2])
+        })
+        .collect();
+    // A square minimum filter is a horizontal one followed by a vertical one.
+    let mut rows = vec![0.0; darkest.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            rows[y * width + x] = darkest[y * width + x0..y * width + x1]
+                .iter()
+                .fold(f32::MAX, |a, &b| a.min(b));
+        }
+    }
+    let mut patches = vec![0.0; darkest.len()];
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+        for x in 0..width {
+            patches[y * width + x] = (y0..y1)
+                .map(|y| rows[y * width + x])
+                .fold(f32::MAX, f32::min);
+        }
+    }
+    patches
+}
+
+/// Smooth `input` while keeping the edges of `guide`, with the guided filter of He, Sun
+/// and Tang (2010). `epsilon` sets how strong an edge in the guide must be to be kept.
+fn guided_filter(
+    guide: &[f32],
+    input: &[f32],
+    width: usize,
+    height: usize,
+    radius: usize,
+    epsilon: f32,
+) -> Vec<f32> {
+    let mean = |values: &[f32]| box_mean(values, width, height, radius);
+    let product = |a: &[f32], b: &[f32]| -> Vec<f32> {
+        a.iter().zip(b).map(|(a, b)| a * b).collect()
+    };
+    let mean_guide = mean(guide);
+    let mean_input = mean(input);
+    let correlation = mean(&product(guide, input));
+    let variance = mean(&product(guide, guide));
+
+    let mut a = vec![0.0; guide.len()];
+    let mut b = vec![0.0; guide.len()];
+    for i in 0..guide.len() {
+        let covariance = correlation[i] - mean_guide[i] * mean_input[i];
+        let var = variance[i] - mean_guide[i] * mean_guide[i];
+        a[i] = covariance / (var + epsilon);
+        b[i] = mean_input[i] - a[i] * mean_guide[i];
+    }
+    let (mean_a, mean_b) = (mean(&a), mean(&b));
+    (0..guide.len())
+        .map(|i| mean_a[i] * guide[i] + mean_b[i])
+        .collect()
+}
+
+/// The mean of the values over the square of the given radius around each, clipped to
+/// the edges of the image.
+fn box_mean(values: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    let stride = width + 1;
+    let mut table = vec![0.0f64; stride * (height + 1)];
+    for y in 0..height {
+        let mut row = 0.0;
+        for x in 0..width {
+            row += values[y * width + x] as f64;
+            table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row;
+        }
+    }
+    let mut means = Vec::with_capacity(values.len());
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            let sum = table[y1 * stride + x1]
+                - table[y0 * stride + x1]
+                - table[y1 * stride + x0]
+                + table[y0 * stride + x0];
+            means.push((sum / ((x1 - x0) * (y1 - y0)) as f64) as f32);
+        }
+    }
+    means
+}
+
+/// Smooth the skin in a portrait while keeping its texture, by frequency separation.
+///
+/// The image is split into a low-frequency layer, a blur holding its tones and colours,
+/// and a high-frequency layer, the difference holding fine texture such as pores and
+/// hair. Only the low-frequency layer is smoothed, evening out blotches and shadows, so
+/// the skin doesn't look plastic. Smoothing is limited to skin-coloured pixels, and
+/// backs off near strong edges, such as the outline of a face, to avoid haloes.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `strength` - How much to smooth the skin, from 0 (not at all) to 1.
+/// # Example
+///
+/// ```
+/// use photon_rs::effects::skin_smooth;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("portrait.jpg");
+/// skin_smooth(&mut img, 0.6);
+/// ```
+#[wasm_bindgen]
+pub fn skin_smooth(photon_image: &mut PhotonImage, strength: f32) {
+    let width = photon_image.width as usize;
+    let height = photon_image.height as usize;
+    let strength = strength.clamp(0.0, 1.0);
+    if width == 0 || height == 0 || strength == 0.0 {
+        return;
+    }
+    // Separate at about the size of pores, relative to the size of the portrait.
+    let sigma = (width.min(height) as f32 / 300.0).max(1.5);
+    let mut low = photon_image.clone();
+    variable_blur(&mut low, &vec![sigma; width * height]);
+    let mut smooth = low.clone();
+    variable_blur(&mut smooth, &vec![4.0 * sigma; width * height]);
+
+    for ((px, low), smooth) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(4)
+        .zip(low.raw_pixels.chunks_exact(4))
+        .zip(smooth.raw_pixels.chunks_exact(4))
+    {
+        let (_, cb, cr) = helpers::to_ycbcr(px);
+        let skin = skin_likelihood(cb, cr);
+        if skin == 0.0 {
+            continue;
+        }
+        // Where smoothing would change the tone a lot, there's an edge nearby.
+        let change = (0..3)
+            .map(|c| (smooth[c] as f32 - low[c] as f32).abs())
+            .fold(0.0, f32::max);
+        let edge = (-(change / 24.0).powi(2)).exp();
+        let amount = strength * skin * edge;
+        for c in 0..3 {
+            // Replace the low frequencies with smoothed ones, keeping the high ones.
+            let shift = (smooth[c] as f32 - low[c] as f32) * amount;
+            px[c] = (px[c] as f32 + shift).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// How much a colour, as BT.601 chroma from -0.5 to 0.5, looks like skin, from 0 to 1.
+/// Skin tones of all shades fall in a small box of chroma, with a soft edge around it.
+fn skin_likelihood(cb: f32, cr: f32) -> f32 {
+    let outside = |v: f32, low: f32, high: f32| (low - v).max(v - high).max(0.0);
+    let distance = outside(cb, -0.2, 0.0).max(outside(cr, 0.02, 0.18));
+    let t = (1.0 - distance / 0.05).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,58 @@
+//! Errors returned by Photon's fallible functions.
+
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// The error type for Photon operations which can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhotonError {
+    /// The operation was cancelled through a `CancellationToken` before it completed.
+    /// The image it was working on has been left unmodified.
+    Cancelled,
+    /// A colour could not be parsed, eg: it isn't valid hex, or the name of a CSS
+    /// colour.
+    InvalidColour(String),
+    /// The font data could not be parsed, eg: it is not a TrueType font.
+    InvalidFont(String),
+    /// A 3D LUT could not be created, eg: its size is out of range.
+    InvalidLut(String),
+    /// A pipeline of operations could not be parsed, or one of its operations has invalid
+    /// parameters. The image it was working on has been left unmodified.
+    InvalidPipeline(String),
+    /// An image file could not be read or written, eg: it doesn't exist, or isn't in a
+    /// supported format.
+    Io(String),
+    /// Data could not be hidden in an image, because it doesn't fit, or could not be
+    /// extracted from one, because none was hidden in it.
+    Steganography(String),
+    /// Frames could not be stitched into a panorama, eg: because they don't overlap
+    /// enough to be aligned.
+    Stitching(String),
+}
+
+impl fmt::Display for PhotonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhotonError::Cancelled => write!(f, "operation was cancelled"),
+            PhotonError::InvalidColour(msg) => write!(f, "invalid colour: {}", msg),
+            PhotonError::InvalidFont(msg) => write!(f, "invalid font: {}", msg),
+            PhotonError::InvalidLut(msg) => write!(f, "invalid LUT: {}", msg),
+            PhotonError::InvalidPipeline(msg) => write!(f, "invalid pipeline: {}", msg),
+            PhotonError::Io(msg) => write!(f, "i/o error: {}", msg),
+            PhotonError::Steganography(msg) => write!(f, "steganography error: {}", msg),
+            PhotonError::Stitching(msg) => write!(f, "couldn't stitch frames: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PhotonError {}
+
+/// Convert a PhotonError into a JS error, so it can be thrown across the WASM boundary.
+impl From<PhotonError> for JsValue {
+    fn from(err: PhotonError) -> Self {
+        JsValue::from_str(&err.to_string())
+    }
+}
+
+/// A specialised `Result` type for Photon operations.
+pub type Result<T> = std::result::Result<T, PhotonError>;
@@ -0,0 +1,467 @@
+//! Feature detection, description and matching, and homography estimation, for aligning
+//! overlapping images.
+
+use crate::{helpers, PhotonImage};
+
+/// The number of pixels around the edge of an image in which features aren't detected,
+/// so that their descriptors fit within the image.
+pub(crate) const BORDER: usize = 8;
+
+/// A projective transform of the plane, as a row-major 3x3 matrix.
+pub(crate) type Homography = [f64; 9];
+
+/// The identity homography.
+pub(crate) const IDENTITY: Homography = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+/// The luma of each pixel of an image.
+pub(crate) fn gray(photon_image: &PhotonImage) -> Vec<f32> {
+    photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f32)
+        .collect()
+}
+
+/// Blur single-channel values with a 5-tap binomial kernel, clamping at the edges.
+pub(crate) fn blur(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    const KERNEL: [f32; 5] =
+        [1.0 / 16.0, 4.0 / 16.0, 6.0 / 16.0, 4.0 / 16.0, 1.0 / 16.0];
+    let clamp = |v: isize, max: usize| v.max(0).min(max as isize - 1) as usize;
+    let mut rows = vec![0.0; values.len()];
+    for y in 0..height {
+        for x in 0..width {
+            rows[y * width + x] = KERNEL
+                .iter()
+                .enumerate()
+                .map(|(k, w)| {
+                    w * values[y * width + clamp(x as isize + k as isize - 2, width)]
+                })
+                .sum();
+        }
+    }
+    let mut out = vec![0.0; values.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[y * width + x] = KERNEL
+                .iter()
+                .enumerate()
+                .map(|(k, w)| {
+                    w * rows[clamp(y as isize + k as isize - 2, height) * width + x]
+                })
+                .sum();
+        }
+    }
+    out
+}
+
+/// The Harris corner response of each pixel: high at corners, negative along edges, and
+/// near zero in flat regions.
+pub(crate) fn harris_response(gray: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let at = |x: isize, y: isize| {
+        let x = x.max(0).min(width as isize - 1) as usize;
+        let y = y.max(0).min(height as isize - 1) as usize;
+        gray[y * width + x]
+    };
+    let len = width * height;
+    let (mut xx, mut yy, mut xy) = (vec![0.0; len], vec![0.0; len], vec![0.0; len]);
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let gx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+            let gy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+            let i = y as usize * width + x as usize;
+            xx[i] = gx * gx;
+            yy[i] = gy * gy;
+            xy[i] = gx * gy;
+        }
+    }
+    let (xx, yy, xy) = (
+        blur(&xx, width, height),
+        blur(&yy, width, height),
+        blur(&xy, width, height),
+    );
+    (0..len)
+        .map(|i| {
+            let trace = xx[i] + yy[i];
+            xx[i] * yy[i] - xy[i] * xy[i] - 0.04 * trace * trace
+        })
+        .collect()
+}
+
+/// Find the pixels whose corner response is above `min_response`, and at least `border`
+/// pixels from the edges, strongest first.
+///
+/// With `non_max_suppression`, only the pixels whose response is greater than all of
+/// their neighbours' are kept, so each corner is only found once.
+pub(crate) fn peaks(
+    response: &[f32],
+    width: usize,
+    height: usize,
+    min_response: f32,
+    border: usize,
+    non_max_suppression: bool,
+) -> Vec<(usize, usize)> {
+    let border = border.max(1);
+    if width <= 2 * border || height <= 2 * border {
+        return vec![];
+    }
+    let mut corners = vec![];
+    for y in border..height - border {
+        for x in border..width - border {
+            let r = response[y * width + x];
+            if r <= min_response {
+                continue;
+            }
+            // Break ties between equal neighbours in favour of the first in raster order.
+            let is_max = !non_max_suppression
+                || (0..9).filter(|&n| n != 4).all(|n| {
+                    let (nx, ny) = (x + n % 3 - 1, y + n / 3 - 1);
+                    let other = response[ny * width + nx];
+                    if n < 4 {
+                        r > other
+                    } else {
+                        r >= other
+                    }
+                });
+            if is_max {
+                corners.push((r, x, y));
+            }
+        }
+    }
+    corners.sort_by(|a, b| b.0.total_cmp(&a.0));
+    corners.into_iter().map(|(_, x, y)| (x, y)).collect()
+}
+
+/// The FAST-9 corner score of each pixel: 0 unless at least 9 contiguous pixels of the
+/// circle of 16 around it are all brighter, or all darker, than it by more than
+/// `threshold`, and otherwise the total amount by which the circle's pixels exceed the
+/// threshold.
+pub(crate) fn fast_response(
+    gray: &[f32],
+    width: usize,
+    height: usize,
+    threshold: f32,
+) -> Vec<f32> {
+    const CIRCLE: [(isize, isize); 16] = [
+        (0, -3),
+        (1, -3),
+        (2, -2),
+        (3, -1),
+        (3, 0),
+        (3, 1),
+        (2, 2),
+        (1, 3),
+        (0, 3),
+        (-1, 3),
+        (-2, 2),
+        (-3, 1),
+        (-3, 0),
+        (-3, -1),
+        (-2, -2),
+        (-1, -3),
+    ];
+    let mut response = vec![0.0; width * height];
+    if width < 7 || height < 7 {
+        return response;
+    }
+    for y in 3..height - 3 {
+        for x in 3..width - 3 {
+            let centre = gray[y * width + x];
+            let mut differences = [0.0f32; 16];
+            for (d, &(dx, dy)) in differences.iter_mut().zip(CIRCLE.iter()) {
+                let i = (y as isize + dy) as usize * width + (x as isize + dx) as usize;
+                *d = gray[i] - centre;
+            }
+            // Look for an arc of 9, going round the circle twice so that arcs can wrap.
+            let has_arc = |brighter: bool| {
+                let mut run = 0;
+                for k in 0..32 {
+                    let d = differences[k % 16];
+                    let exceeds = if brighter {
+                        d > threshold
+                    } else {
+                        d < -threshold
+                    };
+                    run = if exceeds { run + 1 } else { 0 };
+                    if run >= 9 {
+                        return true;
+                    }
+                }
+                false
+            };
+            if has_arc(true) || has_arc(false) {
+                response[y * width + x] = differences
+                    .iter()
+                    .map(|d| (d.abs() - threshold).max(0.0))
+                    .sum();
+            }
+        }
+    }
+    response
+}
+
+/// Describe the neighbourhood of a feature, by sampling an 8x8 grid of a blurred image
+/// across the 15x15 window around it, normalised so that it doesn't depend on brightness
+/// or contrast.
+pub(crate) fn describe(blurred: &[f32], width: usize, x: usize, y: usize) -> [f32; 64] {
+    let mut descriptor = [0.0; 64];
+    for (i, value) in descriptor.iter_mut().enumerate() {
+        let sx = x + (i % 8) * 2 - 7;
+        let sy = y + (i / 8) * 2 - 7;
+        *value = blurred[sy * width + sx];
+    }
+    let mean = descriptor.iter().sum::<f32>() / 64.0;
+    let norm = descriptor
+        .iter()
+        .map(|v| (v - mean) * (v - mean))
+        .sum::<f32>()
+        .sqrt();
+    for value in descriptor.iter_mut() {
+        *value = if norm > 1e-3 {
+            (*value - mean) / norm
+        } else {
+            0.0
+        };
+    }
+    descriptor
+}
+
+/// Match descriptors, returning the pairs of indices of `a` and `b` whose best match is
+/// clearly better than their second best match (Lowe's ratio test), and which are each
+/// other's best match.
+pub(crate) fn match_descriptors(
+    a: &[[f32; 64]],
+    b: &[[f32; 64]],
+) -> Vec<(usize, usize)> {
+    let distance = |p: &[f32; 64], q: &[f32; 64]| -> f32 {
+        p.iter().zip(q.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+    };
+    let best = |p: &[f32; 64], candidates: &[[f32; 64]]| {
+        let mut first = (f32::INFINITY, 0);
+        let mut second = f32::INFINITY;
+        for (j, q) in candidates.iter().enumerate() {
+            let d = distance(p, q);
+            if d < first.0 {
+                second = first.0;
+                first = (d, j);
+            } else if d < second {
+                second = d;
+            }
+        }
+        (first.1, first.0 < 0.64 * second)
+    };
+    a.iter()
+        .enumerate()
+        .filter_map(|(i, p)| {
+            let (j, distinct) = best(p, b);
+            if distinct && !b.is_empty() && best(&b[j], a).0 == i {
+                Some((i, j))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Apply a homography to a point, returning `None` if it maps to infinity.
+pub(crate) fn transform(h: &Homography, x: f64, y: f64) -> Option<(f64, f64)> {
+    let w = h[6] * x + h[7] * y + h[8];
+    if w.abs() < 1e-12 {
+        return None;
+    }
+    Some((
+        (h[0] * x + h[1] * y + h[2]) / w,
+        (h[3] * x + h[4] * y + h[5]) / w,
+    ))
+}
+
+/// Multiply two homographies, so that the result applies `b` and then `a`.
+pub(crate) fn multiply(a: &Homography, b: &Homography) -> Homography {
+    let mut out = [0.0; 9];
+    for row in 0..3 {
+        for col in 0..3 {
+            out[row * 3 + col] = (0..3).map(|k| a[row * 3 + k] * b[k * 3 + col]).sum();
+        }
+    }
+    out
+}
+
+/// Invert a homography, returning `None` if it's singular.
+pub(crate) fn invert(h: &Homography) -> Option<Homography> {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+        h[r0 * 3 + c0] * h[r1 * 3 + c1] - h[r0 * 3 + c1] * h[r1 * 3 + c0]
+    };
+    let adjugate = [
+        cofactor(1, 2, 1, 2),
+        -cofactor(0, 2, 1, 2),
+        cofactor(0, 1, 1, 2),
+        -cofactor(1, 2, 0, 2),
+        cofactor(0, 2, 0, 2),
+        -cofactor(0, 1, 0, 2),
+        cofactor(1, 2, 0, 1),
+        -cofactor(0, 2, 0, 1),
+        cofactor(0, 1, 0, 1),
+    ];
+    let det = h[0] * adjugate[0] + h[1] * adjugate[3] + h[2] * adjugate[6];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut out = [0.0; 9];
+    for (o, a) in out.iter_mut().zip(adjugate.iter()) {
+        *o = a / det;
+    }
+    Some(out)
+}
+
+/// A similarity transform which moves the centroid of some points to the origin, and
+/// scales them to an average distance of √2 from it, for numerical stability.
+fn normalising_transform(points: &[(f64, f64)]) -> Homography {
+    let n = points.len() as f64;
+    let cx = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let cy = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let spread = points
+        .iter()
+        .map(|p| ((p.0 - cx).powi(2) + (p.1 - cy).powi(2)).sqrt())
+        .sum::<f64>()
+        / n;
+    let s = if spread > 1e-9 {
+        2f64.sqrt() / spread
+    } else {
+        1.0
+    };
+    [s, 0.0, -s * cx, 0.0, s, -s * cy, 0.0, 0.0, 1.0]
+}
+
+/// Fit the homography mapping each `from` point to its `to` point, by least squares.
+/// At least 4 pairs of points are needed.
+pub(crate) fn fit_homography(
+    from: &[(f64, f64)],
+    to: &[(f64, f64)],
+) -> Option<Homography> {
+    if from.len() < 4 || from.len() != to.len() {
+        return None;
+    }
+    let t_from = normalising_transform(from);
+    let t_to = normalising_transform(to);
+
+    // Build the normal equations for the 8 unknowns, with h[8] fixed at 1.
+    let mut ata = [[0.0f64; 8]; 8];
+    let mut atb = [0.0f64; 8];
+    for (p, q) in from.iter().zip(to) {
+        let (x, y) = transform(&t_from, p.0, p.1)?;
+        let (u, v) = transform(&t_to, q.0, q.1)?;
+        let rows = [
+            ([x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y], u),
+            ([0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y], v),
+        ];
+        for (row, b) in rows.iter() {
+            for (i, (ata_row, atb_value)) in ata.iter_mut().zip(&mut atb).enumerate() {
+                for (a, r) in ata_row.iter_mut().zip(row.iter()) {
+                    *a += row[i] * r;
+                }
+                *atb_value += row[i] * b;
+            }
+        }
+    }
+    let h = solve(ata, atb)?;
+    let normalised = [h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0];
+    let h = multiply(&invert(&t_to)?, &multiply(&normalised, &t_from));
+    if h[8].abs() < 1e-12 {
+        return None;
+    }
+    let mut out = [0.0; 9];
+    for (o, v) in out.iter_mut().zip(h.iter()) {
+        *o = v / h[8];
+    }
+    Some(out)
+}
+
+/// Solve a system of 8 linear equations, by Gaussian elimination with partial pivoting.
+fn solve(mut a: [[f64; 8]; 8], mut b: [f64; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot =
+            (col..8).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col];
+        for row in col + 1..8 {
+            let factor = a[row][col] / pivot_row[col];
+            for (value, pivot_value) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot_value;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; 8];
+    for row in (0..8).rev() {
+        let sum: f64 = (row + 1..8).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Robustly fit a homography to matched points, some of which may be wrong, with RANSAC.
+///
+/// Returns the homography, refitted to all of the inliers, and the number of inliers: the
+/// pairs which it maps to within `tolerance` pixels of each other.
+pub(crate) fn ransac_homography(
+    from: &[(f64, f64)],
+    to: &[(f64, f64)],
+    iterations: usize,
+    tolerance: f64,
+) -> Option<(Homography, usize)> {
+    if from.len() < 4 {
+        return None;
+    }
+    let inliers = |h: &Homography| -> Vec<usize> {
+        (0..from.len())
+            .filter(|&i| match transform(h, from[i].0, from[i].1) {
+                Some((x, y)) => {
+                    (x - to[i].0).powi(2) + (y - to[i].1).powi(2)
+                        <= tolerance * tolerance
+                }
+                None => false,
+            })
+            .collect()
+    };
+
+    // A fixed seed keeps the results reproducible.
+    let mut state: u64 = 0x853c_49e6_748f_ea9b;
+    let mut random = |n: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % n as u64) as usize
+    };
+
+    let mut best: Vec<usize> = vec![];
+    for _ in 0..iterations {
+        let mut sample = [0; 4];
+        for index in sample.iter_mut() {
+            *index = random(from.len());
+        }
+        if (1..4).any(|i| sample[..i].contains(&sample[i])) {
+            continue;
+        }
+        let sample_from: Vec<_> = sample.iter().map(|&i| from[i]).collect();
+        let sample_to: Vec<_> = sample.iter().map(|&i| to[i]).collect();
+        if let Some(h) = fit_homography(&sample_from, &sample_to) {
+            let candidate = inliers(&h);
+            if candidate.len() > best.len() {
+                best = candidate;
+            }
+        }
+    }
+    if best.len() < 4 {
+        return None;
+    }
+    let inlier_from: Vec<_> = best.iter().map(|&i| from[i]).collect();
+    let inlier_to: Vec<_> = best.iter().map(|&i| to[i]).collect();
+    let h = fit_homography(&inlier_from, &inlier_to)?;
+    let count = inliers(&h).len();
+    Some((h, count))
+}
@@ -0,0 +1,214 @@
+//! A C API, so that C, C++ and Swift applications can embed Photon.
+//!
+//! Images are created from raw RGBA buffers, and are owned by the caller until freed with
+//! `photon_image_free`. Operations are named, and take their parameters as a JSON object,
+//! using the same operations as the `pipeline` module, eg: `gaussian_blur` with
+//! `{"sigma": 2}`.
+//!
+//! Functions which can fail return 0 on success and -1 on failure, in which case
+//! `photon_last_error` describes what went wrong. Panics are caught rather than unwound
+//! into the caller, and are reported in the same way. A C header can be generated with
+//! `cbindgen --config cbindgen.toml --output photon.h`.
+//!
+//! ```c
+//! PhotonImage *img = photon_image_new(pixels, width * height * 4, width, height);
+//! if (photon_image_apply(img, "gaussian_blur", "{\"sigma\": 2}") != 0) {
+//!     fprintf(stderr, "%s\n", photon_last_error());
+//! }
+//! memcpy(out, photon_image_pixels(img), photon_image_len(img));
+//! photon_image_free(img);
+//! ```
+
+use crate::error::{PhotonError, Result};
+use crate::pipeline::{Operation, Pipeline};
+use crate::PhotonImage;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Create an image from a buffer of raw RGBA pixels, which is copied.
+///
+/// Returns null if `len` isn't `width * height * 4`.
+///
+/// # Safety
+/// `pixels` must point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_new(
+    pixels: *const u8,
+    len: usize,
+    width: u32,
+    height: u32,
+) -> *mut PhotonImage {
+    if pixels.is_null() || len as u64 != width as u64 * height as u64 * 4 {
+        set_last_error("pixel buffer doesn't match the image dimensions");
+        return ptr::null_mut();
+    }
+    let raw_pixels = slice::from_raw_parts(pixels, len).to_vec();
+    Box::into_raw(Box::new(PhotonImage::new(raw_pixels, width, height)))
+}
+
+/// Free an image created by `photon_image_new`. Passing null does nothing.
+///
+/// # Safety
+/// `img` must have been returned by `photon_image_new`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_free(img: *mut PhotonImage) {
+    if !img.is_null() {
+        drop(Box::from_raw(img));
+    }
+}
+
+/// Get the width of an image.
+///
+/// # Safety
+/// `img` must be a valid image.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_width(img: *const PhotonImage) -> u32 {
+    (*img).width
+}
+
+/// Get the height of an image.
+///
+/// # Safety
+/// `img` must be a valid image.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_height(img: *const PhotonImage) -> u32 {
+    (*img).height
+}
+
+/// Get a pointer to an image's raw RGBA pixels, which is valid until the image is next
+/// modified or freed.
+///
+/// # Safety
+/// `img` must be a valid image.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_pixels(img: *const PhotonImage) -> *const u8 {
+    (*img).raw_pixels.as_ptr()
+}
+
+/// Get the length in bytes of an image's raw RGBA pixels.
+///
+/// # Safety
+/// `img` must be a valid image.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_len(img: *const PhotonImage) -> usize {
+    (*img).raw_pixels.len()
+}
+
+/// Apply a named operation to an image, eg: "resize", "grayscale" or "filter".
+///
+/// `params` is a JSON object with the operation's parameters, eg: `{"w": 800}`, and may be
+/// null for operations without any. If the operation is unknown or its parameters are
+/// invalid, -1 is returned and the image is left unmodified. If the operation panics, -1 is
+/// also returned, but the image may have been partly modified.
+///
+/// # Safety
+/// `img` must be a valid image, and `op` and `params` (if not null) must be NUL-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_apply(
+    img: *mut PhotonImage,
+    op: *const c_char,
+    params: *const c_char,
+) -> c_int {
+    to_status(|| {
+        parse_operation(op, params).and_then(|operation| operation.apply(&mut *img))
+    })
+}
+
+/// Apply a JSON array of pipeline operations to an image, eg:
+/// `[{"op": "resize", "w": 800}, {"op": "sharpen"}]`.
+///
+/// If any operation is invalid, -1 is returned and the image is left unmodified. If an
+/// operation panics, -1 is also returned, but the image may have been partly modified.
+///
+/// # Safety
+/// `img` must be a valid image, and `spec` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn photon_image_apply_pipeline(
+    img: *mut PhotonImage,
+    spec: *const c_char,
+) -> c_int {
+    to_status(|| {
+        to_str(spec)
+            .and_then(Pipeline::from_json)
+            .and_then(|pipeline| pipeline.apply(&mut *img))
+    })
+}
+
+/// Get a description of the last error on this thread, or null if there hasn't been one.
+///
+/// The string is valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn photon_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |msg| msg.as_ptr())
+    })
+}
+
+unsafe fn parse_operation(
+    op: *const c_char,
+    params: *const c_char,
+) -> Result<Operation> {
+    let mut object = if params.is_null() {
+        serde_json::Map::new()
+    } else {
+        match serde_json::from_str(to_str(params)?) {
+            Ok(serde_json::Value::Object(object)) => object,
+            _ => {
+                return Err(PhotonError::InvalidPipeline(
+                    "params must be a JSON object".into(),
+                ))
+            }
+        }
+    };
+    object.insert("op".to_string(), to_str(op)?.into());
+    serde_json::from_value(serde_json::Value::Object(object))
+        .map_err(|err| PhotonError::InvalidPipeline(err.to_string()))
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(PhotonError::InvalidPipeline(
+            "unexpected null string".into(),
+        ));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| PhotonError::InvalidPipeline("strings must be UTF-8".into()))
+}
+
+/// Run `f`, converting its result to a status code. Unwinding across an `extern "C"`
+/// boundary is undefined behaviour, so a panic is caught and reported as an error.
+fn to_status<F: FnOnce() -> Result<()>>(f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => 0,
+        Ok(Err(err)) => {
+            set_last_error(&err.to_string());
+            -1
+        }
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            set_last_error(&format!("panicked: {}", msg));
+            -1
+        }
+    }
+}
+
+fn set_last_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', "")).expect("NUL bytes were removed");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+}
@@ -1,10 +1,13 @@
-//! Preset color filters.
+//! Preset color filters, and custom filters composed from simple adjustments.
 
 extern crate image;
 use crate::colour_spaces;
 use crate::colour_spaces::mix_with_colour;
-use crate::effects::{adjust_contrast, inc_brightness};
+use crate::effects::{adjust_contrast, devignette, inc_brightness};
 use crate::monochrome;
+#[cfg(feature = "noise")]
+use crate::noise;
+use crate::simd;
 use crate::{PhotonImage, Rgb};
 use wasm_bindgen::prelude::*;
 
@@ -48,15 +51,7 @@ pub fn neue(photon_image: &mut PhotonImage) {
 /// ```
 #[wasm_bindgen]
 pub fn lix(photon_image: &mut PhotonImage) {
-    let end = photon_image.get_raw_pixels().len() - 4;
-
-    for i in (0..end).step_by(4) {
-        let r_val = photon_image.raw_pixels[i];
-        let g_val = photon_image.raw_pixels[i + 1];
-
-        photon_image.raw_pixels[i] = 255 - r_val;
-        photon_image.raw_pixels[i + 1] = 255 - g_val;
-    }
+    simd::invert_channels(&mut photon_image.raw_pixels, [true, true, false]);
 }
 
 /// Solarization on the Red and Blue channels.
@@ -74,15 +69,7 @@ pub fn lix(photon_image: &mut PhotonImage) {
 /// ```
 #[wasm_bindgen]
 pub fn ryo(photon_image: &mut PhotonImage) {
-    let end = photon_image.get_raw_pixels().len() - 4;
-
-    for i in (0..end).step_by(4) {
-        let r_val = photon_image.raw_pixels[i];
-        let b_val = photon_image.raw_pixels[i + 2];
-
-        photon_image.raw_pixels[i] = 255 - r_val;
-        photon_image.raw_pixels[i + 2] = 255 - b_val;
-    }
+    simd::invert_channels(&mut photon_image.raw_pixels, [true, false, true]);
 }
 
 /// Apply a filter to an image. Over 20 filters are available.
@@ -117,6 +104,7 @@ pub fn ryo(photon_image: &mut PhotonImage) {
 /// ```
 #[wasm_bindgen]
 pub fn filter(img: &mut PhotonImage, filter_name: &str) {
+    // Keep `FILTER_NAMES` in sync with the names matched below.
     let oceanic_rgb = Rgb::new(0, 89, 173);
     let islands_rgb = Rgb::new(0, 24, 95);
     let marine_rgb = Rgb::new(0, 14, 119);
@@ -156,6 +144,77 @@ pub fn filter(img: &mut PhotonImage, filter_name: &str) {
     };
 }
 
+/// Apply a filter to an image at a given strength, by blending the filtered image with
+/// the original.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `filter_name` - The filter's name, as accepted by `filter`, eg: "oceanic".
+/// * `amount` - How strongly to apply the filter, from 0 (leaving the image unchanged) to
+/// 1 (the same as `filter`). Values outside of this range are clamped.
+/// # Example
+///
+/// ```
+/// // For example, to add a filter called "vintage" to an image at half strength:
+/// use photon_rs::filters::filter_with_strength;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// filter_with_strength(&mut img, "vintage", 0.5);
+/// ```
+#[wasm_bindgen]
+pub fn filter_with_strength(img: &mut PhotonImage, filter_name: &str, amount: f32) {
+    with_strength(img, amount, |img| filter(img, filter_name));
+}
+
+/// Apply an effect to an image, then blend the result with the original, by an `amount`
+/// from 0 (leaving the image unchanged) to 1 (the effect's full result).
+fn with_strength<F: FnOnce(&mut PhotonImage)>(
+    img: &mut PhotonImage,
+    amount: f32,
+    effect: F,
+) {
+    let amount = amount.clamp(0.0, 1.0);
+    if amount == 0.0 {
+        return;
+    }
+    let original = img.raw_pixels.clone();
+    effect(img);
+    if amount == 1.0 {
+        return;
+    }
+    for (filtered, &before) in img.raw_pixels.iter_mut().zip(&original) {
+        let mixed = before as f32 + (*filtered as f32 - before as f32) * amount;
+        *filtered = mixed.round() as u8;
+    }
+}
+
+/// The names of the filters which `filter` accepts.
+pub(crate) const FILTER_NAMES: &[&str] = &[
+    "oceanic",
+    "islands",
+    "marine",
+    "seagreen",
+    "flagblue",
+    "diamante",
+    "liquid",
+    "radio",
+    "twenties",
+    "rosetint",
+    "mauve",
+    "bluechrome",
+    "vintage",
+    "perfume",
+    "serenity",
+    "golden",
+    "pastel_pink",
+    "cali",
+    "dramatic",
+    "firenze",
+    "obsidian",
+    "lofi",
+];
+
 /// Apply a lofi effect to an image.
 ///
 /// # Arguments
@@ -290,3 +349,326 @@ pub fn obsidian(img: &mut PhotonImage) {
     monochrome::grayscale(img);
     adjust_contrast(img, 25.0);
 }
+
+/// One step of a custom filter.
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    AlterChannels(i16, i16, i16),
+    /// A lookup table mapping each value to its new value, and which of the red, green and
+    /// blue channels it's applied to.
+    Curve([bool; 3], Vec<u8>),
+    MixColour((u8, u8, u8), f32),
+    /// A 3x3 matrix, in row-major order, by which each pixel's red, green and blue values
+    /// are multiplied.
+    ColourMatrix([f32; 9]),
+    Contrast(f32),
+    Saturation(f32),
+    Vignette(f32),
+    #[cfg(feature = "noise")]
+    Grain(f32, f32, u64),
+}
+
+/// A named filter, composed of adjustments which are applied in order. Create one with
+/// `FilterBuilder`, and apply it with `apply`.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomFilter {
+    name: String,
+    steps: Vec<Step>,
+}
+
+#[wasm_bindgen]
+impl CustomFilter {
+    /// Get the name of the filter.
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Build a `CustomFilter` from a sequence of adjustments, so that new looks can be defined
+/// without adding them to `filter`.
+///
+/// # Example
+/// ```
+/// use photon_rs::filters::{apply, FilterBuilder};
+/// use photon_rs::PhotonImage;
+///
+/// let faded = FilterBuilder::new("faded")
+///     .curves(&[0, 30, 255, 230])
+///     .alter_channels(10, 0, -10)
+///     .vignette(0.4)
+///     .build();
+///
+/// let mut img = PhotonImage::new(vec![120; 16 * 16 * 4], 16, 16);
+/// apply(&mut img, &faded);
+/// assert_eq!(faded.get_name(), "faded");
+/// ```
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterBuilder {
+    filter: CustomFilter,
+}
+
+#[wasm_bindgen]
+impl FilterBuilder {
+    #[wasm_bindgen(constructor)]
+    /// Start building a filter with no adjustments.
+    pub fn new(name: &str) -> FilterBuilder {
+        FilterBuilder {
+            filter: CustomFilter {
+                name: name.to_string(),
+                steps: vec![],
+            },
+        }
+    }
+
+    /// Add amounts between -255 and 255 to the red, green and blue channels, as with
+    /// `channels::alter_channels`.
+    pub fn alter_channels(self, r_amt: i16, g_amt: i16, b_amt: i16) -> FilterBuilder {
+        self.then(Step::AlterChannels(r_amt, g_amt, b_amt))
+    }
+
+    /// Apply a tone curve to the red, green and blue channels.
+    ///
+    /// The curve is given by its control points, as `[in0, out0, in1, out1, ...]`, which
+    /// are joined by straight lines. Values below the first point or above the last are
+    /// mapped to the first or last point's output, and an empty curve changes nothing.
+    pub fn curves(self, points: &[u8]) -> FilterBuilder {
+        self.then(Step::Curve([true; 3], curve_lut(points)))
+    }
+
+    /// Apply a tone curve to a single channel, given as for `curves`.
+    ///
+    /// # Panics
+    /// Panics if `channel` isn't 0 (red), 1 (green) or 2 (blue).
+    pub fn channel_curve(self, channel: usize, points: &[u8]) -> FilterBuilder {
+        if channel > 2 {
+            panic!("Invalid channel index passed. Channel must be 0, 1, or 2 (Red=0, Green=1, Blue=2)");
+        }
+        let mut channels = [false; 3];
+        channels[channel] = true;
+        self.then(Step::Curve(channels, curve_lut(points)))
+    }
+
+    /// Mix the image with a colour, with an `opacity` from 0 to 1, as with
+    /// `colour_spaces::mix_with_colour`.
+    pub fn mix_colour(self, r: u8, g: u8, b: u8, opacity: f32) -> FilterBuilder {
+        self.then(Step::MixColour((r, g, b), opacity))
+    }
+
+    /// Multiply each pixel's red, green and blue values by a 3x3 matrix, given in row-major
+    /// order, so that each output channel is a weighted sum of the input channels. The
+    /// identity matrix, `[1, 0, 0, 0, 1, 0, 0, 0, 1]`, changes nothing.
+    ///
+    /// # Panics
+    /// Panics if `matrix` doesn't have 9 elements.
+    pub fn colour_matrix(self, matrix: &[f32]) -> FilterBuilder {
+        if matrix.len() != 9 {
+            panic!(
+                "A colour matrix must have 9 elements, not {}.",
+                matrix.len()
+            );
+        }
+        let mut elements = [0.0; 9];
+        elements.copy_from_slice(matrix);
+        self.then(Step::ColourMatrix(elements))
+    }
+
+    /// Adjust the contrast by an amount between -255 and 255.
+    pub fn contrast(self, contrast: f32) -> FilterBuilder {
+        self.then(Step::Contrast(contrast))
+    }
+
+    /// Saturate (positive) or desaturate (negative) by an amount between -1 and 1.
+    pub fn saturation(self, level: f32) -> FilterBuilder {
+        self.then(Step::Saturation(level))
+    }
+
+    /// Darken the corners: 0.5 darkens the very corners by half.
+    pub fn vignette(self, strength: f32) -> FilterBuilder {
+        self.then(Step::Vignette(strength))
+    }
+
+    /// Add film grain, as with `noise::film_grain`.
+    #[cfg(feature = "noise")]
+    pub fn grain(self, intensity: f32, size: f32, seed: u64) -> FilterBuilder {
+        self.then(Step::Grain(intensity, size, seed))
+    }
+
+    /// Finish building the filter.
+    pub fn build(self) -> CustomFilter {
+        self.filter
+    }
+}
+
+impl FilterBuilder {
+    fn then(mut self, step: Step) -> FilterBuilder {
+        self.filter.steps.push(step);
+        self
+    }
+}
+
+/// A lookup table for the curve through `points`, given as `[in0, out0, in1, out1, ...]`.
+fn curve_lut(points: &[u8]) -> Vec<u8> {
+    let mut points: Vec<(u8, u8)> =
+        points.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+    points.sort_by_key(|&(input, _)| input);
+    points.dedup_by_key(|&mut (input, _)| input);
+    if points.is_empty() {
+        return (0..=255).collect();
+    }
+    (0..=255u8)
+        .map(|v| {
+            let next = points.iter().position(|&(input, _)| input >= v);
+            match next {
+                None => points[points.len() - 1].1,
+                Some(0) => points[0].1,
+                Some(i) => {
+                    let (x0, y0) = (points[i - 1].0 as f32, points[i - 1].1 as f32);
+                    let (x1, y1) = (points[i].0 as f32, points[i].1 as f32);
+                    let t = (v as f32 - x0) / (x1 - x0);
+                    (y0 + (y1 - y0) * t).round() as u8
+                }
+            }
+        })
+        .collect()
+}
+
+/// Apply a custom filter to an image.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `filter` - The filter, created with `FilterBuilder`.
+/// # Example
+///
+/// ```
+/// use photon_rs::filters::{apply, FilterBuilder};
+/// use photon_rs::native::open_image;
+///
+/// let warm = FilterBuilder::new("warm")
+///     .alter_channels(20, 5, -15)
+///     .contrast(15.0)
+///     .build();
+/// let mut img = open_image("img.jpg");
+/// apply(&mut img, &warm);
+/// ```
+#[wasm_bindgen]
+pub fn apply(img: &mut PhotonImage, filter: &CustomFilter) {
+    for step in &filter.steps {
+        match step {
+            Step::AlterChannels(r, g, b) => {
+                simd::add_rgb(&mut img.raw_pixels, [*r, *g, *b]);
+            }
+            Step::Curve(channels, lut) => {
+                for px in img.raw_pixels.chunks_exact_mut(4) {
+                    for c in (0..3).filter(|&c| channels[c]) {
+                        px[c] = lut[px[c] as usize];
+                    }
+                }
+            }
+            Step::MixColour((r, g, b), opacity) => {
+                mix_with_colour(img, Rgb::new(*r, *g, *b), *opacity);
+            }
+            Step::ColourMatrix(m) => {
+                for px in img.raw_pixels.chunks_exact_mut(4) {
+                    let (r, g, b) = (px[0] as f32, px[1] as f32, px[2] as f32);
+                    for (c, row) in m.chunks_exact(3).enumerate() {
+                        let value = row[0] * r + row[1] * g + row[2] * b;
+                        px[c] = value.round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+            Step::Contrast(contrast) => adjust_contrast(img, *contrast),
+            Step::Saturation(level) => colour_spaces::saturate_hsl(img, *level),
+            Step::Vignette(strength) => devignette(img, -strength),
+            #[cfg(feature = "noise")]
+            Step::Grain(intensity, size, seed) => {
+                noise::film_grain(img, *intensity, *size, true, *seed);
+            }
+        }
+    }
+}
+
+/// A film stock, whose look `film` emulates.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilmStock {
+    /// Kodak Kodachrome: rich, warm reds, deep shadows and high contrast.
+    Kodachrome,
+    /// Fujifilm Velvia: intensely saturated, with vivid greens and blues.
+    Velvia,
+    /// Kodak Portra: soft contrast, lifted shadows and warm, natural skin tones.
+    Portra,
+    /// Kodak Ektar: vivid colour with fine grain.
+    Ektar,
+    /// Kodak Tri-X: punchy black and white, with coarse grain.
+    TriX,
+}
+
+/// The filter which emulates a film stock.
+///
+/// # Arguments
+/// * `stock` - The film stock.
+/// # Example
+///
+/// ```
+/// use photon_rs::filters::{film_stock, FilmStock};
+///
+/// let portra = film_stock(FilmStock::Portra);
+/// assert_eq!(portra.get_name(), "portra");
+/// ```
+#[wasm_bindgen]
+pub fn film_stock(stock: FilmStock) -> CustomFilter {
+    let builder = match stock {
+        FilmStock::Kodachrome => FilterBuilder::new("kodachrome")
+            .colour_matrix(&[1.15, -0.1, -0.05, -0.05, 1.1, -0.05, -0.05, -0.15, 1.2])
+            .curves(&[0, 8, 64, 52, 192, 206, 255, 250]),
+        FilmStock::Velvia => FilterBuilder::new("velvia")
+            .colour_matrix(&[1.05, -0.05, 0.0, -0.05, 1.15, -0.1, 0.0, -0.1, 1.1])
+            .saturation(0.25)
+            .curves(&[0, 0, 64, 50, 192, 210, 255, 255]),
+        FilmStock::Portra => FilterBuilder::new("portra")
+            .colour_matrix(&[1.05, 0.02, -0.02, 0.0, 1.0, 0.0, -0.03, 0.0, 0.95])
+            .saturation(-0.1)
+            .curves(&[0, 18, 128, 132, 255, 245]),
+        FilmStock::Ektar => FilterBuilder::new("ektar")
+            .colour_matrix(&[1.1, -0.05, -0.05, -0.05, 1.1, -0.05, -0.05, -0.05, 1.1])
+            .saturation(0.15)
+            .curves(&[0, 4, 64, 58, 192, 200, 255, 252]),
+        FilmStock::TriX => FilterBuilder::new("tri-x")
+            .colour_matrix(&[0.3, 0.59, 0.11, 0.3, 0.59, 0.11, 0.3, 0.59, 0.11])
+            .curves(&[0, 6, 64, 48, 192, 214, 255, 250]),
+    };
+    #[cfg(feature = "noise")]
+    let builder = match stock {
+        FilmStock::Kodachrome => builder.grain(0.03, 1.0, 0),
+        FilmStock::Velvia => builder.grain(0.02, 1.0, 0),
+        FilmStock::Portra => builder.grain(0.04, 1.5, 0),
+        FilmStock::Ektar => builder.grain(0.015, 1.0, 0),
+        FilmStock::TriX => builder.grain(0.08, 1.5, 0),
+    };
+    builder.build()
+}
+
+/// Emulate the look of a film stock, by applying its tone curve and colour response, and
+/// adding its grain when the `noise` feature is enabled.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `stock` - The film stock.
+/// * `strength` - How strongly to apply the look, from 0 (leaving the image unchanged) to
+/// 1 (the full look).
+/// # Example
+///
+/// ```
+/// use photon_rs::filters::{film, FilmStock};
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// film(&mut img, FilmStock::Kodachrome, 0.8);
+/// ```
+#[wasm_bindgen]
+pub fn film(img: &mut PhotonImage, stock: FilmStock, strength: f32) {
+    let filter = film_stock(stock);
+    with_strength(img, strength, |img| apply(img, &filter));
+}
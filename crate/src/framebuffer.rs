@@ -0,0 +1,367 @@
+//! Convert to and from the framebuffer layouts used by desktop GUI libraries, so effects
+//! can be run on live preview buffers.
+//!
+//! Framebuffers may pad each row, so every function takes a `stride`: the distance from
+//! the start of one row to the start of the next, in bytes for byte buffers, and in pixels
+//! for `u16` and `u32` buffers. When rows aren't padded, the stride is `width * 4` and
+//! `width` respectively.
+//!
+//! - `minifb` and `softbuffer` use `u32` buffers in the `Xrgb` layout.
+//! - `egui`'s `ColorImage::from_rgba_unmultiplied` takes `Rgba` bytes.
+//! - Windows DIBs and most capture APIs use `Bgra` bytes.
+//! - Many embedded displays use `u16` buffers in the `Rgb565` layout.
+//!
+//! The `copy_from_*` and `write_*` functions reuse existing buffers, so nothing is
+//! allocated per frame.
+//!
+//! # Example
+//! ```
+//! use photon_rs::framebuffer::{copy_from_u32, write_u32, U32Layout};
+//! use photon_rs::PhotonImage;
+//!
+//! // A 2x2 minifb window buffer.
+//! let mut buffer: Vec<u32> = vec![0x00ff8000; 4];
+//! let mut img = PhotonImage::new(vec![0; 16], 2, 2);
+//!
+//! // Every frame:
+//! copy_from_u32(&mut img, &buffer, 2, U32Layout::Xrgb);
+//! photon_rs::channels::invert(&mut img);
+//! write_u32(&img, &mut buffer, 2, U32Layout::Xrgb);
+//! ```
+
+use crate::PhotonImage;
+
+/// The order of the four bytes of each pixel in a byte buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteLayout {
+    Rgba,
+    Bgra,
+    Argb,
+    Abgr,
+}
+
+impl ByteLayout {
+    /// The positions of the red, green, blue and alpha bytes within a pixel.
+    fn offsets(self) -> [usize; 4] {
+        match self {
+            ByteLayout::Rgba => [0, 1, 2, 3],
+            ByteLayout::Bgra => [2, 1, 0, 3],
+            ByteLayout::Argb => [1, 2, 3, 0],
+            ByteLayout::Abgr => [3, 2, 1, 0],
+        }
+    }
+}
+
+/// How each pixel is packed into a `u32`, from the most to the least significant byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum U32Layout {
+    /// `0x00RRGGBB`, with the top byte ignored, and written as 0. Used by `minifb` and
+    /// `softbuffer`.
+    Xrgb,
+    /// `0xAARRGGBB`. Cairo's `ARGB32` uses this packing with premultiplied alpha, so
+    /// `alpha::unpremultiply` such buffers after reading them, and `alpha::premultiply`
+    /// images before writing them.
+    Argb,
+    /// `0xAABBGGRR`, which is the `Rgba` byte layout read as a little-endian `u32`.
+    Abgr,
+}
+
+/// How each pixel is packed into a `u16`, from the most to the least significant bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum U16Layout {
+    /// 5 bits of red, 6 of green and 5 of blue.
+    Rgb565,
+    /// 5 bits of blue, 6 of green and 5 of red.
+    Bgr565,
+}
+
+/// Create a PhotonImage from a byte framebuffer.
+///
+/// # Arguments
+/// * `data` - The framebuffer.
+/// * `width` - The width of the framebuffer, in pixels.
+/// * `height` - The height of the framebuffer, in pixels.
+/// * `stride` - The number of bytes from the start of one row to the start of the next.
+/// * `layout` - The order of the bytes of each pixel.
+pub fn from_bytes(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    layout: ByteLayout,
+) -> PhotonImage {
+    let mut photon_image =
+        PhotonImage::new(vec![0; width as usize * height as usize * 4], width, height);
+    copy_from_bytes(&mut photon_image, data, stride, layout);
+    photon_image
+}
+
+/// Copy a byte framebuffer, of the same size as the image, into the image.
+///
+/// # Panics
+/// Panics if the framebuffer is too small for the image's size and the stride.
+pub fn copy_from_bytes(
+    photon_image: &mut PhotonImage,
+    data: &[u8],
+    stride: usize,
+    layout: ByteLayout,
+) {
+    let row_len = check_stride(photon_image, data.len(), stride, 4);
+    let [r, g, b, a] = layout.offsets();
+    for (row, src) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(row_len)
+        .zip(data.chunks(stride))
+    {
+        for (px, src) in row.chunks_exact_mut(4).zip(src[..row_len].chunks_exact(4)) {
+            px.copy_from_slice(&[src[r], src[g], src[b], src[a]]);
+        }
+    }
+}
+
+/// Write an image into a byte framebuffer of the same size.
+///
+/// Any padding at the end of each row is left unchanged.
+///
+/// # Panics
+/// Panics if the framebuffer is too small for the image's size and the stride.
+pub fn write_bytes(
+    photon_image: &PhotonImage,
+    out: &mut [u8],
+    stride: usize,
+    layout: ByteLayout,
+) {
+    let row_len = check_stride(photon_image, out.len(), stride, 4);
+    let [r, g, b, a] = layout.offsets();
+    for (row, dst) in photon_image
+        .raw_pixels
+        .chunks_exact(row_len)
+        .zip(out.chunks_mut(stride))
+    {
+        for (px, dst) in row.chunks_exact(4).zip(dst[..row_len].chunks_exact_mut(4)) {
+            dst[r] = px[0];
+            dst[g] = px[1];
+            dst[b] = px[2];
+            dst[a] = px[3];
+        }
+    }
+}
+
+/// Convert an image to a new, unpadded byte framebuffer.
+pub fn to_bytes(photon_image: &PhotonImage, layout: ByteLayout) -> Vec<u8> {
+    let mut out = vec![0; photon_image.raw_pixels.len()];
+    write_bytes(
+        photon_image,
+        &mut out,
+        photon_image.width as usize * 4,
+        layout,
+    );
+    out
+}
+
+/// Create a PhotonImage from a `u32` framebuffer.
+///
+/// # Arguments
+/// * `data` - The framebuffer.
+/// * `width` - The width of the framebuffer, in pixels.
+/// * `height` - The height of the framebuffer, in pixels.
+/// * `stride` - The number of pixels from the start of one row to the start of the next.
+/// * `layout` - How each pixel is packed into a `u32`.
+pub fn from_u32(
+    data: &[u32],
+    width: u32,
+    height: u32,
+    stride: usize,
+    layout: U32Layout,
+) -> PhotonImage {
+    let mut photon_image =
+        PhotonImage::new(vec![0; width as usize * height as usize * 4], width, height);
+    copy_from_u32(&mut photon_image, data, stride, layout);
+    photon_image
+}
+
+/// Copy a `u32` framebuffer, of the same size as the image, into the image.
+///
+/// # Panics
+/// Panics if the framebuffer is too small for the image's size and the stride.
+pub fn copy_from_u32(
+    photon_image: &mut PhotonImage,
+    data: &[u32],
+    stride: usize,
+    layout: U32Layout,
+) {
+    let row_len = check_stride(photon_image, data.len(), stride, 1);
+    let width = photon_image.width as usize;
+    for (row, src) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(row_len * 4)
+        .zip(data.chunks(stride))
+    {
+        for (px, &packed) in row.chunks_exact_mut(4).zip(&src[..width]) {
+            let [b0, b1, b2, b3] = packed.to_be_bytes();
+            let rgba = match layout {
+                U32Layout::Xrgb => [b1, b2, b3, 255],
+                U32Layout::Argb => [b1, b2, b3, b0],
+                U32Layout::Abgr => [b3, b2, b1, b0],
+            };
+            px.copy_from_slice(&rgba);
+        }
+    }
+}
+
+/// Write an image into a `u32` framebuffer of the same size.
+///
+/// Any padding at the end of each row is left unchanged.
+///
+/// # Panics
+/// Panics if the framebuffer is too small for the image's size and the stride.
+pub fn write_u32(
+    photon_image: &PhotonImage,
+    out: &mut [u32],
+    stride: usize,
+    layout: U32Layout,
+) {
+    let row_len = check_stride(photon_image, out.len(), stride, 1);
+    let width = photon_image.width as usize;
+    for (row, dst) in photon_image
+        .raw_pixels
+        .chunks_exact(row_len * 4)
+        .zip(out.chunks_mut(stride))
+    {
+        for (px, packed) in row.chunks_exact(4).zip(&mut dst[..width]) {
+            let bytes = match layout {
+                U32Layout::Xrgb => [0, px[0], px[1], px[2]],
+                U32Layout::Argb => [px[3], px[0], px[1], px[2]],
+                U32Layout::Abgr => [px[3], px[2], px[1], px[0]],
+            };
+            *packed = u32::from_be_bytes(bytes);
+        }
+    }
+}
+
+/// Convert an image to a new, unpadded `u32` framebuffer.
+pub fn to_u32(photon_image: &PhotonImage, layout: U32Layout) -> Vec<u32> {
+    let mut out = vec![0; photon_image.raw_pixels.len() / 4];
+    write_u32(photon_image, &mut out, photon_image.width as usize, layout);
+    out
+}
+
+/// Create an opaque PhotonImage from a `u16` framebuffer.
+///
+/// # Arguments
+/// * `data` - The framebuffer.
+/// * `width` - The width of the framebuffer, in pixels.
+/// * `height` - The height of the framebuffer, in pixels.
+/// * `stride` - The number of pixels from the start of one row to the start of the next.
+/// * `layout` - How each pixel is packed into a `u16`.
+pub fn from_u16(
+    data: &[u16],
+    width: u32,
+    height: u32,
+    stride: usize,
+    layout: U16Layout,
+) -> PhotonImage {
+    let mut photon_image =
+        PhotonImage::new(vec![0; width as usize * height as usize * 4], width, height);
+    copy_from_u16(&mut photon_image, data, stride, layout);
+    photon_image
+}
+
+/// Copy a `u16` framebuffer, of the same size as the image, into the image, making it
+/// opaque. Each channel is scaled to the full range of 0 to 255, so that white stays
+/// white.
+///
+/// # Panics
+/// Panics if the framebuffer is too small for the image's size and the stride.
+pub fn copy_from_u16(
+    photon_image: &mut PhotonImage,
+    data: &[u16],
+    stride: usize,
+    layout: U16Layout,
+) {
+    let row_len = check_stride(photon_image, data.len(), stride, 1);
+    let width = photon_image.width as usize;
+    for (row, src) in photon_image
+        .raw_pixels
+        .chunks_exact_mut(row_len * 4)
+        .zip(data.chunks(stride))
+    {
+        for (px, &packed) in row.chunks_exact_mut(4).zip(&src[..width]) {
+            let (high, g, low) = (packed >> 11, (packed >> 5) & 0x3f, packed & 0x1f);
+            let (r, b) = match layout {
+                U16Layout::Rgb565 => (high, low),
+                U16Layout::Bgr565 => (low, high),
+            };
+            px.copy_from_slice(&[
+                ((r << 3) | (r >> 2)) as u8,
+                ((g << 2) | (g >> 4)) as u8,
+                ((b << 3) | (b >> 2)) as u8,
+                255,
+            ]);
+        }
+    }
+}
+
+/// Write an image into a `u16` framebuffer of the same size, rounding each channel to the
+/// nearest level, and ignoring alpha.
+///
+/// Any padding at the end of each row is left unchanged.
+///
+/// # Panics
+/// Panics if the framebuffer is too small for the image's size and the stride.
+pub fn write_u16(
+    photon_image: &PhotonImage,
+    out: &mut [u16],
+    stride: usize,
+    layout: U16Layout,
+) {
+    let row_len = check_stride(photon_image, out.len(), stride, 1);
+    let width = photon_image.width as usize;
+    let level = |v: u8, max: u16| (v as u16 * max + 127) / 255;
+    for (row, dst) in photon_image
+        .raw_pixels
+        .chunks_exact(row_len * 4)
+        .zip(out.chunks_mut(stride))
+    {
+        for (px, packed) in row.chunks_exact(4).zip(&mut dst[..width]) {
+            let (high, low) = match layout {
+                U16Layout::Rgb565 => (px[0], px[2]),
+                U16Layout::Bgr565 => (px[2], px[0]),
+            };
+            *packed = level(high, 31) << 11 | level(px[1], 63) << 5 | level(low, 31);
+        }
+    }
+}
+
+/// Convert an image to a new, unpadded `u16` framebuffer.
+pub fn to_u16(photon_image: &PhotonImage, layout: U16Layout) -> Vec<u16> {
+    let mut out = vec![0; photon_image.raw_pixels.len() / 4];
+    write_u16(photon_image, &mut out, photon_image.width as usize, layout);
+    out
+}
+
+/// Check that a framebuffer of `len` units, with rows `stride` units apart, holds the
+/// image, where each pixel takes `units_per_pixel` units. Returns the length of a row of
+/// the image, in units.
+fn check_stride(
+    photon_image: &PhotonImage,
+    len: usize,
+    stride: usize,
+    units_per_pixel: usize,
+) -> usize {
+    let row_len = photon_image.width as usize * units_per_pixel;
+    let height = photon_image.height as usize;
+    if row_len == 0 {
+        panic!("Can't convert an image with a width of 0.");
+    }
+    if stride < row_len {
+        panic!(
+            "Stride {} is smaller than a row of {} units.",
+            stride, row_len
+        );
+    }
+    if height > 0 && len < stride * (height - 1) + row_len {
+        panic!("Framebuffer of {} units is too small for the image.", len);
+    }
+    row_len
+}
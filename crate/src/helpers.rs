@@ -2,6 +2,10 @@
 
 extern crate base64;
 extern crate image;
+#[cfg(feature = "framebuffer")]
+use crate::framebuffer::{self, ByteLayout, U16Layout, U32Layout};
+#[cfg(any(feature = "analysis", feature = "drawing"))]
+use crate::Connectivity;
 use crate::{PhotonImage, Rgb};
 use image::{DynamicImage, ImageBuffer};
 extern crate wasm_bindgen;
@@ -49,3 +53,248 @@ pub fn dyn_image_from_raw(photon_image: &PhotonImage) -> DynamicImage {
     .unwrap();
     image::ImageRgba8(img_buffer)
 }
+
+/// The Rec. 601 luma (perceived brightness) of an RGBA pixel.
+pub(crate) fn luma(px: &[u8]) -> u8 {
+    let luma = 0.299 * px[0] as f32 + 0.587 * px[1] as f32 + 0.114 * px[2] as f32;
+    luma.round().min(255.0) as u8
+}
+
+/// Convert the RGB channels of a pixel to full range BT.601 YCbCr, between 0 and 1 for
+/// luma, and -0.5 and 0.5 for chroma.
+#[cfg(any(feature = "conv", feature = "effects"))]
+pub(crate) fn to_ycbcr(px: &[u8]) -> (f32, f32, f32) {
+    let channel = |c: usize| px[c] as f32 / 255.0;
+    let (r, g, b) = (channel(0), channel(1), channel(2));
+    (
+        0.299 * r + 0.587 * g + 0.114 * b,
+        -0.168_736 * r - 0.331_264 * g + 0.5 * b,
+        0.5 * r - 0.418_688 * g - 0.081_312 * b,
+    )
+}
+
+/// Convert full range BT.601 YCbCr back to RGB.
+#[cfg(any(feature = "conv", feature = "effects"))]
+pub(crate) fn from_ycbcr(y: f32, cb: f32, cr: f32) -> [u8; 3] {
+    let channel = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    [
+        channel(y + 1.402 * cr),
+        channel(y - 0.344_136 * cb - 0.714_136 * cr),
+        channel(y + 1.772 * cb),
+    ]
+}
+
+/// Sample a pixel of an image at a fractional position, which must be within the image,
+/// with bilinear interpolation.
+pub(crate) fn sample_bilinear(photon_image: &PhotonImage, x: f64, y: f64) -> [u8; 4] {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let at = |x: usize, y: usize, c: usize| {
+        photon_image.raw_pixels[(y * width + x) * 4 + c] as f64
+    };
+    let mut out = [0; 4];
+    for (c, value) in out.iter_mut().enumerate() {
+        let top = at(x0, y0, c) * (1.0 - fx) + at(x1, y0, c) * fx;
+        let bottom = at(x0, y1, c) * (1.0 - fx) + at(x1, y1, c) * fx;
+        *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    out
+}
+
+/// Find the contiguous region of pixels around `(x, y)` whose channels are all within
+/// `tolerance` of that pixel's, returning whether each pixel of the image is in it.
+///
+/// # Panics
+/// Panics if `(x, y)` is outside of the image.
+#[cfg(any(feature = "analysis", feature = "drawing"))]
+pub(crate) fn similar_region(
+    photon_image: &PhotonImage,
+    x: u32,
+    y: u32,
+    tolerance: u8,
+    connectivity: Connectivity,
+) -> Vec<bool> {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    if x as usize >= width || y as usize >= height {
+        panic!(
+            "({}, {}) is outside of the {}x{} image.",
+            x, y, width, height
+        );
+    }
+    let pixels = &photon_image.raw_pixels;
+    let start = y as usize * width + x as usize;
+    let seed = &pixels[start * 4..start * 4 + 4];
+    let similar = |i: usize| {
+        pixels[i * 4..i * 4 + 4]
+            .iter()
+            .zip(seed)
+            .all(|(&a, &b)| (a as i16 - b as i16).abs() <= tolerance as i16)
+    };
+    let offsets: &[(isize, isize)] = match connectivity {
+        Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+        Connectivity::Eight => &[
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ],
+    };
+
+    let mut region = vec![false; width * height];
+    region[start] = true;
+    let mut stack = vec![start];
+    while let Some(i) = stack.pop() {
+        let (px, py) = ((i % width) as isize, (i / width) as isize);
+        for &(dx, dy) in offsets {
+            let (nx, ny) = (px + dx, py + dy);
+            if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                continue;
+            }
+            let n = ny as usize * width + nx as usize;
+            if !region[n] && similar(n) {
+                region[n] = true;
+                stack.push(n);
+            }
+        }
+    }
+    region
+}
+
+/// Swap the red and blue bytes of each 4-byte pixel, converting RGBA to BGRA, or BGRA to
+/// RGBA, in place. Any bytes after the last whole pixel are left unchanged.
+#[cfg(feature = "framebuffer")]
+pub fn swap_red_blue(pixels: &mut [u8]) {
+    if let Some(photon_image) = pixel_row(pixels) {
+        let row = &mut pixels[..photon_image.raw_pixels.len()];
+        let stride = row.len();
+        framebuffer::write_bytes(&photon_image, row, stride, ByteLayout::Bgra);
+    }
+}
+
+/// Copy the rows of a buffer whose rows are padded to `stride` bytes apart, eg: by a
+/// capture API or a GPU, into a new buffer without the padding.
+///
+/// # Arguments
+/// * `data` - The padded buffer.
+/// * `row_len` - The length of each row without its padding, in bytes.
+/// * `stride` - The number of bytes from the start of one row to the start of the next.
+/// * `height` - The number of rows.
+/// # Panics
+/// Panics if `stride` is smaller than `row_len`, or the buffer is too small for `height`
+/// rows.
+pub fn remove_row_padding(
+    data: &[u8],
+    row_len: usize,
+    stride: usize,
+    height: usize,
+) -> Vec<u8> {
+    if stride < row_len {
+        panic!(
+            "Stride {} is smaller than a row of {} bytes.",
+            stride, row_len
+        );
+    }
+    if height > 0 && data.len() < stride * (height - 1) + row_len {
+        panic!(
+            "Buffer of {} bytes is too small for {} rows.",
+            data.len(),
+            height
+        );
+    }
+    let mut out = Vec::with_capacity(row_len * height);
+    for y in 0..height {
+        out.extend_from_slice(&data[y * stride..y * stride + row_len]);
+    }
+    out
+}
+
+/// Copy the rows of an unpadded buffer into a new buffer whose rows are `stride` bytes
+/// apart, with the padding at the end of each row filled with zeros, eg: to meet the row
+/// alignment required by a GPU texture upload or a bitmap format.
+///
+/// # Arguments
+/// * `data` - The unpadded buffer, whose length must be a multiple of `row_len`.
+/// * `row_len` - The length of each row, in bytes.
+/// * `stride` - The number of bytes from the start of one row to the start of the next.
+/// # Panics
+/// Panics if `row_len` is 0, or `stride` is smaller than it.
+pub fn add_row_padding(data: &[u8], row_len: usize, stride: usize) -> Vec<u8> {
+    if row_len == 0 || stride < row_len {
+        panic!(
+            "Stride {} is smaller than a row of {} bytes.",
+            stride, row_len
+        );
+    }
+    let mut out = Vec::with_capacity(data.len() / row_len * stride);
+    for row in data.chunks_exact(row_len) {
+        out.extend_from_slice(row);
+        out.resize(out.len() + stride - row_len, 0);
+    }
+    out
+}
+
+/// Unpack 16-bit RGB565 pixels, as used by many embedded displays and their
+/// framebuffers, to opaque RGBA bytes. Each channel is scaled to the full range of 0 to
+/// 255, so that white stays white.
+///
+/// To convert a whole framebuffer, with padded rows or in BGR565, use
+/// `framebuffer::from_u16`.
+#[cfg(feature = "framebuffer")]
+pub fn unpack_rgb565(data: &[u16]) -> Vec<u8> {
+    if data.is_empty() {
+        return vec![];
+    }
+    let width = data.len() as u32;
+    framebuffer::from_u16(data, width, 1, data.len(), U16Layout::Rgb565).raw_pixels
+}
+
+/// Pack RGBA bytes into 16-bit RGB565 pixels, rounding each channel to the nearest level
+/// and ignoring alpha.
+#[cfg(feature = "framebuffer")]
+pub fn pack_rgb565(rgba: &[u8]) -> Vec<u16> {
+    match pixel_row(rgba) {
+        Some(photon_image) => framebuffer::to_u16(&photon_image, U16Layout::Rgb565),
+        None => vec![],
+    }
+}
+
+/// Unpack 32-bit `0xAARRGGBB` pixels, as used by Cairo, Skia, Qt's `QImage` and most
+/// windowing systems, to RGBA bytes.
+///
+/// To convert a whole framebuffer, with padded rows or in another packing, use
+/// `framebuffer::from_u32`.
+#[cfg(feature = "framebuffer")]
+pub fn unpack_argb32(data: &[u32]) -> Vec<u8> {
+    if data.is_empty() {
+        return vec![];
+    }
+    let width = data.len() as u32;
+    framebuffer::from_u32(data, width, 1, data.len(), U32Layout::Argb).raw_pixels
+}
+
+/// Pack RGBA bytes into 32-bit `0xAARRGGBB` pixels.
+#[cfg(feature = "framebuffer")]
+pub fn pack_argb32(rgba: &[u8]) -> Vec<u32> {
+    match pixel_row(rgba) {
+        Some(photon_image) => framebuffer::to_u32(&photon_image, U32Layout::Argb),
+        None => vec![],
+    }
+}
+
+/// Wrap the whole pixels of an RGBA buffer in a single-row image, or `None` if it holds
+/// no pixels, so that they can be converted with the `framebuffer` functions.
+#[cfg(feature = "framebuffer")]
+fn pixel_row(rgba: &[u8]) -> Option<PhotonImage> {
+    let width = rgba.len() / 4;
+    if width == 0 {
+        return None;
+    }
+    let pixels = rgba[..width * 4].to_vec();
+    Some(PhotonImage::new(pixels, width as u32, 1))
+}
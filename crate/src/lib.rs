@@ -10,6 +10,9 @@
 //! - **Colour manipulation**: Work with the image in various colour spaces such as HSL, LCh, and sRGB, and adjust the colours accordingly.
 //! - **Filters**: Over 30 pre-set filters available, incorporating various effects and transformations.
 //! - **Text**: Apply text to imagery in artistic ways, or to watermark, etc.,
+//! - **Drawing**: Draw anti-aliased lines, shapes and curves, eg: for annotations.
+//! - **Analysis**: Measure images, eg: channel statistics for automated quality checks.
+//! - **Pipelines**: Apply a sequence of edits described in JSON, eg: sent by a client to an image server.
 //! - **Watermarking**: Watermark images in multiple formats.
 //! - **Blending**: Blend images together using 10 different techniques, change image backgrounds.
 //!
@@ -33,6 +36,27 @@
 //! that allow for direct, low-level access to channel manipulation.
 //! To view a full demo of filtered imagery, visit the [official website](https://silvia-odwyer.github.io/photon).
 //!
+//! ### Cargo Features
+//! The `analysis`, `effects`, `text`, `conv`, `drawing`, `filters`, `noise`, `pipeline`,
+//! `framebuffer`, `mask`, `quantize`, `region`, `stego` and `video` modules are each behind a
+//! cargo feature of the same name, all of which are enabled by default. So are the `pyramid`
+//! feature, for the multi-band blends of the `multiple` module, the `gif` feature, for
+//! writing animated GIFs, and the `css-colours` feature, for parsing CSS colour names.
+//! WebAssembly users who only need a few modules can disable the default features to shrink
+//! the size of the `.wasm` binary.
+//!
+//! The optional `shaping` feature shapes text drawn by the `text` module, for ligatures,
+//! complex scripts and right-to-left text.
+//!
+//! The optional `ffi` feature exposes a C API, for embedding Photon in C, C++ and Swift
+//! applications.
+//!
+//! The optional `promises` feature adds async variants of the pipeline functions, which
+//! return a `Promise` and yield to the browser's event loop between operations.
+//!
+//! The optional `cli` feature builds the `photon` command-line tool, and the `batch` module
+//! for processing many files at once.
+//!
 //! ### WebAssembly Use
 //! To allow for universal communication between the core Rust library and WebAssembly, the functions have been generalised to allow for both native and in-browser use.
 //! [Check out the official guide](https://silvia-odwyer.github.io/photon/guide/) on how to get started with Photon on the web.
@@ -41,8 +65,11 @@
 //! View the [official demo of WASM in action](https://silvia-odwyer.github.io/photon).
 
 use base64::{decode, encode};
-use image::{GenericImage, GenericImageView};
+use error::PhotonError;
+use image::{GenericImage, GenericImageView, Pixel};
+use js_sys::Uint8Array;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::Clamped;
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
@@ -101,10 +128,33 @@ impl PhotonImage {
     }
 
     /// Get the PhotonImage's pixels as a Vec of u8s.
+    ///
+    /// This copies every pixel; see `get_raw_pixels_view` to avoid the copy.
     pub fn get_raw_pixels(&self) -> Vec<u8> {
         self.raw_pixels.clone()
     }
 
+    /// Get a `Uint8Array` view of the PhotonImage's pixels in wasm memory, without copying
+    /// them.
+    ///
+    /// Writing to the view changes the image's pixels directly. The view is only valid
+    /// until the next call into Photon: any call which allocates memory may grow wasm's
+    /// memory, which detaches the view, leaving it empty, and any operation which replaces
+    /// the image's pixels, such as a resize, leaves it pointing at freed memory. Get a new
+    /// view after each call, and never keep one after freeing the image.
+    ///
+    /// # Example
+    /// ```js
+    /// const view = img.get_raw_pixels_view();
+    /// ctx.putImageData(new ImageData(new Uint8ClampedArray(view.buffer, view.byteOffset,
+    ///   view.length), img.get_width(), img.get_height()), 0, 0);
+    /// ```
+    pub fn get_raw_pixels_view(&self) -> Uint8Array {
+        // Safe as long as the caller follows the rules above, as the view borrows the
+        // pixels beyond the lifetime of `self`.
+        unsafe { Uint8Array::view(&self.raw_pixels) }
+    }
+
     /// Get the height of the PhotonImage.
     pub fn get_height(&self) -> u32 {
         self.height
@@ -134,6 +184,31 @@ impl PhotonImage {
         .unwrap()
     }
 
+    /// Create a thumbnail of the image, shrunk in proportion so that its longest side is
+    /// at most `max_dim` pixels. Images which already fit are copied unchanged, rather
+    /// than enlarged.
+    ///
+    /// Large reductions are fast, as the image is first halved repeatedly with a box
+    /// filter, and only resized the rest of the way with a triangle filter.
+    ///
+    /// # Example
+    /// ```
+    /// use photon_rs::native::open_image;
+    ///
+    /// let img = open_image("img.jpg");
+    /// let thumbnail = img.thumbnail(256);
+    /// assert!(thumbnail.get_width().max(thumbnail.get_height()) <= 256);
+    /// ```
+    pub fn thumbnail(&self, max_dim: u32) -> PhotonImage {
+        let longest = self.width.max(self.height);
+        if longest <= max_dim {
+            return self.clone();
+        }
+        let scale = max_dim as f64 / longest as f64;
+        let size = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+        transform::downscale(self, size(self.width), size(self.height))
+    }
+
     /// Convert ImageData to raw pixels, and update the PhotonImage's raw pixels to this.
     pub fn set_imgdata(&mut self, img_data: ImageData) {
         let width = img_data.width();
@@ -145,6 +220,233 @@ impl PhotonImage {
     }
 }
 
+impl PhotonImage {
+    /// Consume the PhotonImage, returning its raw pixels without copying them.
+    pub fn into_raw_pixels(self) -> Vec<u8> {
+        self.raw_pixels
+    }
+}
+
+/// Chainable, non-destructive use of the in-place operations.
+///
+/// Every operation which takes a `&mut PhotonImage` can be applied through these methods,
+/// so functional pipelines don't need to clone images by hand.
+///
+/// # Example
+/// ```
+/// use photon_rs::channels::alter_red_channel;
+/// use photon_rs::conv::gaussian_blur;
+/// use photon_rs::effects::solarize;
+/// use photon_rs::PhotonImage;
+///
+/// let original = PhotonImage::new(vec![120; 16 * 16 * 4], 16, 16);
+/// let edited = original
+///     .applied(solarize)
+///     .apply(|img| gaussian_blur(img, 2))
+///     .apply(|img| alter_red_channel(img, 25));
+/// assert_ne!(edited.get_raw_pixels(), original.get_raw_pixels());
+/// ```
+impl PhotonImage {
+    /// Apply an in-place operation to the image, and return it.
+    pub fn apply<F: FnOnce(&mut PhotonImage)>(mut self, op: F) -> PhotonImage {
+        op(&mut self);
+        self
+    }
+
+    /// Apply an in-place operation to a copy of the image, leaving the original unchanged.
+    pub fn applied<F: FnOnce(&mut PhotonImage)>(&self, op: F) -> PhotonImage {
+        self.clone().apply(op)
+    }
+}
+
+/// Owned, chainable variants of common operations, which are also available from
+/// JavaScript, where `apply` can't take a closure.
+///
+/// Each method consumes the image and returns the edited image, so in JavaScript the
+/// original object can't be used afterwards. Call `copy` first to keep it.
+///
+/// # Example
+/// ```js
+/// const thumbnail = img.copy().grayscaled().resized(200, 200, SamplingFilter.Triangle);
+/// ```
+#[wasm_bindgen]
+impl PhotonImage {
+    /// Copy the image, eg: to keep it before calling one of the methods which consume it.
+    pub fn copy(&self) -> PhotonImage {
+        self.clone()
+    }
+
+    /// Invert the colours of the image. See `channels::invert`.
+    pub fn inverted(self) -> PhotonImage {
+        self.apply(channels::invert)
+    }
+
+    /// Convert the image to grayscale. See `monochrome::grayscale`.
+    pub fn grayscaled(self) -> PhotonImage {
+        self.apply(monochrome::grayscale)
+    }
+
+    /// Apply a sepia tone to the image. See `monochrome::sepia`.
+    pub fn sepia_toned(self) -> PhotonImage {
+        self.apply(monochrome::sepia)
+    }
+
+    /// Increase the saturation of the image in the HSL colour space. See
+    /// `colour_spaces::saturate_hsl`.
+    pub fn saturated(self, level: f32) -> PhotonImage {
+        self.apply(|img| colour_spaces::saturate_hsl(img, level))
+    }
+
+    /// Lighten the image in the HSL colour space. See `colour_spaces::lighten_hsl`.
+    pub fn lightened(self, level: f32) -> PhotonImage {
+        self.apply(|img| colour_spaces::lighten_hsl(img, level))
+    }
+
+    /// Darken the image in the HSL colour space. See `colour_spaces::darken_hsl`.
+    pub fn darkened(self, level: f32) -> PhotonImage {
+        self.apply(|img| colour_spaces::darken_hsl(img, level))
+    }
+
+    /// Rotate the hue of the image in the HSL colour space. See
+    /// `colour_spaces::hue_rotate_hsl`.
+    pub fn hue_rotated(self, degrees: f32) -> PhotonImage {
+        self.apply(|img| colour_spaces::hue_rotate_hsl(img, degrees))
+    }
+
+    /// Flip the image horizontally. See `transform::fliph`.
+    pub fn flipped_h(self) -> PhotonImage {
+        self.apply(transform::fliph)
+    }
+
+    /// Flip the image vertically. See `transform::flipv`.
+    pub fn flipped_v(self) -> PhotonImage {
+        self.apply(transform::flipv)
+    }
+
+    /// Resize the image. See `transform::resize`.
+    pub fn resized(
+        self,
+        width: u32,
+        height: u32,
+        sampling_filter: transform::SamplingFilter,
+    ) -> PhotonImage {
+        transform::resize(&self, width, height, sampling_filter)
+    }
+
+    /// Crop the image to the part of it which lies within `region`.
+    pub fn cropped(self, region: &Rect) -> PhotonImage {
+        transform::crop_rect(&self, region)
+    }
+
+    /// Blur the image with a Gaussian blur. See `conv::gaussian_blur`.
+    #[cfg(feature = "conv")]
+    pub fn blurred(self, radius: i32) -> PhotonImage {
+        self.apply(|img| conv::gaussian_blur(img, radius))
+    }
+
+    /// Sharpen the image. See `conv::sharpen`.
+    #[cfg(feature = "conv")]
+    pub fn sharpened(self) -> PhotonImage {
+        self.apply(conv::sharpen)
+    }
+
+    /// Solarize the image. See `effects::solarize`.
+    #[cfg(feature = "effects")]
+    pub fn solarized(self) -> PhotonImage {
+        self.apply(effects::solarize)
+    }
+
+    /// Brighten or darken the image. See `effects::adjust_brightness`.
+    #[cfg(feature = "effects")]
+    pub fn brightness_adjusted(self, amount: i16) -> PhotonImage {
+        self.apply(|img| effects::adjust_brightness(img, amount))
+    }
+
+    /// Adjust the contrast of the image. See `effects::adjust_contrast`.
+    #[cfg(feature = "effects")]
+    pub fn contrast_adjusted(self, contrast: f32) -> PhotonImage {
+        self.apply(|img| effects::adjust_contrast(img, contrast))
+    }
+
+    /// Apply one of the preset filters to the image. See `filters::filter`.
+    #[cfg(feature = "filters")]
+    pub fn filtered(self, filter_name: &str) -> PhotonImage {
+        self.apply(|img| filters::filter(img, filter_name))
+    }
+}
+
+/// View a PhotonImage through the `image` crate's traits, so it can be passed directly to
+/// `image` and `imageproc` functions, without copying it into a `DynamicImage`.
+///
+/// # Example
+/// ```
+/// use image::{GenericImage, GenericImageView, Rgba};
+/// use photon_rs::PhotonImage;
+///
+/// let mut img = PhotonImage::new(vec![0; 4 * 4 * 4], 4, 4);
+/// img.put_pixel(1, 2, Rgba([255, 0, 0, 255]));
+/// assert_eq!(img.dimensions(), (4, 4));
+/// assert_eq!(img.get_pixel(1, 2), Rgba([255, 0, 0, 255]));
+/// ```
+impl GenericImageView for PhotonImage {
+    type Pixel = image::Rgba<u8>;
+    type InnerImageView = PhotonImage;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (0, 0, self.width, self.height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> image::Rgba<u8> {
+        let index = self.pixel_index(x, y);
+        *image::Rgba::from_slice(&self.raw_pixels[index..index + 4])
+    }
+
+    fn inner(&self) -> &PhotonImage {
+        self
+    }
+}
+
+impl GenericImage for PhotonImage {
+    type InnerImage = PhotonImage;
+
+    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut image::Rgba<u8> {
+        let index = self.pixel_index(x, y);
+        image::Rgba::from_slice_mut(&mut self.raw_pixels[index..index + 4])
+    }
+
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: image::Rgba<u8>) {
+        *self.get_pixel_mut(x, y) = pixel;
+    }
+
+    fn blend_pixel(&mut self, x: u32, y: u32, pixel: image::Rgba<u8>) {
+        self.get_pixel_mut(x, y).blend(&pixel);
+    }
+
+    fn inner_mut(&mut self) -> &mut PhotonImage {
+        self
+    }
+}
+
+impl PhotonImage {
+    /// The index of the first byte of the pixel at (x, y) in `raw_pixels`.
+    ///
+    /// # Panics
+    /// Panics if (x, y) is outside of the image.
+    fn pixel_index(&self, x: u32, y: u32) -> usize {
+        if x >= self.width || y >= self.height {
+            panic!(
+                "Pixel ({}, {}) is outside of the {}x{} image.",
+                x, y, self.width, self.height
+            );
+        }
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+}
+
 /// Create a new PhotonImage from a raw Vec of u8s representing raw image pixels.
 impl From<ImageData> for PhotonImage {
     fn from(imgdata: ImageData) -> Self {
@@ -205,6 +507,38 @@ impl Rgb {
     pub fn get_blue(&self) -> u8 {
         self.b
     }
+
+    /// Parse a colour from hex notation, as in CSS, eg: `"#6495ed"`, or `"#69e"` for
+    /// `"#6699ee"`. The `#` is optional.
+    ///
+    /// # Errors
+    /// Returns `PhotonError::InvalidColour` if the colour isn't 3 or 6 hex digits.
+    pub fn from_hex(hex: &str) -> error::Result<Rgb> {
+        match parse_hex(hex) {
+            Some(([r, g, b, _], false)) => Ok(Rgb::new(r, g, b)),
+            _ => Err(invalid_colour(hex)),
+        }
+    }
+
+    /// Look up one of the named colours of CSS, eg: `"cornflowerblue"`, ignoring case.
+    ///
+    /// # Errors
+    /// Returns `PhotonError::InvalidColour` if there's no colour of that name, or the
+    /// `css-colours` feature is disabled.
+    pub fn from_name(name: &str) -> error::Result<Rgb> {
+        let [r, g, b] = css_colour(name).ok_or_else(|| invalid_colour(name))?;
+        Ok(Rgb::new(r, g, b))
+    }
+}
+
+/// Parse a colour from hex notation or a CSS name, as `Rgb::from_hex` and
+/// `Rgb::from_name` do.
+impl FromStr for Rgb {
+    type Err = PhotonError;
+
+    fn from_str(colour: &str) -> error::Result<Rgb> {
+        Rgb::from_hex(colour).or_else(|_| Rgb::from_name(colour))
+    }
 }
 
 impl From<Vec<u8>> for Rgb {
@@ -216,6 +550,260 @@ impl From<Vec<u8>> for Rgb {
     }
 }
 
+/// RGBA color type, where the alpha channel is the colour's opacity (0 is transparent).
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rgba {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+#[wasm_bindgen]
+impl Rgba {
+    #[wasm_bindgen(constructor)]
+    /// Create a new RGBA struct.
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    /// Set the Red value.
+    pub fn set_red(&mut self, r: u8) {
+        self.r = r;
+    }
+
+    /// Set the Green value.
+    pub fn set_green(&mut self, g: u8) {
+        self.g = g;
+    }
+
+    /// Set the Blue value.
+    pub fn set_blue(&mut self, b: u8) {
+        self.b = b;
+    }
+
+    /// Set the alpha value.
+    pub fn set_alpha(&mut self, a: u8) {
+        self.a = a;
+    }
+
+    /// Get the Red value.
+    pub fn get_red(&self) -> u8 {
+        self.r
+    }
+
+    /// Get the Green value.
+    pub fn get_green(&self) -> u8 {
+        self.g
+    }
+
+    /// Get the Blue value.
+    pub fn get_blue(&self) -> u8 {
+        self.b
+    }
+
+    /// Get the alpha value.
+    pub fn get_alpha(&self) -> u8 {
+        self.a
+    }
+
+    /// Parse a colour from hex notation, as in CSS, eg: `"#6495ed"`, or with an alpha
+    /// value, `"#6495ed80"`. The short forms `"#69e"` and `"#69e8"` are also accepted,
+    /// and the `#` is optional. Colours without an alpha value are opaque.
+    ///
+    /// # Errors
+    /// Returns `PhotonError::InvalidColour` if the colour isn't 3, 4, 6 or 8 hex digits.
+    pub fn from_hex(hex: &str) -> error::Result<Rgba> {
+        let ([r, g, b, a], _) = parse_hex(hex).ok_or_else(|| invalid_colour(hex))?;
+        Ok(Rgba::new(r, g, b, a))
+    }
+
+    /// Look up one of the named colours of CSS, eg: `"cornflowerblue"`, ignoring case.
+    /// The colour is opaque, except for `"transparent"`, which is transparent black.
+    ///
+    /// # Errors
+    /// Returns `PhotonError::InvalidColour` if there's no colour of that name, or the
+    /// `css-colours` feature is disabled.
+    pub fn from_name(name: &str) -> error::Result<Rgba> {
+        if name.trim().eq_ignore_ascii_case("transparent") {
+            return Ok(Rgba::new(0, 0, 0, 0));
+        }
+        let [r, g, b] = css_colour(name).ok_or_else(|| invalid_colour(name))?;
+        Ok(Rgba::new(r, g, b, 255))
+    }
+}
+
+/// Parse a colour from hex notation or a CSS name, as `Rgba::from_hex` and
+/// `Rgba::from_name` do.
+impl FromStr for Rgba {
+    type Err = PhotonError;
+
+    fn from_str(colour: &str) -> error::Result<Rgba> {
+        Rgba::from_hex(colour).or_else(|_| Rgba::from_name(colour))
+    }
+}
+
+/// Convert an RGB colour to an opaque RGBA colour.
+impl From<Rgb> for Rgba {
+    fn from(rgb: Rgb) -> Self {
+        Rgba::new(rgb.r, rgb.g, rgb.b, 255)
+    }
+}
+
+/// Parse 3, 4, 6 or 8 hex digits, optionally after a `#`, as `[r, g, b, a]`, along with
+/// whether an alpha value was given.
+fn parse_hex(hex: &str) -> Option<([u8; 4], bool)> {
+    let hex = hex.trim();
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = |i: usize, len: usize| {
+        let v = u8::from_str_radix(&digits[i * len..(i + 1) * len], 16).unwrap();
+        // A single digit is repeated, so "f" means "ff".
+        if len == 1 {
+            v * 17
+        } else {
+            v
+        }
+    };
+    match digits.len() {
+        3 | 6 => {
+            let len = digits.len() / 3;
+            Some(([value(0, len), value(1, len), value(2, len), 255], false))
+        }
+        4 | 8 => {
+            let len = digits.len() / 4;
+            let rgba = [value(0, len), value(1, len), value(2, len), value(3, len)];
+            Some((rgba, true))
+        }
+        _ => None,
+    }
+}
+
+/// Look up one of the named colours of CSS, which are only recognised with the
+/// `css-colours` feature.
+#[cfg(feature = "css-colours")]
+fn css_colour(name: &str) -> Option<[u8; 3]> {
+    css_colours::lookup(name)
+}
+
+#[cfg(not(feature = "css-colours"))]
+fn css_colour(_name: &str) -> Option<[u8; 3]> {
+    None
+}
+
+/// The error for a colour which can't be parsed.
+fn invalid_colour(colour: &str) -> PhotonError {
+    PhotonError::InvalidColour(format!(
+        "{:?} isn't a hex colour or a CSS colour name",
+        colour
+    ))
+}
+
+impl From<Vec<u8>> for Rgba {
+    fn from(vec: Vec<u8>) -> Self {
+        if vec.len() != 4 {
+            panic!("Vec length must be equal to 4.")
+        }
+        Rgba::new(vec[0], vec[1], vec[2], vec[3])
+    }
+}
+
+/// A rectangular region of an image, in pixels.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[wasm_bindgen]
+impl Rect {
+    #[wasm_bindgen(constructor)]
+    /// Create a new rectangle, from the position of its top-left corner and its size.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Get the x-coordinate of the top-left corner.
+    pub fn get_x(&self) -> u32 {
+        self.x
+    }
+
+    /// Get the y-coordinate of the top-left corner.
+    pub fn get_y(&self) -> u32 {
+        self.y
+    }
+
+    /// Get the width.
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    /// Get the height.
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// The position of a pixel of an image.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Point {
+    x: u32,
+    y: u32,
+}
+
+#[wasm_bindgen]
+impl Point {
+    #[wasm_bindgen(constructor)]
+    /// Create a new point.
+    pub fn new(x: u32, y: u32) -> Point {
+        Point { x, y }
+    }
+
+    /// Get the x-coordinate.
+    pub fn get_x(&self) -> u32 {
+        self.x
+    }
+
+    /// Get the y-coordinate.
+    pub fn get_y(&self) -> u32 {
+        self.y
+    }
+}
+
+/// Which neighbours of a pixel are connected to it, when finding regions of pixels.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only the pixels above, below, left and right of it.
+    Four = 4,
+    /// Its diagonal neighbours too.
+    Eight = 8,
+}
+
+impl Rect {
+    /// The part of this rectangle which lies within an image of the given size, as
+    /// `(x0, y0, x1, y1)` where `(x1, y1)` is exclusive.
+    pub(crate) fn clip(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let x0 = self.x.min(width);
+        let y0 = self.y.min(height);
+        let x1 = self.x.saturating_add(self.width).min(width);
+        let y1 = self.y.saturating_add(self.height).min(height);
+        (x0, y0, x1, y1)
+    }
+}
+
 ///! [temp] Check if WASM is supported.
 #[wasm_bindgen]
 pub fn run() -> Result<(), JsValue> {
@@ -342,17 +930,69 @@ fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+// The `alpha`, `gamma` and `iter` modules are used by the modules which are always built,
+// such as `channels` and `transform`, so aren't behind features of their own.
+pub mod alpha;
+#[cfg(feature = "analysis")]
+pub mod analysis;
+#[cfg(feature = "cli")]
+pub mod batch;
+pub mod cancel;
 pub mod channels;
 pub mod colour_spaces;
+#[cfg(feature = "conv")]
 pub mod conv;
+#[cfg(feature = "css-colours")]
+mod css_colours;
+#[cfg(feature = "drawing")]
+pub mod drawing;
+#[cfg(feature = "effects")]
 pub mod effects;
+pub mod error;
+// Matching and homographies are only used by `stitch`, which also needs `pyramid`.
+#[cfg(feature = "analysis")]
+#[cfg_attr(not(feature = "pyramid"), allow(dead_code))]
+mod features;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "filters")]
 pub mod filters;
+#[cfg(feature = "framebuffer")]
+pub mod framebuffer;
+pub mod gamma;
 pub mod helpers;
-mod iter;
+pub mod iter;
+#[cfg(feature = "pipeline")]
+pub mod lut;
+#[cfg(feature = "mask")]
+pub mod mask;
 pub mod monochrome;
 pub mod multiple;
 pub mod native;
+#[cfg(feature = "noise")]
 pub mod noise;
+#[cfg(feature = "pipeline")]
+pub mod pipeline;
+#[cfg(feature = "pipeline")]
+pub mod preset;
+#[cfg(feature = "promises")]
+pub mod promises;
+#[cfg(feature = "pyramid")]
+mod pyramid;
+#[cfg(feature = "quantize")]
+pub mod quantize;
+#[cfg(feature = "region")]
+pub mod region;
+#[cfg(feature = "pipeline")]
+pub mod session;
+mod simd;
+#[cfg(feature = "stego")]
+pub mod stego;
 mod tests;
+#[cfg(feature = "text")]
 pub mod text;
+#[cfg(feature = "texture")]
+pub mod texture;
 pub mod transform;
+#[cfg(feature = "video")]
+pub mod video;
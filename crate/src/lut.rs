@@ -0,0 +1,213 @@
+//! Bake the colour adjustments of a pipeline into a 3D lookup table (LUT), which can be
+//! exported as a `.cube` file for use in video editors and GPU shaders.
+//!
+//! A 3D LUT samples a colour transform at a lattice of `size`³ colours, and colours in
+//! between are found by trilinear interpolation. Only operations which change each pixel
+//! independently of its neighbours can be represented, so resizing, cropping, flipping,
+//! blurring and sharpening (including `auto_enhance`, which sharpens) are skipped when
+//! baking. Operations which adapt to the image, such as `auto_contrast`, adapt to the
+//! lattice rather than to any particular photo.
+//!
+//! # Example
+//! ```
+//! use photon_rs::lut::{apply_lut, bake};
+//! use photon_rs::pipeline::{Operation, Pipeline};
+//! use photon_rs::PhotonImage;
+//!
+//! let pipeline = Pipeline::new()
+//!     .then(Operation::Resize { w: Some(800), h: None })
+//!     .then(Operation::Filter { name: "vintage".to_string() });
+//! let lut = bake(&pipeline, 33).unwrap();
+//! let cube = lut.to_cube("vintage");
+//! assert!(cube.contains("LUT_3D_SIZE 33"));
+//!
+//! let mut img = PhotonImage::new(vec![120; 16 * 16 * 4], 16, 16);
+//! apply_lut(&mut img, &lut);
+//! ```
+
+use crate::error::{PhotonError, Result};
+use crate::pipeline::{Operation, Pipeline};
+use crate::PhotonImage;
+use std::fmt::Write;
+use wasm_bindgen::prelude::*;
+
+/// The largest number of samples along each axis of a LUT.
+const MAX_SIZE: usize = 256;
+
+/// A 3D lookup table, mapping each colour of a lattice to a new colour.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut3D {
+    size: usize,
+    /// The output colours, from 0 to 1, with the red index changing fastest, then green,
+    /// then blue, as in `.cube` files.
+    table: Vec<[f32; 3]>,
+}
+
+#[wasm_bindgen]
+impl Lut3D {
+    /// Get the number of samples along each axis.
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /// Convert the LUT to the text of an Adobe/Resolve `.cube` file.
+    pub fn to_cube(&self, title: &str) -> String {
+        let mut cube = String::new();
+        let title = title.replace('"', "'");
+        writeln!(cube, "TITLE \"{}\"", title).unwrap();
+        writeln!(cube, "LUT_3D_SIZE {}", self.size).unwrap();
+        writeln!(cube, "DOMAIN_MIN 0.0 0.0 0.0").unwrap();
+        writeln!(cube, "DOMAIN_MAX 1.0 1.0 1.0").unwrap();
+        for [r, g, b] in &self.table {
+            writeln!(cube, "{:.6} {:.6} {:.6}", r, g, b).unwrap();
+        }
+        cube
+    }
+}
+
+impl Lut3D {
+    /// Look up a colour, whose channels are from 0 to 1, interpolating between the
+    /// lattice's colours.
+    pub fn lookup(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let mut lower = [0; 3];
+        let mut fraction = [0.0; 3];
+        for c in 0..3 {
+            let position = rgb[c].clamp(0.0, 1.0) * max;
+            lower[c] = (position.floor() as usize).min(self.size - 2);
+            fraction[c] = position - lower[c] as f32;
+        }
+
+        let mut out = [0.0; 3];
+        for corner in 0..8 {
+            let mut index = 0;
+            let mut weight = 1.0;
+            for c in (0..3).rev() {
+                let upper = (corner >> c) & 1;
+                index = index * self.size + lower[c] + upper;
+                weight *= if upper == 1 {
+                    fraction[c]
+                } else {
+                    1.0 - fraction[c]
+                };
+            }
+            for (value, sample) in out.iter_mut().zip(&self.table[index]) {
+                *value += weight * sample;
+            }
+        }
+        out
+    }
+}
+
+/// Bake the colour adjustments of a pipeline into a 3D LUT.
+///
+/// # Arguments
+/// * `pipeline` - The pipeline whose colour adjustments to bake. Operations which move
+/// pixels or mix them with their neighbours are skipped.
+/// * `size` - The number of samples along each axis, between 2 and 256, eg: 33. Larger
+/// LUTs are more accurate, but `size`³ colours must be stored.
+/// # Errors
+/// Returns `PhotonError::InvalidLut` if `size` is less than 2 or more than 256, or
+/// `PhotonError::InvalidPipeline` if any of the pipeline's operations is invalid.
+/// # Example
+///
+/// ```
+/// use photon_rs::lut::bake;
+/// use photon_rs::pipeline::Pipeline;
+///
+/// let pipeline = Pipeline::from_json(r#"[{"op": "sepia"}]"#).unwrap();
+/// let cube = bake(&pipeline, 17).unwrap().to_cube("sepia");
+/// ```
+#[wasm_bindgen]
+pub fn bake(pipeline: &Pipeline, size: usize) -> Result<Lut3D> {
+    if !(2..=MAX_SIZE).contains(&size) {
+        return Err(PhotonError::InvalidLut(format!(
+            "size must be between 2 and {}, not {}",
+            MAX_SIZE, size
+        )));
+    }
+
+    // Lay the lattice out as `size` rows of `size` * `size` pixels, followed by a spare
+    // row, as some operations leave the last pixel of an image unchanged.
+    let level = |i: usize| (i as f32 * 255.0 / (size - 1) as f32).round() as u8;
+    let mut raw_pixels = Vec::with_capacity(size * size * (size + 1) * 4);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                raw_pixels.extend_from_slice(&[level(r), level(g), level(b), 255]);
+            }
+        }
+    }
+    raw_pixels.resize(size * size * (size + 1) * 4, 255);
+    let (width, height) = ((size * size) as u32, (size + 1) as u32);
+    let mut lattice = PhotonImage::new(raw_pixels, width, height);
+
+    let colour_operations: Vec<Operation> = pipeline
+        .operations()
+        .iter()
+        .filter(|operation| is_colour_operation(operation))
+        .cloned()
+        .collect();
+    crate::pipeline::run(&mut lattice, &colour_operations)?;
+
+    let table = lattice
+        .raw_pixels
+        .chunks_exact(4)
+        .take(size * size * size)
+        .map(|px| {
+            [
+                px[0] as f32 / 255.0,
+                px[1] as f32 / 255.0,
+                px[2] as f32 / 255.0,
+            ]
+        })
+        .collect();
+    Ok(Lut3D { size, table })
+}
+
+/// Apply a 3D LUT to the colours of an image.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `lut` - The LUT to apply.
+#[wasm_bindgen]
+pub fn apply_lut(photon_image: &mut PhotonImage, lut: &Lut3D) {
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        let rgb = [
+            px[0] as f32 / 255.0,
+            px[1] as f32 / 255.0,
+            px[2] as f32 / 255.0,
+        ];
+        for (channel, value) in px.iter_mut().zip(&lut.lookup(rgb)) {
+            *channel = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Whether an operation changes each pixel independently of the others.
+fn is_colour_operation(operation: &Operation) -> bool {
+    match operation {
+        Operation::Resize { .. }
+        | Operation::Crop { .. }
+        | Operation::FlipH
+        | Operation::FlipV
+        | Operation::GaussianBlur { .. }
+        | Operation::BoxBlur
+        | Operation::Sharpen
+        | Operation::Kuwahara { .. }
+        | Operation::NlMeans { .. }
+        | Operation::AutoEnhance => false,
+        Operation::Brightness { .. }
+        | Operation::Contrast { .. }
+        | Operation::Saturate { .. }
+        | Operation::HueRotate { .. }
+        | Operation::Grayscale
+        | Operation::Sepia
+        | Operation::Invert
+        | Operation::Solarize
+        | Operation::Tint { .. }
+        | Operation::AutoContrast { .. }
+        | Operation::Filter { .. } => true,
+    }
+}
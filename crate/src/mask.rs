@@ -0,0 +1,106 @@
+//! Create masks with soft, feathered edges, for local edits with
+//! `multiple::blend_with_mask` and `region::apply_masked`.
+//!
+//! A mask is an opaque, grayscale PhotonImage, which is white where an edit should apply
+//! fully, black where it shouldn't apply at all, and grey in between. Feathering fades the
+//! edge of a shape smoothly over a number of pixels, centred on its outline, so that
+//! edits blend into the rest of the image without a visible seam.
+//!
+//! # Example
+//! ```
+//! use photon_rs::mask::ellipse;
+//!
+//! // A soft spotlight in the middle of a 200x100 image.
+//! let spotlight = ellipse(200, 100, 100.0, 50.0, 60.0, 30.0, 20.0);
+//! assert_eq!(spotlight.get_raw_pixels()[(50 * 200 + 100) * 4], 255);
+//! assert_eq!(spotlight.get_raw_pixels()[0], 0);
+//! ```
+
+use crate::{PhotonImage, Rect};
+use wasm_bindgen::prelude::*;
+
+/// Create a mask of an ellipse, or a circle when its radii are equal.
+///
+/// # Arguments
+/// * `width` - The width of the mask.
+/// * `height` - The height of the mask.
+/// * `cx` - The x-coordinate of the ellipse's centre.
+/// * `cy` - The y-coordinate of the ellipse's centre.
+/// * `rx` - The horizontal radius of the ellipse.
+/// * `ry` - The vertical radius of the ellipse.
+/// * `feather` - The width of the soft edge, in pixels, or 0 for a hard edge.
+#[wasm_bindgen]
+pub fn ellipse(
+    width: u32,
+    height: u32,
+    cx: f32,
+    cy: f32,
+    rx: f32,
+    ry: f32,
+    feather: f32,
+) -> PhotonImage {
+    let (rx, ry) = (rx.max(f32::EPSILON), ry.max(f32::EPSILON));
+    from_distance(width, height, feather, |x, y| {
+        let (dx, dy) = (x - cx, y - cy);
+        let k = ((dx / rx).powi(2) + (dy / ry).powi(2)).sqrt();
+        // Approximate the distance to the outline by dividing the distance in normalised
+        // units by the gradient of `k`.
+        let gradient = ((dx / (rx * rx)).powi(2) + (dy / (ry * ry)).powi(2)).sqrt();
+        if gradient == 0.0 {
+            -rx.min(ry)
+        } else {
+            (k - 1.0) * k / gradient
+        }
+    })
+}
+
+/// Create a mask of a rectangle.
+///
+/// # Arguments
+/// * `width` - The width of the mask.
+/// * `height` - The height of the mask.
+/// * `region` - The rectangle.
+/// * `feather` - The width of the soft edge, in pixels, or 0 for a hard edge.
+#[wasm_bindgen]
+pub fn rect(width: u32, height: u32, region: &Rect, feather: f32) -> PhotonImage {
+    let half_width = region.width as f32 / 2.0;
+    let half_height = region.height as f32 / 2.0;
+    let cx = region.x as f32 + half_width;
+    let cy = region.y as f32 + half_height;
+    from_distance(width, height, feather, |x, y| {
+        let qx = (x - cx).abs() - half_width;
+        let qy = (y - cy).abs() - half_height;
+        let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+        outside + qx.max(qy).min(0.0)
+    })
+}
+
+/// Create a mask from the signed distance of each pixel's centre from a shape's outline,
+/// which is negative inside the shape.
+fn from_distance<F: Fn(f32, f32) -> f32>(
+    width: u32,
+    height: u32,
+    feather: f32,
+    distance: F,
+) -> PhotonImage {
+    let half = feather.max(0.0) / 2.0;
+    let mut raw_pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let d = distance(x as f32 + 0.5, y as f32 + 0.5);
+            let coverage = if half == 0.0 {
+                if d <= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            } else {
+                let t = ((half - d) / (2.0 * half)).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            };
+            let v = (coverage * 255.0).round() as u8;
+            raw_pixels.extend_from_slice(&[v, v, v, 255]);
+        }
+    }
+    PhotonImage::new(raw_pixels, width, height)
+}
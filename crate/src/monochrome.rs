@@ -1,8 +1,9 @@
 //! Monochrome-related effects and greyscaling/duotoning.
 
 extern crate image;
+use crate::alpha;
 use crate::helpers;
-use crate::PhotonImage;
+use crate::{PhotonImage, Rgb};
 use image::{GenericImage, GenericImageView};
 use wasm_bindgen::prelude::*;
 use crate::iter::ImageIterator;
@@ -297,25 +298,27 @@ pub fn decompose_max(img: &mut PhotonImage) {
 /// ```
 #[wasm_bindgen]
 pub fn grayscale_shades(mut photon_image: &mut PhotonImage, num_shades: u8) {
-    let mut img = helpers::dyn_image_from_raw(&photon_image);
+    alpha::apply_alpha_mode(photon_image, |photon_image| {
+        let mut img = helpers::dyn_image_from_raw(&photon_image);
 
-    for (x, y) in ImageIterator::with_dimension(&img.dimensions()) {
-        let px = img.get_pixel(x, y);
+        for (x, y) in ImageIterator::with_dimension(&img.dimensions()) {
+            let px = img.get_pixel(x, y);
 
-        let conversion: f32 = 255.0 / (num_shades as f32 - 1.0);
-        let (r_val, g_val, b_val) =
-            (px.data[0] as u32, px.data[1] as u32, px.data[2] as u32);
+            let conversion: f32 = 255.0 / (num_shades as f32 - 1.0);
+            let (r_val, g_val, b_val) =
+                (px.data[0] as u32, px.data[1] as u32, px.data[2] as u32);
 
-        let avg: f32 = (r_val + g_val + b_val) as f32 / 3.0;
+            let avg: f32 = (r_val + g_val + b_val) as f32 / 3.0;
 
-        let dividend = avg / conversion as f32;
+            let dividend = avg / conversion as f32;
 
-        let gray = ((dividend + 0.5) * conversion) as u8;
+            let gray = ((dividend + 0.5) * conversion) as u8;
 
-        img.put_pixel(x, y, image::Rgba([gray, gray, gray, 255]));
-    }
-    let raw_pixels = img.raw_pixels();
-    photon_image.raw_pixels = raw_pixels;
+            img.put_pixel(x, y, image::Rgba([gray, gray, gray, 255]));
+        }
+        let raw_pixels = img.raw_pixels();
+        photon_image.raw_pixels = raw_pixels;
+    })
 }
 
 /// Convert an image to grayscale by setting a pixel's 3 RGB values to the Red channel's value.
@@ -445,3 +448,148 @@ pub fn threshold(img: &mut PhotonImage, threshold: u32) {
         img.raw_pixels[i + 2] = v as u8;
     }
 }
+
+/// Convert an image to grayscale, and quantize it to a number of evenly spaced shades, eg:
+/// for screen printing.
+///
+/// With dithering, the error from quantizing each pixel is diffused to its neighbours
+/// (Floyd-Steinberg), so gradients are kept as patterns of the available shades rather than
+/// becoming bands.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `levels` - The number of shades, including black and white. Values below 2 are
+/// treated as 2.
+/// * `dither` - Whether to dither the image.
+/// # Example
+///
+/// ```
+/// // For example, to reduce an image to four dithered shades of gray:
+/// use photon_rs::monochrome::posterize_gray;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// posterize_gray(&mut img, 4_u8, true);
+/// ```
+#[wasm_bindgen]
+pub fn posterize_gray(photon_image: &mut PhotonImage, levels: u8, dither: bool) {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    let step = 255.0 / (levels.max(2) - 1) as f32;
+    let mut gray: Vec<f32> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| 0.299 * px[0] as f32 + 0.587 * px[1] as f32 + 0.114 * px[2] as f32)
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let old = gray[i].clamp(0.0, 255.0);
+            let new = (old / step).round() * step;
+            gray[i] = new;
+            if !dither {
+                continue;
+            }
+            let error = old - new;
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                if nx >= 0 && (nx as usize) < width && y + dy < height {
+                    gray[(y + dy) * width + nx as usize] += error * weight;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+
+    for (px, value) in photon_image.raw_pixels.chunks_exact_mut(4).zip(gray) {
+        let value = value.round() as u8;
+        px[0] = value;
+        px[1] = value;
+        px[2] = value;
+    }
+}
+
+/// Map an image's luminance onto three ink colours, so that its shadows take on the first,
+/// its midtones the second, and its highlights the third, blending smoothly between them.
+/// This extends duotoning with a third ink, as in print.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `shadow` - The colour of black.
+/// * `mid` - The colour of mid-gray.
+/// * `highlight` - The colour of white.
+/// # Example
+///
+/// ```
+/// // For example, to map an image onto a deep blue, a warm brown and a cream:
+/// use photon_rs::monochrome::tritone;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("img.jpg");
+/// tritone(
+///     &mut img,
+///     Rgb::new(20, 24, 60),
+///     Rgb::new(150, 100, 70),
+///     Rgb::new(250, 240, 215),
+/// );
+/// ```
+#[wasm_bindgen]
+pub fn tritone(photon_image: &mut PhotonImage, shadow: Rgb, mid: Rgb, highlight: Rgb) {
+    tone_map(photon_image, &[shadow, mid, highlight]);
+}
+
+/// Map an image's luminance onto four ink colours, from its shadows to its highlights,
+/// blending smoothly between them.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `shadow` - The colour of black.
+/// * `dark_mid` - The colour of dark gray, a third of the way to white.
+/// * `light_mid` - The colour of light gray, two thirds of the way to white.
+/// * `highlight` - The colour of white.
+/// # Example
+///
+/// ```
+/// use photon_rs::monochrome::quadtone;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgb;
+///
+/// let mut img = open_image("img.jpg");
+/// quadtone(
+///     &mut img,
+///     Rgb::new(10, 10, 30),
+///     Rgb::new(90, 60, 80),
+///     Rgb::new(200, 150, 110),
+///     Rgb::new(255, 250, 235),
+/// );
+/// ```
+#[wasm_bindgen]
+pub fn quadtone(
+    photon_image: &mut PhotonImage,
+    shadow: Rgb,
+    dark_mid: Rgb,
+    light_mid: Rgb,
+    highlight: Rgb,
+) {
+    tone_map(photon_image, &[shadow, dark_mid, light_mid, highlight]);
+}
+
+/// Replace each pixel's colour with its luminance's position along a gradient through
+/// evenly spaced inks, the first for black and the last for white.
+pub(crate) fn tone_map(photon_image: &mut PhotonImage, inks: &[Rgb]) {
+    let segments = (inks.len() - 1) as f32;
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        let position = helpers::luma(px) as f32 / 255.0 * segments;
+        let i = (position as usize).min(inks.len() - 2);
+        let t = position - i as f32;
+        let (from, to) = (&inks[i], &inks[i + 1]);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        px[0] = mix(from.r, to.r);
+        px[1] = mix(from.g, to.g);
+        px[2] = mix(from.b, to.b);
+    }
+}
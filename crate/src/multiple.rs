@@ -1,13 +1,20 @@
 //! Image manipulation with multiple images, including adding watermarks, changing backgrounds, etc.,
 
 extern crate image;
-extern crate rand;
 use crate::channels::color_sim;
-use crate::{helpers, GenericImage, PhotonImage, Rgb};
+#[cfg(all(feature = "analysis", feature = "pyramid"))]
+use crate::error::{PhotonError, Result};
+#[cfg(feature = "analysis")]
+use crate::features;
+use crate::gamma;
+use crate::iter::ImageIterator;
+#[cfg(feature = "pyramid")]
+use crate::pyramid::{self, Plane};
+use crate::transform::{crop_rect, resize, SamplingFilter};
+use crate::{helpers, GenericImage, PhotonImage, Point, Rect, Rgb, Rgba};
 use image::{DynamicImage, GenericImageView, RgbaImage};
-use palette::{Blend, Gradient, Lab, Lch, LinSrgba, Pixel, Srgb, Srgba};
+use palette::{Blend, Gradient, Lab, Lch, LinSrgba, Mix, Pixel, Srgb, Srgba};
 use wasm_bindgen::prelude::*;
-use crate::iter::ImageIterator;
 
 /// Add a watermark to an image.
 ///
@@ -80,36 +87,19 @@ pub fn blend(
         for y in 0..height {
             let px_data = img.get_pixel(x, y).data;
 
-            let color: LinSrgba = LinSrgba::from_raw(&px_data).into_format();
+            let color = gamma::blendable(px_data);
 
             let px_data2 = img2.get_pixel(x, y).data;
 
-            let color2: LinSrgba = LinSrgba::from_raw(&px_data2).into_format();
-
-            let blended = match blend_mode {
-                // Match a single value
-                "overlay" => color2.overlay(color),
-                "over" => color2.over(color),
-                "atop" => color2.atop(color),
-                "xor" => color2.xor(color),
-                "plus" => color2.plus(color),
-                "multiply" => color2.multiply(color),
-                "burn" => color2.burn(color),
-                "difference" => color2.difference(color),
-                "soft_light" => color2.soft_light(color),
-                "hard_light" => color2.hard_light(color),
-                "dodge" => color2.dodge(color),
-                "exclusion" => color2.exclusion(color),
-                "lighten" => color2.lighten(color),
-                "darken" => color2.darken(color),
-                _ => color2.overlay(color),
-            };
+            let color2 = gamma::blendable(px_data2);
+
+            let blended = blend_colours(color2, color, blend_mode);
 
             img.put_pixel(
                 x,
                 y,
                 image::Rgba {
-                    data: blended.into_format().into_raw(),
+                    data: gamma::from_blendable(blended),
                 },
             );
         }
@@ -118,6 +108,294 @@ pub fn blend(
     photon_image.raw_pixels = dynimage.raw_pixels();
 }
 
+/// Mix two images together, pixel by pixel, in proportions given by a mask.
+///
+/// Where the mask is white, the result is taken from `top`; where it's black, the base
+/// image is left unchanged, and shades of grey mix the two. Transparent areas of the mask
+/// count as black, so a mask painted onto a transparent layer only covers the painted
+/// strokes. This allows gradual transitions between two versions of an image, and
+/// hand-painted local edits.
+///
+/// # Arguments
+/// * `img` - The base image, which is modified in place.
+/// * `top` - The image to mix into the base image. Must be the same size as `img`.
+/// * `mask` - The mask whose brightness controls the mix. Must be the same size as `img`.
+/// # Example
+///
+/// ```
+/// // For example, to fade from one image to another from left to right:
+/// use photon_rs::multiple::blend_with_mask;
+/// use photon_rs::native::open_image;
+///
+/// let mut img = open_image("img.jpg");
+/// let top = open_image("img2.jpg");
+/// let mask = open_image("gradient.png");
+/// blend_with_mask(&mut img, &top, &mask);
+/// ```
+#[wasm_bindgen]
+pub fn blend_with_mask(
+    photon_image: &mut PhotonImage,
+    top: &PhotonImage,
+    mask: &PhotonImage,
+) {
+    let size = (photon_image.width, photon_image.height);
+    if (top.width, top.height) != size || (mask.width, mask.height) != size {
+        panic!("The top image and mask must be the same size as the base image.");
+    }
+
+    let pixels = photon_image.raw_pixels.chunks_exact_mut(4);
+    let others = top
+        .raw_pixels
+        .chunks_exact(4)
+        .zip(mask.raw_pixels.chunks_exact(4));
+    for (px, (top_px, mask_px)) in pixels.zip(others) {
+        let amount = helpers::luma(mask_px) as f32 * mask_px[3] as f32 / (255.0 * 255.0);
+        if amount == 0.0 {
+            continue;
+        }
+        let base = gamma::blendable([px[0], px[1], px[2], px[3]]);
+        let top = gamma::blendable([top_px[0], top_px[1], top_px[2], top_px[3]]);
+        px.copy_from_slice(&gamma::from_blendable(base.mix(&top, amount)));
+    }
+}
+
+/// Paste part of one image into another seamlessly, by Poisson (gradient-domain)
+/// blending, as described by Pérez, Gangnet and Blake (2003).
+///
+/// Instead of copying the patch's colours, the pasted region keeps the patch's gradients
+/// (its texture and detail), while its colours are solved for to match the base image
+/// all around the edge of the mask. An object pasted from a photo taken in different
+/// light takes on the colours and lighting of its new surroundings.
+///
+/// # Arguments
+/// * `base` - The image to paste into.
+/// * `patch` - The image to paste from.
+/// * `mask` - A mask of the same size as the patch, which is white over the part of the
+/// patch to paste, eg: made with the `mask` module. Leave a margin of background around
+/// the object, which will be blended away.
+/// * `position` - Where the patch's top-left corner is placed in the base image. Any
+/// part of the patch outside the base image is ignored.
+/// # Panics
+/// Panics if the mask isn't the same size as the patch.
+/// # Example
+///
+/// ```
+/// use photon_rs::mask::ellipse;
+/// use photon_rs::multiple::poisson_blend;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Point;
+///
+/// let mut base = open_image("beach.jpg");
+/// let boat = open_image("boat.jpg");
+/// let (w, h) = (boat.get_width(), boat.get_height());
+/// let (rx, ry) = (w as f32 / 2.0, h as f32 / 2.0);
+/// let mask = ellipse(w, h, rx, ry, rx, ry, 0.0);
+/// poisson_blend(&mut base, &boat, &mask, &Point::new(200, 340));
+/// ```
+#[wasm_bindgen]
+pub fn poisson_blend(
+    base: &mut PhotonImage,
+    patch: &PhotonImage,
+    mask: &PhotonImage,
+    position: &Point,
+) {
+    if (mask.width, mask.height) != (patch.width, patch.height) {
+        panic!("The mask must be the same size as the patch.");
+    }
+    let (width, height) = (base.width as i64, base.height as i64);
+    let (left, top) = (position.x as i64, position.y as i64);
+
+    // Number the pixels of the base image that are to be solved for.
+    let mut index = vec![usize::MAX; (width * height) as usize];
+    let mut region = vec![];
+    for (i, px) in mask.raw_pixels.chunks_exact(4).enumerate() {
+        let (px_x, px_y) = (i as i64 % mask.width as i64, i as i64 / mask.width as i64);
+        let (x, y) = (left + px_x, top + px_y);
+        if x < width && y < height && helpers::luma(px) >= 128 && px[3] >= 128 {
+            index[(y * width + x) as usize] = region.len();
+            region.push((x, y));
+        }
+    }
+    if region.is_empty() {
+        return;
+    }
+
+    let neighbours = |x: i64, y: i64| {
+        IntoIterator::into_iter([(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)])
+            .filter(|&(nx, ny)| nx >= 0 && ny >= 0 && nx < width && ny < height)
+    };
+    let patch_value = |x: i64, y: i64, c: usize| -> Option<f64> {
+        let (px, py) = (x - left, y - top);
+        if px < 0 || py < 0 || px >= patch.width as i64 || py >= patch.height as i64 {
+            return None;
+        }
+        Some(patch.raw_pixels[((py * patch.width as i64 + px) * 4) as usize + c] as f64)
+    };
+    // The discrete Laplacian over the region: each pixel's value times its number of
+    // neighbours, less the values of its neighbours in the region.
+    let laplacian = |values: &[f64]| -> Vec<f64> {
+        region
+            .iter()
+            .zip(values)
+            .map(|(&(x, y), &value)| {
+                neighbours(x, y).fold(0.0, |sum, (nx, ny)| {
+                    let j = index[(ny * width + nx) as usize];
+                    sum + value - if j == usize::MAX { 0.0 } else { values[j] }
+                })
+            })
+            .collect()
+    };
+
+    for c in 0..3 {
+        // The patch's gradients, plus the fixed base values around the region.
+        let target: Vec<f64> = region
+            .iter()
+            .map(|&(x, y)| {
+                let here = patch_value(x, y, c).unwrap_or(0.0);
+                neighbours(x, y).fold(0.0, |sum, (nx, ny)| {
+                    let gradient = patch_value(nx, ny, c).map_or(0.0, |v| here - v);
+                    let i = (ny * width + nx) as usize;
+                    let fixed = if index[i] == usize::MAX {
+                        base.raw_pixels[i * 4 + c] as f64
+                    } else {
+                        0.0
+                    };
+                    sum + gradient + fixed
+                })
+            })
+            .collect();
+
+        // Solve by conjugate gradients, starting from the patch's own colours.
+        let mut solution: Vec<f64> = region
+            .iter()
+            .map(|&(x, y)| patch_value(x, y, c).unwrap_or(0.0))
+            .collect();
+        let applied = laplacian(&solution);
+        let mut residual: Vec<f64> =
+            target.iter().zip(&applied).map(|(t, a)| t - a).collect();
+        let mut direction = residual.clone();
+        let mut error: f64 = residual.iter().map(|r| r * r).sum();
+        for _ in 0..MAX_POISSON_ITERATIONS {
+            if error < 1e-4 * region.len() as f64 {
+                break;
+            }
+            let applied = laplacian(&direction);
+            let step = error
+                / direction
+                    .iter()
+                    .zip(&applied)
+                    .map(|(d, a)| d * a)
+                    .sum::<f64>();
+            for i in 0..region.len() {
+                solution[i] += step * direction[i];
+                residual[i] -= step * applied[i];
+            }
+            let next_error: f64 = residual.iter().map(|r| r * r).sum();
+            for (d, r) in direction.iter_mut().zip(&residual) {
+                *d = r + next_error / error * *d;
+            }
+            error = next_error;
+        }
+
+        for (&(x, y), value) in region.iter().zip(solution) {
+            let i = ((y * width + x) * 4) as usize + c;
+            base.raw_pixels[i] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Blend two images through a mask across a Laplacian pyramid (multi-band blending), so
+/// that the transition between them is smooth without looking blurry or ghosted.
+///
+/// Each band of detail is blended over a distance that suits it: broad areas of colour
+/// and lighting fade gradually into each other, while fine detail switches over sharply,
+/// so even a hard-edged mask gives an invisible seam. The alpha channel is mixed
+/// through the mask directly.
+///
+/// # Arguments
+/// * `a` - The image to show where the mask is black.
+/// * `b` - The image to show where the mask is white.
+/// * `mask` - A mask, eg: made with the `mask` module, which is white where `b` should
+/// show, black where `a` should, and grey in between.
+/// * `levels` - The number of bands of detail. More levels blend broad differences in
+/// colour over a wider area. It's reduced if the images are too small for it.
+/// # Panics
+/// Panics if the images and the mask aren't all the same size.
+/// # Example
+///
+/// ```
+/// use photon_rs::mask::rect;
+/// use photon_rs::multiple::pyramid_blend;
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rect;
+///
+/// // The famous "orapple": the left of an apple and the right of an orange.
+/// let apple = open_image("apple.jpg");
+/// let orange = open_image("orange.jpg");
+/// let (w, h) = (apple.get_width(), apple.get_height());
+/// let right = rect(w, h, &Rect::new(w / 2, 0, w - w / 2, h), 0.0);
+/// let orapple = pyramid_blend(&apple, &orange, &right, 6);
+/// ```
+#[cfg(feature = "pyramid")]
+#[wasm_bindgen]
+pub fn pyramid_blend(
+    a: &PhotonImage,
+    b: &PhotonImage,
+    mask: &PhotonImage,
+    levels: u32,
+) -> PhotonImage {
+    let size = (a.width, a.height);
+    if (b.width, b.height) != size || (mask.width, mask.height) != size {
+        panic!("Both images and the mask must be the same size.");
+    }
+    let (width, height) = (a.width as usize, a.height as usize);
+    let amounts: Vec<f32> = mask
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f32 * px[3] as f32 / (255.0 * 255.0))
+        .collect();
+
+    let mut weights = vec![Plane::new(width, height, 1), Plane::new(width, height, 1)];
+    for (i, &amount) in amounts.iter().enumerate() {
+        weights[0].data[i] = 1.0 - amount;
+        weights[1].data[i] = amount;
+    }
+    let mut blended = fuse(&[a.clone(), b.clone()], weights, levels.max(1) as usize);
+    for (i, px) in blended.raw_pixels.chunks_exact_mut(4).enumerate() {
+        let alpha = a.raw_pixels[i * 4 + 3] as f32 * (1.0 - amounts[i])
+            + b.raw_pixels[i * 4 + 3] as f32 * amounts[i];
+        px[3] = alpha.round() as u8;
+    }
+    blended
+}
+
+/// Blend `color2` onto `color` using one of the blend modes accepted by `blend`.
+/// Unrecognised blend modes fall back to "overlay".
+pub(crate) fn blend_colours(
+    color2: LinSrgba,
+    color: LinSrgba,
+    blend_mode: &str,
+) -> LinSrgba {
+    match blend_mode {
+        // Match a single value
+        "overlay" => color2.overlay(color),
+        "over" => color2.over(color),
+        "atop" => color2.atop(color),
+        "xor" => color2.xor(color),
+        "plus" => color2.plus(color),
+        "multiply" => color2.multiply(color),
+        "burn" => color2.burn(color),
+        "difference" => color2.difference(color),
+        "soft_light" => color2.soft_light(color),
+        "hard_light" => color2.hard_light(color),
+        "dodge" => color2.dodge(color),
+        "exclusion" => color2.exclusion(color),
+        "lighten" => color2.lighten(color),
+        "darken" => color2.darken(color),
+        _ => color2.overlay(color),
+    }
+}
+
 /// Change the background of an image (using a green screen/color screen).
 ///
 /// # Arguments
@@ -217,3 +495,565 @@ pub fn apply_gradient(mut image: &mut PhotonImage) {
 
     blend(&mut image, &gradient, "overlay");
 }
+
+/// Merge bracketed exposures of the same scene into a single, well-exposed image, using
+/// Mertens' exposure fusion.
+///
+/// Each frame is weighted, pixel by pixel, by how well-exposed, saturated and detailed it
+/// is there, and the frames are blended across a Laplacian pyramid, so that the seams
+/// between them aren't visible. Unlike HDR merging, the exposure times aren't needed, and
+/// no tone mapping is required. The alpha channel is taken from the first frame.
+///
+/// # Arguments
+/// * `frames` - The exposures, which must all be the same size, and aligned.
+/// # Example
+///
+/// ```
+/// use photon_rs::multiple::exposure_fusion;
+/// use photon_rs::native::open_image;
+///
+/// let frames = vec![open_image("dark.jpg"), open_image("bright.jpg")];
+/// let fused = exposure_fusion(&frames);
+/// ```
+#[cfg(feature = "pyramid")]
+pub fn exposure_fusion(frames: &[PhotonImage]) -> PhotonImage {
+    check_frames(frames);
+    let weights: Vec<Plane> = frames.iter().map(exposure_weights).collect();
+    fuse(frames, weights, 8)
+}
+
+/// Merge several shots of the same scene, each focused at a different distance, into a
+/// single image which is sharp throughout (also known as focus stacking).
+///
+/// For each pixel, the frame with the most detail around it, measured by the energy of its
+/// Laplacian, is chosen. The chosen regions are then blended across a Laplacian pyramid, so
+/// that the seams between them aren't visible. The alpha channel is taken from the first
+/// frame.
+///
+/// # Arguments
+/// * `frames` - The shots, which must all be the same size, and aligned.
+/// # Example
+///
+/// ```
+/// use photon_rs::multiple::focus_stack;
+/// use photon_rs::native::open_image;
+///
+/// let frames = vec![open_image("near.jpg"), open_image("far.jpg")];
+/// let stacked = focus_stack(&frames);
+/// ```
+#[cfg(feature = "pyramid")]
+pub fn focus_stack(frames: &[PhotonImage]) -> PhotonImage {
+    let first = check_frames(frames);
+    let (width, height) = (first.width as usize, first.height as usize);
+    let energies: Vec<Vec<f32>> = frames.iter().map(laplacian_energy).collect();
+
+    let mut weights = vec![Plane::new(width, height, 1); frames.len()];
+    for i in 0..width * height {
+        let mut sharpest = 0;
+        for (frame, energy) in energies.iter().enumerate() {
+            if energy[i] > energies[sharpest][i] {
+                sharpest = frame;
+            }
+        }
+        weights[sharpest].data[i] = 1.0;
+    }
+    fuse(frames, weights, 8)
+}
+
+/// How `stack` combines the values of a pixel across frames.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackMethod {
+    /// The average value, which reduces random noise the most, and simulates a long
+    /// exposure, blurring anything which moves.
+    Mean,
+    /// The middle value, which removes anything present in only a few frames, such as
+    /// passers-by, satellite trails or hot pixels.
+    Median,
+}
+
+/// Combine several aligned frames of the same scene, pixel by pixel, to reduce noise.
+///
+/// Stacking `n` frames reduces random noise by a factor of about `√n`, which is how
+/// astrophotographs are made from many short exposures.
+///
+/// # Arguments
+/// * `frames` - The frames, which must all be the same size, and aligned.
+/// * `method` - How to combine the values of each pixel.
+/// # Example
+///
+/// ```
+/// use photon_rs::multiple::{stack, StackMethod};
+/// use photon_rs::native::open_image;
+///
+/// let frames = vec![open_image("1.jpg"), open_image("2.jpg"), open_image("3.jpg")];
+/// let stacked = stack(&frames, StackMethod::Median);
+/// ```
+pub fn stack(frames: &[PhotonImage], method: StackMethod) -> PhotonImage {
+    let first = check_frames(frames);
+    let mut values = vec![0u8; frames.len()];
+    let raw_pixels = (0..first.raw_pixels.len())
+        .map(|i| {
+            for (value, frame) in values.iter_mut().zip(frames) {
+                *value = frame.raw_pixels[i];
+            }
+            match method {
+                StackMethod::Mean => {
+                    let sum: u32 = values.iter().map(|&v| v as u32).sum();
+                    (sum as f32 / values.len() as f32).round() as u8
+                }
+                StackMethod::Median => {
+                    values.sort_unstable();
+                    let middle = values.len() / 2;
+                    if values.len().is_multiple_of(2) {
+                        (values[middle - 1] as u16 + values[middle] as u16).div_ceil(2)
+                            as u8
+                    } else {
+                        values[middle]
+                    }
+                }
+            }
+        })
+        .collect();
+    PhotonImage::new(raw_pixels, first.width, first.height)
+}
+
+/// Stitch overlapping photos, taken by turning the camera between shots, into a panorama.
+///
+/// Corners are detected in each frame and matched against the previous frame, and the
+/// homography between them is estimated from the matches with RANSAC, so that each frame
+/// can be warped into the perspective of the first. Where frames overlap, each pixel is
+/// taken from the frame whose centre is nearest, and the seams between them are hidden by
+/// blending across a Laplacian pyramid. Pixels of the result which aren't covered by any
+/// frame are transparent.
+///
+/// # Arguments
+/// * `frames` - The photos, in order, with each overlapping the one before it.
+/// # Errors
+/// Returns `PhotonError::Stitching` if there are no frames, or if a frame can't be aligned
+/// with the one before it, eg: because they don't overlap enough, or the overlap lacks
+/// texture.
+/// # Example
+///
+/// ```
+/// use photon_rs::multiple::stitch;
+/// use photon_rs::native::open_image;
+///
+/// let frames = vec![open_image("left.jpg"), open_image("right.jpg")];
+/// let panorama = stitch(&frames).unwrap();
+/// ```
+#[cfg(all(feature = "analysis", feature = "pyramid"))]
+pub fn stitch(frames: &[PhotonImage]) -> Result<PhotonImage> {
+    match frames.len() {
+        0 => {
+            return Err(PhotonError::Stitching(
+                "at least one frame is needed".into(),
+            ))
+        }
+        1 => return Ok(frames[0].clone()),
+        _ => {}
+    }
+
+    // Map each frame into the perspective of the first.
+    let keypoints: Vec<_> = frames.iter().map(detect_features).collect();
+    let mut to_first = vec![features::IDENTITY];
+    for i in 1..frames.len() {
+        let (points, descriptors) = &keypoints[i];
+        let (prev_points, prev_descriptors) = &keypoints[i - 1];
+        let matches = features::match_descriptors(descriptors, prev_descriptors);
+        let from: Vec<_> = matches.iter().map(|&(a, _)| points[a]).collect();
+        let to: Vec<_> = matches.iter().map(|&(_, b)| prev_points[b]).collect();
+        let homography = match features::ransac_homography(&from, &to, 1000, 3.0) {
+            Some((h, inliers)) if inliers >= MIN_INLIERS => h,
+            _ => {
+                return Err(PhotonError::Stitching(format!(
+                    "frame {} couldn't be aligned with frame {}",
+                    i,
+                    i - 1
+                )))
+            }
+        };
+        to_first.push(features::multiply(&to_first[i - 1], &homography));
+    }
+
+    // Find the bounds of the panorama.
+    let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+    let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    let mut frame_area = 0.0;
+    for (frame, h) in frames.iter().zip(&to_first) {
+        let (w, ht) = (frame.width as f64 - 1.0, frame.height as f64 - 1.0);
+        for &(x, y) in &[(0.0, 0.0), (w, 0.0), (0.0, ht), (w, ht)] {
+            let (x, y) = features::transform(h, x, y).unwrap_or((f64::NAN, f64::NAN));
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        frame_area += (w + 1.0) * (ht + 1.0);
+    }
+    let width = (max_x - min_x - 1e-6).ceil() + 1.0;
+    let height = (max_y - min_y - 1e-6).ceil() + 1.0;
+    // A wildly distorted panorama means that an alignment was wrong.
+    if !(width.is_finite() && height.is_finite()) || width * height > 4.0 * frame_area {
+        return Err(PhotonError::Stitching(
+            "the frames couldn't be aligned consistently".into(),
+        ));
+    }
+    let (width, height) = (width as usize, height as usize);
+    let offset = [1.0, 0.0, -min_x, 0.0, 1.0, -min_y, 0.0, 0.0, 1.0];
+
+    // Warp each frame onto the panorama, and use the nearest frame for each pixel.
+    let mut warped = vec![];
+    let mut weights = vec![Plane::new(width, height, 1); frames.len()];
+    let mut nearest = vec![f64::INFINITY; width * height];
+    let mut covered = vec![false; width * height];
+    for (index, (frame, h)) in frames.iter().zip(&to_first).enumerate() {
+        let to_frame =
+            features::invert(&features::multiply(&offset, h)).ok_or_else(|| {
+                PhotonError::Stitching(format!("frame {} couldn't be warped", index))
+            })?;
+        let (fw, fh) = (frame.width as f64, frame.height as f64);
+        let mut out =
+            PhotonImage::new(vec![0; width * height * 4], width as u32, height as u32);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = match features::transform(&to_frame, x as f64, y as f64) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                // Allow half a pixel of slack, so that rounding doesn't leave gaps.
+                if sx < -0.5 || sy < -0.5 || sx > fw - 0.5 || sy > fh - 0.5 {
+                    continue;
+                }
+                let (sx, sy) = (sx.clamp(0.0, fw - 1.0), sy.clamp(0.0, fh - 1.0));
+                let i = y * width + x;
+                out.raw_pixels[i * 4..i * 4 + 4]
+                    .copy_from_slice(&helpers::sample_bilinear(frame, sx, sy));
+                covered[i] = true;
+                let distance =
+                    ((sx / fw - 0.5).powi(2) + (sy / fh - 0.5).powi(2)).sqrt();
+                if distance < nearest[i] {
+                    nearest[i] = distance;
+                    for weight in weights.iter_mut() {
+                        weight.data[i] = 0.0;
+                    }
+                    weights[index].data[i] = 1.0;
+                }
+            }
+        }
+        warped.push(out);
+    }
+
+    let mut panorama = fuse(&warped, weights, 8);
+    for (px, &covered) in panorama.raw_pixels.chunks_exact_mut(4).zip(&covered) {
+        px[3] = if covered { 255 } else { 0 };
+    }
+    Ok(panorama)
+}
+
+/// The arrangement of the images in a collage.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollageLayout {
+    /// Equal cells in rows, with as many columns as rows, or one more.
+    Grid,
+    /// Equal cells side by side in a single row.
+    Strip,
+    /// The first image in a large cell, the size of two by two cells, in the top-left
+    /// corner, with the others beside and below it, three cells to a row.
+    Featured,
+}
+
+/// Arrange several images into a single collage, as is common for posts on social media.
+///
+/// Every cell is the size of the first image. Each image is scaled to cover its cell,
+/// keeping its aspect ratio, and cropped about its centre to fit.
+///
+/// # Arguments
+/// * `images` - The images, in the order in which they fill the layout.
+/// * `layout` - The arrangement of the images.
+/// * `gap` - The space between the cells, and around the edge of the collage, in pixels.
+/// * `background` - The colour of the gaps, and of any unused cells.
+///
+/// Images with no pixels leave their cells filled with the background colour.
+/// # Panics
+/// Panics if there are no images.
+/// # Example
+///
+/// ```
+/// use photon_rs::multiple::{collage, CollageLayout};
+/// use photon_rs::native::open_image;
+/// use photon_rs::Rgba;
+///
+/// let images = vec![open_image("a.jpg"), open_image("b.jpg"), open_image("c.jpg")];
+/// let white = Rgba::new(255, 255, 255, 255);
+/// let grid = collage(&images, CollageLayout::Grid, 8, &white);
+/// ```
+pub fn collage(
+    images: &[PhotonImage],
+    layout: CollageLayout,
+    gap: u32,
+    background: &Rgba,
+) -> PhotonImage {
+    let first = images.first().expect("A collage needs at least one image.");
+    let (cell_width, cell_height) = (first.width, first.height);
+    let count = images.len() as u32;
+
+    // The column, row and span of each image's cell, and the number of columns.
+    let (columns, cells): (u32, Vec<(u32, u32, u32)>) = match layout {
+        CollageLayout::Grid => {
+            let columns = (count as f64).sqrt().ceil() as u32;
+            let cells = (0..count).map(|i| (i % columns, i / columns, 1)).collect();
+            (columns, cells)
+        }
+        CollageLayout::Strip => (count, (0..count).map(|i| (i, 0, 1)).collect()),
+        CollageLayout::Featured => {
+            let mut cells = vec![(0, 0, 2)];
+            cells.extend((0..count - 1).map(|i| match i {
+                0 | 1 => (2, i, 1),
+                _ => ((i - 2) % 3, 2 + (i - 2) / 3, 1),
+            }));
+            (if count == 1 { 2 } else { 3 }, cells)
+        }
+    };
+    let rows = cells
+        .iter()
+        .map(|&(_, row, span)| row + span)
+        .max()
+        .unwrap();
+
+    let width = columns * cell_width + (columns + 1) * gap;
+    let height = rows * cell_height + (rows + 1) * gap;
+    let fill = [
+        background.get_red(),
+        background.get_green(),
+        background.get_blue(),
+        background.get_alpha(),
+    ];
+    let mut canvas =
+        PhotonImage::new(fill.repeat((width * height) as usize), width, height);
+
+    for (image, &(column, row, span)) in images.iter().zip(&cells) {
+        let x = gap + column * (cell_width + gap);
+        let y = gap + row * (cell_height + gap);
+        let (fit_width, fit_height) = (
+            span * cell_width + (span - 1) * gap,
+            span * cell_height + (span - 1) * gap,
+        );
+        // An image with no pixels can't be scaled to cover its cell, so leaves it empty.
+        let is_empty = |width: u32, height: u32| width == 0 || height == 0;
+        if is_empty(image.width, image.height) || is_empty(fit_width, fit_height) {
+            continue;
+        }
+        let fitted = cover(image, fit_width, fit_height);
+        let row_len = (fitted.width * 4) as usize;
+        for (dy, src) in fitted.raw_pixels.chunks_exact(row_len).enumerate() {
+            let start = (((y + dy as u32) * width + x) * 4) as usize;
+            canvas.raw_pixels[start..start + row_len].copy_from_slice(src);
+        }
+    }
+    canvas
+}
+
+/// Scale an image to cover the given size, keeping its aspect ratio, and crop it about its
+/// centre to that size. Neither the image nor the size may be empty.
+fn cover(photon_image: &PhotonImage, width: u32, height: u32) -> PhotonImage {
+    let (image_width, image_height) = (photon_image.width, photon_image.height);
+    let scale =
+        (width as f64 / image_width as f64).max(height as f64 / image_height as f64);
+    let crop_width = ((width as f64 / scale).round() as u32).clamp(1, image_width);
+    let crop_height = ((height as f64 / scale).round() as u32).clamp(1, image_height);
+    let region = Rect::new(
+        (image_width - crop_width) / 2,
+        (image_height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    );
+    resize(
+        &crop_rect(photon_image, &region),
+        width,
+        height,
+        SamplingFilter::Triangle,
+    )
+}
+
+/// The fewest matches which must agree on the alignment of two frames for it to be
+/// trusted.
+#[cfg(all(feature = "analysis", feature = "pyramid"))]
+const MIN_INLIERS: usize = 10;
+
+/// The most iterations of conjugate gradients when solving for a Poisson blend, which
+/// usually converges in far fewer.
+const MAX_POISSON_ITERATIONS: usize = 2000;
+
+/// Detect the corners of a frame, returning their positions and descriptors.
+#[cfg(all(feature = "analysis", feature = "pyramid"))]
+fn detect_features(frame: &PhotonImage) -> (Vec<(f64, f64)>, Vec<[f32; 64]>) {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let gray = features::gray(frame);
+    let response = features::harris_response(&gray, width, height);
+    let strongest = response.iter().cloned().fold(0.0, f32::max);
+    let mut corners = features::peaks(
+        &response,
+        width,
+        height,
+        0.01 * strongest,
+        features::BORDER,
+        true,
+    );
+    corners.truncate(1000);
+    let blurred = features::blur(&gray, width, height);
+    let points = corners.iter().map(|&(x, y)| (x as f64, y as f64)).collect();
+    let descriptors = corners
+        .iter()
+        .map(|&(x, y)| features::describe(&blurred, width, x, y))
+        .collect();
+    (points, descriptors)
+}
+
+/// Blend frames across a Laplacian pyramid of at most `max_levels` levels, using a
+/// weight for each pixel of each frame. The weights are normalised, so that they sum to 1 at
+/// each pixel, unless they are all 0.
+#[cfg(feature = "pyramid")]
+fn fuse(
+    frames: &[PhotonImage],
+    mut weights: Vec<Plane>,
+    max_levels: usize,
+) -> PhotonImage {
+    let first = &frames[0];
+    let (width, height) = (first.width as usize, first.height as usize);
+    let levels = pyramid::levels_for(width, height, max_levels);
+
+    for i in 0..width * height {
+        let total: f32 = weights.iter().map(|w| w.data[i]).sum();
+        if total > 0.0 {
+            for w in weights.iter_mut() {
+                w.data[i] /= total;
+            }
+        }
+    }
+
+    let mut fused: Vec<Plane> = vec![];
+    for (frame, weight) in frames.iter().zip(&weights) {
+        let laplacian = pyramid::laplacian_pyramid(&Plane::from_rgb(frame), levels);
+        let weight = pyramid::gaussian_pyramid(weight, levels);
+        if fused.is_empty() {
+            fused = laplacian
+                .iter()
+                .map(|level| Plane::new(level.width, level.height, 3))
+                .collect();
+        }
+        for ((out, detail), weight) in fused.iter_mut().zip(&laplacian).zip(&weight) {
+            for (i, w) in weight.data.iter().enumerate() {
+                for c in 0..3 {
+                    out.data[i * 3 + c] += w * detail.data[i * 3 + c];
+                }
+            }
+        }
+    }
+
+    let mut result = first.clone();
+    pyramid::collapse(&fused).write_rgb(&mut result);
+    result
+}
+
+/// Check that there is at least one frame, and that all of them are the same size, and
+/// return the first.
+fn check_frames(frames: &[PhotonImage]) -> &PhotonImage {
+    let first = match frames.first() {
+        Some(first) => first,
+        None => panic!("At least one frame is needed."),
+    };
+    if frames
+        .iter()
+        .any(|frame| frame.width != first.width || frame.height != first.height)
+    {
+        panic!("All frames must be the same size.");
+    }
+    first
+}
+
+/// The weight of each pixel of a frame in exposure fusion: the product of its contrast,
+/// saturation and well-exposedness, as in Mertens et al.
+#[cfg(feature = "pyramid")]
+fn exposure_weights(frame: &PhotonImage) -> Plane {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let gray: Vec<f32> = frame
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f32 / 255.0)
+        .collect();
+    let at = |x: isize, y: isize| {
+        let x = x.max(0).min(width as isize - 1) as usize;
+        let y = y.max(0).min(height as isize - 1) as usize;
+        gray[y * width + x]
+    };
+
+    let mut weights = Plane::new(width, height, 1);
+    for (i, px) in frame.raw_pixels.chunks_exact(4).enumerate() {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+        let contrast =
+            (4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1))
+                .abs();
+
+        let rgb = [
+            px[0] as f32 / 255.0,
+            px[1] as f32 / 255.0,
+            px[2] as f32 / 255.0,
+        ];
+        let mean = (rgb[0] + rgb[1] + rgb[2]) / 3.0;
+        let saturation =
+            (rgb.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / 3.0).sqrt();
+        let exposedness: f32 = rgb
+            .iter()
+            .map(|v| (-(v - 0.5).powi(2) / (2.0 * 0.2 * 0.2)).exp())
+            .product();
+
+        // A small constant keeps the weights defined where all of the frames are flat.
+        weights.data[i] = contrast * saturation * exposedness + 1e-6;
+    }
+    weights
+}
+
+/// The energy of the Laplacian of a frame's luma, averaged over a 5x5 window around each
+/// pixel, which is high where the frame is in focus.
+#[cfg(feature = "pyramid")]
+fn laplacian_energy(frame: &PhotonImage) -> Vec<f32> {
+    let (width, height) = (frame.width as isize, frame.height as isize);
+    let gray: Vec<f32> = frame
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f32)
+        .collect();
+    let clamped = |values: &[f32], x: isize, y: isize| {
+        let x = x.max(0).min(width - 1);
+        let y = y.max(0).min(height - 1);
+        values[(y * width + x) as usize]
+    };
+
+    let mut energy = vec![0.0; gray.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let laplacian = 4.0 * clamped(&gray, x, y)
+                - clamped(&gray, x - 1, y)
+                - clamped(&gray, x + 1, y)
+                - clamped(&gray, x, y - 1)
+                - clamped(&gray, x, y + 1);
+            energy[(y * width + x) as usize] = laplacian * laplacian;
+        }
+    }
+
+    let mut averaged = vec![0.0; energy.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for dy in -2..=2 {
+                for dx in -2..=2 {
+                    sum += clamped(&energy, x + dx, y + dy);
+                }
+            }
+            averaged[(y * width + x) as usize] = sum / 25.0;
+        }
+    }
+    averaged
+}
@@ -2,10 +2,21 @@
 //! Includes functions that open images from the file-system, etc.,
 
 extern crate image;
-extern crate rand;
 use image::{GenericImageView, ImageBuffer};
 // use wasm_bindgen::prelude::*;
+#[cfg(feature = "gif")]
+use crate::error::{PhotonError, Result};
+#[cfg(feature = "gif")]
+use crate::quantize::{median_cut, remap, Dither};
 use crate::PhotonImage;
+#[cfg(feature = "gif")]
+use gif::{DisposalMethod, Encoder, Frame, Repeat, SetParameter};
+#[cfg(feature = "gif")]
+use std::borrow::Cow;
+#[cfg(feature = "gif")]
+use std::fs::File;
+#[cfg(feature = "gif")]
+use std::io::{BufWriter, Write};
 
 /// Open an image at a given path from the filesystem.
 /// A PhotonImage is returned.
@@ -62,3 +73,253 @@ pub fn save_image(img: PhotonImage, img_path: &str) {
 
     dynimage.save(img_path).unwrap();
 }
+
+/// Where the palettes of an animated GIF's frames come from.
+#[cfg(feature = "gif")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GifPalette {
+    /// One palette, chosen for all the frames together. Gives the smallest files, and
+    /// colours which don't flicker between frames.
+    Global,
+    /// A palette for each frame, chosen for it alone. Gives more accurate colours when
+    /// they change a lot over the animation.
+    PerFrame,
+}
+
+/// Options for writing an animated GIF with `save_gif` or `encode_gif`.
+#[cfg(feature = "gif")]
+#[derive(Clone, Debug)]
+pub struct GifOptions {
+    /// The delay between frames, in hundredths of a second.
+    pub delay: u16,
+    /// How many times to play the animation, or 0 to loop it forever.
+    pub repeat: u16,
+    /// Where the palettes of the frames come from.
+    pub palette: GifPalette,
+    /// The most colours in each palette, from 2 to 256. One is kept for transparency if
+    /// any frame needs it.
+    pub colours: usize,
+    /// How to hide the banding that a limited palette leaves in gradients.
+    pub dither: Dither,
+    /// Whether to store only the part of each frame which changed since the one before,
+    /// leaving the rest transparent so the previous frame shows through.
+    pub delta: bool,
+}
+
+#[cfg(feature = "gif")]
+impl Default for GifOptions {
+    fn default() -> GifOptions {
+        GifOptions {
+            delay: 10,
+            repeat: 0,
+            palette: GifPalette::Global,
+            colours: 256,
+            dither: Dither::Ordered,
+            delta: true,
+        }
+    }
+}
+
+/// Save images as the frames of an animated GIF.
+///
+/// # Arguments
+/// * `frames` - The frames of the animation, which must all be the same size.
+/// * `img_path` - Path for the outputted GIF.
+/// * `options` - The delay between frames, how to choose palettes and dither, and
+///   whether to store only the changes between frames.
+/// # Errors
+/// Returns `PhotonError::Io` if the file can't be written.
+/// # Panics
+/// Panics if there are no frames, they aren't all the same size, either side is larger
+/// than 65535 pixels, or `options.colours` isn't from 2 to 256.
+///
+/// # Example
+/// ```
+/// use photon_rs::native::{open_image, save_gif, GifOptions};
+/// use photon_rs::quantize::Dither;
+///
+/// let frames = vec![open_image("frame1.png"), open_image("frame2.png")];
+/// let options = GifOptions {
+///     delay: 20,
+///     dither: Dither::FloydSteinberg,
+///     ..GifOptions::default()
+/// };
+/// save_gif(&frames, "animation.gif", &options).unwrap();
+/// ```
+#[cfg(feature = "gif")]
+pub fn save_gif(
+    frames: &[PhotonImage],
+    img_path: &str,
+    options: &GifOptions,
+) -> Result<()> {
+    let io_error =
+        |err: std::io::Error| PhotonError::Io(format!("{}: {}", img_path, err));
+    let file = File::create(img_path).map_err(io_error)?;
+    let mut writer = BufWriter::new(file);
+    write_gif(&mut writer, frames, options).map_err(io_error)?;
+    writer.flush().map_err(io_error)
+}
+
+/// Encode images as the frames of an animated GIF, in memory.
+///
+/// # Arguments
+/// * `frames` - The frames of the animation, which must all be the same size.
+/// * `options` - The delay between frames, how to choose palettes and dither, and
+///   whether to store only the changes between frames.
+/// # Panics
+/// Panics if there are no frames, they aren't all the same size, either side is larger
+/// than 65535 pixels, or `options.colours` isn't from 2 to 256.
+#[cfg(feature = "gif")]
+pub fn encode_gif(frames: &[PhotonImage], options: &GifOptions) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_gif(&mut bytes, frames, options).expect("writing to a Vec can't fail");
+    bytes
+}
+
+/// Encode frames as a GIF, to any writer.
+#[cfg(feature = "gif")]
+fn write_gif<W: Write>(
+    writer: &mut W,
+    frames: &[PhotonImage],
+    options: &GifOptions,
+) -> std::io::Result<()> {
+    let first = frames.first().expect("A GIF needs at least one frame.");
+    let (width, height) = (first.width, first.height);
+    if frames
+        .iter()
+        .any(|f| (f.width, f.height) != (width, height))
+    {
+        panic!("The frames of a GIF must all be the same size.");
+    }
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        panic!("A GIF can be at most 65535 pixels on each side.");
+    }
+    if options.colours < 2 || options.colours > 256 {
+        panic!(
+            "A GIF palette must have from 2 to 256 colours, not {}.",
+            options.colours
+        );
+    }
+
+    // Each frame may be stored as a delta from the one before only if neither has
+    // transparent pixels, as a GIF frame can't make a visible pixel transparent again
+    // without clearing the whole frame.
+    let opaque: Vec<bool> = frames
+        .iter()
+        .map(|f| f.raw_pixels.chunks_exact(4).all(|px| px[3] >= 128))
+        .collect();
+    let is_delta: Vec<bool> = (0..frames.len())
+        .map(|i| options.delta && i > 0 && opaque[i - 1] && opaque[i])
+        .collect();
+    let needs_transparency = opaque.contains(&false) || is_delta.contains(&true);
+    let colours = options.colours - needs_transparency as usize;
+
+    let global = match options.palette {
+        GifPalette::Global => Some(median_cut(frames, colours)),
+        GifPalette::PerFrame => None,
+    };
+    let global_table = global
+        .as_ref()
+        .map_or(vec![], |p| flatten(p, needs_transparency));
+    let mut encoder = Encoder::new(writer, width as u16, height as u16, &global_table)?;
+    encoder.set(if options.repeat == 0 {
+        Repeat::Infinite
+    } else {
+        Repeat::Finite(options.repeat)
+    })?;
+
+    for (i, frame) in frames.iter().enumerate() {
+        let palette = match &global {
+            Some(palette) => Cow::Borrowed(palette),
+            None => Cow::Owned(median_cut(std::slice::from_ref(frame), colours)),
+        };
+        let transparent = palette.len() as u8;
+        let mut indices = remap(frame, &palette, options.dither);
+        for (index, px) in indices.iter_mut().zip(frame.raw_pixels.chunks_exact(4)) {
+            if px[3] < 128 {
+                *index = transparent;
+            }
+        }
+
+        // Crop a delta frame to the pixels which changed, and make the unchanged pixels
+        // within that transparent, so the frame before shows through.
+        let (mut left, mut top, mut right, mut bottom) = (0, 0, width, height);
+        if is_delta[i] {
+            let previous = &frames[i - 1].raw_pixels;
+            let changed = |p: u32| {
+                let at = p as usize * 4;
+                previous[at..at + 3] != frame.raw_pixels[at..at + 3]
+            };
+            let bounds = (0..width * height).filter(|&p| changed(p)).fold(
+                None::<(u32, u32, u32, u32)>,
+                |b, p| {
+                    let (x, y) = (p % width, p / width);
+                    Some(match b {
+                        None => (x, y, x + 1, y + 1),
+                        Some((l, t, r, b)) => {
+                            (l.min(x), t.min(y), r.max(x + 1), b.max(y + 1))
+                        }
+                    })
+                },
+            );
+            match bounds {
+                Some(bounds) => {
+                    (left, top, right, bottom) = bounds;
+                    for p in 0..width * height {
+                        if !changed(p) {
+                            indices[p as usize] = transparent;
+                        }
+                    }
+                }
+                // Nothing changed, so store a single transparent pixel.
+                None => {
+                    (right, bottom) = (1, 1);
+                    indices[0] = transparent;
+                }
+            }
+        }
+        let mut buffer = Vec::with_capacity(((right - left) * (bottom - top)) as usize);
+        for y in top..bottom {
+            let row = (y * width) as usize;
+            buffer
+                .extend_from_slice(&indices[row + left as usize..row + right as usize]);
+        }
+
+        let next_is_delta = is_delta.get(i + 1).copied().unwrap_or(false);
+        encoder.write_frame(&Frame {
+            delay: options.delay,
+            dispose: if next_is_delta {
+                DisposalMethod::Keep
+            } else {
+                DisposalMethod::Background
+            },
+            transparent: if needs_transparency {
+                Some(transparent)
+            } else {
+                None
+            },
+            left: left as u16,
+            top: top as u16,
+            width: (right - left) as u16,
+            height: (bottom - top) as u16,
+            palette: match options.palette {
+                GifPalette::Global => None,
+                GifPalette::PerFrame => Some(flatten(&palette, needs_transparency)),
+            },
+            buffer: Cow::Owned(buffer),
+            ..Frame::default()
+        })?;
+    }
+    Ok(())
+}
+
+/// Flatten a palette into a GIF colour table, with an extra entry after its colours for
+/// transparent pixels if needed.
+#[cfg(feature = "gif")]
+fn flatten(palette: &[[u8; 3]], transparency: bool) -> Vec<u8> {
+    let mut table: Vec<u8> = palette.iter().flatten().copied().collect();
+    if transparency {
+        table.extend_from_slice(&[0, 0, 0]);
+    }
+    table
+}
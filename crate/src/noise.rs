@@ -4,14 +4,54 @@ extern crate image;
 extern crate rand;
 use image::Pixel;
 use image::{GenericImage, GenericImageView};
-use rand::Rng;
-// use wasm_bindgen::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
 use crate::helpers;
 use crate::PhotonImage;
 use crate::iter::ImageIterator;
 
+thread_local! {
+    static SEEDED_RNG: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Seed the random number generator used by `add_noise_rand`, `pink_noise` and
+/// `gaussian_noise`, so that their output is reproducible, eg: in tests, or when the same
+/// asset is rendered on several servers.
+///
+/// After seeding, the sequence of noise produced by successive calls on the current thread
+/// is the same every time. Functions which take a `seed` argument, such as `film_grain`,
+/// don't use this generator, and are always reproducible.
+///
+/// # Arguments
+/// * `seed` - The seed for the random number generator.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::noise::{gaussian_noise, set_seed};
+///
+/// let mut img = open_image("img.jpg");
+/// set_seed(42);
+/// gaussian_noise(&mut img, 10.0, true);
+/// ```
+#[wasm_bindgen]
+pub fn set_seed(seed: u64) {
+    SEEDED_RNG.with(|rng| *rng.borrow_mut() = Some(StdRng::seed_from_u64(seed)));
+}
+
+/// Undo `set_seed`, so that noise is random again.
+#[wasm_bindgen]
+pub fn clear_seed() {
+    SEEDED_RNG.with(|rng| *rng.borrow_mut() = None);
+}
+
 /// Add randomized noise to an image.
-/// This function adds a Gaussian Noise Sample to each pixel through incrementing each channel by a randomized offset.
+/// This function brightens each pixel by a uniformly distributed random offset. For noise which
+/// resembles a camera sensor's, see `gaussian_noise`.
 /// This randomized offset is generated by creating a randomized thread pool.
 /// **[WASM SUPPORT NOT AVAILABLE]**: Randomized thread pools cannot be created with WASM using the code used currently, but
 /// a workaround is oncoming.
@@ -31,7 +71,7 @@ use crate::iter::ImageIterator;
 /// ```
 pub fn add_noise_rand(mut photon_image: PhotonImage) -> PhotonImage {
     let mut img = helpers::dyn_image_from_raw(&photon_image);
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
 
     for (x, y) in ImageIterator::with_dimension(&img.dimensions()) {
         let offset = rng.gen_range(0, 150);
@@ -67,7 +107,7 @@ pub fn add_noise_rand(mut photon_image: PhotonImage) -> PhotonImage {
 /// ```
 pub fn pink_noise(mut photon_image: &mut PhotonImage) {
     let mut img = helpers::dyn_image_from_raw(&photon_image);
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
 
     for (x, y) in ImageIterator::with_dimension(&img.dimensions()) {
         let ran1: f64 = rng.gen(); // generates a float between 0 and 1
@@ -86,3 +126,359 @@ pub fn pink_noise(mut photon_image: &mut PhotonImage) {
     }
     photon_image.raw_pixels = img.raw_pixels();
 }
+
+/// Add Gaussian noise with a standard deviation of `sigma` to an image.
+///
+/// Camera sensor noise is close to Gaussian, so this can be used to match the noise level
+/// of real photos, eg: when compositing a clean render into a photo, or to generate test
+/// images for denoising filters. The noise is reproducible after `set_seed`.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `sigma` - The standard deviation of the noise, on the 0 to 255 scale, eg: 10.0.
+/// * `per_channel` - Whether each channel gets its own noise, or all share the same offset.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::noise::gaussian_noise;
+///
+/// let mut img = open_image("img.jpg");
+/// gaussian_noise(&mut img, 10.0, true);
+/// ```
+#[wasm_bindgen]
+pub fn gaussian_noise(photon_image: &mut PhotonImage, sigma: f32, per_channel: bool) {
+    let mut rng = rng();
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        let shared = sigma as f64 * gaussian(&mut rng);
+        for value in px[..3].iter_mut() {
+            let offset = if per_channel {
+                sigma as f64 * gaussian(&mut rng)
+            } else {
+                shared
+            };
+            *value = num::clamp(*value as f64 + offset, 0.0, 255.0).round() as u8;
+        }
+    }
+}
+
+/// Generate a grayscale image of Perlin noise: a smooth, cloud-like texture which is
+/// useful as a displacement map, or as a source of clouds and grain.
+///
+/// Several octaves of noise are layered, each with twice the frequency and half the
+/// amplitude of the previous one, to add finer detail. The same seed always produces the
+/// same image.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `scale` - The size of the largest features, in pixels, eg: 64.0.
+/// * `octaves` - The number of layers of noise, eg: 4. Must be at least 1.
+/// * `seed` - The seed for the random number generator.
+///
+/// # Example
+///
+/// ```
+/// // For example, to generate a 256x256 cloud texture:
+/// use photon_rs::noise::perlin;
+///
+/// let clouds = perlin(256, 256, 64.0, 4, 42);
+/// ```
+#[wasm_bindgen]
+pub fn perlin(
+    width: u32,
+    height: u32,
+    scale: f32,
+    octaves: u32,
+    seed: u64,
+) -> PhotonImage {
+    let perm = permutation(seed);
+    fractal_noise(width, height, scale, octaves, |x, y| perlin_2d(&perm, x, y))
+}
+
+/// Generate a grayscale image of simplex noise.
+///
+/// Simplex noise looks similar to Perlin noise, but has fewer directional artifacts, since
+/// it is built from a grid of triangles rather than squares. As with `perlin`, several
+/// octaves are layered, and the same seed always produces the same image.
+///
+/// # Arguments
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `scale` - The size of the largest features, in pixels, eg: 64.0.
+/// * `octaves` - The number of layers of noise, eg: 4. Must be at least 1.
+/// * `seed` - The seed for the random number generator.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::noise::simplex;
+///
+/// let texture = simplex(256, 256, 64.0, 4, 42);
+/// ```
+#[wasm_bindgen]
+pub fn simplex(
+    width: u32,
+    height: u32,
+    scale: f32,
+    octaves: u32,
+    seed: u64,
+) -> PhotonImage {
+    let perm = permutation(seed);
+    fractal_noise(width, height, scale, octaves, |x, y| {
+        simplex_2d(&perm, x, y)
+    })
+}
+
+/// Add film grain to an image.
+///
+/// Unlike per-pixel noise, the grain has a size, so it still looks like film grain rather
+/// than digital noise when a high resolution image is viewed or printed at full size. As
+/// with real film, the grain is most visible in the midtones. The same seed always
+/// produces the same grain.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `intensity` - The strength of the grain, as a fraction of the full range of values,
+/// eg: 0.08.
+/// * `size` - The size of the grains in pixels, eg: 1.5. Values below 1 are treated as 1.
+/// * `monochrome` - Whether the grain is the same in every channel, as with black and white
+/// film, or varies per channel, as with colour film.
+/// * `seed` - The seed for the random number generator.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::noise::film_grain;
+///
+/// let mut img = open_image("img.jpg");
+/// film_grain(&mut img, 0.08, 1.5, true, 42);
+/// ```
+#[wasm_bindgen]
+pub fn film_grain(
+    photon_image: &mut PhotonImage,
+    intensity: f32,
+    size: f32,
+    monochrome: bool,
+    seed: u64,
+) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let size = size.max(1.0) as f64;
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+
+    // Grain is random values on a grid with a spacing of `size`, interpolated in between.
+    let grid_width = (width as f64 / size).ceil() as usize + 2;
+    let grid_height = (height as f64 / size).ceil() as usize + 2;
+    let layers = if monochrome { 1 } else { 3 };
+    let grids: Vec<Vec<f64>> = (0..layers)
+        .map(|_| {
+            (0..grid_width * grid_height)
+                .map(|_| gaussian(&mut rng))
+                .collect()
+        })
+        .collect();
+
+    for y in 0..height {
+        let grid_y = y as f64 / size;
+        let (y0, ty) = (grid_y as usize, grid_y.fract());
+        for x in 0..width {
+            let grid_x = x as f64 / size;
+            let (x0, tx) = (grid_x as usize, grid_x.fract());
+            let i = (y * width + x) * 4;
+            let px = &mut photon_image.raw_pixels[i..i + 4];
+
+            let lightness = helpers::luma(px) as f64 / 255.0;
+            let strength =
+                intensity as f64 * 255.0 * (0.5 + 2.0 * lightness * (1.0 - lightness));
+            for c in 0..3 {
+                let grid = &grids[c % layers];
+                let at = |gx: usize, gy: usize| grid[gy * grid_width + gx];
+                let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+                let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+                let grain = top * (1.0 - ty) + bottom * ty;
+                px[c] = num::clamp(px[c] as f64 + strength * grain, 0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+/// A random number generator, derived from the one set by `set_seed` if there is one, or
+/// seeded from a source of entropy otherwise.
+fn rng() -> StdRng {
+    SEEDED_RNG.with(|seeded| match seeded.borrow_mut().as_mut() {
+        Some(seeded) => StdRng::seed_from_u64(seeded.gen()),
+        None => entropy_rng(),
+    })
+}
+
+/// A generator seeded by the operating system.
+#[cfg(not(target_arch = "wasm32"))]
+fn entropy_rng() -> StdRng {
+    StdRng::from_entropy()
+}
+
+/// A generator seeded by `Math.random`, as `rand` can't reach the browser's source of
+/// entropy by itself.
+#[cfg(target_arch = "wasm32")]
+fn entropy_rng() -> StdRng {
+    let random = || (js_sys::Math::random() * u32::MAX as f64) as u64;
+    StdRng::seed_from_u64((random() << 32) | random())
+}
+
+/// A random value from the standard normal distribution, using the Box-Muller transform.
+fn gaussian<R: Rng>(rng: &mut R) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Add salt-and-pepper noise to an image, setting randomly chosen pixels to pure white or
+/// pure black.
+///
+/// This is useful for generating test images for denoising filters, such as a median
+/// filter, or for a stylised, degraded look. The same seed always produces the same noise.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `density` - The fraction of pixels to replace, between 0 and 1, eg: 0.05. Half of them
+/// become white, and half become black.
+/// * `seed` - The seed for the random number generator.
+///
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::noise::salt_and_pepper;
+///
+/// let mut img = open_image("img.jpg");
+/// salt_and_pepper(&mut img, 0.05, 42);
+/// ```
+#[wasm_bindgen]
+pub fn salt_and_pepper(photon_image: &mut PhotonImage, density: f32, seed: u64) {
+    if !(0.0..=1.0).contains(&density) {
+        panic!("Noise density must be between 0 and 1.");
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+        if rng.gen::<f32>() < density {
+            let value = if rng.gen() { 255 } else { 0 };
+            px[..3].copy_from_slice(&[value, value, value]);
+        }
+    }
+}
+
+/// Sum octaves of a noise function with values between -1 and 1 into a grayscale image.
+fn fractal_noise<F: Fn(f64, f64) -> f64>(
+    width: u32,
+    height: u32,
+    scale: f32,
+    octaves: u32,
+    noise: F,
+) -> PhotonImage {
+    if octaves == 0 {
+        panic!("At least one octave of noise is required.");
+    }
+    if scale <= 0.0 {
+        panic!("Noise scale must be greater than 0.");
+    }
+
+    let mut raw_pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (mut total, mut amplitude, mut max_total) = (0.0, 1.0, 0.0);
+            let mut frequency = 1.0 / scale as f64;
+            for _ in 0..octaves {
+                total += amplitude * noise(x as f64 * frequency, y as f64 * frequency);
+                max_total += amplitude;
+                amplitude *= 0.5;
+                frequency *= 2.0;
+            }
+            let value = (total / max_total + 1.0) / 2.0 * 255.0;
+            let value = num::clamp(value.round(), 0.0, 255.0) as u8;
+            raw_pixels.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+    PhotonImage::new(raw_pixels, width, height)
+}
+
+/// A shuffled table of the numbers 0 to 255, repeated twice so that it can be indexed
+/// with the sum of two entries without wrapping.
+fn permutation(seed: u64) -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..256).collect();
+    perm.shuffle(&mut StdRng::seed_from_u64(seed));
+    perm.extend_from_within(..);
+    perm
+}
+
+/// The dot product of one of eight gradient directions, chosen by `hash`, with (x, y).
+fn gradient(hash: usize, x: f64, y: f64) -> f64 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+/// Two-dimensional Perlin noise, between -1 and 1.
+fn perlin_2d(perm: &[usize], x: f64, y: f64) -> f64 {
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let lerp = |a: f64, b: f64, t: f64| a + t * (b - a);
+
+    let (xi, yi) = (x.floor() as i64 & 255, y.floor() as i64 & 255);
+    let (xi, yi) = (xi as usize, yi as usize);
+    let (xf, yf) = (x - x.floor(), y - y.floor());
+    let (u, v) = (fade(xf), fade(yf));
+
+    let aa = perm[perm[xi] + yi];
+    let ab = perm[perm[xi] + yi + 1];
+    let ba = perm[perm[xi + 1] + yi];
+    let bb = perm[perm[xi + 1] + yi + 1];
+
+    let value = lerp(
+        lerp(gradient(aa, xf, yf), gradient(ba, xf - 1.0, yf), u),
+        lerp(
+            gradient(ab, xf, yf - 1.0),
+            gradient(bb, xf - 1.0, yf - 1.0),
+            u,
+        ),
+        v,
+    );
+    num::clamp(value, -1.0, 1.0)
+}
+
+/// Two-dimensional simplex noise, between -1 and 1.
+fn simplex_2d(perm: &[usize], x: f64, y: f64) -> f64 {
+    let f2 = 0.5 * (3.0_f64.sqrt() - 1.0);
+    let g2 = (3.0 - 3.0_f64.sqrt()) / 6.0;
+
+    // Find which triangle of the skewed grid the point lies in.
+    let s = (x + y) * f2;
+    let (i, j) = ((x + s).floor(), (y + s).floor());
+    let t = (i + j) * g2;
+    let (x0, y0) = (x - (i - t), y - (j - t));
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+        (x0, y0, 0, 0),
+        (x0 - i1 as f64 + g2, y0 - j1 as f64 + g2, i1, j1),
+        (x0 - 1.0 + 2.0 * g2, y0 - 1.0 + 2.0 * g2, 1, 1),
+    ];
+
+    let (ii, jj) = (i as i64 & 255, j as i64 & 255);
+    let (ii, jj) = (ii as usize, jj as usize);
+    let mut total = 0.0;
+    for &(cx, cy, di, dj) in corners.iter() {
+        let falloff = 0.5 - cx * cx - cy * cy;
+        if falloff > 0.0 {
+            let hash = perm[ii + di + perm[jj + dj]];
+            total += falloff.powi(4) * gradient(hash, cx, cy);
+        }
+    }
+    num::clamp(70.0 * total, -1.0, 1.0)
+}
@@ -0,0 +1,435 @@
+//! Apply a sequence of operations described by data, eg: an edit spec sent by a client to
+//! an image-proxy server.
+//!
+//! A pipeline is a JSON array of operations, each an object whose `op` field names the
+//! operation, and whose other fields are its parameters:
+//!
+//! ```json
+//! [
+//!     {"op": "resize", "w": 800},
+//!     {"op": "gaussian_blur", "sigma": 2},
+//!     {"op": "filter", "name": "vintage"}
+//! ]
+//! ```
+//!
+//! Every parameter is validated before it is used, so a malformed or malicious spec results
+//! in a `PhotonError::InvalidPipeline` rather than a panic or an enormous allocation.
+//!
+//! # Example
+//! ```
+//! use photon_rs::pipeline::run_json;
+//! use photon_rs::PhotonImage;
+//!
+//! let mut img = PhotonImage::new(vec![120; 32 * 32 * 4], 32, 32);
+//! run_json(&mut img, r#"[{"op": "resize", "w": 16}, {"op": "grayscale"}]"#).unwrap();
+//! assert_eq!(img.get_width(), 16);
+//! ```
+
+use crate::error::{PhotonError, Result};
+use crate::{channels, colour_spaces, conv, effects, filters, monochrome, transform};
+use crate::{PhotonImage, Rect};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use wasm_bindgen::prelude::*;
+
+/// The most pixels an operation may produce, which keeps each image under 256 MiB.
+const MAX_PIXELS: u64 = 1 << 26;
+
+/// The largest blur sigma allowed, which keeps the cost of a blur bounded.
+const MAX_SIGMA: f32 = 100.0;
+
+/// The largest Kuwahara radius allowed, which keeps the cost of the filter bounded.
+const MAX_KUWAHARA_RADIUS: u32 = 32;
+
+/// A single operation in a pipeline, with its parameters.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Operation {
+    /// Resize to `w` by `h` pixels. If only one of them is given, the other is chosen to
+    /// keep the aspect ratio.
+    Resize { w: Option<u32>, h: Option<u32> },
+    /// Crop to the `w` by `h` rectangle whose top-left corner is at (`x`, `y`).
+    Crop { x: u32, y: u32, w: u32, h: u32 },
+    /// Flip horizontally.
+    #[serde(rename = "fliph")]
+    FlipH,
+    /// Flip vertically.
+    #[serde(rename = "flipv")]
+    FlipV,
+    /// Gaussian blur, with a `sigma` between 0 and 100.
+    GaussianBlur { sigma: f32 },
+    /// 3x3 box blur.
+    BoxBlur,
+    /// Sharpen.
+    Sharpen,
+    /// Kuwahara filter, which gives a painterly look, with a `radius` between 0 and 32.
+    Kuwahara { radius: u32 },
+    /// Reduce noise with non-local means, with a `strength` between 0 and 1.
+    NlMeans { strength: f32 },
+    /// Brighten (positive) or darken (negative), by an `amount` between -255 and 255.
+    Brightness { amount: i16 },
+    /// Adjust contrast by an `amount` between -255 and 255.
+    Contrast { amount: f32 },
+    /// Saturate (positive) or desaturate (negative) by an `amount` between -1 and 1.
+    Saturate { amount: f32 },
+    /// Rotate the hue of every pixel by `degrees`, between -360 and 360.
+    HueRotate { degrees: f32 },
+    /// Convert to grayscale.
+    Grayscale,
+    /// Apply a sepia tone.
+    Sepia,
+    /// Invert the colours.
+    Invert,
+    /// Solarize.
+    Solarize,
+    /// Add signed offsets, between -255 and 255, to the red, green and blue channels.
+    Tint { r: i32, g: i32, b: i32 },
+    /// Stretch the contrast, clipping `clip_percent` percent (default 0.5) of pixels at
+    /// each end.
+    AutoContrast {
+        #[serde(default = "default_clip_percent")]
+        clip_percent: f32,
+    },
+    /// Automatically correct the colour balance, contrast, vibrance and sharpness.
+    AutoEnhance,
+    /// Apply one of the preset filters, by `name`, eg: "vintage".
+    Filter { name: String },
+}
+
+fn default_clip_percent() -> f32 {
+    0.5
+}
+
+impl Operation {
+    /// Validate the operation's parameters against the image, then apply it.
+    ///
+    /// If the parameters are invalid, `PhotonError::InvalidPipeline` is returned and the
+    /// image is left unmodified.
+    pub fn apply(&self, photon_image: &mut PhotonImage) -> Result<()> {
+        match self {
+            Operation::Resize { w, h } => {
+                let (width, height) = (photon_image.width, photon_image.height);
+                let (new_width, new_height) = match (*w, *h) {
+                    (Some(w), Some(h)) => (w, h),
+                    (Some(w), None) => (w, scale_dimension(height, w, width)),
+                    (None, Some(h)) => (scale_dimension(width, h, height), h),
+                    (None, None) => return Err(invalid("resize needs `w` or `h`")),
+                };
+                check_dimensions("resize", new_width, new_height)?;
+                *photon_image = transform::resize(
+                    photon_image,
+                    new_width,
+                    new_height,
+                    transform::SamplingFilter::Triangle,
+                );
+            }
+            Operation::Crop { x, y, w, h } => {
+                let fits = |start: u32, len: u32, max: u32| {
+                    len > 0 && start.checked_add(len).is_some_and(|end| end <= max)
+                };
+                if !fits(*x, *w, photon_image.width)
+                    || !fits(*y, *h, photon_image.height)
+                {
+                    return Err(invalid("crop must lie within the image"));
+                }
+                let region = Rect::new(*x, *y, *w, *h);
+                *photon_image = transform::crop_rect(photon_image, &region);
+            }
+            Operation::FlipH => transform::fliph(photon_image),
+            Operation::FlipV => transform::flipv(photon_image),
+            Operation::GaussianBlur { sigma } => {
+                check_range("gaussian_blur sigma", *sigma, 0.0, MAX_SIGMA)?;
+                let radius = sigma.round() as i32;
+                if radius > 0 {
+                    conv::gaussian_blur(photon_image, radius);
+                }
+            }
+            Operation::BoxBlur => conv::box_blur(photon_image),
+            Operation::Sharpen => conv::sharpen(photon_image),
+            Operation::Kuwahara { radius } => {
+                let max = MAX_KUWAHARA_RADIUS as f32;
+                check_range("kuwahara radius", *radius as f32, 0.0, max)?;
+                effects::kuwahara(photon_image, *radius);
+            }
+            Operation::NlMeans { strength } => {
+                check_range("nl_means strength", *strength, 0.0, 1.0)?;
+                conv::nl_means(photon_image, *strength);
+            }
+            Operation::Brightness { amount } => {
+                check_range("brightness amount", *amount as f32, -255.0, 255.0)?;
+                effects::adjust_brightness(photon_image, *amount);
+            }
+            Operation::Contrast { amount } => {
+                check_range("contrast amount", *amount, -255.0, 255.0)?;
+                effects::adjust_contrast(photon_image, *amount);
+            }
+            Operation::Saturate { amount } => {
+                check_range("saturate amount", *amount, -1.0, 1.0)?;
+                if *amount >= 0.0 {
+                    colour_spaces::saturate_hsl(photon_image, *amount);
+                } else {
+                    colour_spaces::desaturate_hsl(photon_image, -amount);
+                }
+            }
+            Operation::HueRotate { degrees } => {
+                check_range("hue_rotate degrees", *degrees, -360.0, 360.0)?;
+                colour_spaces::hue_rotate_hsl(photon_image, *degrees / 360.0);
+            }
+            Operation::Grayscale => monochrome::grayscale(photon_image),
+            Operation::Sepia => monochrome::sepia(photon_image),
+            Operation::Invert => channels::invert(photon_image),
+            Operation::Solarize => effects::solarize(photon_image),
+            Operation::Tint { r, g, b } => {
+                for (name, offset) in [("r", r), ("g", g), ("b", b)].iter() {
+                    let what = format!("tint {}", name);
+                    check_range(&what, **offset as f32, -255.0, 255.0)?;
+                }
+                effects::tint_signed(photon_image, *r, *g, *b);
+            }
+            Operation::AutoContrast { clip_percent } => {
+                check_range("auto_contrast clip_percent", *clip_percent, 0.0, 50.0)?;
+                effects::auto_contrast(photon_image, *clip_percent);
+            }
+            Operation::AutoEnhance => effects::auto_enhance(photon_image),
+            Operation::Filter { name } => {
+                if !filters::FILTER_NAMES.contains(&name.as_str()) {
+                    return Err(invalid(&format!("unknown filter \"{}\"", name)));
+                }
+                filters::filter(photon_image, name);
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of rows above and below a pixel which its result depends on, for the
+    /// operations slow enough to be worth applying to a band of rows at a time, or `None`.
+    pub(crate) fn band_margin(&self) -> Option<u32> {
+        match self {
+            Operation::Kuwahara { radius } => Some(*radius),
+            Operation::NlMeans { .. } => Some(conv::NL_MEANS_REACH),
+            _ => None,
+        }
+    }
+
+    /// Apply the operation to `rows` of `source`, writing the result into the same rows of
+    /// `target`, which must have the same dimensions. Applying it to every band of rows in
+    /// turn gives the same image as `apply`.
+    ///
+    /// Only operations with a `band_margin` can be applied this way.
+    pub(crate) fn apply_to_rows(
+        &self,
+        source: &PhotonImage,
+        target: &mut PhotonImage,
+        rows: Range<u32>,
+    ) -> Result<()> {
+        let margin = self
+            .band_margin()
+            .ok_or_else(|| invalid("operation can't be applied to a band of rows"))?;
+        let (width, height) = (source.width, source.height);
+        let end = rows.end.min(height);
+        if rows.start >= end {
+            return Ok(());
+        }
+        // Include the rows around the band which its pixels depend on.
+        let top = rows.start.saturating_sub(margin);
+        let bottom = end.saturating_add(margin).min(height);
+        let mut band =
+            transform::crop_rect(source, &Rect::new(0, top, width, bottom - top));
+        self.apply(&mut band)?;
+
+        let row_len = width as usize * 4;
+        let skip = (rows.start - top) as usize * row_len;
+        let start = rows.start as usize * row_len;
+        let len = (end - rows.start) as usize * row_len;
+        target.raw_pixels[start..start + len]
+            .copy_from_slice(&band.raw_pixels[skip..skip + len]);
+        Ok(())
+    }
+}
+
+/// A reusable sequence of operations, which can be applied to any number of images.
+///
+/// Build one with `Pipeline::new` and `then`, with the `photon_pipeline!` macro, or from
+/// JSON with `from_json`.
+///
+/// # Example
+/// ```
+/// use photon_rs::pipeline::{Operation, Pipeline};
+/// use photon_rs::PhotonImage;
+///
+/// let thumbnail = Pipeline::new()
+///     .then(Operation::Resize { w: Some(8), h: None })
+///     .then(Operation::Sharpen);
+///
+/// for _ in 0..3 {
+///     let mut img = PhotonImage::new(vec![120; 32 * 16 * 4], 32, 16);
+///     thumbnail.apply(&mut img).unwrap();
+///     assert_eq!(img.get_height(), 4);
+/// }
+/// ```
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Pipeline {
+    operations: Vec<Operation>,
+}
+
+#[wasm_bindgen]
+impl Pipeline {
+    #[wasm_bindgen(constructor)]
+    /// Create an empty pipeline.
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Parse a pipeline from a JSON array of operations.
+    pub fn from_json(spec: &str) -> Result<Pipeline> {
+        serde_json::from_str(spec).map_err(|err| invalid(&err.to_string()))
+    }
+
+    /// Convert the pipeline to a JSON array of operations.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("operations can always be serialized")
+    }
+
+    /// Apply every operation in the pipeline to an image, in order.
+    ///
+    /// If any operation is invalid, `PhotonError::InvalidPipeline` is returned and the
+    /// image is left unmodified, even if earlier operations were valid.
+    pub fn apply(&self, photon_image: &mut PhotonImage) -> Result<()> {
+        run(photon_image, &self.operations)
+    }
+
+    /// Get the number of operations in the pipeline.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Check whether the pipeline has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl Pipeline {
+    /// Add an operation to the end of the pipeline.
+    pub fn then(mut self, operation: Operation) -> Pipeline {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Get the operations in the pipeline.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+}
+
+impl From<Vec<Operation>> for Pipeline {
+    fn from(operations: Vec<Operation>) -> Self {
+        Pipeline { operations }
+    }
+}
+
+/// Build a `Pipeline` from a list of operations, written as `Operation` variants without
+/// the `Operation::` prefix.
+///
+/// # Example
+/// ```
+/// use photon_rs::photon_pipeline;
+/// use photon_rs::PhotonImage;
+///
+/// let pipeline = photon_pipeline![
+///     Resize { w: Some(16), h: None },
+///     GaussianBlur { sigma: 2.0 },
+///     Filter { name: "vintage".to_string() },
+///     Grayscale,
+/// ];
+///
+/// let mut img = PhotonImage::new(vec![120; 32 * 32 * 4], 32, 32);
+/// pipeline.apply(&mut img).unwrap();
+/// ```
+#[macro_export]
+macro_rules! photon_pipeline {
+    ($($op:ident $({ $($field:ident : $value:expr),* $(,)? })?),* $(,)?) => {
+        $crate::pipeline::Pipeline::from(vec![
+            $($crate::pipeline::Operation::$op $({ $($field: $value),* })?),*
+        ])
+    };
+}
+
+/// Apply a sequence of operations to an image, in order.
+///
+/// If any operation is invalid, `PhotonError::InvalidPipeline` is returned and the image is
+/// left unmodified, even if earlier operations were valid.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `operations` - The operations to apply.
+pub fn run(photon_image: &mut PhotonImage, operations: &[Operation]) -> Result<()> {
+    let mut result = photon_image.clone();
+    for operation in operations {
+        operation.apply(&mut result)?;
+    }
+    *photon_image = result;
+    Ok(())
+}
+
+/// Parse a JSON array of operations, and apply them to an image in order.
+///
+/// If the JSON can't be parsed, or any operation is invalid, `PhotonError::InvalidPipeline`
+/// is returned and the image is left unmodified.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `spec` - A JSON array of operations, eg: `[{"op": "gaussian_blur", "sigma": 2}]`.
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::pipeline::run_json;
+///
+/// let mut img = open_image("img.jpg");
+/// let spec = r#"[{"op": "resize", "w": 800}, {"op": "filter", "name": "vintage"}]"#;
+/// run_json(&mut img, spec).unwrap();
+/// ```
+#[wasm_bindgen]
+pub fn run_json(photon_image: &mut PhotonImage, spec: &str) -> Result<()> {
+    Pipeline::from_json(spec)?.apply(photon_image)
+}
+
+fn invalid(msg: &str) -> PhotonError {
+    PhotonError::InvalidPipeline(msg.to_string())
+}
+
+/// Scale `len` by `new_reference / reference`, eg: to find the height of an image resized
+/// to a given width.
+fn scale_dimension(len: u32, new_reference: u32, reference: u32) -> u32 {
+    if reference == 0 {
+        return 0;
+    }
+    let scaled = (len as f64 * new_reference as f64 / reference as f64).round();
+    scaled.clamp(1.0, u32::MAX as f64) as u32
+}
+
+fn check_dimensions(op: &str, width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(invalid(&format!("{} dimensions must be at least 1", op)));
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(invalid(&format!(
+            "{} must produce at most {} pixels",
+            op, MAX_PIXELS
+        )));
+    }
+    Ok(())
+}
+
+fn check_range(what: &str, value: f32, min: f32, max: f32) -> Result<()> {
+    if !(min..=max).contains(&value) {
+        return Err(invalid(&format!(
+            "{} must be between {} and {}",
+            what, min, max
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Named looks which can be saved, shared and applied to any image.
+//!
+//! A preset is a named pipeline of operations, stored as a JSON object, so the same look can
+//! be applied by the WebAssembly frontend and by a Rust backend:
+//!
+//! ```json
+//! {
+//!     "name": "faded film",
+//!     "operations": [
+//!         {"op": "contrast", "amount": -20},
+//!         {"op": "filter", "name": "vintage"}
+//!     ]
+//! }
+//! ```
+//!
+//! # Example
+//! ```
+//! use photon_rs::pipeline::Operation;
+//! use photon_rs::preset::{apply_preset, Preset};
+//! use photon_rs::PhotonImage;
+//!
+//! let preset = Preset::new("moody")
+//!     .then(Operation::Contrast { amount: 30.0 })
+//!     .then(Operation::Saturate { amount: -0.2 });
+//! let json = preset.to_json();
+//!
+//! let shared = Preset::from_json(&json).unwrap();
+//! let mut img = PhotonImage::new(vec![120; 8 * 8 * 4], 8, 8);
+//! apply_preset(&mut img, &shared).unwrap();
+//! assert_eq!(shared, preset);
+//! ```
+
+use crate::error::{PhotonError, Result};
+use crate::pipeline::{Operation, Pipeline};
+use crate::PhotonImage;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// A named sequence of operations.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    name: String,
+    operations: Pipeline,
+}
+
+#[wasm_bindgen]
+impl Preset {
+    #[wasm_bindgen(constructor)]
+    /// Create a preset with no operations.
+    pub fn new(name: &str) -> Preset {
+        Preset {
+            name: name.to_string(),
+            operations: Pipeline::new(),
+        }
+    }
+
+    /// Parse a preset from a JSON object, with a `name` and an `operations` array.
+    pub fn from_json(json: &str) -> Result<Preset> {
+        serde_json::from_str(json)
+            .map_err(|err| PhotonError::InvalidPipeline(err.to_string()))
+    }
+
+    /// Convert the preset to a JSON object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("presets can always be serialized")
+    }
+
+    /// Get the name of the preset.
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Rename the preset.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    /// Add an operation, given as a JSON object, to the end of the preset.
+    pub fn push_json(&mut self, operation: &str) -> Result<()> {
+        let operation = serde_json::from_str(operation)
+            .map_err(|err| PhotonError::InvalidPipeline(err.to_string()))?;
+        self.operations = std::mem::take(&mut self.operations).then(operation);
+        Ok(())
+    }
+}
+
+impl Preset {
+    /// Create a preset from a name and a pipeline of operations.
+    pub fn with_pipeline(name: &str, operations: Pipeline) -> Preset {
+        Preset {
+            name: name.to_string(),
+            operations,
+        }
+    }
+
+    /// Add an operation to the end of the preset.
+    pub fn then(mut self, operation: Operation) -> Preset {
+        self.operations = self.operations.then(operation);
+        self
+    }
+
+    /// Get the operations in the preset.
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.operations
+    }
+}
+
+/// Apply a preset's operations to an image, in order.
+///
+/// If any operation is invalid, `PhotonError::InvalidPipeline` is returned and the image is
+/// left unmodified.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `preset` - The preset to apply.
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::preset::{apply_preset, Preset};
+///
+/// let mut img = open_image("img.jpg");
+/// let json = r#"{"name": "mono", "operations": [{"op": "grayscale"}]}"#;
+/// apply_preset(&mut img, &Preset::from_json(json).unwrap()).unwrap();
+/// ```
+#[wasm_bindgen]
+pub fn apply_preset(photon_image: &mut PhotonImage, preset: &Preset) -> Result<()> {
+    preset.operations.apply(photon_image)
+}
@@ -0,0 +1,128 @@
+//! Run pipelines asynchronously in the browser, returning a `Promise`, so that heavy edits
+//! don't freeze the page.
+//!
+//! WebAssembly runs on the thread which calls it, so a long pipeline run with
+//! `Pipeline.apply` blocks the page from rendering and responding to input until it's done.
+//! `apply_async` instead yields to the event loop before each operation, letting the
+//! browser handle input and paint in between, and checks an optional `CancellationToken`
+//! each time, so that an edit which is no longer wanted can be abandoned early.
+//!
+//! The slowest operations, `kuwahara` and `nl_means`, are also split into bands of rows,
+//! yielding and checking the token between bands, so that even a single one doesn't block
+//! the page for long. Other operations run to completion once started; to keep the page
+//! responsive throughout, run Photon in a Web Worker instead, where these functions also
+//! work.
+//!
+//! # Example
+//! ```js
+//! const pipeline = photon.Pipeline.from_json('[{"op": "gaussian_blur", "sigma": 4}]');
+//! const token = new photon.CancellationToken();
+//! cancelButton.onclick = () => token.cancel();
+//! try {
+//!   const blurred = await photon.apply_async(img, pipeline, token);
+//!   photon.putImageData(canvas, ctx, blurred);
+//! } catch (err) {
+//!   console.log(err); // "operation was cancelled", or an invalid pipeline.
+//! }
+//! ```
+
+use crate::cancel::{self, CancellationToken};
+use crate::pipeline::{Operation, Pipeline};
+use crate::PhotonImage;
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+/// The number of rows of an image processed between yields, by the operations which are
+/// split into bands.
+const BAND_ROWS: u32 = 16;
+
+/// Apply every operation in a pipeline to an image, in order, yielding to the event loop
+/// before each one, and between the bands of rows of the slowest ones.
+///
+/// Returns a `Promise` which resolves to the edited image, or rejects with the error if any
+/// operation is invalid, or if `token` is cancelled before the pipeline finishes.
+///
+/// # Arguments
+/// * `img` - A PhotonImage. It's moved into the promise, so can't be used from JS again.
+/// * `pipeline` - The operations to apply.
+/// * `token` - If given, the pipeline is abandoned once the token is cancelled.
+#[wasm_bindgen]
+pub fn apply_async(
+    photon_image: PhotonImage,
+    pipeline: &Pipeline,
+    token: Option<CancellationToken>,
+) -> Promise {
+    let operations = pipeline.operations().to_vec();
+    future_to_promise(async move {
+        let mut photon_image = photon_image;
+        for operation in &operations {
+            apply_operation(operation, &mut photon_image, token.as_ref()).await?;
+        }
+        Ok(photon_image.into())
+    })
+}
+
+/// Parse a JSON array of operations, and apply them to an image asynchronously, as
+/// `apply_async` does.
+///
+/// # Arguments
+/// * `img` - A PhotonImage. It's moved into the promise, so can't be used from JS again.
+/// * `spec` - A JSON array of operations, eg: `[{"op": "gaussian_blur", "sigma": 2}]`.
+/// * `token` - If given, the pipeline is abandoned once the token is cancelled.
+#[wasm_bindgen]
+pub fn run_json_async(
+    photon_image: PhotonImage,
+    spec: &str,
+    token: Option<CancellationToken>,
+) -> Promise {
+    match Pipeline::from_json(spec) {
+        Ok(pipeline) => apply_async(photon_image, &pipeline, token),
+        Err(err) => Promise::reject(&err.into()),
+    }
+}
+
+/// Apply a single operation to an image, yielding to the event loop and checking `token`
+/// before it, and between its bands of rows if it's split into bands.
+async fn apply_operation(
+    operation: &Operation,
+    photon_image: &mut PhotonImage,
+    token: Option<&CancellationToken>,
+) -> Result<(), JsValue> {
+    if operation.band_margin().is_none() || photon_image.height == 0 {
+        next_task().await?;
+        cancel::check(token)?;
+        operation.apply(photon_image)?;
+        return Ok(());
+    }
+
+    // Each band reads the rows around it, so is computed from the unedited image.
+    let source = photon_image.clone();
+    let mut start = 0;
+    loop {
+        next_task().await?;
+        cancel::check(token)?;
+        let end = start.saturating_add(BAND_ROWS).min(source.height);
+        operation.apply_to_rows(&source, photon_image, start..end)?;
+        if end == source.height {
+            return Ok(());
+        }
+        start = end;
+    }
+}
+
+/// Wait for a new task of the event loop, with `setTimeout`, which is available both on
+/// the page and in workers, so that the browser can handle input and paint meanwhile.
+async fn next_task() -> Result<(), JsValue> {
+    let global = js_sys::global();
+    let set_timeout: Function =
+        Reflect::get(&global, &JsValue::from_str("setTimeout"))?.dyn_into()?;
+    let mut scheduled = Ok(JsValue::UNDEFINED);
+    let promise = Promise::new(&mut |resolve, _reject| {
+        scheduled = set_timeout.call2(&global, &resolve, &JsValue::from(0));
+    });
+    scheduled?;
+    JsFuture::from(promise).await?;
+    Ok(())
+}
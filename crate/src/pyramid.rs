@@ -0,0 +1,181 @@
+//! Gaussian and Laplacian image pyramids, for multi-band blending.
+
+use crate::PhotonImage;
+
+/// An image with floating point channels, stored interleaved, row by row.
+#[derive(Clone, Debug)]
+pub(crate) struct Plane {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub data: Vec<f32>,
+}
+
+impl Plane {
+    /// Create a plane filled with zeros.
+    pub fn new(width: usize, height: usize, channels: usize) -> Plane {
+        Plane {
+            width,
+            height,
+            channels,
+            data: vec![0.0; width * height * channels],
+        }
+    }
+
+    /// Convert the red, green and blue channels of an image to a 3-channel plane, with
+    /// values between 0 and 1.
+    pub fn from_rgb(photon_image: &PhotonImage) -> Plane {
+        let data = photon_image
+            .raw_pixels
+            .chunks_exact(4)
+            .flat_map(|px| px[..3].iter().map(|&v| v as f32 / 255.0))
+            .collect();
+        Plane {
+            width: photon_image.width as usize,
+            height: photon_image.height as usize,
+            channels: 3,
+            data,
+        }
+    }
+
+    /// Write a 3-channel plane, with values between 0 and 1, to the red, green and blue
+    /// channels of an image of the same size, clamping values outside of that range.
+    pub fn write_rgb(&self, photon_image: &mut PhotonImage) {
+        for (px, rgb) in photon_image
+            .raw_pixels
+            .chunks_exact_mut(4)
+            .zip(self.data.chunks_exact(3))
+        {
+            for c in 0..3 {
+                px[c] = (rgb[c] * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    fn at(&self, x: usize, y: usize, c: usize) -> f32 {
+        self.data[(y * self.width + x) * self.channels + c]
+    }
+
+    /// Blur with a 5-tap binomial kernel, then keep every other pixel in each direction.
+    pub fn downsample(&self) -> Plane {
+        const KERNEL: [f32; 5] =
+            [1.0 / 16.0, 4.0 / 16.0, 6.0 / 16.0, 4.0 / 16.0, 1.0 / 16.0];
+        let clamp = |v: isize, max: usize| v.max(0).min(max as isize - 1) as usize;
+
+        let mut rows = Plane::new(self.width.div_ceil(2), self.height, self.channels);
+        for y in 0..self.height {
+            for x in 0..rows.width {
+                for c in 0..self.channels {
+                    let mut sum = 0.0;
+                    for (k, weight) in KERNEL.iter().enumerate() {
+                        let sx = clamp(2 * x as isize + k as isize - 2, self.width);
+                        sum += weight * self.at(sx, y, c);
+                    }
+                    rows.data[(y * rows.width + x) * self.channels + c] = sum;
+                }
+            }
+        }
+
+        let mut out = Plane::new(rows.width, self.height.div_ceil(2), self.channels);
+        for y in 0..out.height {
+            for x in 0..out.width {
+                for c in 0..self.channels {
+                    let mut sum = 0.0;
+                    for (k, weight) in KERNEL.iter().enumerate() {
+                        let sy = clamp(2 * y as isize + k as isize - 2, rows.height);
+                        sum += weight * rows.at(x, sy, c);
+                    }
+                    out.data[(y * out.width + x) * self.channels + c] = sum;
+                }
+            }
+        }
+        out
+    }
+
+    /// Enlarge to `width` by `height` with bilinear interpolation, the inverse of the
+    /// subsampling in `downsample`.
+    pub fn upsample(&self, width: usize, height: usize) -> Plane {
+        let mut out = Plane::new(width, height, self.channels);
+        for y in 0..height {
+            let sy = (y as f32 / 2.0).min((self.height - 1) as f32);
+            let (y0, fy) = (sy.floor() as usize, sy.fract());
+            let y1 = (y0 + 1).min(self.height - 1);
+            for x in 0..width {
+                let sx = (x as f32 / 2.0).min((self.width - 1) as f32);
+                let (x0, fx) = (sx.floor() as usize, sx.fract());
+                let x1 = (x0 + 1).min(self.width - 1);
+                for c in 0..self.channels {
+                    let top = self.at(x0, y0, c) * (1.0 - fx) + self.at(x1, y0, c) * fx;
+                    let bottom =
+                        self.at(x0, y1, c) * (1.0 - fx) + self.at(x1, y1, c) * fx;
+                    out.data[(y * width + x) * self.channels + c] =
+                        top * (1.0 - fy) + bottom * fy;
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The number of pyramid levels which suit an image, stopping before the smallest level
+/// is smaller than about 8 pixels, and never more than `max`.
+pub(crate) fn levels_for(width: usize, height: usize, max: usize) -> usize {
+    let mut levels = 1;
+    let mut size = width.min(height);
+    while size >= 16 && levels < max {
+        size = size.div_ceil(2);
+        levels += 1;
+    }
+    levels
+}
+
+/// Build a Gaussian pyramid, of successively downsampled copies of a plane.
+pub(crate) fn gaussian_pyramid(plane: &Plane, levels: usize) -> Vec<Plane> {
+    let mut pyramid = vec![plane.clone()];
+    for _ in 1..levels {
+        let next = pyramid
+            .last()
+            .expect("the pyramid has a level")
+            .downsample();
+        pyramid.push(next);
+    }
+    pyramid
+}
+
+/// Build a Laplacian pyramid, in which each level holds the detail lost when downsampling
+/// to the next one, and the last level holds the smallest downsampled copy.
+pub(crate) fn laplacian_pyramid(plane: &Plane, levels: usize) -> Vec<Plane> {
+    let gaussian = gaussian_pyramid(plane, levels);
+    let mut pyramid = Vec::with_capacity(levels);
+    for level in 0..levels - 1 {
+        let fine = &gaussian[level];
+        let coarse = gaussian[level + 1].upsample(fine.width, fine.height);
+        let data = fine
+            .data
+            .iter()
+            .zip(&coarse.data)
+            .map(|(f, c)| f - c)
+            .collect();
+        pyramid.push(Plane {
+            width: fine.width,
+            height: fine.height,
+            channels: fine.channels,
+            data,
+        });
+    }
+    pyramid.push(gaussian[levels - 1].clone());
+    pyramid
+}
+
+/// Rebuild a plane from its Laplacian pyramid.
+pub(crate) fn collapse(pyramid: &[Plane]) -> Plane {
+    let mut plane = pyramid.last().expect("the pyramid has a level").clone();
+    for level in pyramid.iter().rev().skip(1) {
+        let mut up = plane.upsample(level.width, level.height);
+        for (u, l) in up.data.iter_mut().zip(&level.data) {
+            *u += l;
+        }
+        plane = up;
+    }
+    plane
+}
@@ -0,0 +1,260 @@
+//! Reduce images to a limited palette of colours, for formats such as GIF, or for a
+//! posterised look.
+//!
+//! Palettes are chosen by median cut: the colours in an image are repeatedly split into
+//! two boxes, at the median of the channel they vary most in, and each box is then
+//! replaced by the average of its colours. Dithering hides the banding that a small
+//! palette leaves in smooth gradients, by mixing neighbouring pixels of nearby colours.
+//!
+//! # Example
+//! ```
+//! use photon_rs::quantize::{quantize, Dither};
+//! use photon_rs::native::open_image;
+//!
+//! let mut img = open_image("img.jpg");
+//! quantize(&mut img, 16, Dither::FloydSteinberg);
+//! ```
+
+use crate::PhotonImage;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+/// The most pixels sampled from the images a palette is chosen for.
+const MAX_SAMPLES: usize = 1 << 18;
+
+/// How the error from reducing each pixel to a palette colour is hidden.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Map each pixel to its nearest palette colour, which leaves bands in gradients.
+    None,
+    /// Diffuse each pixel's error to its unvisited neighbours. Gives the most accurate
+    /// colours, but the pattern changes wherever the image does, which compresses less
+    /// well in animations.
+    FloydSteinberg,
+    /// Offset each pixel by a fixed 4x4 Bayer pattern before mapping it, which gives a
+    /// regular cross-hatch that stays still between frames of an animation.
+    Ordered,
+}
+
+/// Reduce an image to a palette of at most `colours` colours, chosen for it by median
+/// cut. Its alpha channel is left unchanged.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `colours` - The most colours to use, from 1 to 256.
+/// * `dither` - How to hide the banding that a small palette leaves.
+/// # Panics
+/// Panics if `colours` isn't from 1 to 256.
+#[wasm_bindgen]
+pub fn quantize(photon_image: &mut PhotonImage, colours: u32, dither: Dither) {
+    let palette = median_cut(std::slice::from_ref(photon_image), colours as usize);
+    let indices = remap(photon_image, &palette, dither);
+    for (px, &index) in photon_image.raw_pixels.chunks_exact_mut(4).zip(&indices) {
+        px[..3].copy_from_slice(&palette[index as usize]);
+    }
+}
+
+/// Choose a palette of at most `colours` colours for a set of images by median cut, eg:
+/// for every frame of an animation. Pixels which are less than half opaque are ignored.
+///
+/// # Arguments
+/// * `images` - The images to choose a palette for.
+/// * `colours` - The most colours to choose, from 1 to 256.
+/// # Panics
+/// Panics if `colours` isn't from 1 to 256.
+pub fn median_cut(images: &[PhotonImage], colours: usize) -> Vec<[u8; 3]> {
+    if colours == 0 || colours > 256 {
+        panic!(
+            "A palette must have from 1 to 256 colours, not {}.",
+            colours
+        );
+    }
+
+    let total: usize = images.iter().map(|img| img.raw_pixels.len() / 4).sum();
+    let step = total.div_ceil(MAX_SAMPLES).max(1);
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for img in images {
+        for px in img.raw_pixels.chunks_exact(4).step_by(step) {
+            if px[3] >= 128 {
+                *counts.entry([px[0], px[1], px[2]]).or_insert(0) += 1;
+            }
+        }
+    }
+    if counts.is_empty() {
+        return vec![[0, 0, 0]];
+    }
+
+    let mut boxes = vec![ColourBox::new(counts.into_iter().collect())];
+    while boxes.len() < colours {
+        // Split the box with the widest range, weighted by how many pixels it covers.
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.colours.len() > 1)
+            .max_by_key(|(_, b)| b.range().0 as u64 * b.count)
+            .map(|(i, _)| i);
+        match widest {
+            Some(i) => {
+                let (a, b) = boxes.swap_remove(i).split();
+                boxes.push(a);
+                boxes.push(b);
+            }
+            None => break,
+        }
+    }
+    boxes.iter().map(ColourBox::average).collect()
+}
+
+/// Map each pixel of an image to the index of a palette colour, ignoring alpha.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `palette` - The colours to map the image to.
+/// * `dither` - How to hide the banding that a small palette leaves.
+/// # Panics
+/// Panics if the palette is empty or has more than 256 colours.
+pub fn remap(
+    photon_image: &PhotonImage,
+    palette: &[[u8; 3]],
+    dither: Dither,
+) -> Vec<u8> {
+    if palette.is_empty() || palette.len() > 256 {
+        panic!(
+            "A palette must have from 1 to 256 colours, not {}.",
+            palette.len()
+        );
+    }
+    let width = photon_image.width as usize;
+    let pixels = photon_image.raw_pixels.chunks_exact(4);
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut nearest_cached = |rgb: [u8; 3]| {
+        *cache.entry(rgb).or_insert_with(|| {
+            nearest(palette, [rgb[0] as f32, rgb[1] as f32, rgb[2] as f32])
+        })
+    };
+
+    match dither {
+        Dither::None => pixels
+            .map(|px| nearest_cached([px[0], px[1], px[2]]))
+            .collect(),
+        Dither::Ordered => {
+            // Spread the pattern over about the gap between neighbouring palette
+            // colours.
+            let spread = 255.0 / (palette.len() as f32).cbrt();
+            pixels
+                .enumerate()
+                .map(|(i, px)| {
+                    let (x, y) = (i % width, i / width);
+                    let offset = (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5;
+                    let shift =
+                        |v: u8| (v as f32 + offset * spread).clamp(0.0, 255.0) as u8;
+                    nearest_cached([shift(px[0]), shift(px[1]), shift(px[2])])
+                })
+                .collect()
+        }
+        Dither::FloydSteinberg => {
+            let mut indices = Vec::with_capacity(photon_image.raw_pixels.len() / 4);
+            let mut error = vec![[0.0f32; 3]; width + 2];
+            let mut next_error = vec![[0.0f32; 3]; width + 2];
+            for row in photon_image.raw_pixels.chunks_exact(width * 4) {
+                for (x, px) in row.chunks_exact(4).enumerate() {
+                    let mut wanted = [0.0; 3];
+                    for c in 0..3 {
+                        wanted[c] = (px[c] as f32 + error[x + 1][c]).clamp(0.0, 255.0);
+                    }
+                    let index = nearest(palette, wanted);
+                    indices.push(index);
+                    for c in 0..3 {
+                        let e = wanted[c] - palette[index as usize][c] as f32;
+                        error[x + 2][c] += e * 7.0 / 16.0;
+                        next_error[x][c] += e * 3.0 / 16.0;
+                        next_error[x + 1][c] += e * 5.0 / 16.0;
+                        next_error[x + 2][c] += e * 1.0 / 16.0;
+                    }
+                }
+                std::mem::swap(&mut error, &mut next_error);
+                next_error.iter_mut().for_each(|e| *e = [0.0; 3]);
+            }
+            indices
+        }
+    }
+}
+
+/// The 4x4 Bayer matrix, whose thresholds are spread as evenly as possible.
+const BAYER: [[u8; 4]; 4] =
+    [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The index of the palette colour nearest to a colour.
+fn nearest(palette: &[[u8; 3]], rgb: [f32; 3]) -> u8 {
+    let distance = |p: &[u8; 3]| {
+        let dr = p[0] as f32 - rgb[0];
+        let dg = p[1] as f32 - rgb[1];
+        let db = p[2] as f32 - rgb[2];
+        // Weight the channels roughly by how sensitive the eye is to each.
+        2.0 * dr * dr + 4.0 * dg * dg + db * db
+    };
+    let mut best = 0;
+    for (i, p) in palette.iter().enumerate() {
+        if distance(p) < distance(&palette[best]) {
+            best = i;
+        }
+    }
+    best as u8
+}
+
+/// A set of colours, with how many pixels there are of each, for median cut.
+struct ColourBox {
+    colours: Vec<([u8; 3], u32)>,
+    count: u64,
+}
+
+impl ColourBox {
+    fn new(colours: Vec<([u8; 3], u32)>) -> ColourBox {
+        let count = colours.iter().map(|&(_, n)| n as u64).sum();
+        ColourBox { colours, count }
+    }
+
+    /// The largest range of any channel over the box's colours, and that channel.
+    fn range(&self) -> (u8, usize) {
+        (0..3)
+            .map(|c| {
+                let values = self.colours.iter().map(|(rgb, _)| rgb[c]);
+                let (min, max) =
+                    values.fold((255, 0), |(lo, hi), v| (v.min(lo), v.max(hi)));
+                (max - min, c)
+            })
+            .max()
+            .unwrap()
+    }
+
+    /// Split the box in two at the median of its widest channel, by pixel count.
+    fn split(mut self) -> (ColourBox, ColourBox) {
+        let (_, channel) = self.range();
+        self.colours.sort_unstable_by_key(|(rgb, _)| rgb[channel]);
+        let mut seen = 0;
+        let mut at = 1;
+        for (i, &(_, n)) in self.colours.iter().enumerate() {
+            seen += n as u64;
+            if seen * 2 >= self.count {
+                at = i + 1;
+                break;
+            }
+        }
+        let at = at.min(self.colours.len() - 1);
+        let rest = self.colours.split_off(at);
+        (ColourBox::new(self.colours), ColourBox::new(rest))
+    }
+
+    /// The average of the box's colours, weighted by pixel count.
+    fn average(&self) -> [u8; 3] {
+        let mut sum = [0u64; 3];
+        for &(rgb, n) in &self.colours {
+            for c in 0..3 {
+                sum[c] += rgb[c] as u64 * n as u64;
+            }
+        }
+        let average = |s: u64| ((s + self.count / 2) / self.count) as u8;
+        [average(sum[0]), average(sum[1]), average(sum[2])]
+    }
+}
@@ -0,0 +1,160 @@
+//! Apply effects to part of an image only, for local edits such as blurring a licence
+//! plate or a face.
+//!
+//! The part of the image within a rectangle is copied out, edited on its own, and written
+//! back, so the rest of the image is left untouched. Operations which look at neighbouring
+//! pixels, such as blurs, treat the edges of the rectangle as the edges of an image. For
+//! regions of any shape, with soft edges, use `apply_masked` with a mask from the `mask`
+//! module.
+//!
+//! # Example
+//! ```
+//! use photon_rs::channels::invert;
+//! use photon_rs::region::apply_rect;
+//! use photon_rs::{PhotonImage, Rect};
+//!
+//! let mut img = PhotonImage::new(vec![0; 4 * 4 * 4], 4, 4);
+//! apply_rect(&mut img, &Rect::new(0, 0, 2, 4), invert);
+//! assert_eq!(img.get_raw_pixels()[0], 255);
+//! assert_eq!(img.get_raw_pixels()[2 * 4], 0);
+//! ```
+
+#[cfg(feature = "pipeline")]
+use crate::error::{PhotonError, Result};
+use crate::multiple::blend_with_mask;
+#[cfg(feature = "pipeline")]
+use crate::pipeline::Operation;
+use crate::transform::crop_rect;
+use crate::{PhotonImage, Rect};
+#[cfg(feature = "pipeline")]
+use wasm_bindgen::prelude::*;
+
+/// Apply an operation to the part of an image within a rectangle.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `rect` - The part of the image to edit. Any part of it outside the image is ignored.
+/// * `op` - The operation to apply, which must not change the size of the image it's given.
+/// # Panics
+/// Panics if `op` changes the size of the image.
+/// # Example
+///
+/// ```
+/// use photon_rs::conv::gaussian_blur;
+/// use photon_rs::native::open_image;
+/// use photon_rs::region::apply_rect;
+/// use photon_rs::Rect;
+///
+/// let mut img = open_image("car.jpg");
+/// apply_rect(&mut img, &Rect::new(420, 610, 180, 50), |plate| gaussian_blur(plate, 8));
+/// ```
+pub fn apply_rect<F: FnOnce(&mut PhotonImage)>(
+    photon_image: &mut PhotonImage,
+    rect: &Rect,
+    op: F,
+) {
+    let mut region = crop_rect(photon_image, rect);
+    let size = (region.width, region.height);
+    op(&mut region);
+    if (region.width, region.height) != size {
+        panic!("An operation applied to a region must not change its size.");
+    }
+    paste(photon_image, &region, rect);
+}
+
+/// Apply an operation to an image, with its effect faded in and out by a mask.
+///
+/// The operation is applied to a copy of the whole image, which is then mixed into the
+/// image through the mask, as by `multiple::blend_with_mask`.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `mask` - A mask of the same size as the image, which is white where the operation
+///   should apply fully, and black where it shouldn't apply.
+/// * `op` - The operation to apply, which must not change the size of the image it's given.
+/// # Panics
+/// Panics if `op` changes the size of the image, or the mask isn't the same size as it.
+/// # Example
+///
+/// ```
+/// use photon_rs::conv::gaussian_blur;
+/// use photon_rs::mask::ellipse;
+/// use photon_rs::native::open_image;
+/// use photon_rs::region::apply_masked;
+///
+/// let mut img = open_image("portrait.jpg");
+/// let (width, height) = (img.get_width(), img.get_height());
+/// let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+/// // Blur everything but the subject, in the middle.
+/// let mut background = ellipse(width, height, cx, cy, cx * 0.6, cy * 0.8, 80.0);
+/// photon_rs::channels::invert(&mut background);
+/// apply_masked(&mut img, &background, |img| gaussian_blur(img, 6));
+/// ```
+pub fn apply_masked<F: FnOnce(&mut PhotonImage)>(
+    photon_image: &mut PhotonImage,
+    mask: &PhotonImage,
+    op: F,
+) {
+    let mut edited = photon_image.clone();
+    op(&mut edited);
+    if (edited.width, edited.height) != (photon_image.width, photon_image.height) {
+        panic!(
+            "An operation applied through a mask must not change the size of the image."
+        );
+    }
+    blend_with_mask(photon_image, &edited, mask);
+}
+
+/// Apply a pipeline operation, described in JSON, to the part of an image within a
+/// rectangle.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `rect` - The part of the image to edit. Any part of it outside the image is ignored.
+/// * `operation` - A JSON operation, as in a pipeline, eg: `{"op": "gaussian_blur", "sigma": 8}`.
+/// # Errors
+/// Returns `PhotonError::InvalidPipeline` if the operation can't be parsed, is invalid, or
+/// would change the size of the region, as `resize` and `crop` do.
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::region::apply_rect_json;
+/// use photon_rs::Rect;
+///
+/// let mut img = open_image("car.jpg");
+/// let plate = Rect::new(420, 610, 180, 50);
+/// apply_rect_json(&mut img, &plate, r#"{"op": "gaussian_blur", "sigma": 8}"#).unwrap();
+/// ```
+#[cfg(feature = "pipeline")]
+#[wasm_bindgen]
+pub fn apply_rect_json(
+    photon_image: &mut PhotonImage,
+    rect: &Rect,
+    operation: &str,
+) -> Result<()> {
+    let operation: Operation = serde_json::from_str(operation)
+        .map_err(|err| PhotonError::InvalidPipeline(err.to_string()))?;
+    if let Operation::Resize { .. } | Operation::Crop { .. } = operation {
+        return Err(PhotonError::InvalidPipeline(
+            "operations which change the size can't be applied to a region".to_string(),
+        ));
+    }
+    let mut region = crop_rect(photon_image, rect);
+    operation.apply(&mut region)?;
+    paste(photon_image, &region, rect);
+    Ok(())
+}
+
+/// Write an edited region, cropped from an image by `crop_rect`, back into the image.
+fn paste(photon_image: &mut PhotonImage, region: &PhotonImage, rect: &Rect) {
+    let (x0, y0, x1, _) = rect.clip(photon_image.width, photon_image.height);
+    let row_len = ((x1 - x0) * 4) as usize;
+    if row_len == 0 {
+        return;
+    }
+    for (dy, src) in region.raw_pixels.chunks_exact(row_len).enumerate() {
+        let start = (((y0 + dy as u32) * photon_image.width + x0) * 4) as usize;
+        photon_image.raw_pixels[start..start + row_len].copy_from_slice(src);
+    }
+}
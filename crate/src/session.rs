@@ -0,0 +1,156 @@
+//! Undo and redo for interactive editors.
+//!
+//! An `EditSession` wraps an image and records every operation applied to it, so that
+//! they can be undone and redone, and the history shown to the user. Rather than storing
+//! a copy of the image after every operation, it stores one every few operations, and
+//! replays the operations since the nearest copy when undoing.
+//!
+//! # Example
+//! ```
+//! use photon_rs::pipeline::Operation;
+//! use photon_rs::session::EditSession;
+//! use photon_rs::PhotonImage;
+//!
+//! let img = PhotonImage::new(vec![100; 8 * 8 * 4], 8, 8);
+//! let mut session = EditSession::new(img);
+//! session.apply(Operation::Brightness { amount: 20 }).unwrap();
+//! session.apply(Operation::Grayscale).unwrap();
+//!
+//! session.undo();
+//! assert_eq!(session.history(), &[Operation::Brightness { amount: 20 }]);
+//! session.redo();
+//! assert_eq!(session.history().len(), 2);
+//! ```
+
+use crate::error::{PhotonError, Result};
+use crate::pipeline::Operation;
+use crate::PhotonImage;
+use wasm_bindgen::prelude::*;
+
+/// The number of operations between the stored copies of the image.
+const CHECKPOINT_INTERVAL: usize = 8;
+
+/// An image being edited, with the history of operations applied to it.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct EditSession {
+    image: PhotonImage,
+    history: Vec<Operation>,
+    undone: Vec<Operation>,
+    /// Copies of the image, each paired with the number of operations applied to it. The
+    /// first is always the original image.
+    checkpoints: Vec<(usize, PhotonImage)>,
+}
+
+#[wasm_bindgen]
+impl EditSession {
+    #[wasm_bindgen(constructor)]
+    /// Start editing an image.
+    pub fn new(photon_image: PhotonImage) -> EditSession {
+        EditSession {
+            image: photon_image.clone(),
+            history: vec![],
+            undone: vec![],
+            checkpoints: vec![(0, photon_image)],
+        }
+    }
+
+    /// Apply an operation, given as a JSON object, eg: `{"op": "gaussian_blur", "sigma": 2}`.
+    ///
+    /// See the `pipeline` module for the available operations. Applying an operation
+    /// clears the operations which could be redone.
+    pub fn apply_json(&mut self, operation: &str) -> Result<()> {
+        let operation = serde_json::from_str(operation)
+            .map_err(|err| PhotonError::InvalidPipeline(err.to_string()))?;
+        self.apply(operation)
+    }
+
+    /// Undo the last operation. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let operation = match self.history.pop() {
+            Some(operation) => operation,
+            None => return false,
+        };
+        self.undone.push(operation);
+
+        let applied = self.history.len();
+        self.checkpoints.retain(|(count, _)| *count <= applied);
+        let (count, checkpoint) = self.checkpoints.last().expect("the original is kept");
+        let mut image = checkpoint.clone();
+        for operation in &self.history[*count..] {
+            operation
+                .apply(&mut image)
+                .expect("operations in the history have already succeeded");
+        }
+        self.image = image;
+        true
+    }
+
+    /// Redo the last undone operation. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.undone.pop() {
+            Some(operation) => {
+                operation
+                    .apply(&mut self.image)
+                    .expect("undone operations have already succeeded");
+                self.record(operation);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check whether there is an operation to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /// Check whether there is an operation to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Get the operations applied so far, oldest first, as a JSON array.
+    pub fn history_json(&self) -> String {
+        serde_json::to_string(&self.history)
+            .expect("operations can always be serialized")
+    }
+
+    /// Get a copy of the image, as it is after the operations applied so far.
+    pub fn get_image(&self) -> PhotonImage {
+        self.image.clone()
+    }
+}
+
+impl EditSession {
+    /// Apply an operation to the image, and record it in the history.
+    ///
+    /// If the operation is invalid, `PhotonError::InvalidPipeline` is returned, and neither
+    /// the image nor the history change. Applying an operation clears the operations which
+    /// could be redone.
+    pub fn apply(&mut self, operation: Operation) -> Result<()> {
+        operation.apply(&mut self.image)?;
+        self.undone.clear();
+        self.record(operation);
+        Ok(())
+    }
+
+    /// Get the operations applied so far, oldest first.
+    pub fn history(&self) -> &[Operation] {
+        &self.history
+    }
+
+    /// Get the image, as it is after the operations applied so far.
+    pub fn image(&self) -> &PhotonImage {
+        &self.image
+    }
+
+    fn record(&mut self, operation: Operation) {
+        self.history.push(operation);
+        let (last, _) = self.checkpoints.last().expect("the original is kept");
+        if self.history.len() - last >= CHECKPOINT_INTERVAL {
+            self.checkpoints
+                .push((self.history.len(), self.image.clone()));
+        }
+    }
+}
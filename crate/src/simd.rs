@@ -0,0 +1,124 @@
+//! Vectorised kernels for the hot per-pixel loops.
+//!
+//! When the crate is built for `wasm32` with the `wasm-simd` feature *and* the `simd128`
+//! target feature enabled (`RUSTFLAGS="-C target-feature=+simd128"`), these kernels process
+//! four RGBA pixels (16 bytes) per instruction using `core::arch::wasm32` intrinsics.
+//! On every other target, a scalar implementation with identical results is used instead.
+//!
+//! All kernels operate on a slice of raw RGBA pixels and leave the alpha channel untouched.
+
+#[cfg(all(
+    target_arch = "wasm32",
+    target_feature = "simd128",
+    feature = "wasm-simd"
+))]
+use core::arch::wasm32::*;
+
+/// Invert the selected R, G and B channels (`255 - value`) of every pixel in `pixels`.
+pub(crate) fn invert_channels(pixels: &mut [u8], channels: [bool; 3]) {
+    let mask = [
+        if channels[0] { 255 } else { 0 },
+        if channels[1] { 255 } else { 0 },
+        if channels[2] { 255 } else { 0 },
+    ];
+    xor_rgb(pixels, mask);
+}
+
+/// Add a signed amount to each of the R, G and B channels of every pixel, saturating at 0 and 255.
+pub(crate) fn add_rgb(pixels: &mut [u8], amounts: [i16; 3]) {
+    let pos = [
+        num::clamp(amounts[0], 0, 255) as u8,
+        num::clamp(amounts[1], 0, 255) as u8,
+        num::clamp(amounts[2], 0, 255) as u8,
+    ];
+    let neg = [
+        num::clamp(amounts[0].saturating_neg(), 0, 255) as u8,
+        num::clamp(amounts[1].saturating_neg(), 0, 255) as u8,
+        num::clamp(amounts[2].saturating_neg(), 0, 255) as u8,
+    ];
+    add_sub_rgb(pixels, pos, neg);
+}
+
+#[cfg(all(
+    target_arch = "wasm32",
+    target_feature = "simd128",
+    feature = "wasm-simd"
+))]
+fn splat_rgb(c: [u8; 3]) -> v128 {
+    u8x16(
+        c[0], c[1], c[2], 0, c[0], c[1], c[2], 0, c[0], c[1], c[2], 0, c[0], c[1], c[2],
+        0,
+    )
+}
+
+#[cfg(all(
+    target_arch = "wasm32",
+    target_feature = "simd128",
+    feature = "wasm-simd"
+))]
+fn xor_rgb(pixels: &mut [u8], mask: [u8; 3]) {
+    let v_mask = splat_rgb(mask);
+    let mut chunks = pixels.chunks_exact_mut(16);
+    for chunk in &mut chunks {
+        // Safety: `chunk` is exactly 16 bytes long, and v128 loads/stores are unaligned.
+        unsafe {
+            let ptr = chunk.as_mut_ptr() as *mut v128;
+            v128_store(ptr, v128_xor(v128_load(ptr), v_mask));
+        }
+    }
+    xor_rgb_scalar(chunks.into_remainder(), mask);
+}
+
+#[cfg(not(all(
+    target_arch = "wasm32",
+    target_feature = "simd128",
+    feature = "wasm-simd"
+)))]
+fn xor_rgb(pixels: &mut [u8], mask: [u8; 3]) {
+    xor_rgb_scalar(pixels, mask);
+}
+
+fn xor_rgb_scalar(pixels: &mut [u8], mask: [u8; 3]) {
+    for px in pixels.chunks_exact_mut(4) {
+        px[0] ^= mask[0];
+        px[1] ^= mask[1];
+        px[2] ^= mask[2];
+    }
+}
+
+#[cfg(all(
+    target_arch = "wasm32",
+    target_feature = "simd128",
+    feature = "wasm-simd"
+))]
+fn add_sub_rgb(pixels: &mut [u8], pos: [u8; 3], neg: [u8; 3]) {
+    let v_pos = splat_rgb(pos);
+    let v_neg = splat_rgb(neg);
+    let mut chunks = pixels.chunks_exact_mut(16);
+    for chunk in &mut chunks {
+        // Safety: `chunk` is exactly 16 bytes long, and v128 loads/stores are unaligned.
+        unsafe {
+            let ptr = chunk.as_mut_ptr() as *mut v128;
+            let v = u8x16_sub_sat(u8x16_add_sat(v128_load(ptr), v_pos), v_neg);
+            v128_store(ptr, v);
+        }
+    }
+    add_sub_rgb_scalar(chunks.into_remainder(), pos, neg);
+}
+
+#[cfg(not(all(
+    target_arch = "wasm32",
+    target_feature = "simd128",
+    feature = "wasm-simd"
+)))]
+fn add_sub_rgb(pixels: &mut [u8], pos: [u8; 3], neg: [u8; 3]) {
+    add_sub_rgb_scalar(pixels, pos, neg);
+}
+
+fn add_sub_rgb_scalar(pixels: &mut [u8], pos: [u8; 3], neg: [u8; 3]) {
+    for px in pixels.chunks_exact_mut(4) {
+        for c in 0..3 {
+            px[c] = px[c].saturating_add(pos[c]).saturating_sub(neg[c]);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Hide data in images with least-significant-bit steganography.
+//!
+//! Each bit of data is stored in the lowest bit of one of the image's red, green or blue
+//! channels, after a 32-bit header holding the data's length, so each pixel holds three
+//! bits. Alpha channels are left alone, as browsers may premultiply them. Changing the
+//! lowest bit of a channel changes its value by at most 1, which isn't visible, but the
+//! data is only recoverable from losslessly saved images, such as PNGs.
+//!
+//! Given a `seed`, the bits are scattered over the image in an order derived from it,
+//! rather than written from the top-left pixel onwards, so the same seed is needed to
+//! extract them.
+//!
+//! # Example
+//! ```
+//! use photon_rs::stego::{embed, extract};
+//! use photon_rs::PhotonImage;
+//!
+//! let mut img = PhotonImage::new(vec![128; 32 * 32 * 4], 32, 32);
+//! embed(&mut img, b"Hello, world!", Some(42)).unwrap();
+//! assert_eq!(extract(&img, Some(42)).unwrap(), b"Hello, world!");
+//! ```
+
+use crate::error::{PhotonError, Result};
+use crate::PhotonImage;
+use wasm_bindgen::prelude::*;
+
+/// The number of bits used to store the length of the data.
+const HEADER_BITS: usize = 32;
+
+/// The number of bytes of data which can be hidden in an image.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+#[wasm_bindgen]
+pub fn capacity(photon_image: &PhotonImage) -> usize {
+    let bits = photon_image.raw_pixels.len() / 4 * 3;
+    bits.saturating_sub(HEADER_BITS) / 8
+}
+
+/// Hide data in the lowest bits of an image's colour channels.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `data` - The data to hide.
+/// * `seed` - If given, the bits are scattered over the image in an order derived from it.
+/// # Errors
+/// Returns `PhotonError::Steganography` if the data doesn't fit in the image, in which
+/// case the image is left unmodified.
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::stego::embed;
+///
+/// let mut img = open_image("img.jpg");
+/// embed(&mut img, b"A secret message", None).unwrap();
+/// ```
+#[wasm_bindgen]
+pub fn embed(
+    photon_image: &mut PhotonImage,
+    data: &[u8],
+    seed: Option<u64>,
+) -> Result<()> {
+    let available = capacity(photon_image);
+    if data.len() > available || data.len() > u32::MAX as usize {
+        return Err(PhotonError::Steganography(format!(
+            "{} bytes don't fit in the image, which can hold {}",
+            data.len(),
+            available
+        )));
+    }
+
+    let header = (data.len() as u32).to_be_bytes();
+    let bits = header
+        .iter()
+        .chain(data)
+        .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    let slots = slots(photon_image, seed);
+    for (&slot, bit) in slots.iter().zip(bits) {
+        let channel = &mut photon_image.raw_pixels[slot];
+        *channel = (*channel & !1) | bit;
+    }
+    Ok(())
+}
+
+/// Extract data hidden in an image by `embed`.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `seed` - The seed which the data was hidden with, if any.
+/// # Errors
+/// Returns `PhotonError::Steganography` if the image's header doesn't describe data which
+/// fits in it, eg: because nothing was hidden in it, or the wrong seed was given.
+/// # Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::stego::extract;
+///
+/// let img = open_image("hidden.png");
+/// let message = extract(&img, None).unwrap();
+/// ```
+#[wasm_bindgen]
+pub fn extract(photon_image: &PhotonImage, seed: Option<u64>) -> Result<Vec<u8>> {
+    let slots = slots(photon_image, seed);
+    let pixels = &photon_image.raw_pixels;
+    let byte_at = |index: usize| {
+        slots[index * 8..index * 8 + 8]
+            .iter()
+            .fold(0u8, |byte, &slot| (byte << 1) | (pixels[slot] & 1))
+    };
+
+    if slots.len() < HEADER_BITS {
+        return Err(PhotonError::Steganography(
+            "the image is too small to hold any data".into(),
+        ));
+    }
+    let len =
+        u32::from_be_bytes([byte_at(0), byte_at(1), byte_at(2), byte_at(3)]) as usize;
+    if len > capacity(photon_image) {
+        return Err(PhotonError::Steganography(
+            "the image doesn't contain any hidden data".into(),
+        ));
+    }
+    Ok((0..len).map(|i| byte_at(i + HEADER_BITS / 8)).collect())
+}
+
+/// The indices in `raw_pixels` of the colour channels which hold successive bits, either
+/// in order, or shuffled by `seed`.
+fn slots(photon_image: &PhotonImage, seed: Option<u64>) -> Vec<usize> {
+    let mut slots: Vec<usize> = (0..photon_image.raw_pixels.len())
+        .filter(|i| i % 4 != 3)
+        .collect();
+    if let Some(seed) = seed {
+        // Shuffle with Fisher-Yates, using a xorshift generator whose state can't be 0.
+        let mut state = seed ^ 0x9e37_79b9_7f4a_7c15;
+        if state == 0 {
+            state = 1;
+        }
+        for i in (1..slots.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            slots.swap(i, (state % (i as u64 + 1)) as usize);
+        }
+    }
+    slots
+}
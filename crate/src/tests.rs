@@ -1,8 +1,13 @@
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::cancel::CancellationToken;
     use crate::channels::*;
-    use crate::PhotonImage;
+    use crate::colour_spaces::hsl_cancellable;
+    #[cfg(feature = "conv")]
+    use crate::conv::gaussian_blur_cancellable;
+    use crate::error::PhotonError;
+    use crate::{Connectivity, PhotonImage, Point, Rect, Rgba};
     #[test]
     fn test_alter_red_channel() {
         let width = 4;
@@ -29,6 +34,40 @@ mod test {
         assert_eq!(photon_image.raw_pixels, altered_r_channel_pix);
     }
 
+    #[test]
+    fn test_simd_invert_channels() {
+        use crate::simd::invert_channels;
+
+        let mut pixels = vec![0, 10, 200, 255, 255, 128, 1, 7];
+        invert_channels(&mut pixels, [true, false, true]);
+        assert_eq!(pixels, vec![255, 10, 55, 255, 0, 128, 254, 7]);
+    }
+
+    #[test]
+    fn test_simd_add_rgb_saturates() {
+        use crate::simd::add_rgb;
+
+        let mut pixels = vec![250, 10, 100, 255, 3, 200, 0, 9];
+        add_rgb(&mut pixels, [10, -20, 300]);
+        assert_eq!(pixels, vec![255, 0, 255, 255, 13, 180, 255, 9]);
+    }
+
+    #[test]
+    #[cfg(feature = "filters")]
+    fn test_lix_ryo() {
+        use crate::filters::{lix, ryo};
+
+        let mut img = PhotonImage::new(vec![0, 10, 200, 255, 255, 128, 1, 7], 2, 1);
+        lix(&mut img);
+        assert_eq!(img.raw_pixels, [255, 245, 200, 255, 0, 127, 1, 7]);
+        ryo(&mut img);
+        assert_eq!(img.raw_pixels, [0, 245, 55, 255, 255, 127, 254, 7]);
+
+        let mut empty = PhotonImage::new(vec![], 0, 0);
+        lix(&mut empty);
+        ryo(&mut empty);
+    }
+
     #[test]
     fn test_alter_blue_channel() {
         let width = 4;
@@ -158,4 +197,3085 @@ mod test {
         swap_channels(&mut photon_image, 1, 0);
         assert_eq!(photon_image.raw_pixels, correct_pix);
     }
+
+    #[cfg(feature = "conv")]
+    #[test]
+    fn test_cancelled_blur_leaves_image_unmodified() {
+        let raw_pix: Vec<u8> = (0..8 * 8 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        let mut photon_image = PhotonImage::new(raw_pix.clone(), 8, 8);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let res = gaussian_blur_cancellable(&mut photon_image, 2, &token);
+        assert_eq!(res, Err(PhotonError::Cancelled));
+        assert_eq!(photon_image.raw_pixels, raw_pix);
+
+        token.reset();
+        assert!(gaussian_blur_cancellable(&mut photon_image, 2, &token).is_ok());
+        assert_ne!(photon_image.raw_pixels, raw_pix);
+    }
+
+    #[test]
+    fn test_cancelled_colour_space_effect() {
+        let raw_pix: Vec<u8> = (0..4 * 4 * 4).map(|i| (i * 13 % 256) as u8).collect();
+        let mut photon_image = PhotonImage::new(raw_pix.clone(), 4, 4);
+
+        let token = CancellationToken::new();
+        token.clone().cancel();
+        let res = hsl_cancellable(&mut photon_image, "saturate", 0.2, &token);
+        assert_eq!(res, Err(PhotonError::Cancelled));
+        assert_eq!(photon_image.raw_pixels, raw_pix);
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn test_draw_text_with_font() {
+        use crate::text::{draw_text_with_font, Font};
+
+        let bytes = include_bytes!("../fonts/Roboto-Black.ttf").to_vec();
+        let font = Font::from_bytes(bytes).unwrap();
+        let mut photon_image = PhotonImage::new(vec![0; 64 * 32 * 4], 64, 32);
+        draw_text_with_font(&mut photon_image, "Hi", 2, 2, &font, 24.0);
+        assert!(photon_image.raw_pixels.iter().any(|&v| v > 0));
+
+        assert!(Font::from_bytes(vec![1, 2, 3, 4]).is_err());
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn test_draw_text_with_options_wraps_within_max_width() {
+        use crate::text::{draw_text_with_options, Font, TextAlign, TextOptions};
+
+        let mut photon_image = PhotonImage::new(vec![0; 200 * 200 * 4], 200, 200);
+        let mut options = TextOptions::new(20.0);
+        options.set_max_width(60);
+        options.set_align(TextAlign::Center);
+        let text = "several short words which must wrap";
+        draw_text_with_options(
+            &mut photon_image,
+            text,
+            100,
+            10,
+            &Font::roboto(),
+            &options,
+        );
+
+        // Every drawn pixel lies within the centred 60px column, across several lines.
+        let mut max_row = 0;
+        for (i, px) in photon_image.raw_pixels.chunks(4).enumerate() {
+            if px[0] > 0 {
+                let (x, y) = (i % 200, i / 200);
+                assert!((69..=131).contains(&x), "pixel drawn at x = {}", x);
+                max_row = max_row.max(y);
+            }
+        }
+        assert!(max_row > 60);
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn test_draw_text_with_background_and_outline() {
+        use crate::text::{draw_text_with_options, Font, TextOptions};
+        use crate::Rgb;
+
+        let mut photon_image = PhotonImage::new(vec![0; 100 * 60 * 4], 100, 60);
+        let mut options = TextOptions::new(20.0);
+        options.set_background(5, Rgb::new(0, 0, 255), 1.0);
+        options.set_outline(2, Rgb::new(255, 0, 0));
+        options.set_shadow(3, 3, 2, Rgb::new(0, 255, 0));
+        draw_text_with_options(
+            &mut photon_image,
+            "Hi",
+            20,
+            20,
+            &Font::roboto(),
+            &options,
+        );
+
+        let pixel = |x: usize, y: usize| {
+            let i = (y * 100 + x) * 4;
+            &photon_image.raw_pixels[i..i + 4]
+        };
+        // The padding inside the background box is filled, but outside it is untouched.
+        assert_eq!(pixel(16, 16), &[0, 0, 255, 255]);
+        assert_eq!(pixel(10, 10), &[0, 0, 0, 0]);
+        // The text, outline and shadow are all drawn.
+        let pixels: Vec<&[u8]> = photon_image.raw_pixels.chunks(4).collect();
+        assert!(pixels.iter().any(|px| px[..3] == [255, 255, 255]));
+        assert!(pixels.iter().any(|px| px[..3] == [255, 0, 0]));
+        assert!(pixels.iter().any(|px| px[1] > 0 && px[0] == 0));
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn test_draw_text_with_emoji_atlas() {
+        use crate::text::{draw_text_with_font, EmojiAtlas, Font};
+
+        let red = PhotonImage::new([255, 0, 0, 255].repeat(8 * 8), 8, 8);
+        let mut atlas = EmojiAtlas::new();
+        atlas.insert("\u{1F389}", red);
+        assert!(atlas.contains("\u{1F389}\u{FE0F}"));
+
+        let mut font = Font::roboto();
+        font.set_emoji_atlas(&atlas);
+        let mut photon_image = PhotonImage::new(vec![0; 100 * 40 * 4], 100, 40);
+        draw_text_with_font(&mut photon_image, "a\u{1F389}\u{FE0F}", 0, 0, &font, 20.0);
+
+        // The emoji is drawn as a 20x20 red square after the "a".
+        let red_pixels = photon_image
+            .raw_pixels
+            .chunks(4)
+            .filter(|px| px == &[255, 0, 0, 255])
+            .count();
+        assert!(red_pixels >= 18 * 18, "only {} red pixels", red_pixels);
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn test_draw_text_with_default_emoji_atlas() {
+        use crate::text::{
+            clear_default_emoji_atlas, draw_text, draw_text_with_border,
+            set_default_emoji_atlas, EmojiAtlas,
+        };
+
+        let red = PhotonImage::new([255, 0, 0, 255].repeat(8 * 8), 8, 8);
+        let mut atlas = EmojiAtlas::new();
+        atlas.insert("\u{1F389}", red);
+        set_default_emoji_atlas(&atlas);
+
+        let red_pixels = |img: &PhotonImage| {
+            img.raw_pixels
+                .chunks(4)
+                .filter(|px| px == &[255, 0, 0, 255])
+                .count()
+        };
+        let mut photon_image = PhotonImage::new(vec![0; 200 * 120 * 4], 200, 120);
+        draw_text(&mut photon_image, "a\u{1F389}", 0, 0);
+        assert!(red_pixels(&photon_image) >= 80 * 80);
+
+        // The border is drawn in black below and to the left of the text.
+        let mut bordered =
+            PhotonImage::new([0, 0, 255, 255].repeat(200 * 120), 200, 120);
+        draw_text_with_border(&mut bordered, "a\u{1F389}", 0, 10);
+        assert!(red_pixels(&bordered) >= 80 * 80);
+        assert!(bordered.raw_pixels.chunks(4).any(|px| px == [0, 0, 0, 255]));
+
+        clear_default_emoji_atlas();
+        let mut plain = PhotonImage::new(vec![0; 200 * 120 * 4], 200, 120);
+        draw_text(&mut plain, "a\u{1F389}", 0, 0);
+        assert_eq!(red_pixels(&plain), 0);
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn test_measure_text() {
+        use crate::text::{measure_text, Font};
+
+        let font = Font::roboto();
+        let short = measure_text("Photon", &font, 40.0);
+        let long = measure_text("Photon Photon", &font, 40.0);
+        assert!(short.get_width() > 0.0);
+        assert!(long.get_width() > 2.0 * short.get_width());
+        assert!(short.get_baseline() > 0.0 && short.get_baseline() < short.get_height());
+        assert_eq!(measure_text("", &font, 40.0).get_width(), 0.0);
+    }
+
+    #[cfg(feature = "shaping")]
+    #[test]
+    fn test_shaping_lays_out_rtl_text_right_to_left() {
+        use crate::text::{
+            clear_default_emoji_atlas, draw_text, draw_text_with_font,
+            set_default_emoji_atlas, EmojiAtlas, Font,
+        };
+
+        let red = PhotonImage::new([255, 0, 0, 255].repeat(8 * 8), 8, 8);
+        let mut atlas = EmojiAtlas::new();
+        atlas.insert("\u{1F389}", red);
+        let mut font = Font::roboto();
+        font.set_emoji_atlas(&atlas);
+
+        // The emoji follows the Hebrew letter, so is drawn to its left.
+        let mut photon_image = PhotonImage::new(vec![0; 100 * 40 * 4], 100, 40);
+        draw_text_with_font(&mut photon_image, "\u{5D0}\u{1F389}", 0, 0, &font, 20.0);
+        let i = (10 * 100 + 2) * 4;
+        assert_eq!(&photon_image.raw_pixels[i..i + 4], &[255, 0, 0, 255]);
+
+        // draw_text is laid out the same way, with the default font.
+        set_default_emoji_atlas(&atlas);
+        let mut photon_image = PhotonImage::new(vec![0; 200 * 120 * 4], 200, 120);
+        draw_text(&mut photon_image, "\u{5D0}\u{1F389}", 0, 0);
+        clear_default_emoji_atlas();
+        let i = (50 * 200 + 5) * 4;
+        assert_eq!(&photon_image.raw_pixels[i..i + 4], &[255, 0, 0, 255]);
+    }
+
+    #[cfg(feature = "drawing")]
+    #[test]
+    fn test_drawing_shapes() {
+        use crate::drawing::{draw_line, fill_circle, fill_polygon};
+        use crate::Rgba;
+
+        let white = Rgba::new(255, 255, 255, 255);
+        let pixel = |img: &PhotonImage, x: u32, y: u32| {
+            let i = ((y * img.get_width() + x) * 4) as usize;
+            img.raw_pixels[i]
+        };
+
+        let mut photon_image = PhotonImage::new(vec![0; 20 * 20 * 4], 20, 20);
+        draw_line(&mut photon_image, 2.0, 10.0, 18.0, 10.0, &white, 2.0);
+        assert_eq!(pixel(&photon_image, 10, 9), 255);
+        assert_eq!(pixel(&photon_image, 10, 12), 0);
+
+        let mut photon_image = PhotonImage::new(vec![0; 20 * 20 * 4], 20, 20);
+        fill_circle(&mut photon_image, 10.0, 10.0, 5.0, &white);
+        assert_eq!(pixel(&photon_image, 10, 10), 255);
+        assert_eq!(pixel(&photon_image, 1, 1), 0);
+        // Pixels on the edge are partially covered.
+        let edge = pixel(&photon_image, 14, 8);
+        assert!(edge > 0 && edge < 255);
+
+        let mut photon_image = PhotonImage::new(vec![0; 20 * 20 * 4], 20, 20);
+        fill_polygon(&mut photon_image, &[0.0, 0.0, 20.0, 0.0, 0.0, 20.0], &white);
+        assert_eq!(pixel(&photon_image, 2, 2), 255);
+        assert_eq!(pixel(&photon_image, 17, 17), 0);
+
+        // A trailing coordinate without a pair is ignored.
+        let mut odd = PhotonImage::new(vec![0; 20 * 20 * 4], 20, 20);
+        fill_polygon(&mut odd, &[0.0, 0.0, 20.0, 0.0, 0.0, 20.0, 7.0], &white);
+        assert_eq!(odd.raw_pixels, photon_image.raw_pixels);
+    }
+
+    #[test]
+    fn test_round_corners_and_circle_crop() {
+        use crate::transform::{circle_crop, round_corners};
+
+        let alpha = |img: &PhotonImage, x: u32, y: u32| {
+            img.raw_pixels[((y * img.get_width() + x) * 4 + 3) as usize]
+        };
+
+        let mut photon_image = PhotonImage::new(vec![255; 40 * 30 * 4], 40, 30);
+        round_corners(&mut photon_image, 10);
+        assert_eq!(alpha(&photon_image, 0, 0), 0);
+        assert_eq!(alpha(&photon_image, 39, 29), 0);
+        assert_eq!(alpha(&photon_image, 20, 0), 255);
+        assert_eq!(alpha(&photon_image, 5, 5), 255);
+
+        let avatar = circle_crop(&photon_image);
+        assert_eq!((avatar.get_width(), avatar.get_height()), (30, 30));
+        assert_eq!(alpha(&avatar, 0, 0), 0);
+        assert_eq!(alpha(&avatar, 15, 15), 255);
+        assert_eq!(alpha(&avatar, 15, 1), 255);
+    }
+
+    #[cfg(feature = "drawing")]
+    #[test]
+    fn test_fill_gradient() {
+        use crate::drawing::{fill_gradient, Gradient};
+        use crate::{Rect, Rgba};
+
+        let mut photon_image =
+            PhotonImage::new([255, 255, 255, 255].repeat(10 * 10), 10, 10);
+        let mut gradient = Gradient::linear(0.0, 0.0, 0.0, 10.0);
+        gradient.add_stop(0.0, &Rgba::new(0, 0, 0, 0));
+        gradient.add_stop(1.0, &Rgba::new(0, 0, 0, 255));
+        fill_gradient(
+            &mut photon_image,
+            &Rect::new(0, 5, 10, 10),
+            &gradient,
+            "over",
+            1.0,
+        );
+
+        let red = |y: usize| photon_image.raw_pixels[y * 10 * 4];
+        // Above the region is untouched, and the region darkens towards its bottom.
+        assert_eq!(red(4), 255);
+        assert!(red(5) > red(7) && red(7) > red(9));
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_add_border_expands_canvas() {
+        use crate::effects::{add_border, add_double_border};
+        use crate::Rgb;
+
+        let mut photon_image = PhotonImage::new(vec![50; 4 * 2 * 4], 4, 2);
+        add_border(&mut photon_image, 3, Rgb::new(255, 0, 0));
+        assert_eq!(
+            (photon_image.get_width(), photon_image.get_height()),
+            (10, 8)
+        );
+        assert_eq!(&photon_image.raw_pixels[..4], &[255, 0, 0, 255]);
+        let centre = ((3 * 10 + 3) * 4) as usize;
+        assert_eq!(
+            &photon_image.raw_pixels[centre..centre + 4],
+            &[50, 50, 50, 50]
+        );
+
+        add_double_border(&mut photon_image, 3, Rgb::new(0, 0, 0), Rgb::new(9, 9, 9));
+        assert_eq!(
+            (photon_image.get_width(), photon_image.get_height()),
+            (16, 14)
+        );
+        let gap = ((16 + 1) * 4) as usize;
+        assert_eq!(&photon_image.raw_pixels[gap..gap + 4], &[9, 9, 9, 255]);
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_equalize_and_clahe_stretch_contrast() {
+        use crate::effects::{clahe, equalize};
+
+        // A low-contrast gradient, from dark grey to mid grey.
+        let mut raw_pixels = vec![];
+        for x in 0..64 {
+            let v = 80 + x as u8;
+            raw_pixels.extend_from_slice(&[v, v, v, 255]);
+        }
+        let raw_pixels = raw_pixels.repeat(8);
+        let range = |img: &PhotonImage| {
+            let reds = img.raw_pixels.chunks(4).map(|px| px[0]);
+            reds.clone().max().unwrap() - reds.min().unwrap()
+        };
+
+        let mut photon_image = PhotonImage::new(raw_pixels.clone(), 64, 8);
+        equalize(&mut photon_image);
+        assert!(range(&photon_image) > 200);
+
+        let mut photon_image = PhotonImage::new(raw_pixels, 64, 8);
+        clahe(&mut photon_image, 16, 3.0);
+        assert!(range(&photon_image) > 63);
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_stats() {
+        use crate::analysis::stats;
+
+        let raw_pixels = vec![0, 10, 200, 255, 100, 10, 200, 255];
+        let stats = stats(&PhotonImage::new(raw_pixels, 2, 1));
+
+        let red = stats.get_red();
+        assert_eq!(red.get_mean(), 50.0);
+        assert_eq!(red.get_std_dev(), 50.0);
+        assert_eq!((red.get_min(), red.get_max()), (0, 100));
+        assert_eq!(red.get_entropy(), 1.0);
+        assert_eq!(stats.get_green().get_entropy(), 0.0);
+        assert_eq!(stats.get_blue().get_mean(), 200.0);
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_ssim_and_psnr() {
+        use crate::analysis::{psnr, ssim};
+
+        let mut raw_pixels = vec![];
+        for i in 0..16 * 16 {
+            let v = ((i * 37) % 256) as u8;
+            raw_pixels.extend_from_slice(&[v, v, v, 255]);
+        }
+        let original = PhotonImage::new(raw_pixels.clone(), 16, 16);
+        let noisy_pixels = raw_pixels.iter().map(|v| v.saturating_sub(8)).collect();
+        let noisy = PhotonImage::new(noisy_pixels, 16, 16);
+
+        assert!((ssim(&original, &original) - 1.0).abs() < 1e-9);
+        assert!(ssim(&original, &noisy) < 1.0);
+        assert_eq!(psnr(&original, &original), f64::INFINITY);
+        let db = psnr(&original, &noisy);
+        assert!(db > 25.0 && db < 35.0, "psnr was {}", db);
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_average_and_dominant_color() {
+        use crate::analysis::{average_color, dominant_color};
+
+        let mut raw_pixels = [200, 10, 10, 255].repeat(3);
+        raw_pixels.extend_from_slice(&[0, 0, 200, 255]);
+        // Transparent pixels don't count.
+        raw_pixels.extend_from_slice(&[0, 255, 0, 0]);
+        let photon_image = PhotonImage::new(raw_pixels, 5, 1);
+
+        let average = average_color(&photon_image);
+        assert_eq!(
+            (average.get_red(), average.get_green(), average.get_blue()),
+            (150, 8, 58)
+        );
+        let dominant = dominant_color(&photon_image);
+        assert_eq!(
+            (
+                dominant.get_red(),
+                dominant.get_green(),
+                dominant.get_blue()
+            ),
+            (200, 10, 10)
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_sharpness() {
+        use crate::analysis::sharpness;
+
+        // A checkerboard is sharp, and a smooth gradient is not.
+        let mut checkerboard = vec![];
+        let mut gradient = vec![];
+        for y in 0..16 {
+            for x in 0..16 {
+                let c = if (x + y) % 2 == 0 { 0 } else { 255 };
+                checkerboard.extend_from_slice(&[c, c, c, 255]);
+                let g = (x * 8) as u8;
+                gradient.extend_from_slice(&[g, g, g, 255]);
+            }
+        }
+        let sharp = sharpness(&PhotonImage::new(checkerboard, 16, 16));
+        let blurry = sharpness(&PhotonImage::new(gradient, 16, 16));
+        assert!(sharp > 1000.0);
+        assert!(blurry < 1.0);
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_auto_exposure_brightens_dark_images() {
+        use crate::effects::auto_exposure;
+
+        let mut raw_pixels = vec![];
+        for v in 0..32 {
+            raw_pixels.extend_from_slice(&[v * 2, v * 2, v * 2, 255]);
+        }
+        raw_pixels.extend_from_slice(&[255, 255, 255, 255]);
+        let mut photon_image = PhotonImage::new(raw_pixels, 33, 1);
+        auto_exposure(&mut photon_image);
+
+        // Mid-tones are brightened, but black and white stay in place.
+        assert!(photon_image.raw_pixels[16 * 4] > 64);
+        assert_eq!(photon_image.raw_pixels[0], 0);
+        assert_eq!(photon_image.raw_pixels[32 * 4], 255);
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_auto_contrast() {
+        use crate::effects::{auto_contrast, auto_contrast_per_channel};
+
+        let mut raw_pixels = vec![];
+        for v in 100..150 {
+            raw_pixels.extend_from_slice(&[v, v, v - 50, 255]);
+        }
+        let mut photon_image = PhotonImage::new(raw_pixels.clone(), 50, 1);
+        auto_contrast(&mut photon_image, 0.0);
+        // The same stretch is applied to every channel, so the blue channel is clipped
+        // to black before the red and green channels are.
+        assert_eq!(photon_image.raw_pixels[2], 0);
+        assert!(photon_image.raw_pixels[0] > 0);
+        assert_eq!(photon_image.raw_pixels[49 * 4], 255);
+
+        let mut photon_image = PhotonImage::new(raw_pixels, 50, 1);
+        auto_contrast_per_channel(&mut photon_image, 0.0);
+        assert_eq!(&photon_image.raw_pixels[..3], &[0, 0, 0]);
+        assert_eq!(
+            &photon_image.raw_pixels[49 * 4..49 * 4 + 3],
+            &[255, 255, 255]
+        );
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_auto_white_balance_and_enhance() {
+        use crate::effects::{auto_enhance, auto_white_balance};
+
+        let mut photon_image = PhotonImage::new([120, 100, 80, 255].repeat(16), 4, 4);
+        auto_white_balance(&mut photon_image);
+        assert_eq!(photon_image.raw_pixels, [100, 100, 100, 255].repeat(16));
+
+        let mut raw_pixels = vec![];
+        for v in 100..150 {
+            raw_pixels.extend_from_slice(&[v + 20, v, v - 20, 255]);
+        }
+        let mut photon_image = PhotonImage::new(raw_pixels, 50, 1);
+        auto_enhance(&mut photon_image);
+        let first = &photon_image.raw_pixels[..3];
+        let last = &photon_image.raw_pixels[49 * 4..49 * 4 + 3];
+        // The cast is removed and the tones stretched to the full range.
+        assert!(first.iter().all(|&v| v < 20));
+        assert!(last.iter().all(|&v| v > 235));
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_inpaint() {
+        use crate::effects::inpaint;
+
+        // A 7x7 horizontal gradient, with a scratch down the middle column.
+        let mut raw_pixels = vec![];
+        for _ in 0..7 {
+            for x in 0..7u8 {
+                raw_pixels.extend_from_slice(&[x * 30, 100, 100, 255]);
+            }
+        }
+        let expected = raw_pixels.clone();
+        let mut mask = vec![0; 7 * 7 * 4];
+        for y in 0..7 {
+            raw_pixels[(y * 7 + 3) * 4] = 255;
+            mask[(y * 7 + 3) * 4..(y * 7 + 4) * 4].copy_from_slice(&[255; 4]);
+        }
+        let mut photon_image = PhotonImage::new(raw_pixels, 7, 7);
+        let mask = PhotonImage::new(mask, 7, 7);
+        inpaint(&mut photon_image, &mask, 3);
+
+        for y in 0..7 {
+            let i = (y * 7 + 3) * 4;
+            assert!((photon_image.raw_pixels[i] as i32 - expected[i] as i32).abs() <= 5);
+            assert_eq!(photon_image.raw_pixels[i + 1], 100);
+        }
+        assert_eq!(photon_image.raw_pixels[..12], expected[..12]);
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_saliency_map() {
+        use crate::analysis::saliency_map;
+
+        // A bright disc on a smooth gradient should stand out.
+        let in_disc = |x: usize, y: usize, radius: i32| {
+            let (dx, dy) = (x as i32 - 20, y as i32 - 40);
+            dx * dx + dy * dy <= radius * radius
+        };
+        let mut raw_pixels = vec![];
+        for y in 0..64 {
+            for x in 0..64 {
+                let v = if in_disc(x, y, 6) { 255 } else { 40 + x as u8 };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let photon_image = PhotonImage::new(raw_pixels, 64, 64);
+        let saliency = saliency_map(&photon_image);
+        assert_eq!(saliency.get_width(), 64);
+
+        let (mut inside, mut outside) = ((0.0, 0.0), (0.0, 0.0));
+        for (i, px) in saliency.raw_pixels.chunks(4).enumerate() {
+            let total = if in_disc(i % 64, i / 64, 8) {
+                &mut inside
+            } else {
+                &mut outside
+            };
+            total.0 += px[0] as f64;
+            total.1 += 1.0;
+        }
+        assert!(inside.0 / inside.1 > 4.0 * outside.0 / outside.1);
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_diff() {
+        use crate::analysis::diff;
+
+        let img_a = PhotonImage::new([0, 0, 0, 255].repeat(4), 2, 2);
+        let mut img_b = img_a.clone();
+        img_b.raw_pixels[0] = 2;
+        img_b.raw_pixels[6] = 20;
+
+        let (image, changed_pixels) = diff(&img_a, &img_b, 2).into_parts();
+        assert_eq!(changed_pixels, 1);
+        assert_eq!(&image.raw_pixels[4..8], &[255, 0, 0, 255]);
+        assert_eq!(&image.raw_pixels[..4], &[192, 192, 192, 255]);
+        assert_eq!(diff(&img_a, &img_b, 0).get_changed_pixels(), 2);
+    }
+
+    #[cfg(feature = "noise")]
+    #[test]
+    fn test_perlin_and_simplex() {
+        use crate::noise::{perlin, simplex};
+
+        for generate in [perlin, simplex].iter() {
+            let texture = generate(32, 16, 8.0, 3, 7);
+            assert_eq!((texture.get_width(), texture.get_height()), (32, 16));
+            assert_eq!(texture.raw_pixels, generate(32, 16, 8.0, 3, 7).raw_pixels);
+            assert_ne!(texture.raw_pixels, generate(32, 16, 8.0, 3, 8).raw_pixels);
+
+            let min = texture.raw_pixels.iter().step_by(4).min().unwrap();
+            let max = texture.raw_pixels.iter().step_by(4).max().unwrap();
+            assert!(max - min > 64);
+        }
+    }
+
+    #[cfg(feature = "noise")]
+    #[test]
+    fn test_film_grain() {
+        use crate::noise::film_grain;
+
+        let original = PhotonImage::new([128, 128, 128, 255].repeat(16 * 16), 16, 16);
+        let mut grainy = original.clone();
+        film_grain(&mut grainy, 0.1, 2.0, true, 1);
+        assert_ne!(grainy.raw_pixels, original.raw_pixels);
+        for px in grainy.raw_pixels.chunks(4) {
+            assert!(px[0] == px[1] && px[1] == px[2] && px[3] == 255);
+        }
+
+        let mut again = original.clone();
+        film_grain(&mut again, 0.1, 2.0, true, 1);
+        assert_eq!(again.raw_pixels, grainy.raw_pixels);
+    }
+
+    #[cfg(feature = "noise")]
+    #[test]
+    fn test_salt_and_pepper() {
+        use crate::noise::salt_and_pepper;
+
+        let mut photon_image =
+            PhotonImage::new([100, 150, 200, 255].repeat(100 * 100), 100, 100);
+        salt_and_pepper(&mut photon_image, 0.1, 3);
+        let (mut salt, mut pepper) = (0, 0);
+        for px in photon_image.raw_pixels.chunks(4) {
+            match px {
+                [255, 255, 255, 255] => salt += 1,
+                [0, 0, 0, 255] => pepper += 1,
+                _ => assert_eq!(px, &[100, 150, 200, 255]),
+            }
+        }
+        assert!((400..600).contains(&salt) && (400..600).contains(&pepper));
+    }
+
+    #[cfg(feature = "noise")]
+    #[test]
+    fn test_gaussian_noise() {
+        use crate::noise::gaussian_noise;
+
+        let mut photon_image =
+            PhotonImage::new([128, 128, 128, 255].repeat(100 * 100), 100, 100);
+        gaussian_noise(&mut photon_image, 10.0, false);
+        let offsets: Vec<f64> = photon_image
+            .raw_pixels
+            .chunks(4)
+            .map(|px| {
+                assert!(px[0] == px[1] && px[1] == px[2]);
+                px[0] as f64 - 128.0
+            })
+            .collect();
+        let mean = offsets.iter().sum::<f64>() / offsets.len() as f64;
+        let variance = offsets.iter().map(|o| (o - mean).powi(2)).sum::<f64>()
+            / offsets.len() as f64;
+        assert!(mean.abs() < 1.0);
+        assert!((variance.sqrt() - 10.0).abs() < 1.0);
+    }
+
+    #[cfg(feature = "noise")]
+    #[test]
+    fn test_set_seed() {
+        use crate::noise::{clear_seed, gaussian_noise, set_seed};
+
+        let original = PhotonImage::new([128, 128, 128, 255].repeat(8 * 8), 8, 8);
+        let noisy = |seed| {
+            set_seed(seed);
+            let mut first = original.clone();
+            gaussian_noise(&mut first, 10.0, true);
+            let mut second = original.clone();
+            gaussian_noise(&mut second, 10.0, true);
+            (first.raw_pixels, second.raw_pixels)
+        };
+
+        let (first, second) = noisy(1);
+        assert_ne!(first, second);
+        assert_eq!(noisy(1), (first.clone(), second));
+        assert_ne!(noisy(2).0, first);
+        clear_seed();
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_offset_with_direction() {
+        use crate::effects::{offset_with_direction, OffsetDirection};
+
+        let raw_pixels: Vec<u8> = (0..3).flat_map(|v| vec![v * 10, 0, 0, 255]).collect();
+        let mut photon_image = PhotonImage::new(raw_pixels, 3, 1);
+        offset_with_direction(&mut photon_image, 0, -1, OffsetDirection::Horizontal);
+        let reds: Vec<u8> = photon_image.raw_pixels.chunks(4).map(|px| px[0]).collect();
+        assert_eq!(reds, vec![10, 20, 20]);
+
+        offset_with_direction(&mut photon_image, 0, 2, OffsetDirection::Horizontal);
+        let reds: Vec<u8> = photon_image.raw_pixels.chunks(4).map(|px| px[0]).collect();
+        assert_eq!(reds, vec![10, 10, 10]);
+
+        // Moving vertically in a single-row image leaves it unchanged.
+        let before = photon_image.raw_pixels.clone();
+        offset_with_direction(&mut photon_image, 0, 5, OffsetDirection::Vertical);
+        assert_eq!(photon_image.raw_pixels, before);
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_adjust_brightness() {
+        use crate::effects::{adjust_brightness, inc_brightness};
+
+        let raw_pixels = vec![10, 20, 30, 40, 100, 250, 5, 255];
+        let mut photon_image = PhotonImage::new(raw_pixels.clone(), 2, 1);
+        adjust_brightness(&mut photon_image, -20);
+        assert_eq!(photon_image.raw_pixels, vec![0, 0, 10, 40, 80, 230, 0, 255]);
+
+        let mut photon_image = PhotonImage::new(raw_pixels.clone(), 2, 1);
+        adjust_brightness(&mut photon_image, i16::MIN);
+        assert_eq!(photon_image.raw_pixels, vec![0, 0, 0, 40, 0, 0, 0, 255]);
+
+        // Only the saturated channel is clamped, and the last pixel is brightened too.
+        let mut photon_image = PhotonImage::new(raw_pixels, 2, 1);
+        inc_brightness(&mut photon_image, 10);
+        assert_eq!(
+            photon_image.raw_pixels,
+            vec![20, 30, 40, 40, 110, 255, 15, 255]
+        );
+    }
+
+    #[cfg(feature = "effects")]
+    #[test]
+    fn test_tint_signed() {
+        use crate::effects::{tint, tint_signed};
+
+        let mut photon_image = PhotonImage::new(vec![100, 200, 30, 255], 1, 1);
+        tint_signed(&mut photon_image, -120, 80, -10);
+        assert_eq!(photon_image.raw_pixels, vec![0, 255, 20, 255]);
+
+        // Large offsets saturate instead of overflowing.
+        let mut photon_image = PhotonImage::new(vec![100, 200, 30, 255], 1, 1);
+        tint(&mut photon_image, 300, 0, u32::MAX);
+        assert_eq!(photon_image.raw_pixels, vec![255, 200, 255, 255]);
+    }
+
+    #[cfg(feature = "conv")]
+    #[test]
+    fn test_alpha_mode() {
+        use crate::alpha::{with_alpha_mode, AlphaMode};
+        use crate::conv::{box_blur, gaussian_blur, sharpen};
+        use crate::transform::{resize, SamplingFilter};
+
+        // An opaque white logo on a transparent black background.
+        let mut raw_pixels = vec![];
+        for _ in 0..8 {
+            raw_pixels.extend([255, 255, 255, 255].repeat(4));
+            raw_pixels.extend([0, 0, 0, 0].repeat(4));
+        }
+        let logo = PhotonImage::new(raw_pixels, 8, 8);
+
+        // Blurring mustn't darken the edge of the logo, but should soften its alpha.
+        let mut blurred = logo.clone();
+        gaussian_blur(&mut blurred, 2);
+        let edge = &blurred.raw_pixels[(3 * 8 + 4) * 4..(3 * 8 + 5) * 4];
+        assert_eq!(&edge[..3], &[255, 255, 255]);
+        assert!(edge[3] > 0 && edge[3] < 255);
+
+        let mut blurred = logo.clone();
+        with_alpha_mode(&mut blurred, AlphaMode::Preserve, |img| {
+            gaussian_blur(img, 2)
+        });
+        let alpha = |img: &PhotonImage| -> Vec<u8> {
+            img.raw_pixels.iter().skip(3).step_by(4).cloned().collect()
+        };
+        assert_eq!(alpha(&blurred), alpha(&logo));
+        assert_eq!(&blurred.raw_pixels[3 * 4..3 * 4 + 3], &[255, 255, 255]);
+
+        let mut blurred = logo.clone();
+        box_blur(&mut blurred);
+        let edge = &blurred.raw_pixels[(3 * 8 + 4) * 4..(3 * 8 + 5) * 4];
+        assert_eq!(edge, &[255, 255, 255, 85]);
+
+        let mut sharpened = logo.clone();
+        with_alpha_mode(&mut sharpened, AlphaMode::Preserve, sharpen);
+        assert_eq!(alpha(&sharpened), alpha(&logo));
+
+        let resized = resize(&logo, 3, 3, SamplingFilter::Triangle);
+        for px in resized.raw_pixels.chunks(4).filter(|px| px[3] > 0) {
+            assert_eq!(&px[..3], &[255, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn test_alpha_mode_preserved_by_colour_adjustments() {
+        use crate::alpha::{set_alpha_mode, AlphaMode};
+        use crate::channels::selective_saturate;
+        use crate::colour_spaces::{hsl, lch};
+        use crate::monochrome::grayscale_shades;
+        use crate::Rgb;
+
+        let original = PhotonImage::new([200, 40, 40, 128].repeat(4), 2, 2);
+        let alpha = |img: &PhotonImage| -> Vec<u8> {
+            img.raw_pixels.iter().skip(3).step_by(4).cloned().collect()
+        };
+        let ops: Vec<fn(&mut PhotonImage)> = vec![
+            |img| lch(img, "saturate", 0.2),
+            |img| hsl(img, "shift_hue", 0.5),
+            |img| selective_saturate(img, Rgb::new(210, 40, 40), 0.2),
+            |img| grayscale_shades(img, 4),
+        ];
+        for op in ops {
+            let mut img = original.clone();
+            op(&mut img);
+            assert_eq!(alpha(&img), vec![255; 4]);
+
+            let mut img = original.clone();
+            set_alpha_mode(AlphaMode::Preserve);
+            op(&mut img);
+            set_alpha_mode(AlphaMode::Default);
+            assert_eq!(alpha(&img), alpha(&original));
+            assert_ne!(img.raw_pixels, original.raw_pixels);
+        }
+    }
+
+    #[test]
+    fn test_chainable_api() {
+        use crate::channels::{alter_blue_channel, alter_red_channel};
+
+        let original = PhotonImage::new([100, 100, 100, 255].repeat(4), 2, 2);
+        let edited = original
+            .applied(|img| alter_red_channel(img, 20))
+            .apply(|img| alter_blue_channel(img, -20));
+        assert_eq!(&edited.raw_pixels[..4], &[120, 100, 80, 255]);
+        assert_eq!(original.raw_pixels, [100, 100, 100, 255].repeat(4));
+    }
+
+    #[test]
+    fn test_owned_chainable_methods() {
+        use crate::transform::SamplingFilter;
+        use crate::Rect;
+
+        let mut raw_pixels = vec![];
+        for x in 0..4 {
+            raw_pixels.extend_from_slice(&[x * 60, 100, 100, 255]);
+        }
+        let original = PhotonImage::new(raw_pixels.repeat(2), 4, 2);
+        let edited = original
+            .copy()
+            .inverted()
+            .flipped_h()
+            .cropped(&Rect::new(0, 0, 2, 2));
+        assert_eq!((edited.width, edited.height), (2, 2));
+        assert_eq!(
+            &edited.raw_pixels[..8],
+            &[75, 155, 155, 255, 135, 155, 155, 255]
+        );
+
+        let resized = edited.resized(1, 1, SamplingFilter::Triangle);
+        assert_eq!((resized.width, resized.height), (1, 1));
+
+        let gray = original.copy().grayscaled();
+        assert_eq!(&gray.raw_pixels[..4], &[66, 66, 66, 255]);
+        assert_eq!(original.raw_pixels, raw_pixels.repeat(2));
+    }
+
+    #[test]
+    fn test_generic_image() {
+        use image::{GenericImage, GenericImageView, Rgba};
+
+        let mut photon_image = PhotonImage::new(vec![0; 3 * 2 * 4], 3, 2);
+        photon_image.put_pixel(2, 1, Rgba([10, 20, 30, 255]));
+        photon_image.blend_pixel(0, 0, Rgba([200, 200, 200, 255]));
+        assert_eq!(photon_image.dimensions(), (3, 2));
+        assert_eq!(photon_image.get_pixel(2, 1), Rgba([10, 20, 30, 255]));
+        assert_eq!(&photon_image.raw_pixels[..4], &[200, 200, 200, 255]);
+        assert_eq!(&photon_image.raw_pixels[20..], &[10, 20, 30, 255]);
+        assert_eq!(photon_image.pixels().count(), 6);
+    }
+
+    #[cfg(feature = "pipeline")]
+    #[test]
+    fn test_run_json() {
+        use crate::error::PhotonError;
+        use crate::pipeline::run_json;
+
+        let mut photon_image = PhotonImage::new([200, 100, 50, 255].repeat(8 * 4), 8, 4);
+        let spec = r#"[
+            {"op": "resize", "w": 4},
+            {"op": "crop", "x": 1, "y": 0, "w": 2, "h": 2},
+            {"op": "brightness", "amount": -50}
+        ]"#;
+        run_json(&mut photon_image, spec).unwrap();
+        assert_eq!(
+            (photon_image.get_width(), photon_image.get_height()),
+            (2, 2)
+        );
+        assert_eq!(&photon_image.raw_pixels[..4], &[150, 50, 0, 255]);
+
+        let mut photon_image = PhotonImage::new(vec![200, 50, 50, 255], 1, 1);
+        let spec = r#"[{"op": "hue_rotate", "degrees": 120}]"#;
+        run_json(&mut photon_image, spec).unwrap();
+        assert_eq!(photon_image.raw_pixels, vec![50, 200, 50, 255]);
+
+        // Blurs wider than the image are limited to its size.
+        let mut photon_image = PhotonImage::new(vec![120; 16 * 16 * 4], 16, 16);
+        let spec = r#"[{"op": "gaussian_blur", "sigma": 100}]"#;
+        run_json(&mut photon_image, spec).unwrap();
+        assert!(photon_image.raw_pixels.iter().all(|&v| v >= 119));
+
+        // Invalid specs leave the image untouched.
+        let before = photon_image.clone();
+        for spec in &[
+            r#"[{"op": "grayscale"}, {"op": "crop", "x": 1, "y": 1, "w": 2, "h": 2}]"#,
+            r#"[{"op": "resize", "w": 1000000}]"#,
+            r#"[{"op": "resize", "w": 10000, "h": 10000}]"#,
+            r#"[{"op": "gaussian_blur", "sigma": 2, "radius": 3}]"#,
+            r#"[{"op": "filter", "name": "nonexistent"}]"#,
+            r#"[{"op": "rm -rf"}]"#,
+            "not json",
+        ] {
+            match run_json(&mut photon_image, spec) {
+                Err(PhotonError::InvalidPipeline(_)) => {}
+                other => panic!("{} gave {:?}", spec, other),
+            }
+            assert_eq!(photon_image.raw_pixels, before.raw_pixels);
+        }
+    }
+
+    #[cfg(feature = "pipeline")]
+    #[test]
+    fn test_photon_pipeline_macro() {
+        use crate::photon_pipeline;
+        use crate::pipeline::{Operation, Pipeline};
+
+        let pipeline = photon_pipeline![
+            Resize {
+                w: Some(2),
+                h: Some(2)
+            },
+            Brightness { amount: 10 },
+            Tint { r: -10, g: 0, b: 5 },
+        ];
+        let built = Pipeline::new()
+            .then(Operation::Resize {
+                w: Some(2),
+                h: Some(2),
+            })
+            .then(Operation::Brightness { amount: 10 })
+            .then(Operation::Tint { r: -10, g: 0, b: 5 });
+        assert_eq!(pipeline, built);
+        assert_eq!(Pipeline::from_json(&pipeline.to_json()).unwrap(), pipeline);
+
+        for _ in 0..2 {
+            let mut photon_image =
+                PhotonImage::new([100, 100, 100, 255].repeat(16), 4, 4);
+            pipeline.apply(&mut photon_image).unwrap();
+            assert_eq!(photon_image.raw_pixels, [100, 110, 115, 255].repeat(4));
+        }
+    }
+
+    #[cfg(feature = "pipeline")]
+    #[test]
+    fn test_operation_applied_in_bands() {
+        use crate::pipeline::Operation;
+
+        let mut seed = 3u32;
+        // A gradient with mild noise, which both operations smooth.
+        let raw_pixels: Vec<u8> = (0..24 * 37 * 4)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (i / 4 % 24 * 6 + i / 96 * 3 + (seed >> 16) as usize % 24) as u8
+            })
+            .collect();
+        let source = PhotonImage::new(raw_pixels, 24, 37);
+
+        for operation in [
+            Operation::Kuwahara { radius: 3 },
+            Operation::NlMeans { strength: 0.6 },
+        ]
+        .iter()
+        {
+            let mut whole = source.clone();
+            operation.apply(&mut whole).unwrap();
+            let mut banded = source.clone();
+            for start in (0..37).step_by(5) {
+                operation
+                    .apply_to_rows(&source, &mut banded, start..start + 5)
+                    .unwrap();
+            }
+            assert_ne!(whole.raw_pixels, source.raw_pixels);
+            assert_eq!(banded.raw_pixels, whole.raw_pixels);
+        }
+
+        let mut img = source.clone();
+        let invalid = Operation::Kuwahara { radius: 33 };
+        assert!(invalid.apply_to_rows(&source, &mut img, 0..5).is_err());
+        assert!(Operation::Invert
+            .apply_to_rows(&source, &mut img, 0..5)
+            .is_err());
+        assert_eq!(img.raw_pixels, source.raw_pixels);
+    }
+
+    #[cfg(feature = "pipeline")]
+    #[test]
+    fn test_edit_session_undo_redo() {
+        use crate::pipeline::Operation;
+        use crate::session::EditSession;
+
+        let original = PhotonImage::new([100, 100, 100, 255].repeat(4), 2, 2);
+        let mut session = EditSession::new(original.clone());
+        for _ in 0..10 {
+            session.apply(Operation::Brightness { amount: 10 }).unwrap();
+        }
+        assert!(session.apply_json(r#"{"op": "brightness"}"#).is_err());
+        assert_eq!(session.history().len(), 10);
+        assert_eq!(session.image().raw_pixels[0], 200);
+
+        while session.undo() {}
+        assert_eq!(session.image().raw_pixels, original.raw_pixels);
+        assert!(!session.can_undo());
+
+        for _ in 0..9 {
+            assert!(session.redo());
+        }
+        assert_eq!(session.image().raw_pixels[0], 190);
+        assert!(session.undo());
+        assert_eq!(session.image().raw_pixels[0], 180);
+
+        session.apply_json(r#"{"op": "grayscale"}"#).unwrap();
+        assert!(!session.can_redo());
+        assert!(session.history_json().ends_with(r#"{"op":"grayscale"}]"#));
+    }
+
+    #[cfg(feature = "pipeline")]
+    #[test]
+    fn test_preset_round_trip() {
+        use crate::pipeline::Operation;
+        use crate::preset::{apply_preset, Preset};
+
+        let mut preset = Preset::new("bright");
+        preset
+            .push_json(r#"{"op": "brightness", "amount": 20}"#)
+            .unwrap();
+        assert!(preset.push_json(r#"{"op": "blur"}"#).is_err());
+        let preset = preset.then(Operation::Brightness { amount: 15 });
+
+        let shared = Preset::from_json(&preset.to_json()).unwrap();
+        assert_eq!(shared, preset);
+        assert_eq!(shared.get_name(), "bright");
+        assert_eq!(shared.pipeline().len(), 2);
+        assert!(Preset::from_json(r#"{"name": "x", "ops": []}"#).is_err());
+
+        let mut photon_image = PhotonImage::new([100, 100, 100, 255].repeat(4), 2, 2);
+        apply_preset(&mut photon_image, &shared).unwrap();
+        assert_eq!(photon_image.raw_pixels, [135, 135, 135, 255].repeat(4));
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_batch_output_path() {
+        use crate::batch::{output_path, DEFAULT_TEMPLATE};
+        use std::path::{Path, PathBuf};
+
+        let input = Path::new("photos/beach.jpg");
+        assert_eq!(
+            output_path(DEFAULT_TEMPLATE, input, 1),
+            PathBuf::from("photos/beach-photon.jpg")
+        );
+        assert_eq!(
+            output_path("out/{n}-{stem}.png", input, 3),
+            PathBuf::from("out/3-beach.png")
+        );
+        assert_eq!(
+            output_path("{dir}/small/{name}", Path::new("beach.jpg"), 1),
+            PathBuf::from("./small/beach.jpg")
+        );
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn test_batch_watch() {
+        use crate::batch::{save, watch};
+        use crate::cancel::CancellationToken;
+        use crate::pipeline::Pipeline;
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let name = format!("photon-watch-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let token = CancellationToken::new();
+        let (sender, receiver) = mpsc::channel();
+        let watcher = {
+            let (dir, token) = (dir.clone(), token.clone());
+            thread::spawn(move || {
+                let pipeline = Pipeline::from_json(r#"[{"op": "grayscale"}]"#).unwrap();
+                let on_processed = |input: &std::path::Path, result| {
+                    sender.send((input.to_path_buf(), result)).unwrap();
+                };
+                // Outputs are written back into the watched directory.
+                let template = "{dir}/{stem}-out.png";
+                watch(&dir, template, &pipeline, &token, on_processed)
+            })
+        };
+        thread::sleep(Duration::from_millis(500));
+
+        let input = dir.join("new.png");
+        save(&PhotonImage::new(vec![200, 100, 0, 255], 1, 1), &input).unwrap();
+        let timeout = Duration::from_secs(10);
+        let (processed, result) = receiver.recv_timeout(timeout).unwrap();
+        assert_eq!(processed.file_name(), input.file_name());
+        assert_eq!(result.unwrap(), dir.join("new-out.png"));
+        // The output isn't picked up and processed again.
+        assert!(receiver.recv_timeout(Duration::from_secs(2)).is_err());
+
+        token.cancel();
+        watcher.join().unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_ffi_apply() {
+        use crate::ffi::*;
+        use std::ffi::{CStr, CString};
+
+        let pixels = [100u8, 100, 100, 255].repeat(4);
+        unsafe {
+            assert!(photon_image_new(pixels.as_ptr(), 15, 2, 2).is_null());
+            let img = photon_image_new(pixels.as_ptr(), pixels.len(), 2, 2);
+            let op = CString::new("brightness").unwrap();
+            let params = CString::new(r#"{"amount": 20}"#).unwrap();
+            assert_eq!(photon_image_apply(img, op.as_ptr(), params.as_ptr()), 0);
+            let len = photon_image_len(img);
+            let pixels = std::slice::from_raw_parts(photon_image_pixels(img), len);
+            assert_eq!(pixels[..4], [120, 120, 120, 255]);
+
+            let spec = CString::new(r#"[{"op": "resize", "w": 4}]"#).unwrap();
+            assert_eq!(photon_image_apply_pipeline(img, spec.as_ptr()), 0);
+            assert_eq!(photon_image_width(img), 4);
+            assert_eq!(photon_image_height(img), 4);
+
+            let params = CString::new("[1]").unwrap();
+            assert_eq!(photon_image_apply(img, op.as_ptr(), params.as_ptr()), -1);
+            let msg = CStr::from_ptr(photon_last_error()).to_str().unwrap();
+            assert!(msg.contains("JSON object"));
+            photon_image_free(img);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "framebuffer")]
+    fn test_framebuffer_layouts() {
+        use crate::framebuffer::*;
+
+        // A 2x1 BGRA framebuffer, with 4 bytes of padding per row.
+        let bgra = [30, 20, 10, 255, 60, 50, 40, 128, 0, 0, 0, 0];
+        let photon_image = from_bytes(&bgra, 2, 1, 12, ByteLayout::Bgra);
+        assert_eq!(photon_image.raw_pixels, [10, 20, 30, 255, 40, 50, 60, 128]);
+        assert_eq!(
+            to_bytes(&photon_image, ByteLayout::Argb)[..4],
+            [255, 10, 20, 30]
+        );
+
+        let packed = to_u32(&photon_image, U32Layout::Xrgb);
+        assert_eq!(packed, [0x000a141e, 0x0028323c]);
+        let round_trip = from_u32(&packed, 2, 1, 2, U32Layout::Xrgb);
+        assert_eq!(round_trip.raw_pixels[4..], [40, 50, 60, 255]);
+
+        let mut padded = vec![7; 6];
+        write_u32(&photon_image, &mut padded, 3, U32Layout::Argb);
+        assert_eq!(padded[..3], [0xff0a141e, 0x8028323c, 7]);
+
+        // White, black, red, green and blue, in one row padded to 6 pixels.
+        let rgb565 = [0xffff, 0x0000, 0xf800, 0x07e0, 0x001f, 7];
+        let photon_image = from_u16(&rgb565, 5, 1, 6, U16Layout::Rgb565);
+        assert_eq!(
+            photon_image.raw_pixels[..8],
+            [255, 255, 255, 255, 0, 0, 0, 255]
+        );
+        assert_eq!(
+            photon_image.raw_pixels[8..],
+            [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255]
+        );
+        assert_eq!(to_u16(&photon_image, U16Layout::Rgb565), rgb565[..5]);
+        assert_eq!(
+            to_u16(&photon_image, U16Layout::Bgr565),
+            [0xffff, 0x0000, 0x001f, 0x07e0, 0xf800]
+        );
+        let grey = PhotonImage::new(vec![128, 128, 128, 0], 1, 1);
+        assert_eq!(to_u16(&grey, U16Layout::Rgb565), [0x8410]);
+    }
+
+    #[test]
+    #[cfg(feature = "pyramid")]
+    fn test_exposure_fusion() {
+        use crate::multiple::exposure_fusion;
+
+        // A detailed, well-exposed frame, and a flat, blown-out one.
+        let mut raw_pixels = vec![];
+        for i in 0..32 * 32 {
+            let px = if (i % 32 + i / 32) % 2 == 0 {
+                [100, 140, 180, 255]
+            } else {
+                [140, 100, 60, 255]
+            };
+            raw_pixels.extend_from_slice(&px);
+        }
+        let detailed = PhotonImage::new(raw_pixels, 32, 32);
+        let blown = PhotonImage::new(vec![255; 32 * 32 * 4], 32, 32);
+
+        let fused = exposure_fusion(&[blown, detailed.clone()]);
+        for (a, b) in fused.raw_pixels.iter().zip(&detailed.raw_pixels) {
+            assert!((*a as i32 - *b as i32).abs() <= 3);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pyramid")]
+    fn test_focus_stack() {
+        use crate::multiple::focus_stack;
+
+        // Each frame is detailed on one half, and blurred to flat grey on the other.
+        let frame = |sharp_left: bool| {
+            let mut raw_pixels = vec![];
+            for i in 0..32 * 32 {
+                let (x, y) = (i % 32, i / 32);
+                let v = if (x < 16) != sharp_left {
+                    130
+                } else if (x + y) % 2 == 0 {
+                    100
+                } else {
+                    160
+                };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+            PhotonImage::new(raw_pixels, 32, 32)
+        };
+        let stacked = focus_stack(&[frame(true), frame(false)]);
+        for y in 0..32 {
+            for &x in &[2, 29] {
+                let expected = if (x + y) % 2 == 0 { 100 } else { 160 };
+                let v = stacked.raw_pixels[(y * 32 + x) * 4] as i32;
+                assert!((v - expected).abs() <= 6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stack() {
+        use crate::multiple::{stack, StackMethod};
+
+        let frame = |v: u8| PhotonImage::new(vec![v, v, v, 255], 1, 1);
+        let frames = [frame(10), frame(20), frame(90)];
+        assert_eq!(
+            stack(&frames, StackMethod::Mean).raw_pixels,
+            vec![40, 40, 40, 255]
+        );
+        assert_eq!(
+            stack(&frames, StackMethod::Median).raw_pixels,
+            vec![20, 20, 20, 255]
+        );
+        assert_eq!(
+            stack(&frames[1..], StackMethod::Median).raw_pixels,
+            vec![55, 55, 55, 255]
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "analysis", feature = "pyramid"))]
+    fn test_stitch() {
+        use crate::multiple::stitch;
+
+        // A scene of randomly shaded 4x4 blocks, photographed as two overlapping halves.
+        let shade = |x: usize, y: usize| {
+            let hash = ((x / 4) as u32).wrapping_mul(73_856_093)
+                ^ ((y / 4) as u32).wrapping_mul(19_349_663);
+            let hash = hash.wrapping_mul(2_654_435_761);
+            (hash >> 24) as u8
+        };
+        let crop = |left: usize| {
+            let mut raw_pixels = vec![];
+            for y in 0..80 {
+                for x in left..left + 80 {
+                    let v = shade(x, y);
+                    raw_pixels.extend_from_slice(&[v, v, v, 255]);
+                }
+            }
+            PhotonImage::new(raw_pixels, 80, 80)
+        };
+        let panorama = stitch(&[crop(0), crop(40)]).unwrap();
+        assert!(panorama.width >= 120 && panorama.width <= 121);
+        assert!(panorama.height >= 80 && panorama.height <= 81);
+        for y in 10..70 {
+            for &x in &[10, 60, 110] {
+                let px = &panorama.raw_pixels[(y * panorama.width as usize + x) * 4..];
+                assert!((px[0] as i32 - shade(x, y) as i32).abs() <= 8);
+                assert_eq!(px[3], 255);
+            }
+        }
+
+        let flat = PhotonImage::new(vec![128; 80 * 80 * 4], 80, 80);
+        assert!(stitch(&[flat.clone(), flat]).is_err());
+    }
+
+    #[test]
+    fn test_blend_with_mask() {
+        use crate::multiple::blend_with_mask;
+
+        let mut img = PhotonImage::new([0, 0, 0, 255].repeat(3), 3, 1);
+        let top = PhotonImage::new([200, 100, 50, 255].repeat(3), 3, 1);
+        let mask = PhotonImage::new(
+            vec![0, 0, 0, 255, 255, 255, 255, 255, 128, 128, 128, 255],
+            3,
+            1,
+        );
+        blend_with_mask(&mut img, &top, &mask);
+        assert_eq!(&img.raw_pixels[0..4], &[0, 0, 0, 255]);
+        assert_eq!(&img.raw_pixels[4..8], &[200, 100, 50, 255]);
+        assert!((img.raw_pixels[8] as i32 - 100).abs() <= 1);
+        assert!((img.raw_pixels[9] as i32 - 50).abs() <= 1);
+    }
+
+    #[test]
+    #[cfg(feature = "video")]
+    fn test_decode_frame() {
+        use crate::video::{decode_frame, FrameFormat};
+
+        // A 2x2 frame with a padded row, which is mid-grey in YUV.
+        let i420 = [126, 126, 0, 126, 126, 0, 128, 0, 128, 0];
+        let img = decode_frame(&i420, 2, 2, FrameFormat::I420, 3);
+        assert_eq!(img.raw_pixels, [128, 128, 128, 255].repeat(4));
+        let nv12 = [126, 126, 0, 126, 126, 0, 128, 128, 0];
+        let img = decode_frame(&nv12, 2, 2, FrameFormat::NV12, 3);
+        assert_eq!(img.raw_pixels, [128, 128, 128, 255].repeat(4));
+
+        let bgrx = [30, 20, 10, 0];
+        let img = decode_frame(&bgrx, 1, 1, FrameFormat::BGRX, 4);
+        assert_eq!(img.raw_pixels, vec![10, 20, 30, 255]);
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_kuwahara() {
+        use crate::effects::{generalized_kuwahara, kuwahara};
+
+        // A noisy dark left half, and a noisy bright right half.
+        let mut raw_pixels = vec![];
+        for y in 0..20 {
+            for x in 0..20 {
+                let noise = ((x * 7 + y * 13) % 11) as u8;
+                let v = if x < 10 { 45 } else { 195 } + noise;
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let noisy = PhotonImage::new(raw_pixels, 20, 20);
+
+        let mut classic = noisy.clone();
+        kuwahara(&mut classic, 3);
+        let mut anisotropic = noisy.clone();
+        generalized_kuwahara(&mut anisotropic, 3, 8, 1.0);
+        for img in &[classic, anisotropic] {
+            for x in 0..20 {
+                let v = img.raw_pixels[(10 * 20 + x) * 4] as i32;
+                let expected = if x < 10 { 50 } else { 200 };
+                // The noise is smoothed out, but the edge stays sharp.
+                assert!((v - expected).abs() <= 3);
+            }
+        }
+
+        // Pixels near the borders are handled too.
+        let mut tiny = PhotonImage::new(vec![9, 9, 9, 255], 1, 1);
+        generalized_kuwahara(&mut tiny, 4, 8, 1.0);
+        assert_eq!(tiny.raw_pixels, vec![9, 9, 9, 255]);
+    }
+
+    #[test]
+    #[cfg(feature = "region")]
+    fn test_apply_rect() {
+        use crate::region::apply_rect;
+
+        let mut img = PhotonImage::new(vec![10; 4 * 3 * 4], 4, 3);
+        apply_rect(&mut img, &Rect::new(2, 1, 5, 5), |region| {
+            assert_eq!((region.width, region.height), (2, 2));
+            region.raw_pixels.iter_mut().for_each(|v| *v = 99);
+        });
+        for y in 0..3 {
+            for x in 0..4 {
+                let expected = if x >= 2 && y >= 1 { 99 } else { 10 };
+                assert_eq!(img.raw_pixels[(y * 4 + x) * 4], expected);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "mask", feature = "region"))]
+    fn test_masks() {
+        use crate::mask::{ellipse, rect};
+        use crate::region::apply_masked;
+
+        let value = |img: &PhotonImage, x: u32, y: u32| {
+            img.raw_pixels[((y * img.width + x) * 4) as usize]
+        };
+        let hard = ellipse(20, 10, 10.0, 5.0, 8.0, 4.0, 0.0);
+        assert_eq!(value(&hard, 10, 5), 255);
+        assert_eq!(value(&hard, 17, 5), 255);
+        assert_eq!(value(&hard, 19, 5), 0);
+        assert_eq!(value(&hard, 10, 9), 0);
+
+        // Feathering fades the edge symmetrically about the outline.
+        let soft = rect(40, 21, &Rect::new(10, 0, 20, 21), 8.0);
+        assert_eq!(value(&soft, 20, 10), 255);
+        assert_eq!(value(&soft, 0, 10), 0);
+        assert!((value(&soft, 10, 10) as i32 - 128).abs() <= 32);
+        assert!(value(&soft, 7, 10) > 0 && value(&soft, 7, 10) < 128);
+        assert!(value(&soft, 13, 10) > 128 && value(&soft, 13, 10) < 255);
+
+        let mut img = PhotonImage::new(vec![0; 40 * 21 * 4], 40, 21);
+        apply_masked(&mut img, &soft, |img| {
+            img.raw_pixels.iter_mut().for_each(|v| *v = 255)
+        });
+        assert_eq!(value(&img, 0, 10), 0);
+        assert_eq!(value(&img, 20, 10), 255);
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_tilt_shift() {
+        use crate::effects::tilt_shift;
+
+        // Alternating black and white columns.
+        let raw_pixels = [[0, 0, 0, 255], [255, 255, 255, 255]]
+            .concat()
+            .repeat(20 * 40);
+        let mut img = PhotonImage::new(raw_pixels, 40, 40);
+        tilt_shift(&mut img, 0.5, 0.2, 6.0);
+        let at = |x: usize, y: usize| img.raw_pixels[(y * 40 + x) * 4] as i32;
+        // The band in the middle stays sharp, and the top and bottom are blurred to grey.
+        assert_eq!((at(10, 20), at(11, 20)), (0, 255));
+        for &y in &[0, 39] {
+            assert!((at(10, y) - 128).abs() <= 2 && (at(11, y) - 128).abs() <= 2);
+        }
+        assert!(at(10, 25) > 0 && at(10, 25) < at(10, 39));
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_depth_of_field() {
+        use crate::effects::depth_of_field;
+
+        // Alternating black and white columns, with a depth map which is near (white) on
+        // the left, and far (black) on the right.
+        let raw_pixels = [[0, 0, 0, 255], [255, 255, 255, 255]]
+            .concat()
+            .repeat(20 * 40);
+        let mut img = PhotonImage::new(raw_pixels, 40, 40);
+        let mut depth_pixels = vec![];
+        for _ in 0..40 {
+            for x in 0..40 {
+                let v = if x < 20 { 255 } else { 0 };
+                depth_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let depth_map = PhotonImage::new(depth_pixels, 40, 40);
+
+        depth_of_field(&mut img, &depth_map, 1.0, 6.0);
+        let at = |x: usize| img.raw_pixels[(20 * 40 + x) * 4] as i32;
+        assert_eq!((at(4), at(5)), (0, 255));
+        assert!((at(34) - 128).abs() <= 2 && (at(35) - 128).abs() <= 2);
+    }
+
+    #[test]
+    #[cfg(feature = "conv")]
+    fn test_bokeh_blur() {
+        use crate::conv::{bokeh_blur, ApertureShape};
+
+        // A single bright point on a dark background.
+        let mut raw_pixels = [20, 20, 20, 255].repeat(21 * 21);
+        raw_pixels[(10 * 21 + 10) * 4..(10 * 21 + 10) * 4 + 3]
+            .copy_from_slice(&[255; 3]);
+        let point = PhotonImage::new(raw_pixels, 21, 21);
+        let at =
+            |img: &PhotonImage, x: usize, y: usize| img.raw_pixels[(y * 21 + x) * 4];
+
+        let mut disc = point.clone();
+        bokeh_blur(&mut disc, 5.0, ApertureShape::Disc, 0.0);
+        // The point is spread evenly over a disc with a crisp edge.
+        assert_eq!(at(&disc, 10, 10), at(&disc, 13, 10));
+        assert_eq!(at(&disc, 10, 10), at(&disc, 10, 6));
+        assert!(at(&disc, 10, 10) > 20);
+        assert_eq!(at(&disc, 17, 10), 20);
+        assert_eq!(at(&disc, 14, 14), 20);
+
+        // Boosting highlights makes the disc brighter.
+        let mut boosted = point.clone();
+        bokeh_blur(&mut boosted, 5.0, ApertureShape::Disc, 8.0);
+        assert!(at(&boosted, 10, 10) > at(&disc, 10, 10) + 20);
+
+        // A hexagon with a flat top reaches further sideways than upwards.
+        let mut hexagon = point;
+        bokeh_blur(&mut hexagon, 5.0, ApertureShape::Hexagon, 0.0);
+        assert!(at(&hexagon, 15, 10) > 20);
+        assert_eq!(at(&hexagon, 10, 15), 20);
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_zoom_blur() {
+        use crate::effects::zoom_blur;
+
+        // A checkerboard of 2x2 squares.
+        let mut raw_pixels = vec![];
+        for y in 0..41 {
+            for x in 0..41 {
+                let v = if (x / 2 + y / 2) % 2 == 0 { 0 } else { 255 };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let mut img = PhotonImage::new(raw_pixels.clone(), 41, 41);
+        zoom_blur(&mut img, 20.0, 20.0, 0.3);
+        let at = |x: usize, y: usize| img.raw_pixels[(y * 41 + x) * 4];
+        // The centre stays sharp, while the edges are blurred.
+        assert_eq!(at(20, 20), raw_pixels[(20 * 41 + 20) * 4]);
+        for &(x, y) in &[(0, 20), (20, 0), (40, 40), (5, 35)] {
+            assert!(at(x, y) > 0 && at(x, y) < 255);
+        }
+
+        let mut empty = PhotonImage::new(vec![], 0, 0);
+        zoom_blur(&mut empty, 0.0, 0.0, 0.3);
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_spin_blur() {
+        use crate::effects::spin_blur;
+
+        // Black on the left and white on the right.
+        let mut raw_pixels = vec![];
+        for _ in 0..41 {
+            for x in 0..41 {
+                let v = if x < 20 { 0 } else { 255 };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let mut img = PhotonImage::new(raw_pixels, 41, 41);
+        spin_blur(&mut img, 20.0, 20.0, 40.0);
+        let at = |x: usize, y: usize| img.raw_pixels[(y * 41 + x) * 4];
+        // Arcs run across the boundary above and below the centre, but not beside it.
+        assert!(at(19, 0) > 0 && at(21, 0) < 255);
+        assert!(at(19, 40) > 0 && at(21, 40) < 255);
+        assert_eq!((at(0, 20), at(40, 20)), (0, 255));
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_jpegify() {
+        use crate::effects::jpegify;
+
+        let mut raw_pixels = vec![];
+        for y in 0..32 {
+            for x in 0..32 {
+                let v = if x < 16 { 0 } else { 255 };
+                raw_pixels.extend_from_slice(&[v, y * 8, 100, 200]);
+            }
+        }
+        let original = PhotonImage::new(raw_pixels, 32, 32);
+        let difference = |img: &PhotonImage| -> i32 {
+            img.raw_pixels
+                .iter()
+                .zip(&original.raw_pixels)
+                .map(|(&a, &b)| (a as i32 - b as i32).abs())
+                .sum()
+        };
+
+        let mut light = original.clone();
+        jpegify(&mut light, 95, 1);
+        let mut heavy = original.clone();
+        jpegify(&mut heavy, 5, 4);
+        assert!(difference(&heavy) > 4 * difference(&light));
+        assert!(heavy.raw_pixels.chunks_exact(4).all(|px| px[3] == 200));
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_vhs() {
+        use crate::effects::{vhs, VhsOptions};
+
+        let mut raw_pixels = vec![];
+        for _ in 0..40 {
+            for x in 0..40 {
+                let px = if x < 20 {
+                    [200, 30, 30, 255]
+                } else {
+                    [30, 30, 200, 255]
+                };
+                raw_pixels.extend_from_slice(&px);
+            }
+        }
+        let original = PhotonImage::new(raw_pixels, 40, 40);
+
+        let mut off = VhsOptions::new();
+        off.set_jitter(0.0);
+        off.set_chroma_bleed(0);
+        off.set_scanlines(0.0);
+        off.set_noise(0.0);
+        off.set_tracking(0.0);
+        let mut unchanged = original.clone();
+        vhs(&mut unchanged, &off);
+        assert_eq!(unchanged.raw_pixels, original.raw_pixels);
+
+        let mut a = original.clone();
+        vhs(&mut a, &VhsOptions::new());
+        let mut b = original.clone();
+        vhs(&mut b, &VhsOptions::new());
+        assert_eq!(a.raw_pixels, b.raw_pixels);
+        assert_ne!(a.raw_pixels, original.raw_pixels);
+        assert!(a.raw_pixels.chunks_exact(4).all(|px| px[3] == 255));
+    }
+
+    #[test]
+    #[cfg(feature = "quantize")]
+    fn test_quantize() {
+        use crate::quantize::{quantize, Dither};
+        use std::collections::HashSet;
+
+        let mut raw_pixels = vec![];
+        for _ in 0..16 {
+            for x in 0..64 {
+                raw_pixels.extend_from_slice(&[x * 4, 255 - x * 4, 128, 255]);
+            }
+        }
+        let original = PhotonImage::new(raw_pixels, 64, 16);
+        let mean = |img: &PhotonImage, x0: usize| -> f32 {
+            let mut sum = 0.0;
+            for y in 0..16 {
+                for x in x0..x0 + 8 {
+                    sum += img.raw_pixels[(y * 64 + x) * 4] as f32;
+                }
+            }
+            sum / 128.0
+        };
+
+        for dither in [Dither::None, Dither::FloydSteinberg, Dither::Ordered] {
+            let mut img = original.clone();
+            quantize(&mut img, 4, dither);
+            let colours: HashSet<&[u8]> =
+                img.raw_pixels.chunks_exact(4).map(|px| &px[..3]).collect();
+            assert!(colours.len() <= 4);
+            if dither != Dither::None {
+                // Dithering keeps the average colour of each area close to the original.
+                for x0 in (0..64).step_by(8) {
+                    assert!((mean(&img, x0) - mean(&original, x0)).abs() < 20.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "gif")]
+    fn test_encode_gif() {
+        use crate::native::{encode_gif, GifOptions, GifPalette};
+        use gif::{ColorOutput, DisposalMethod, SetParameter};
+
+        let mut frames = vec![];
+        for i in 0..3 {
+            let mut raw_pixels = vec![];
+            for y in 0..20 {
+                for x in 0..20 {
+                    let moving = (5..10).contains(&y) && x / 5 == i;
+                    let px = if moving {
+                        [255, 0, 0, 255]
+                    } else {
+                        [0, 0, 255, 255]
+                    };
+                    raw_pixels.extend_from_slice(&px);
+                }
+            }
+            frames.push(PhotonImage::new(raw_pixels, 20, 20));
+        }
+
+        // Decode a GIF, drawing each frame over the ones before as a viewer would.
+        let decode = |bytes: &[u8]| -> Vec<Vec<u8>> {
+            let mut decoder = gif::Decoder::new(bytes);
+            decoder.set(ColorOutput::RGBA);
+            let mut reader = decoder.read_info().unwrap();
+            let mut canvas = vec![0; 20 * 20 * 4];
+            let mut decoded = vec![];
+            while let Some(frame) = reader.read_next_frame().unwrap() {
+                let (left, top) = (frame.left as usize, frame.top as usize);
+                for (i, px) in frame.buffer.chunks_exact(4).enumerate() {
+                    let x = left + i % frame.width as usize;
+                    let y = top + i / frame.width as usize;
+                    if px[3] != 0 {
+                        canvas[(y * 20 + x) * 4..][..4].copy_from_slice(px);
+                    }
+                }
+                decoded.push(canvas.clone());
+                if frame.dispose == DisposalMethod::Background {
+                    canvas = vec![0; 20 * 20 * 4];
+                }
+            }
+            decoded
+        };
+
+        for palette in [GifPalette::Global, GifPalette::PerFrame] {
+            for delta in [false, true] {
+                let options = GifOptions {
+                    palette,
+                    colours: 16,
+                    delta,
+                    ..GifOptions::default()
+                };
+                let decoded = decode(&encode_gif(&frames, &options));
+                assert_eq!(decoded.len(), 3);
+                for (frame, original) in decoded.iter().zip(&frames) {
+                    assert_eq!(frame, &original.raw_pixels);
+                }
+            }
+        }
+
+        let full = GifOptions {
+            delta: false,
+            ..GifOptions::default()
+        };
+        let delta_size = encode_gif(&frames, &GifOptions::default()).len();
+        assert!(delta_size < encode_gif(&frames, &full).len());
+    }
+
+    #[test]
+    fn test_simulate_cvd() {
+        use crate::colour_spaces::{daltonize, simulate_cvd, Cvd};
+
+        // A red and a green which are easy to tell apart with normal colour vision.
+        let original = PhotonImage::new(vec![200, 60, 40, 255, 90, 140, 40, 128], 2, 1);
+        let distance = |img: &PhotonImage| -> i32 {
+            let px = &img.raw_pixels;
+            (0..3)
+                .map(|c| (px[c] as i32 - px[4 + c] as i32).abs())
+                .sum()
+        };
+
+        let mut grey = PhotonImage::new(vec![128, 128, 128, 255], 1, 1);
+        for kind in [Cvd::Protanopia, Cvd::Deuteranopia, Cvd::Tritanopia] {
+            simulate_cvd(&mut grey, kind);
+            assert!(grey.raw_pixels[..3]
+                .iter()
+                .all(|&v| (127..=129).contains(&v)));
+        }
+
+        let mut simulated = original.clone();
+        simulate_cvd(&mut simulated, Cvd::Deuteranopia);
+        assert!(distance(&simulated) < distance(&original) / 2);
+        assert_eq!(simulated.raw_pixels[7], 128);
+
+        let mut corrected = original.clone();
+        daltonize(&mut corrected, Cvd::Deuteranopia);
+        simulate_cvd(&mut corrected, Cvd::Deuteranopia);
+        assert!(distance(&corrected) > distance(&simulated));
+    }
+
+    #[test]
+    fn test_apply_colormap() {
+        use crate::colour_spaces::{apply_colormap, Colormap};
+
+        let gradient: Vec<u8> = (0..=255u8).flat_map(|v| vec![v, v, v, 200]).collect();
+        let mut img = PhotonImage::new(gradient, 256, 1);
+        apply_colormap(&mut img, &Colormap::viridis());
+        let px = |img: &PhotonImage, x: usize| img.raw_pixels[x * 4..x * 4 + 4].to_vec();
+        assert_eq!(px(&img, 0), vec![0x44, 0x01, 0x54, 200]);
+        assert_eq!(px(&img, 255), vec![0xfd, 0xe7, 0x25, 200]);
+
+        let mut img = PhotonImage::new(vec![0, 0, 0, 255, 128, 128, 128, 255], 2, 1);
+        apply_colormap(&mut img, &Colormap::custom(&[0, 0, 255, 255, 0, 0]));
+        assert_eq!(px(&img, 0), vec![0, 0, 255, 255]);
+        assert_eq!(px(&img, 1), vec![128, 0, 127, 255]);
+    }
+
+    #[test]
+    fn test_invert_channels() {
+        use crate::channels::{convert_negative, invert_channels, partial_invert};
+        use crate::Rgb;
+
+        let mut img = PhotonImage::new(vec![0, 10, 200, 255, 255, 128, 1, 7], 2, 1);
+        invert_channels(&mut img, true, false, true);
+        assert_eq!(img.raw_pixels, vec![255, 10, 55, 255, 0, 128, 254, 7]);
+
+        let mut img = PhotonImage::new(vec![0, 100, 255, 9], 1, 1);
+        partial_invert(&mut img, 0.0);
+        assert_eq!(img.raw_pixels, vec![0, 100, 255, 9]);
+        partial_invert(&mut img, 0.5);
+        assert_eq!(img.raw_pixels, vec![128, 128, 128, 9]);
+        let mut img = PhotonImage::new(vec![0, 100, 255, 9], 1, 1);
+        partial_invert(&mut img, 1.0);
+        assert_eq!(img.raw_pixels, vec![255, 155, 0, 9]);
+
+        // A negative of a gray ramp, through an orange film base.
+        let base = [220.0, 140.0, 90.0];
+        let mut raw_pixels = vec![];
+        for x in 0..64 {
+            let exposure = 1.0 - x as f32 / 80.0;
+            for b in &base {
+                raw_pixels.push((b * exposure) as u8);
+            }
+            raw_pixels.push(255);
+        }
+        let mut img = PhotonImage::new(raw_pixels, 64, 1);
+        convert_negative(&mut img, Rgb::new(220, 140, 90));
+        let px = |x: usize| &img.raw_pixels[x * 4..x * 4 + 3];
+        assert!(px(0).iter().all(|&v| v < 10));
+        assert!(px(63).iter().all(|&v| v > 245));
+        // The orange mask is removed, leaving the ramp close to gray.
+        for x in 0..64 {
+            let (min, max) = (px(x).iter().min().unwrap(), px(x).iter().max().unwrap());
+            assert!(max - min < 24);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "conv")]
+    fn test_sharpen_amount() {
+        use crate::conv::{sharpen, sharpen_amount};
+
+        let mut raw_pixels = vec![];
+        for _ in 0..8 {
+            for x in 0..8 {
+                let v = if x < 4 { 80 } else { 160 };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let original = PhotonImage::new(raw_pixels, 8, 8);
+        let edge = |img: &PhotonImage| -> (u8, u8) {
+            let row = &img.raw_pixels[4 * 8 * 4..];
+            (row[3 * 4], row[4 * 4])
+        };
+
+        let mut unchanged = original.clone();
+        sharpen_amount(&mut unchanged, 0.0);
+        assert_eq!(edge(&unchanged), (80, 160));
+
+        let mut full = original.clone();
+        sharpen_amount(&mut full, 1.0);
+        let mut fixed = original.clone();
+        sharpen(&mut fixed);
+        assert_eq!(full.raw_pixels, fixed.raw_pixels);
+
+        let mut half = original.clone();
+        sharpen_amount(&mut half, 0.5);
+        assert_eq!(edge(&half), (40, 200));
+        assert_eq!(edge(&full), (0, 240));
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_dehaze() {
+        use crate::effects::dehaze;
+
+        // Coloured squares seen through a bluish-white haze, which thickens towards the
+        // top, with a patch of thick haze in the corner.
+        let (width, height) = (64, 48);
+        let haze = [0.85, 0.85, 0.9];
+        let mut clear = vec![];
+        let mut hazy = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let colour = match (x / 8 + y / 8) % 3 {
+                    0 => [0.8, 0.1, 0.05],
+                    1 => [0.05, 0.6, 0.2],
+                    _ => [0.1, 0.2, 0.7],
+                };
+                let t = if x < 8 && y < 8 {
+                    0.05
+                } else {
+                    0.4 + 0.3 * y as f32 / height as f32
+                };
+                for c in 0..3 {
+                    clear.push((colour[c] * 255.0) as u8);
+                    let value = colour[c] * t + haze[c] * (1.0 - t);
+                    hazy.push((value * 255.0).round() as u8);
+                }
+                clear.push(255);
+                hazy.push(255);
+            }
+        }
+        let hazy = PhotonImage::new(hazy, width as u32, height as u32);
+        let error = |img: &PhotonImage| -> f32 {
+            img.raw_pixels
+                .iter()
+                .zip(&clear)
+                .map(|(&a, &b)| (a as f32 - b as f32).abs())
+                .sum::<f32>()
+                / clear.len() as f32
+        };
+
+        let mut unchanged = hazy.clone();
+        dehaze(&mut unchanged, 0.0);
+        assert_eq!(unchanged.raw_pixels, hazy.raw_pixels);
+
+        let mut half = hazy.clone();
+        dehaze(&mut half, 0.5);
+        let mut full = hazy.clone();
+        dehaze(&mut full, 1.0);
+        assert!(error(&half) < error(&hazy));
+        assert!(error(&full) < error(&half));
+        assert!(error(&full) < 15.0);
+    }
+
+    #[test]
+    #[cfg(feature = "conv")]
+    fn test_denoise() {
+        use crate::conv::{denoise, nl_means};
+
+        // A sharp edge between two grays, with noise in brightness and colour.
+        let mut seed = 1u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((seed >> 16) % 41) as i32 - 20
+        };
+        let mut raw_pixels = vec![];
+        for _ in 0..32 {
+            for x in 0..32 {
+                let base = if x < 16 { 60 } else { 190 };
+                let luma = noise() / 4;
+                for _ in 0..3 {
+                    raw_pixels.push((base + luma + noise()) as u8);
+                }
+                raw_pixels.push(255);
+            }
+        }
+        let noisy = PhotonImage::new(raw_pixels, 32, 32);
+        // The mean difference between the channels of each pixel, ie: colour noise.
+        let colourfulness = |img: &PhotonImage| -> f32 {
+            img.raw_pixels
+                .chunks_exact(4)
+                .map(|px| {
+                    let (r, g, b) = (px[0] as i32, px[1] as i32, px[2] as i32);
+                    ((r - g).abs() + (g - b).abs() + (b - r).abs()) as f32
+                })
+                .sum::<f32>()
+                / 1024.0
+        };
+        let variance = |img: &PhotonImage| -> f32 {
+            let luma: Vec<f32> = (0..32)
+                .flat_map(|y| (2..14).map(move |x| (y * 32 + x) * 4))
+                .map(|i| {
+                    img.raw_pixels[i..i + 3]
+                        .iter()
+                        .map(|&v| v as f32)
+                        .sum::<f32>()
+                })
+                .collect();
+            let mean = luma.iter().sum::<f32>() / luma.len() as f32;
+            luma.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / luma.len() as f32
+        };
+        let edge = |img: &PhotonImage, x: usize| -> f32 {
+            (0..32)
+                .map(|y| img.raw_pixels[(y * 32 + x) * 4 + 1] as f32)
+                .sum::<f32>()
+                / 32.0
+        };
+
+        let mut unchanged = noisy.clone();
+        denoise(&mut unchanged, 0.0, 0.0);
+        for (a, b) in unchanged.raw_pixels.iter().zip(&noisy.raw_pixels) {
+            assert!((*a as i32 - *b as i32).abs() <= 1);
+        }
+
+        let mut denoised = noisy.clone();
+        denoise(&mut denoised, 0.5, 1.0);
+        assert!(colourfulness(&denoised) < colourfulness(&noisy) / 3.0);
+        assert!(variance(&denoised) < variance(&noisy) / 2.0);
+        // The edge between the grays stays sharp.
+        assert!(edge(&denoised, 16) - edge(&denoised, 15) > 110.0);
+        assert_eq!(denoised.raw_pixels[3], 255);
+
+        let mut unchanged = noisy.clone();
+        nl_means(&mut unchanged, 0.0);
+        assert_eq!(unchanged.raw_pixels, noisy.raw_pixels);
+
+        let mut denoised = noisy.clone();
+        nl_means(&mut denoised, 1.0);
+        assert!(variance(&denoised) < variance(&noisy) / 2.0);
+        assert!(edge(&denoised, 16) - edge(&denoised, 15) > 110.0);
+        assert_eq!(denoised.raw_pixels[3], 255);
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_skin_smooth() {
+        use crate::effects::skin_smooth;
+
+        // Skin with fine texture and a dark blotch, next to a blue background.
+        let mut seed = 7u32;
+        let mut texture = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((seed >> 16) % 21) as i32 - 10
+        };
+        let mut raw_pixels = vec![];
+        for y in 0..48i32 {
+            for x in 0..48i32 {
+                let blotch = if (x - 24).pow(2) + (y - 24).pow(2) < 36 {
+                    -25
+                } else {
+                    0
+                };
+                let offset = blotch + texture();
+                let (r, g, b) = if x < 40 {
+                    (220, 170, 140)
+                } else {
+                    (40, 60, 160)
+                };
+                raw_pixels.push((r + offset) as u8);
+                raw_pixels.push((g + offset) as u8);
+                raw_pixels.push((b + offset) as u8);
+                raw_pixels.push(255);
+            }
+        }
+        let original = PhotonImage::new(raw_pixels, 48, 48);
+        let red =
+            |img: &PhotonImage, x: usize, y: usize| img.raw_pixels[(y * 48 + x) * 4];
+        let blotch = |img: &PhotonImage| -> f32 {
+            let reds = (21..28).flat_map(|y| (21..28).map(move |x| (x, y)));
+            reds.map(|(x, y)| red(img, x, y) as f32).sum::<f32>() / 49.0
+        };
+        let texture = |img: &PhotonImage| -> i32 {
+            let steps =
+                (5..19).map(|x| red(img, x, 10) as i32 - red(img, x + 1, 10) as i32);
+            steps.map(i32::abs).sum()
+        };
+
+        let mut img = original.clone();
+        skin_smooth(&mut img, 1.0);
+        assert!(blotch(&img) > blotch(&original) + 5.0);
+        assert!((texture(&img) - texture(&original)).abs() < 10);
+        // The background isn't skin, so is left alone.
+        let background = ((10 * 48 + 45) * 4, (10 * 48 + 46) * 4);
+        assert_eq!(
+            img.raw_pixels[background.0..background.1],
+            original.raw_pixels[background.0..background.1]
+        );
+    }
+
+    #[test]
+    fn test_white_balance_from_point() {
+        use crate::colour_spaces::white_balance_from_point;
+
+        // An orange cast over a gray card on the left and a blue object on the right.
+        let mut raw_pixels = vec![];
+        for _ in 0..8 {
+            for x in 0..8 {
+                let px = if x < 4 {
+                    [180, 140, 100, 255]
+                } else {
+                    [60, 90, 200, 200]
+                };
+                raw_pixels.extend_from_slice(&px);
+            }
+        }
+        let mut img = PhotonImage::new(raw_pixels, 8, 8);
+        white_balance_from_point(&mut img, 1, 3);
+
+        let card = &img.raw_pixels[(3 * 8 + 1) * 4..][..4];
+        assert!((card[0] as i32 - card[1] as i32).abs() <= 1);
+        assert!((card[1] as i32 - card[2] as i32).abs() <= 1);
+        let object = &img.raw_pixels[(3 * 8 + 6) * 4..][..4];
+        assert!(object[0] < 60 && object[2] > 200);
+        assert_eq!(object[3], 200);
+    }
+
+    #[test]
+    #[cfg(feature = "mask")]
+    fn test_poisson_blend() {
+        use crate::multiple::poisson_blend;
+
+        // A base whose red channel ramps from left to right, and a patch of a bright
+        // square with a darker stripe through it.
+        let mut raw_pixels = vec![];
+        for _ in 0..24 {
+            for x in 0..24 {
+                raw_pixels.extend_from_slice(&[x * 8, 100, 50, 255]);
+            }
+        }
+        let mut base = PhotonImage::new(raw_pixels, 24, 24);
+        let mut raw_pixels = vec![];
+        for _ in 0..10 {
+            for x in 0..10 {
+                let v = if x == 5 { 180 } else { 240 };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let patch = PhotonImage::new(raw_pixels, 10, 10);
+        let mask = crate::mask::rect(10, 10, &Rect::new(1, 1, 8, 8), 0.0);
+        let original = base.clone();
+        poisson_blend(&mut base, &patch, &mask, &Point::new(7, 7));
+
+        let at = |img: &PhotonImage, x: usize, y: usize, c: usize| {
+            img.raw_pixels[(y * 24 + x) * 4 + c] as i32
+        };
+        // Outside the mask, the base is unchanged.
+        assert_eq!(
+            base.raw_pixels[..7 * 24 * 4],
+            original.raw_pixels[..7 * 24 * 4]
+        );
+        // Inside, the stripe's edges are kept, but the colours follow the base.
+        for c in 0..3 {
+            let step = at(&base, 12, 12, c) - at(&base, 11, 12, c);
+            assert!((step - (180 - 240)).abs() <= 10, "{}", step);
+        }
+        assert!((at(&base, 9, 12, 1) - 100).abs() < 10);
+        assert!((at(&base, 9, 12, 2) - 50).abs() < 10);
+        assert!(at(&base, 14, 12, 0) > at(&base, 9, 12, 0));
+    }
+
+    #[test]
+    #[cfg(feature = "pyramid")]
+    fn test_pyramid_blend() {
+        use crate::multiple::pyramid_blend;
+
+        let flat = |r: u8, g: u8, b: u8, a: u8| {
+            PhotonImage::new([r, g, b, a].repeat(64 * 64), 64, 64)
+        };
+        let dark = flat(40, 60, 80, 255);
+        let light = flat(200, 180, 160, 128);
+        let right = crate::mask::rect(64, 64, &Rect::new(32, 0, 32, 64), 0.0);
+        let blended = pyramid_blend(&dark, &light, &right, 5);
+
+        let at = |x: usize| &blended.raw_pixels[(32 * 64 + x) * 4..][..4];
+        assert_eq!(at(0)[..3], [40, 60, 80]);
+        assert_eq!(at(63)[..3], [200, 180, 160]);
+        assert_eq!((at(0)[3], at(63)[3]), (255, 128));
+        // The seam is spread over several pixels, rising steadily.
+        assert!(at(31)[0] > 60 && at(32)[0] < 180);
+        for x in 1..64 {
+            assert!(at(x)[0] >= at(x - 1)[0]);
+        }
+
+        // With a single level, it's the same as mixing through the mask.
+        let hard = pyramid_blend(&dark, &light, &right, 1);
+        assert_eq!(hard.raw_pixels[(32 * 64 + 31) * 4], 40);
+        assert_eq!(hard.raw_pixels[(32 * 64 + 32) * 4], 200);
+    }
+
+    #[test]
+    fn test_auto_straighten() {
+        use crate::transform::{auto_straighten, fliph, skew_angle};
+
+        // Hard-edged stripes, 8 pixels wide, falling by 3 degrees towards the right.
+        let (sin, cos) = 3.0f32.to_radians().sin_cos();
+        let (width, height) = (160, 120);
+        let mut raw_pixels = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let across = y as f32 * cos - x as f32 * sin;
+                let v = if (across / 8.0).floor() as i32 % 2 == 0 {
+                    255
+                } else {
+                    0
+                };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let img = PhotonImage::new(raw_pixels, width, height);
+        assert!((skew_angle(&img) - 3.0).abs() < 0.2);
+        let mut mirrored = img.clone();
+        fliph(&mut mirrored);
+        assert!((skew_angle(&mirrored) + 3.0).abs() < 0.2);
+
+        // Once level, the stripes run along the rows, so each row is nearly uniform.
+        let level = auto_straighten(&img);
+        assert!(level.get_width() < width && level.get_height() < height);
+        let ratio = level.get_width() as f32 / level.get_height() as f32;
+        assert!((ratio - 4.0 / 3.0).abs() < 0.02);
+        let row_range = |y: u32| {
+            let start = (y * level.get_width() * 4) as usize;
+            let row = &level.raw_pixels[start..start + level.get_width() as usize * 4];
+            let values = row.iter().step_by(4);
+            values.clone().max().unwrap() - values.min().unwrap()
+        };
+        let uniform = (0..level.get_height())
+            .filter(|&y| row_range(y) < 64)
+            .count();
+        assert!(uniform as u32 > level.get_height() * 3 / 4);
+
+        let flat = PhotonImage::new(vec![100; 16 * 16 * 4], 16, 16);
+        assert_eq!(skew_angle(&flat), 0.0);
+        assert_eq!(auto_straighten(&flat).raw_pixels, flat.raw_pixels);
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_crop_to_subject() {
+        use crate::transform::{crop_to_subject, subject_rect};
+
+        // A dark 30x20 box on a white background.
+        let mut raw_pixels = vec![];
+        for y in 0..90 {
+            for x in 0..120 {
+                let inside = (60..90).contains(&x) && (40..60).contains(&y);
+                let v = if inside { 30 } else { 255 };
+                raw_pixels.extend_from_slice(&[v, v, v, 255]);
+            }
+        }
+        let img = PhotonImage::new(raw_pixels, 120, 90);
+
+        // The edges of the box are found on the pixels either side of its outline.
+        let tight = subject_rect(&img, 0.0);
+        let near = |a: u32, b: u32| (a as i32 - b as i32).abs() <= 2;
+        assert!(near(tight.get_x(), 60) && near(tight.get_y(), 40));
+        assert!(near(tight.get_width(), 30) && near(tight.get_height(), 20));
+
+        let padded = subject_rect(&img, 0.5);
+        assert_eq!(padded.get_x(), tight.get_x() - 16);
+        assert_eq!(padded.get_width(), tight.get_width() + 32);
+        assert_eq!(padded.get_height(), tight.get_height() + 32);
+
+        // The padding is limited to the image.
+        let loose = subject_rect(&img, 1.0);
+        assert_eq!(loose.get_y() + loose.get_height(), 90);
+
+        let framed = crop_to_subject(&img, 0.5);
+        assert_eq!(framed.get_width(), padded.get_width());
+        assert_eq!(framed.get_height(), padded.get_height());
+        assert_eq!(framed.raw_pixels[0], 255);
+
+        let flat = PhotonImage::new(vec![100; 16 * 16 * 4], 16, 16);
+        let whole = subject_rect(&flat, 0.1);
+        assert_eq!((whole.get_width(), whole.get_height()), (16, 16));
+    }
+
+    #[test]
+    #[cfg(feature = "framebuffer")]
+    fn test_pixel_format_helpers() {
+        use crate::helpers::*;
+
+        let mut pixels = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
+        swap_red_blue(&mut pixels);
+        assert_eq!(pixels, [3, 2, 1, 4, 7, 6, 5, 8, 9]);
+        pixels.pop();
+
+        // Two rows of one pixel, padded to 8 bytes.
+        let padded = add_row_padding(&pixels, 4, 8);
+        assert_eq!(padded, [3, 2, 1, 4, 0, 0, 0, 0, 7, 6, 5, 8, 0, 0, 0, 0]);
+        assert_eq!(remove_row_padding(&padded, 4, 8, 2), pixels);
+        // The last row doesn't need its padding.
+        assert_eq!(remove_row_padding(&padded[..12], 4, 8, 2), pixels);
+
+        let rgb565 = [0xffff, 0x0000, 0xf800, 0x07e0, 0x001f];
+        let rgba = unpack_rgb565(&rgb565);
+        assert_eq!(rgba[..4], [255, 255, 255, 255]);
+        assert_eq!(rgba[4..8], [0, 0, 0, 255]);
+        assert_eq!(rgba[8..], [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255]);
+        assert_eq!(pack_rgb565(&rgba), rgb565);
+        assert_eq!(pack_rgb565(&[128, 128, 128, 0]), [0x8410]);
+
+        let argb = [0x80ff4000, 0xff000000];
+        let rgba = unpack_argb32(&argb);
+        assert_eq!(rgba, [255, 64, 0, 128, 0, 0, 0, 255]);
+        assert_eq!(pack_argb32(&rgba), argb);
+        assert!(pack_argb32(&[1, 2, 3]).is_empty());
+        assert!(unpack_rgb565(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_colours() {
+        use crate::error::PhotonError;
+        use crate::Rgb;
+
+        let rgb = Rgb::from_hex("#6495ed").unwrap();
+        assert_eq!(
+            (rgb.get_red(), rgb.get_green(), rgb.get_blue()),
+            (100, 149, 237)
+        );
+        let rgb = Rgb::from_hex("69E").unwrap();
+        assert_eq!(
+            (rgb.get_red(), rgb.get_green(), rgb.get_blue()),
+            (102, 153, 238)
+        );
+        assert!(Rgb::from_hex("#6495ed80").is_err());
+        assert!(Rgb::from_hex("#12345g").is_err());
+        assert!(matches!(
+            Rgb::from_name("blurple"),
+            Err(PhotonError::InvalidColour(_))
+        ));
+
+        assert_eq!(
+            Rgba::from_hex("#6495ed80"),
+            Ok(Rgba::new(100, 149, 237, 128))
+        );
+        assert_eq!(Rgba::from_hex("#69e8"), Ok(Rgba::new(102, 153, 238, 136)));
+        assert_eq!(Rgba::from_hex("#000"), Ok(Rgba::new(0, 0, 0, 255)));
+        assert_eq!(Rgba::from_name("transparent"), Ok(Rgba::new(0, 0, 0, 0)));
+        assert!("#12345".parse::<Rgba>().is_err());
+        assert_eq!(Rgba::from(Rgb::new(1, 2, 3)), Rgba::new(1, 2, 3, 255));
+    }
+
+    #[test]
+    #[cfg(feature = "css-colours")]
+    fn test_parse_css_colour_names() {
+        use crate::Rgb;
+
+        let rgb = Rgb::from_name("CornflowerBlue").unwrap();
+        assert_eq!(
+            (rgb.get_red(), rgb.get_green(), rgb.get_blue()),
+            (100, 149, 237)
+        );
+        let rgb: Rgb = "rebeccapurple".parse().unwrap();
+        assert_eq!(rgb.get_blue(), 153);
+        assert_eq!("white".parse(), Ok(Rgba::new(255, 255, 255, 255)));
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        // A 1000x600 image, whose left half is red and right half is blue.
+        let mut raw_pixels = Vec::with_capacity(1000 * 600 * 4);
+        for _ in 0..600 {
+            for x in 0..1000 {
+                let px = if x < 500 {
+                    [255, 0, 0, 255]
+                } else {
+                    [0, 0, 255, 255]
+                };
+                raw_pixels.extend_from_slice(&px);
+            }
+        }
+        let img = PhotonImage::new(raw_pixels, 1000, 600);
+
+        let thumbnail = img.thumbnail(100);
+        assert_eq!((thumbnail.get_width(), thumbnail.get_height()), (100, 60));
+        let at = |x: usize, y: usize| &thumbnail.raw_pixels[(y * 100 + x) * 4..][..4];
+        assert_eq!(at(10, 30), [255, 0, 0, 255]);
+        assert_eq!(at(90, 30), [0, 0, 255, 255]);
+        assert!(at(49, 30)[0] > at(50, 30)[0]);
+
+        // Odd sizes and extreme ratios keep their proportions.
+        let tall = PhotonImage::new([7, 7, 7, 255].repeat(33 * 999), 33, 999);
+        let thumbnail = tall.thumbnail(64);
+        assert_eq!((thumbnail.get_width(), thumbnail.get_height()), (2, 64));
+        assert!(thumbnail
+            .raw_pixels
+            .chunks_exact(4)
+            .all(|px| px == [7, 7, 7, 255]));
+        let line = PhotonImage::new(vec![9; 4000 * 4], 4000, 1);
+        assert_eq!(line.thumbnail(10).get_height(), 1);
+
+        // Images which already fit aren't enlarged.
+        let small = PhotonImage::new(vec![1; 40 * 30 * 4], 40, 30);
+        assert_eq!(small.thumbnail(100).raw_pixels, small.raw_pixels);
+
+        // Transparent pixels don't darken the edges of opaque ones.
+        let mut raw_pixels = vec![0; 64 * 64 * 4];
+        for px in raw_pixels.chunks_exact_mut(4).step_by(2) {
+            px.copy_from_slice(&[255, 255, 255, 255]);
+        }
+        let thumbnail = PhotonImage::new(raw_pixels, 64, 64).thumbnail(8);
+        assert!(thumbnail.raw_pixels.chunks_exact(4).all(|px| px[0] == 255));
+        assert!(thumbnail.raw_pixels.chunks_exact(4).all(|px| px[3] == 128));
+    }
+
+    #[test]
+    #[cfg(feature = "texture")]
+    fn test_gpu_buffer_data() {
+        use crate::texture::{gpu_buffer_data, gpu_bytes_per_row};
+
+        assert_eq!(gpu_bytes_per_row(64), 256);
+        assert_eq!(gpu_bytes_per_row(65), 512);
+
+        let img = PhotonImage::new([1, 2, 3, 4].repeat(65 * 3), 65, 3);
+        let data = gpu_buffer_data(&img);
+        assert_eq!(data.len(), 512 * 3);
+        for row in data.chunks_exact(512) {
+            assert_eq!(row[..65 * 4], img.raw_pixels[..65 * 4]);
+            assert!(row[65 * 4..].iter().all(|&v| v == 0));
+        }
+    }
+
+    #[test]
+    fn test_collage() {
+        use crate::multiple::{collage, CollageLayout};
+
+        let solid = |v: u8, width: u32, height: u32| {
+            let raw_pixels = [v, v, v, 255].repeat((width * height) as usize);
+            PhotonImage::new(raw_pixels, width, height)
+        };
+        let images = [solid(10, 20, 10), solid(100, 40, 40), solid(200, 10, 30)];
+        let background = Rgba::new(255, 0, 0, 255);
+        let at = |img: &PhotonImage, x: u32, y: u32| {
+            let i = ((y * img.width + x) * 4) as usize;
+            img.raw_pixels[i..i + 4].to_vec()
+        };
+
+        let grid = collage(&images, CollageLayout::Grid, 2, &background);
+        assert_eq!((grid.width, grid.height), (2 * 20 + 3 * 2, 2 * 10 + 3 * 2));
+        assert_eq!(at(&grid, 0, 0), vec![255, 0, 0, 255]);
+        assert_eq!(at(&grid, 12, 7), vec![10, 10, 10, 255]);
+        assert_eq!(at(&grid, 34, 7), vec![100, 100, 100, 255]);
+        assert_eq!(at(&grid, 12, 19), vec![200, 200, 200, 255]);
+        // The fourth cell is unused.
+        assert_eq!(at(&grid, 34, 19), vec![255, 0, 0, 255]);
+
+        let strip = collage(&images, CollageLayout::Strip, 0, &background);
+        assert_eq!((strip.width, strip.height), (60, 10));
+
+        let featured = collage(&images, CollageLayout::Featured, 0, &background);
+        assert_eq!((featured.width, featured.height), (60, 20));
+        assert_eq!(at(&featured, 20, 15), vec![10, 10, 10, 255]);
+        assert_eq!(at(&featured, 50, 5), vec![100, 100, 100, 255]);
+        assert_eq!(at(&featured, 50, 15), vec![200, 200, 200, 255]);
+
+        // Images with no pixels leave their cells empty, rather than panicking.
+        let images = [solid(10, 20, 10), solid(0, 0, 5), solid(0, 7, 0)];
+        let strip = collage(&images, CollageLayout::Strip, 0, &background);
+        assert_eq!(at(&strip, 30, 5), vec![255, 0, 0, 255]);
+        // So do images whose cells have no pixels, when the first image has none.
+        let images = [solid(0, 0, 0), solid(100, 40, 40)];
+        let featured = collage(&images, CollageLayout::Featured, 2, &background);
+        assert_eq!((featured.width, featured.height), (8, 6));
+        let red = [255, 0, 0, 255];
+        assert!(featured.raw_pixels.chunks(4).all(|px| px == red));
+    }
+
+    #[test]
+    fn test_upscale_2x() {
+        use crate::transform::{upscale_2x, UpscaleMethod};
+
+        // A black diagonal line on white.
+        let mut raw_pixels = vec![];
+        for i in 0..16 * 16 {
+            let v = if i % 16 == i / 16 { 0 } else { 255 };
+            raw_pixels.extend_from_slice(&[v, v, v, 255]);
+        }
+        let line = PhotonImage::new(raw_pixels, 16, 16);
+
+        let upscaled = upscale_2x(&line, UpscaleMethod::EdgeDirected);
+        assert_eq!((upscaled.width, upscaled.height), (32, 32));
+        let at = |x: usize, y: usize| upscaled.raw_pixels[(y * 32 + x) * 4];
+        for y in 0..16 {
+            for x in 0..16 {
+                // The source pixels are kept.
+                assert_eq!(at(x * 2, y * 2), line.raw_pixels[(y * 16 + x) * 4]);
+            }
+        }
+        // The line is interpolated along its length, so it stays dark, rather than
+        // being blurred to grey.
+        for i in (3..28).step_by(2) {
+            assert!(at(i, i) < 16);
+        }
+        assert_eq!(at(5, 13), 255);
+    }
+
+    #[test]
+    #[cfg(feature = "conv")]
+    fn test_deconvolve_rl() {
+        use crate::conv::deconvolve_rl;
+
+        // Bars of alternating brightness, blurred horizontally over 5 pixels.
+        let sharp: Vec<u8> = (0..32 * 8)
+            .map(|i| if i % 32 / 4 % 2 == 0 { 40 } else { 200 })
+            .collect();
+        let blurred: Vec<u8> = (0..32 * 8)
+            .map(|i| {
+                let (x, y) = ((i % 32) as isize, i / 32);
+                let sum: u32 = (x - 2..=x + 2)
+                    .map(|sx| sharp[y * 32 + sx.clamp(0, 31) as usize] as u32)
+                    .sum();
+                (sum as f32 / 5.0).round() as u8
+            })
+            .collect();
+        let to_image = |values: &[u8]| {
+            let raw_pixels = values.iter().flat_map(|&v| vec![v, v, v, 255]).collect();
+            PhotonImage::new(raw_pixels, 32, 8)
+        };
+        let error = |img: &PhotonImage| -> u32 {
+            img.raw_pixels
+                .chunks_exact(4)
+                .zip(&sharp)
+                .map(|(px, &v)| (px[0] as i32 - v as i32).unsigned_abs())
+                .sum()
+        };
+
+        let mut img = to_image(&blurred);
+        let before = error(&img);
+        deconvolve_rl(&mut img, &[1.0; 5], 5, 30);
+        assert!(error(&img) * 2 < before);
+        assert!(img.raw_pixels.chunks_exact(4).all(|px| px[3] == 255));
+    }
+
+    #[test]
+    fn test_undistort() {
+        use crate::transform::undistort;
+
+        let raw_pixels: Vec<u8> =
+            (0..21 * 15 * 4).map(|i| (i * 7 % 251) as u8).collect();
+        let img = PhotonImage::new(raw_pixels, 21, 15);
+
+        // Without any distortion, the image is unchanged.
+        assert_eq!(
+            undistort(&img, 0.0, 0.0, 0.0, 0.0).raw_pixels,
+            img.raw_pixels
+        );
+
+        // Correcting pincushion distortion pulls the corners in from outside of the image,
+        // while the centre stays put.
+        let corrected = undistort(&img, 0.3, 0.0, 0.0, 0.0);
+        assert_eq!((corrected.width, corrected.height), (21, 15));
+        assert_eq!(corrected.raw_pixels[3], 0);
+        let centre = (7 * 21 + 10) * 4;
+        assert_eq!(
+            corrected.raw_pixels[centre..centre + 4],
+            img.raw_pixels[centre..centre + 4]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_devignette() {
+        use crate::effects::{devignette, devignette_auto};
+
+        let grey = PhotonImage::new(vec![150; 32 * 24 * 4], 32, 24);
+        let mut img = grey.clone();
+        devignette(&mut img, 0.0);
+        assert_eq!(img.raw_pixels, grey.raw_pixels);
+
+        // Darken the corners of an evenly lit image, then undo it.
+        devignette(&mut img, -0.5);
+        let corner = img.raw_pixels[0];
+        assert!(corner < 120);
+        let strength = devignette_auto(&mut img);
+        assert!(strength > 0.3);
+        assert!(
+            (img.raw_pixels[0] as i32 - 150).abs() < (corner as i32 - 150).abs() / 2
+        );
+        let centre = (12 * 32 + 16) * 4;
+        assert!((img.raw_pixels[centre] as i32 - 150).abs() <= 2);
+
+        let mut empty = PhotonImage::new(vec![], 0, 0);
+        assert_eq!(devignette_auto(&mut empty), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "effects")]
+    fn test_chroma_key() {
+        use crate::effects::chroma_key;
+        use crate::Rgb;
+
+        // A red square on a green screen, with a darker crease in the screen, and a pale
+        // foreground pixel lit by green light from the screen.
+        let mut raw_pixels = vec![];
+        for i in 0..16 * 16 {
+            let px = match (i % 16, i / 16) {
+                (4..=11, 4..=11) => [200, 30, 30, 255],
+                (0, 0) => [180, 200, 150, 255],
+                (_, 14) => [0, 120, 44, 255],
+                _ => [0, 177, 64, 255],
+            };
+            raw_pixels.extend_from_slice(&px);
+        }
+        let mut img = PhotonImage::new(raw_pixels, 16, 16);
+        chroma_key(&mut img, Rgb::new(0, 177, 64), 0.15, 0.1);
+
+        let at = |x: usize, y: usize| &img.raw_pixels[(y * 16 + x) * 4..][..4];
+        assert_eq!(at(1, 1)[3], 0);
+        assert_eq!(at(3, 14)[3], 0);
+        assert_eq!(at(8, 8), &[200, 30, 30, 255]);
+        assert_eq!(at(0, 0)[3], 255);
+        assert!(at(0, 0)[1] < 200);
+    }
+
+    #[test]
+    #[cfg(feature = "drawing")]
+    fn test_flood_fill() {
+        use crate::drawing::flood_fill;
+
+        // A white image, split by a black diagonal line which is only one pixel thick.
+        let mut raw_pixels = vec![];
+        for i in 0..8 * 8 {
+            let v = if i % 8 + i / 8 == 7 {
+                0
+            } else {
+                250 - (i % 3) as u8
+            };
+            raw_pixels.extend_from_slice(&[v, v, v, 255]);
+        }
+        let img = PhotonImage::new(raw_pixels, 8, 8);
+        let red = Rgba::new(255, 0, 0, 255);
+        let is_red = |img: &PhotonImage, x: usize, y: usize| {
+            img.raw_pixels[(y * 8 + x) * 4..][..4] == [255, 0, 0, 255]
+        };
+
+        let mut four = img.clone();
+        flood_fill(&mut four, 0, 0, &red, 2, Connectivity::Four);
+        assert!(is_red(&four, 3, 3));
+        assert!(!is_red(&four, 3, 4));
+        assert!(!is_red(&four, 7, 7));
+
+        // The fill can leak diagonally between the pixels of the line.
+        let mut eight = img.clone();
+        flood_fill(&mut eight, 0, 0, &red, 2, Connectivity::Eight);
+        assert!(!is_red(&eight, 3, 4));
+        assert!(is_red(&eight, 7, 7));
+
+        // With no tolerance, only pixels of exactly the same colour are filled.
+        let mut exact = img;
+        flood_fill(&mut exact, 0, 0, &red, 0, Connectivity::Four);
+        assert!(!is_red(&exact, 1, 0));
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_select_similar() {
+        use crate::analysis::select_similar;
+
+        // Two blue patches, separated by a white column.
+        let mut raw_pixels = vec![];
+        for i in 0..9 * 4 {
+            let px = if i % 9 == 4 {
+                [255, 255, 255, 255]
+            } else {
+                [20, 40, 200, 255]
+            };
+            raw_pixels.extend_from_slice(&px);
+        }
+        let img = PhotonImage::new(raw_pixels, 9, 4);
+        let mask = select_similar(&img, 1, 1, 10);
+        assert_eq!((mask.width, mask.height), (9, 4));
+        let at = |x: usize, y: usize| &mask.raw_pixels[(y * 9 + x) * 4..][..4];
+        assert_eq!(at(0, 3), &[255, 255, 255, 255]);
+        assert_eq!(at(3, 0), &[255, 255, 255, 255]);
+        assert_eq!(at(4, 0), &[0, 0, 0, 255]);
+        assert_eq!(at(8, 2), &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_connected_components() {
+        use crate::analysis::connected_components;
+
+        // A 2x2 square, a diagonal pair of pixels, and a single pixel.
+        let foreground = [(1, 1), (2, 1), (1, 2), (2, 2), (5, 1), (6, 2), (4, 5)];
+        let mut raw_pixels = vec![0; 8 * 7 * 4];
+        for &(x, y) in foreground.iter() {
+            raw_pixels[(y * 8 + x) * 4..][..3].copy_from_slice(&[255, 255, 255]);
+        }
+        let img = PhotonImage::new(raw_pixels, 8, 7);
+
+        let (labels, components) = connected_components(&img, Connectivity::Four);
+        assert_eq!(components.len(), 4);
+        let square = components[0];
+        assert_eq!(square.get_area(), 4);
+        assert_eq!(square.get_bounding_box(), Rect::new(1, 1, 2, 2));
+        assert_eq!(
+            (square.get_centroid_x(), square.get_centroid_y()),
+            (1.5, 1.5)
+        );
+        assert_eq!(labels.get_label(2, 2), 1);
+        assert_eq!(labels.get_label(6, 2), 3);
+        assert_eq!(labels.get_label(0, 0), 0);
+
+        let (labels, components) = connected_components(&img, Connectivity::Eight);
+        assert_eq!(components.len(), 3);
+        assert_eq!(components[1].get_area(), 2);
+        assert_eq!(labels.get_label(6, 2), 2);
+        assert_eq!(labels.get_label(4, 5), 3);
+    }
+
+    #[test]
+    #[cfg(all(feature = "analysis", feature = "drawing"))]
+    fn test_find_contours() {
+        use crate::analysis::find_contours;
+        use crate::drawing::draw_contours;
+
+        // A 5x5 square with a hole in its centre, and a single pixel.
+        let mut img = PhotonImage::new(vec![0; 10 * 8 * 4], 10, 8);
+        for y in 1..6 {
+            for x in 1..6 {
+                if (x, y) != (3, 3) {
+                    img.raw_pixels[(y * 10 + x) * 4..][..3].copy_from_slice(&[255; 3]);
+                }
+            }
+        }
+        img.raw_pixels[(6 * 10 + 8) * 4..][..3].copy_from_slice(&[255; 3]);
+
+        let contours = find_contours(&img);
+        assert_eq!(contours.len(), 3);
+        let (outer, hole) = (&contours[0], &contours[1]);
+        assert_eq!(outer.len(), 16);
+        assert_eq!(outer[0], Point::new(1, 1));
+        assert!(outer.contains(&Point::new(5, 5)));
+        assert!(!outer.contains(&Point::new(2, 2)));
+        // The hole's border cuts diagonally across the corners around it.
+        assert_eq!(hole.len(), 4);
+        assert!(hole.contains(&Point::new(3, 2)) && !hole.contains(&Point::new(2, 2)));
+        assert_eq!(contours[2], vec![Point::new(8, 6)]);
+
+        let mut canvas = PhotonImage::new(vec![0; 10 * 8 * 4], 10, 8);
+        draw_contours(&mut canvas, &contours, &Rgba::new(255, 0, 0, 255), 1.0);
+        let red = |x: usize, y: usize| canvas.raw_pixels[(y * 10 + x) * 4];
+        assert_eq!(red(3, 1), 255);
+        assert_eq!(red(7, 3), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_hough() {
+        use crate::analysis::{hough_circles, hough_lines};
+
+        // A horizontal line, and a circle of radius 10.
+        let mut img = PhotonImage::new(vec![0; 60 * 40 * 4], 60, 40);
+        for i in 0..60 * 40 {
+            let (x, y) = ((i % 60) as f32, (i / 60) as f32);
+            let distance = ((x - 30.0).powi(2) + (y - 24.0).powi(2)).sqrt();
+            if y == 5.0 || (distance - 10.0).abs() < 0.5 {
+                img.raw_pixels[i * 4..i * 4 + 3].copy_from_slice(&[255; 3]);
+            }
+        }
+
+        let lines = hough_lines(&img, 40);
+        assert!(!lines.is_empty());
+        let line = lines[0];
+        assert_eq!(line.get_votes(), 60);
+        assert!((line.get_theta() - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert_eq!(line.get_rho(), 5.0);
+
+        let circles = hough_circles(&img, 8, 12, 0.6);
+        assert!(!circles.is_empty());
+        let circle = circles[0];
+        assert_eq!(
+            (circle.get_x(), circle.get_y(), circle.get_radius()),
+            (30, 24, 10)
+        );
+        assert!(circles.iter().all(|c| c.get_radius() != 10 || c == &circle));
+
+        // A vertical line is found once, at 0°, and not again near 180° with rho negated.
+        let mut img = PhotonImage::new(vec![0; 40 * 40 * 4], 40, 40);
+        for y in 0..40 {
+            let i = (y * 40 + 20) * 4;
+            img.raw_pixels[i..i + 3].copy_from_slice(&[255; 3]);
+        }
+        let lines = hough_lines(&img, 20);
+        assert_eq!(lines.len(), 1);
+        assert_eq!((lines[0].get_theta(), lines[0].get_rho()), (0.0, 20.0));
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_corners() {
+        use crate::analysis::{corners, CornerMethod};
+
+        // A white square on black, with corners at (10, 10) and (29, 29).
+        let mut img = PhotonImage::new(vec![0; 40 * 40 * 4], 40, 40);
+        for y in 10..30 {
+            for x in 10..30 {
+                let i = (y * 40 + x) * 4;
+                img.raw_pixels[i..i + 3].copy_from_slice(&[255; 3]);
+            }
+        }
+        let expected = [(10, 10), (29, 10), (10, 29), (29, 29)];
+        let near = |p: &Point, (x, y): (i64, i64)| {
+            (p.get_x() as i64 - x).abs() <= 2 && (p.get_y() as i64 - y).abs() <= 2
+        };
+
+        for &(method, threshold) in
+            [(CornerMethod::Harris, 0.1), (CornerMethod::Fast, 20.0)].iter()
+        {
+            let found = corners(&img, method, threshold, true);
+            assert_eq!(found.len(), 4, "{:?}", method);
+            assert!(expected
+                .iter()
+                .all(|&corner| found.iter().any(|p| near(p, corner))));
+            assert!(corners(&img, method, threshold, false).len() > 4);
+        }
+
+        let flat = PhotonImage::new(vec![128; 20 * 20 * 4], 20, 20);
+        assert!(corners(&flat, CornerMethod::Harris, 0.01, true).is_empty());
+        assert!(corners(&flat, CornerMethod::Fast, 20.0, true).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "stego")]
+    fn test_stego() {
+        use crate::stego::{capacity, embed, extract};
+
+        let pixels: Vec<u8> = (0..20 * 20 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        let original = PhotonImage::new(pixels, 20, 20);
+        assert_eq!(capacity(&original), (20 * 20 * 3 - 32) / 8);
+        let message = b"The quick brown fox jumps over the lazy dog";
+
+        let mut img = original.clone();
+        embed(&mut img, message, None).unwrap();
+        assert_eq!(extract(&img, None).unwrap(), message.to_vec());
+        assert!(img
+            .raw_pixels
+            .iter()
+            .zip(&original.raw_pixels)
+            .enumerate()
+            .all(|(i, (&a, &b))| (a as i16 - b as i16).abs() <= 1
+                && (i % 4 != 3 || a == b)));
+
+        let mut seeded = original.clone();
+        embed(&mut seeded, message, Some(7)).unwrap();
+        assert_eq!(extract(&seeded, Some(7)).unwrap(), message.to_vec());
+        assert_ne!(seeded.raw_pixels, img.raw_pixels);
+
+        let mut small = PhotonImage::new(vec![0; 4 * 4 * 4], 4, 4);
+        assert!(matches!(
+            embed(&mut small, message, None),
+            Err(PhotonError::Steganography(_))
+        ));
+        assert_eq!(small.raw_pixels, vec![0; 4 * 4 * 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "filters")]
+    fn test_filter_with_strength() {
+        use crate::filters::{filter, filter_with_strength};
+
+        let pixels: Vec<u8> = (0..8 * 8 * 4).map(|i| (i * 13 % 256) as u8).collect();
+        let original = PhotonImage::new(pixels, 8, 8);
+        let mut full = original.clone();
+        filter(&mut full, "oceanic");
+
+        let mut none = original.clone();
+        filter_with_strength(&mut none, "oceanic", 0.0);
+        assert_eq!(none.raw_pixels, original.raw_pixels);
+
+        let mut strong = original.clone();
+        filter_with_strength(&mut strong, "oceanic", 2.0);
+        assert_eq!(strong.raw_pixels, full.raw_pixels);
+
+        let mut half = original.clone();
+        filter_with_strength(&mut half, "oceanic", 0.5);
+        for ((&h, &o), &f) in half
+            .raw_pixels
+            .iter()
+            .zip(&original.raw_pixels)
+            .zip(&full.raw_pixels)
+        {
+            let expected = (o as f32 + f as f32) / 2.0;
+            assert!((h as f32 - expected).abs() <= 0.5);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "filters")]
+    fn test_custom_filter() {
+        use crate::filters::{apply, FilterBuilder};
+
+        let pixels: Vec<u8> = (0..4 * 4 * 4).map(|i| (i * 16 % 256) as u8).collect();
+        let original = PhotonImage::new(pixels, 4, 4);
+
+        let mut shifted = original.clone();
+        apply(&mut shifted, &FilterBuilder::new("none").build());
+        assert_eq!(shifted.raw_pixels, original.raw_pixels);
+        let shift = FilterBuilder::new("shift")
+            .alter_channels(10, 0, -300)
+            .build();
+        apply(&mut shifted, &shift);
+        for (px, before) in shifted
+            .raw_pixels
+            .chunks_exact(4)
+            .zip(original.raw_pixels.chunks_exact(4))
+        {
+            assert_eq!(px[0], before[0].saturating_add(10));
+            assert_eq!(px[1..], [before[1], 0, before[3]]);
+        }
+
+        // Invert the red channel, and clip the green channel's highlights.
+        let curves = FilterBuilder::new("curves")
+            .channel_curve(0, &[0, 255, 255, 0])
+            .channel_curve(1, &[0, 0, 128, 255])
+            .build();
+        let mut curved = original.clone();
+        apply(&mut curved, &curves);
+        assert_eq!(curves.get_name(), "curves");
+        for (px, before) in curved
+            .raw_pixels
+            .chunks_exact(4)
+            .zip(original.raw_pixels.chunks_exact(4))
+        {
+            assert_eq!(px[0], 255 - before[0]);
+            let expected = (before[1] as f32 * 255.0 / 128.0).round().min(255.0);
+            assert_eq!(px[1], expected as u8);
+            assert_eq!(px[2..], before[2..]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pipeline")]
+    fn test_lut_bake() {
+        use crate::lut::{apply_lut, bake};
+        use crate::pipeline::{Operation, Pipeline};
+
+        let pipeline = Pipeline::new()
+            .then(Operation::Resize {
+                w: Some(3),
+                h: None,
+            })
+            .then(Operation::Invert);
+        assert!(matches!(
+            bake(&pipeline, 1),
+            Err(PhotonError::InvalidLut(_))
+        ));
+        let lut = bake(&pipeline, 5).unwrap();
+        assert_eq!(lut.get_size(), 5);
+        assert_eq!(lut.lookup([0.0, 0.0, 1.0]), [1.0, 1.0, 0.0]);
+
+        let cube = lut.to_cube("invert");
+        let lines: Vec<&str> = cube.lines().collect();
+        assert_eq!(lines[0], "TITLE \"invert\"");
+        assert_eq!(lines[1], "LUT_3D_SIZE 5");
+        assert_eq!(lines.len(), 4 + 5 * 5 * 5);
+        assert_eq!(lines[4], "1.000000 1.000000 1.000000");
+        assert_eq!(lines[5], "0.749020 1.000000 1.000000");
+
+        let pixels: Vec<u8> = (0..6 * 6 * 4).map(|i| (i * 11 % 256) as u8).collect();
+        let mut img = PhotonImage::new(pixels.clone(), 6, 6);
+        apply_lut(&mut img, &lut);
+        for (px, before) in img.raw_pixels.chunks_exact(4).zip(pixels.chunks_exact(4)) {
+            for c in 0..3 {
+                assert!((px[c] as i16 - (255 - before[c]) as i16).abs() <= 1);
+            }
+            assert_eq!(px[3], before[3]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "filters")]
+    fn test_film() {
+        use crate::filters::{film, FilmStock};
+
+        let pixels: Vec<u8> = (0..8 * 8 * 4).map(|i| (i * 29 % 256) as u8).collect();
+        let original = PhotonImage::new(pixels, 8, 8);
+
+        let mut unchanged = original.clone();
+        film(&mut unchanged, FilmStock::Velvia, 0.0);
+        assert_eq!(unchanged.raw_pixels, original.raw_pixels);
+
+        let mut mono = original.clone();
+        film(&mut mono, FilmStock::TriX, 1.0);
+        assert!(mono
+            .raw_pixels
+            .chunks_exact(4)
+            .all(|px| px[0] == px[1] && px[1] == px[2]));
+
+        for &stock in [
+            FilmStock::Kodachrome,
+            FilmStock::Velvia,
+            FilmStock::Portra,
+            FilmStock::Ektar,
+        ]
+        .iter()
+        {
+            let mut img = original.clone();
+            film(&mut img, stock, 1.0);
+            assert_ne!(img.raw_pixels, original.raw_pixels, "{:?}", stock);
+        }
+    }
+
+    #[test]
+    fn test_linear_light() {
+        use crate::gamma::{get_linear_light, with_linear_light};
+        use crate::transform::{resize, SamplingFilter};
+
+        // Alternating black and white columns average to half of white's light, which is
+        // 188 in sRGB.
+        let pixels = [[0, 0, 0, 255], [255, 255, 255, 255]].concat().repeat(8);
+        let mut img = PhotonImage::new(pixels, 16, 1);
+        let shrink = |img: &mut PhotonImage| resize(img, 1, 1, SamplingFilter::Triangle);
+        let srgb = shrink(&mut img).raw_pixels;
+        let linear = with_linear_light(&mut img, true, shrink).raw_pixels;
+        assert!((srgb[0] as i16 - 128).abs() <= 8);
+        assert!((linear[0] as i16 - 188).abs() <= 8);
+        assert_eq!(linear[3], 255);
+        assert!(!get_linear_light());
+
+        // Transparent pixels don't darken visible ones.
+        let pixels = [[0, 0, 0, 0], [255, 0, 0, 255]].concat().repeat(8);
+        let mut img = PhotonImage::new(pixels, 16, 1);
+        let linear = with_linear_light(&mut img, true, shrink).raw_pixels;
+        assert_eq!(linear[..3], [255, 0, 0]);
+        assert!((linear[3] as i16 - 128).abs() <= 8);
+
+        #[cfg(feature = "conv")]
+        {
+            use crate::conv::gaussian_blur;
+
+            // A black left half and a white right half.
+            let pixels: Vec<u8> = (0..16 * 16)
+                .flat_map(|i| if i % 16 < 8 { [0, 0, 0, 255] } else { [255; 4] })
+                .collect();
+            let original = PhotonImage::new(pixels, 16, 16);
+            let mut srgb = original.clone();
+            gaussian_blur(&mut srgb, 2);
+            let mut linear = original.clone();
+            with_linear_light(&mut linear, true, |img| gaussian_blur(img, 2));
+            let edge = (8 * 16 + 8) * 4;
+            assert!(linear.raw_pixels[edge] > srgb.raw_pixels[edge] + 20);
+            assert_eq!(linear.raw_pixels[8 * 16 * 4], 0);
+            assert_eq!(linear.raw_pixels[(8 * 16 + 15) * 4], 255);
+        }
+    }
+
+    #[test]
+    fn test_posterize_gray() {
+        use crate::monochrome::posterize_gray;
+
+        // A horizontal gradient from black to white.
+        let pixels: Vec<u8> = (0..64 * 4 * 4)
+            .map(|i| {
+                if i % 4 == 3 {
+                    255
+                } else {
+                    (i / 4 % 64 * 4) as u8
+                }
+            })
+            .collect();
+        let original = PhotonImage::new(pixels, 64, 4);
+
+        let mut banded = original.clone();
+        posterize_gray(&mut banded, 3, false);
+        let mut shades: Vec<u8> =
+            banded.raw_pixels.chunks_exact(4).map(|px| px[0]).collect();
+        assert!(banded
+            .raw_pixels
+            .chunks_exact(4)
+            .all(|px| px[0] == px[1] && px[1] == px[2] && px[3] == 255));
+        shades.sort_unstable();
+        shades.dedup();
+        assert_eq!(shades, vec![0, 128, 255]);
+
+        // Dithering keeps the average brightness of each column close to the original.
+        let mut dithered = original.clone();
+        posterize_gray(&mut dithered, 2, true);
+        assert!(dithered
+            .raw_pixels
+            .chunks_exact(4)
+            .all(|px| px[0] == 0 || px[0] == 255));
+        let column_mean = |img: &PhotonImage, x0: usize| {
+            let total: u32 = (0..4)
+                .flat_map(|y| (x0..x0 + 16).map(move |x| (y * 64 + x) * 4))
+                .map(|i| img.raw_pixels[i] as u32)
+                .sum();
+            total as f32 / 64.0
+        };
+        for &x0 in [0, 16, 32, 48].iter() {
+            assert!(
+                (column_mean(&dithered, x0) - column_mean(&original, x0)).abs() < 32.0
+            );
+        }
+    }
+
+    #[test]
+    fn test_tritone() {
+        use crate::monochrome::{quadtone, tritone};
+        use crate::Rgb;
+
+        let pixels =
+            [[0, 0, 0, 255], [128, 128, 128, 200], [255, 255, 255, 255]].concat();
+        let mut img = PhotonImage::new(pixels.clone(), 3, 1);
+        tritone(
+            &mut img,
+            Rgb::new(0, 0, 100),
+            Rgb::new(200, 0, 0),
+            Rgb::new(255, 255, 0),
+        );
+        assert_eq!(img.raw_pixels[..4], [0, 0, 100, 255]);
+        let expected = [200, 0, 0, 200];
+        assert!((0..4).all(|c| (img.raw_pixels[4 + c] as i16 - expected[c]).abs() <= 1));
+        assert_eq!(img.raw_pixels[8..], [255, 255, 0, 255]);
+
+        let mut img = PhotonImage::new(pixels, 3, 1);
+        quadtone(
+            &mut img,
+            Rgb::new(0, 0, 0),
+            Rgb::new(0, 0, 255),
+            Rgb::new(0, 255, 0),
+            Rgb::new(255, 0, 0),
+        );
+        assert_eq!(img.raw_pixels[..4], [0, 0, 0, 255]);
+        // Mid-gray lies halfway between the two middle inks.
+        let mid = &img.raw_pixels[4..8];
+        assert_eq!(mid[0], 0);
+        assert!((mid[1] as i16 - 128).abs() <= 2 && (mid[2] as i16 - 128).abs() <= 2);
+        assert_eq!(img.raw_pixels[8..], [255, 0, 0, 255]);
+    }
 }
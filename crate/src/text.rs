@@ -3,25 +3,406 @@
 //! which is a graphic design library, compatible with Photon.
 
 extern crate image;
-use image::{DynamicImage, Rgba};
-extern crate imageproc;
+use image::{imageops, FilterType, RgbaImage};
 extern crate rusttype;
-use crate::{helpers, PhotonImage};
-use imageproc::distance_transform::Norm;
-use imageproc::drawing::draw_text_mut;
-use imageproc::morphology::dilate_mut;
-use rusttype::{FontCollection, Scale};
+use crate::error::PhotonError;
+use crate::{helpers, PhotonImage, Rgb};
+#[cfg(feature = "shaping")]
+use rusttype::GlyphId;
+use rusttype::{point, FontCollection, PositionedGlyph, Scale};
+use std::cell::RefCell;
+use std::collections::HashMap;
+#[cfg(feature = "shaping")]
+use std::sync::Arc;
+#[cfg(feature = "shaping")]
+use unicode_bidi::ParagraphBidiInfo;
 use wasm_bindgen::prelude::*;
 
+/// A font which text can be drawn with.
+///
+/// Fonts can be loaded from the raw bytes of a TrueType font file (`.ttf`, `.ttc`, or an
+/// `.otf` file with TrueType outlines), such as a file read from disk natively, or an
+/// `ArrayBuffer` fetched on the web. The default font is the bundled Roboto.
+///
+/// Colour glyphs such as emoji are not read from font files. Instead, an `EmojiAtlas` of
+/// images can be attached to a font with `set_emoji_atlas`, and any emoji found in the atlas
+/// are drawn as images in place of the font's glyphs.
+///
+/// With the `shaping` feature enabled, text is shaped using the font's OpenType tables, so
+/// that ligatures, complex scripts and right-to-left text are drawn correctly.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Font {
+    font: rusttype::Font<'static>,
+    emoji: Option<EmojiAtlas>,
+    #[cfg(feature = "shaping")]
+    data: Arc<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl Font {
+    /// Load a font from the bytes of a font file.
+    /// If the file is a font collection, the first font in the collection is used.
+    ///
+    /// # Arguments
+    /// * `bytes` - The contents of the font file.
+    ///
+    /// # Example
+    /// ```
+    /// use photon_rs::text::Font;
+    ///
+    /// let bytes = std::fs::read("fonts/Roboto-Black.ttf").unwrap();
+    /// let font = Font::from_bytes(bytes).unwrap();
+    /// ```
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Font, PhotonError> {
+        #[cfg(feature = "shaping")]
+        let data = Arc::new(bytes.clone());
+        let font = FontCollection::from_bytes(bytes)
+            .and_then(|collection| collection.font_at(0))
+            .map_err(|err| PhotonError::InvalidFont(err.to_string()))?;
+        Ok(Font {
+            font,
+            emoji: None,
+            #[cfg(feature = "shaping")]
+            data,
+        })
+    }
+
+    /// Get the bundled Roboto font.
+    pub fn roboto() -> Font {
+        let bytes = Vec::from(include_bytes!("../fonts/Roboto-Regular.ttf") as &[u8]);
+        Font::from_bytes(bytes).unwrap()
+    }
+
+    /// Draw emoji found in the given atlas as images, rather than with the font's own glyphs.
+    ///
+    /// # Arguments
+    /// * `atlas` - The images to draw emoji with.
+    pub fn set_emoji_atlas(&mut self, atlas: &EmojiAtlas) {
+        self.emoji = Some(atlas.clone());
+    }
+
+    /// Stop drawing emoji from an atlas.
+    pub fn clear_emoji_atlas(&mut self) {
+        self.emoji = None;
+    }
+}
+
+/// A set of images used to draw emoji (or any other character sequence) in text.
+///
+/// Each image is scaled to the height of the font size it is drawn at, keeping its aspect
+/// ratio, and is centred vertically on the line. Transparent pixels in the image are blended
+/// with the background. A trailing emoji presentation selector (U+FE0F) after an emoji in
+/// the text is ignored, so "\u{2764}\u{FE0F}" matches an image inserted for "\u{2764}".
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct EmojiAtlas {
+    images: HashMap<String, PhotonImage>,
+    longest: usize,
+}
+
+#[wasm_bindgen]
+impl EmojiAtlas {
+    #[wasm_bindgen(constructor)]
+    /// Create an empty atlas.
+    pub fn new() -> EmojiAtlas {
+        EmojiAtlas::default()
+    }
+
+    /// Add an image for an emoji, replacing any existing image for it.
+    ///
+    /// # Arguments
+    /// * `emoji` - The emoji, which may be a sequence of several characters, eg: a flag.
+    /// * `image` - The image to draw the emoji with.
+    ///
+    /// # Example
+    /// ```
+    /// use photon_rs::text::{EmojiAtlas, Font};
+    /// use photon_rs::PhotonImage;
+    ///
+    /// let party_popper = PhotonImage::new(vec![255; 72 * 72 * 4], 72, 72);
+    /// let mut atlas = EmojiAtlas::new();
+    /// atlas.insert("\u{1F389}", party_popper);
+    ///
+    /// let mut font = Font::roboto();
+    /// font.set_emoji_atlas(&atlas);
+    /// ```
+    pub fn insert(&mut self, emoji: &str, image: PhotonImage) {
+        let emoji = emoji.trim_end_matches(VARIATION_SELECTOR);
+        if emoji.is_empty() {
+            return;
+        }
+        self.longest = self.longest.max(emoji.len());
+        self.images.insert(emoji.to_string(), image);
+    }
+
+    /// Add emoji from a sprite sheet: an image made up of a grid of equally-sized cells.
+    ///
+    /// # Arguments
+    /// * `sheet` - The sprite sheet.
+    /// * `cell_width` - The width of each cell, in pixels.
+    /// * `cell_height` - The height of each cell, in pixels.
+    /// * `emoji` - The emoji in each cell, separated by whitespace, in the order the cells
+    /// appear in the sheet, left to right and then top to bottom.
+    pub fn insert_sprite_sheet(
+        &mut self,
+        sheet: &PhotonImage,
+        cell_width: u32,
+        cell_height: u32,
+        emoji: &str,
+    ) {
+        if cell_width == 0 || cell_height == 0 {
+            return;
+        }
+        let columns = sheet.width / cell_width;
+        let cells = columns * (sheet.height / cell_height);
+        for (i, emoji) in emoji.split_whitespace().take(cells as usize).enumerate() {
+            let (col, row) = (i as u32 % columns, i as u32 / columns);
+            let mut raw_pixels =
+                Vec::with_capacity((cell_width * cell_height * 4) as usize);
+            for y in row * cell_height..(row + 1) * cell_height {
+                let start = ((y * sheet.width + col * cell_width) * 4) as usize;
+                let end = start + (cell_width * 4) as usize;
+                raw_pixels.extend_from_slice(&sheet.raw_pixels[start..end]);
+            }
+            self.insert(emoji, PhotonImage::new(raw_pixels, cell_width, cell_height));
+        }
+    }
+
+    /// Check whether the atlas has an image for an emoji.
+    pub fn contains(&self, emoji: &str) -> bool {
+        self.images
+            .contains_key(emoji.trim_end_matches(VARIATION_SELECTOR))
+    }
+
+    /// The number of emoji in the atlas.
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Check whether the atlas is empty.
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+}
+
+impl EmojiAtlas {
+    /// Find the longest emoji in the atlas which `text` starts with, returning its image and
+    /// the number of bytes of `text` it spans (including any variation selector after it).
+    fn match_prefix(&self, text: &str) -> Option<(&PhotonImage, usize)> {
+        let mut ends: Vec<usize> = text
+            .char_indices()
+            .map(|(i, ch)| i + ch.len_utf8())
+            .take_while(|&end| end <= self.longest)
+            .collect();
+        ends.reverse();
+
+        ends.into_iter().find_map(|end| {
+            self.images.get(&text[..end]).map(|image| {
+                let rest = &text[end..];
+                let skip = if rest.starts_with(VARIATION_SELECTOR) {
+                    VARIATION_SELECTOR.len_utf8()
+                } else {
+                    0
+                };
+                (image, end + skip)
+            })
+        })
+    }
+}
+
+/// The emoji presentation selector, which may follow an emoji.
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+
+impl Default for Font {
+    /// The bundled Roboto, with the emoji atlas set by `set_default_emoji_atlas`, if any.
+    fn default() -> Font {
+        let mut font = Font::roboto();
+        font.emoji = DEFAULT_EMOJI_ATLAS.with(|atlas| atlas.borrow().clone());
+        font
+    }
+}
+
+thread_local! {
+    static DEFAULT_EMOJI_ATLAS: RefCell<Option<EmojiAtlas>> = const { RefCell::new(None) };
+}
+
+/// Draw emoji found in the given atlas as images in text drawn with the default font, by
+/// `draw_text` and `draw_text_with_border`, on the current thread.
+///
+/// # Arguments
+/// * `atlas` - The images to draw emoji with.
+#[wasm_bindgen]
+pub fn set_default_emoji_atlas(atlas: &EmojiAtlas) {
+    DEFAULT_EMOJI_ATLAS.with(|default| *default.borrow_mut() = Some(atlas.clone()));
+}
+
+/// Stop drawing emoji from an atlas with the default font on the current thread.
+#[wasm_bindgen]
+pub fn clear_default_emoji_atlas() {
+    DEFAULT_EMOJI_ATLAS.with(|default| *default.borrow_mut() = None);
+}
+
+/// The height, in pixels, of text drawn by `draw_text` and `draw_text_with_border`.
+const DEFAULT_FONT_SIZE: f32 = 90.0;
+
+/// Horizontal alignment of each line of text, relative to the x-coordinate it is drawn at.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    /// Lines start at the x-coordinate.
+    Left,
+    /// Lines are centred on the x-coordinate.
+    Center,
+    /// Lines end at the x-coordinate.
+    Right,
+}
+
+/// Which part of the block of text is placed at the y-coordinate it is drawn at.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerticalAnchor {
+    /// The top of the first line.
+    Top,
+    /// The vertical centre of the block of text.
+    Middle,
+    /// The bottom of the last line.
+    Bottom,
+}
+
+/// Layout and styling options for drawing text with `draw_text_with_options`.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct TextOptions {
+    font_size: f32,
+    max_width: Option<u32>,
+    line_height: f32,
+    align: TextAlign,
+    anchor: VerticalAnchor,
+    colour: [u8; 4],
+    outline: Option<(u32, [u8; 4])>,
+    shadow: Option<Shadow>,
+    background: Option<(u32, [u8; 4])>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Shadow {
+    offset_x: i32,
+    offset_y: i32,
+    blur: u32,
+    // How far the shadow is grown beyond the outline of the text, in pixels.
+    spread: u32,
+    colour: [u8; 4],
+}
+
+#[wasm_bindgen]
+impl TextOptions {
+    #[wasm_bindgen(constructor)]
+    /// Create options for white, left-aligned text of the given size (in pixels), which wraps
+    /// at the edge of the image.
+    pub fn new(font_size: f32) -> TextOptions {
+        TextOptions {
+            font_size,
+            max_width: None,
+            line_height: 1.2,
+            align: TextAlign::Left,
+            anchor: VerticalAnchor::Top,
+            colour: [255, 255, 255, 255],
+            outline: None,
+            shadow: None,
+            background: None,
+        }
+    }
+
+    /// Set the font size, in pixels.
+    pub fn set_font_size(&mut self, font_size: f32) {
+        self.font_size = font_size;
+    }
+
+    /// Set the maximum width of a line in pixels. Longer lines are wrapped onto the next line.
+    pub fn set_max_width(&mut self, max_width: u32) {
+        self.max_width = Some(max_width);
+    }
+
+    /// Set the distance between consecutive baselines, as a multiple of the font size.
+    pub fn set_line_height(&mut self, line_height: f32) {
+        self.line_height = line_height;
+    }
+
+    /// Set the horizontal alignment of each line.
+    pub fn set_align(&mut self, align: TextAlign) {
+        self.align = align;
+    }
+
+    /// Set which part of the text block is positioned at the y-coordinate.
+    pub fn set_anchor(&mut self, anchor: VerticalAnchor) {
+        self.anchor = anchor;
+    }
+
+    /// Set the colour of the text.
+    pub fn set_colour(&mut self, colour: Rgb) {
+        self.colour = [colour.r, colour.g, colour.b, 255];
+    }
+
+    /// Draw an outline (stroke) around each glyph.
+    ///
+    /// # Arguments
+    /// * `width` - The width of the outline in pixels. A width of 0 removes the outline.
+    /// * `colour` - The colour of the outline.
+    pub fn set_outline(&mut self, width: u32, colour: Rgb) {
+        self.outline = if width > 0 {
+            Some((width, [colour.r, colour.g, colour.b, 255]))
+        } else {
+            None
+        };
+    }
+
+    /// Draw a drop shadow behind the text (and its outline, if any).
+    ///
+    /// # Arguments
+    /// * `offset_x` - Horizontal offset of the shadow in pixels. Positive values move it right.
+    /// * `offset_y` - Vertical offset of the shadow in pixels. Positive values move it down.
+    /// * `blur` - Blur radius of the shadow in pixels. 0 gives a hard-edged shadow.
+    /// * `colour` - The colour of the shadow.
+    pub fn set_shadow(&mut self, offset_x: i32, offset_y: i32, blur: u32, colour: Rgb) {
+        self.shadow = Some(Shadow {
+            offset_x,
+            offset_y,
+            blur,
+            spread: 0,
+            colour: [colour.r, colour.g, colour.b, 255],
+        });
+    }
+
+    /// Draw a filled rectangle behind the whole block of text.
+    ///
+    /// # Arguments
+    /// * `padding` - Space between the text and the edges of the rectangle, in pixels.
+    /// * `colour` - The colour of the rectangle.
+    /// * `opacity` - Opacity of the rectangle, from 0 (transparent) to 1 (opaque).
+    pub fn set_background(&mut self, padding: u32, colour: Rgb, opacity: f32) {
+        let alpha = (num::clamp(opacity, 0.0, 1.0) * 255.0).round() as u8;
+        self.background = Some((padding, [colour.r, colour.g, colour.b, alpha]));
+    }
+
+    /// Remove any outline, shadow and background previously set.
+    pub fn clear_decorations(&mut self) {
+        self.outline = None;
+        self.shadow = None;
+        self.background = None;
+    }
+}
+
 /// Add bordered-text to an image.
-/// The only font available as of now is Roboto.
-/// Note: A graphic design/text-drawing library is currently being developed, so stay tuned.
+/// The text is drawn in white in the default font, which is Roboto, 90 pixels high, with
+/// a black border 10 pixels below and to the left of it. Emoji are drawn from the atlas set
+/// with `set_default_emoji_atlas`.
 ///
 /// # Arguments
 /// * `photon_image` - A PhotonImage.
 /// * `text` - Text string to be drawn to the image.
-/// * `x` - x-coordinate of where first letter's 1st pixel should be drawn.
-/// * `y` - y-coordinate of where first letter's 1st pixel should be drawn.
+/// * `x` - x-coordinate of where the border's 1st pixel should be drawn.
+/// * `y` - y-coordinate of the top of the border.
 ///
 /// # Example
 /// ```
@@ -34,123 +415,792 @@ use wasm_bindgen::prelude::*;
 /// draw_text_with_border(&mut img, "Welcome to Photon!", 10_u32, 10_u32);
 /// ```
 #[wasm_bindgen]
-pub fn draw_text_with_border(
-    mut photon_img: &mut PhotonImage,
+pub fn draw_text_with_border(photon_img: &mut PhotonImage, text: &str, x: u32, y: u32) {
+    let (text_x, text_y) = (x.saturating_add(10), y.saturating_sub(10));
+    let mut options = TextOptions::new(DEFAULT_FONT_SIZE);
+    options.set_max_width(u32::MAX);
+    // The border is the shape of the text, grown by 4 pixels, drawn as a shadow.
+    options.shadow = Some(Shadow {
+        offset_x: x as i32 - text_x as i32,
+        offset_y: y as i32 - text_y as i32,
+        blur: 0,
+        spread: 4,
+        colour: [0, 0, 0, 255],
+    });
+    draw_text_with_options(photon_img, text, text_x, text_y, &Font::default(), &options);
+}
+
+/// Add text to an image.
+/// The text is drawn in white in the default font, which is Roboto, 90 pixels high. Emoji
+/// are drawn from the atlas set with `set_default_emoji_atlas`. With the `shaping`
+/// feature enabled, the text is shaped and right-to-left runs are reordered, as with
+/// `draw_text_with_font`, which draws text in another font or size.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `text` - Text string to be drawn to the image.
+/// * `x` - x-coordinate of where first letter's 1st pixel should be drawn.
+/// * `y` - y-coordinate of the top of the line of text.
+///
+/// # Example
+/// ```
+/// // For example to draw the string "Welcome to Photon!" at 10, 10:
+/// use photon_rs::native::open_image;
+/// use photon_rs::text::draw_text;
+///
+/// // Open the image. A PhotonImage is returned.
+/// let mut img = open_image("img.jpg");
+/// draw_text(&mut img, "Welcome to Photon!", 10_u32, 10_u32);
+/// ```
+#[wasm_bindgen]
+pub fn draw_text(photon_img: &mut PhotonImage, text: &str, x: u32, y: u32) {
+    draw_text_with_font(photon_img, text, x, y, &Font::default(), DEFAULT_FONT_SIZE);
+}
+
+/// Add text to an image using a custom font.
+///
+/// # Arguments
+/// * `photon_image` - A PhotonImage.
+/// * `text` - Text string to be drawn to the image.
+/// * `x` - x-coordinate of where first letter's 1st pixel should be drawn.
+/// * `y` - y-coordinate of the top of the line of text.
+/// * `font` - The font to draw the text with.
+/// * `font_size` - The height of the text, in pixels.
+///
+/// # Example
+/// ```
+/// // For example to draw the string "Welcome to Photon!" at 10, 10 in Roboto Black:
+/// use photon_rs::native::open_image;
+/// use photon_rs::text::{draw_text_with_font, Font};
+///
+/// let font = Font::from_bytes(std::fs::read("fonts/Roboto-Black.ttf").unwrap()).unwrap();
+/// let mut img = open_image("img.jpg");
+/// draw_text_with_font(&mut img, "Welcome to Photon!", 10_u32, 10_u32, &font, 60_f32);
+/// ```
+#[wasm_bindgen]
+pub fn draw_text_with_font(
+    photon_img: &mut PhotonImage,
     text: &str,
     x: u32,
     y: u32,
+    font: &Font,
+    font_size: f32,
 ) {
-    let mut image = helpers::dyn_image_from_raw(&photon_img).to_rgba();
-
-    let mut image2: DynamicImage =
-        DynamicImage::new_luma8(image.width(), image.height());
-
-    let font = Vec::from(include_bytes!("../fonts/Roboto-Regular.ttf") as &[u8]);
-    let font = FontCollection::from_bytes(font)
-        .unwrap()
-        .into_font()
-        .unwrap();
-    let height = 90f32;
-    let scale = Scale {
-        x: height * 1.0,
-        y: height,
-    };
-    draw_text_mut(
-        &mut image2,
-        Rgba([255u8, 255u8, 255u8, 255u8]),
-        x,
-        y,
-        scale,
-        &font,
+    let colour = [255, 255, 255, 255];
+    draw_glyphs(
+        photon_img,
         text,
+        &Shaper::new(font),
+        font_size,
+        x as f32,
+        y as f32,
+        colour,
     );
+}
 
-    let mut image2 = image2.to_luma();
-    dilate_mut(&mut image2, Norm::LInf, 4u8);
+/// The dimensions of a line of text, as returned by `measure_text`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextMetrics {
+    width: f32,
+    height: f32,
+    baseline: f32,
+}
 
-    // Add a border to the text.
-    for x in 0..image2.width() {
-        for y in 0..image2.height() {
-            let pixval = 255 - image2.get_pixel(x, y).data[0];
-            if pixval != 255 {
-                let new_pix = Rgba([pixval, pixval, pixval, 255]);
-                image.put_pixel(x, y, new_pix);
-            }
-        }
+#[wasm_bindgen]
+impl TextMetrics {
+    /// Get the horizontal advance of the text, in pixels.
+    pub fn get_width(&self) -> f32 {
+        self.width
     }
 
-    draw_text_mut(
-        &mut image,
-        Rgba([255u8, 255u8, 255u8, 255u8]),
-        x + 10,
-        y - 10,
-        scale,
-        &font,
-        text,
-    );
-    let dynimage = image::ImageRgba8(image);
-    photon_img.raw_pixels = dynimage.raw_pixels();
+    /// Get the height of the line of text, from the font's ascent to its descent, in pixels.
+    pub fn get_height(&self) -> f32 {
+        self.height
+    }
+
+    /// Get the distance from the top of the line of text down to its baseline, in pixels.
+    pub fn get_baseline(&self) -> f32 {
+        self.baseline
+    }
 }
 
-/// Add text to an image.
-/// The only font available as of now is Roboto.
-/// Note: A graphic design/text-drawing library is currently being developed, so stay tuned.
+/// Measure a line of text, as it would be drawn by `draw_text_with_font`.
+///
+/// # Arguments
+/// * `text` - Text string to be measured.
+/// * `font` - The font the text would be drawn with.
+/// * `font_size` - The height of the text, in pixels.
+///
+/// # Example
+/// ```
+/// // For example, to right-align text 10 pixels from the right edge of an image:
+/// use photon_rs::text::{draw_text_with_font, measure_text, Font};
+/// use photon_rs::PhotonImage;
+///
+/// let mut img = PhotonImage::new(vec![0; 400 * 100 * 4], 400, 100);
+/// let font = Font::roboto();
+/// let metrics = measure_text("Photon", &font, 40_f32);
+/// let x = img.get_width() - 10 - metrics.get_width().ceil() as u32;
+/// draw_text_with_font(&mut img, "Photon", x, 10_u32, &font, 40_f32);
+/// ```
+#[wasm_bindgen]
+pub fn measure_text(text: &str, font: &Font, font_size: f32) -> TextMetrics {
+    let scale = Scale::uniform(font_size);
+    let v_metrics = font.font.v_metrics(scale);
+    TextMetrics {
+        width: text_width(text, &Shaper::new(font), scale),
+        height: v_metrics.ascent - v_metrics.descent,
+        baseline: v_metrics.ascent,
+    }
+}
+
+/// Draw text which is wrapped and aligned according to the given options.
+///
+/// Text is wrapped at word boundaries so that no line exceeds the maximum width, and newlines
+/// in the text always start a new line. If no maximum width is set, lines are wrapped at the
+/// edge of the image instead of running off it.
 ///
 /// # Arguments
 /// * `photon_image` - A PhotonImage.
 /// * `text` - Text string to be drawn to the image.
-/// * `x` - x-coordinate of where first letter's 1st pixel should be drawn.
-/// * `y` - y-coordinate of where first letter's 1st pixel should be drawn.
+/// * `x` - x-coordinate the lines are aligned to. See `TextAlign`.
+/// * `y` - y-coordinate the block of text is anchored to. See `VerticalAnchor`.
+/// * `font` - The font to draw the text with.
+/// * `options` - The layout and styling options.
 ///
 /// # Example
 /// ```
-/// // For example to draw the string "Welcome to Photon!" at 10, 10:
+/// // For example, to draw a centred caption near the bottom of an image:
 /// use photon_rs::native::open_image;
-/// use photon_rs::text::draw_text;
+/// use photon_rs::text::{draw_text_with_options, Font, TextAlign, TextOptions, VerticalAnchor};
 ///
-/// // Open the image. A PhotonImage is returned.
 /// let mut img = open_image("img.jpg");
-/// draw_text(&mut img, "Welcome to Photon!", 10_u32, 10_u32);
+/// let mut options = TextOptions::new(40_f32);
+/// options.set_max_width(img.get_width() - 40);
+/// options.set_align(TextAlign::Center);
+/// options.set_anchor(VerticalAnchor::Bottom);
+///
+/// let (x, y) = (img.get_width() / 2, img.get_height() - 20);
+/// draw_text_with_options(&mut img, "A long caption which wraps", x, y, &Font::roboto(), &options);
 /// ```
 #[wasm_bindgen]
-pub fn draw_text(mut photon_img: &mut PhotonImage, text: &str, x: u32, y: u32) {
-    let mut image = helpers::dyn_image_from_raw(&photon_img).to_rgba();
-
-    let mut image2: DynamicImage =
-        DynamicImage::new_luma8(image.width(), image.height());
-
-    let font = Vec::from(include_bytes!("../fonts/Roboto-Regular.ttf") as &[u8]);
-    let font = FontCollection::from_bytes(font)
-        .unwrap()
-        .into_font()
-        .unwrap();
-    let height = 90f32;
-    let scale = Scale {
-        x: height * 1.0,
-        y: height,
+pub fn draw_text_with_options(
+    photon_img: &mut PhotonImage,
+    text: &str,
+    x: u32,
+    y: u32,
+    font: &Font,
+    options: &TextOptions,
+) {
+    let scale = Scale::uniform(options.font_size);
+    let max_width = options.max_width.unwrap_or_else(|| {
+        let width = photon_img.width;
+        match options.align {
+            TextAlign::Left => width.saturating_sub(x),
+            TextAlign::Center => 2 * x.min(width.saturating_sub(x)),
+            TextAlign::Right => x,
+        }
+    });
+
+    let shaper = Shaper::new(font);
+    let lines = wrap_lines(text, &shaper, scale, max_width as f32);
+    let line_advance = options.font_size * options.line_height;
+    let v_metrics = font.font.v_metrics(scale);
+    let block_height =
+        line_advance * (lines.len() as f32 - 1.0) + v_metrics.ascent - v_metrics.descent;
+
+    let top = match options.anchor {
+        VerticalAnchor::Top => y as f32,
+        VerticalAnchor::Middle => y as f32 - block_height / 2.0,
+        VerticalAnchor::Bottom => y as f32 - block_height,
     };
-    draw_text_mut(
-        &mut image2,
-        Rgba([255u8, 255u8, 255u8, 255u8]),
-        10,
-        10,
-        scale,
-        &font,
-        text,
-    );
 
-    let mut image2 = image2.to_luma();
-    dilate_mut(&mut image2, Norm::LInf, 4u8);
+    // Position each line, and find the bounds of the whole block.
+    let mut positions = Vec::with_capacity(lines.len());
+    let (mut block_left, mut block_right) = (f32::MAX, f32::MIN);
+    for (i, line) in lines.iter().enumerate() {
+        let line_width = text_width(line, &shaper, scale);
+        let left = match options.align {
+            TextAlign::Left => x as f32,
+            TextAlign::Center => x as f32 - line_width / 2.0,
+            TextAlign::Right => x as f32 - line_width,
+        };
+        block_left = block_left.min(left);
+        block_right = block_right.max(left + line_width);
+        positions.push((left, top + line_advance * i as f32));
+    }
 
-    draw_text_mut(
-        &mut image,
-        Rgba([255u8, 255u8, 255u8, 255u8]),
-        x,
-        y,
-        scale,
-        &font,
-        text,
+    if let Some((padding, colour)) = options.background {
+        let padding = padding as f32;
+        fill_rect(
+            photon_img,
+            (block_left - padding).floor() as i32,
+            (top - padding).floor() as i32,
+            (block_right + padding).ceil() as i32,
+            (top + block_height + padding).ceil() as i32,
+            colour,
+        );
+    }
+
+    // Rasterize the text once into a coverage mask, large enough to hold the outline and a
+    // blurred shadow, so that each decoration can be derived from it. Emoji are drawn as
+    // images, but still cast a shadow and are outlined.
+    let outline_width = options.outline.map_or(0, |(width, _)| width);
+    let blur = options.shadow.map_or(0, |shadow| shadow.blur);
+    let spread = options.shadow.map_or(0, |shadow| shadow.spread);
+    let margin = (outline_width + spread + 3 * blur + 2) as i32;
+    let mut coverage = Coverage::new(
+        block_left.floor() as i32 - margin,
+        top.floor() as i32 - margin,
+        block_right.ceil() as i32 + margin,
+        (top + block_height).ceil() as i32 + margin,
     );
-    let dynimage = image::ImageRgba8(image);
-    photon_img.raw_pixels = dynimage.raw_pixels();
+    let mut emoji = coverage.clone();
+    let mut bitmaps = vec![];
+    for (line, (left, line_top)) in lines.iter().zip(positions) {
+        for (run_x, run) in layout_line(line, &shaper, scale).0 {
+            match run {
+                Run::Text { text, rtl } => {
+                    let x = left + run_x;
+                    for_each_glyph_pixel(
+                        text,
+                        rtl,
+                        &shaper,
+                        scale,
+                        x,
+                        line_top,
+                        |px, py, value| coverage.add(px, py, value),
+                    );
+                }
+                Run::Emoji(image) => {
+                    let ex = (left + run_x).round() as i32;
+                    let ey = emoji_top(font, scale, line_top);
+                    let bitmap = scale_emoji(image, scale);
+                    for (bx, by, px) in bitmap.enumerate_pixels() {
+                        let alpha = px.data[3] as f32 / 255.0;
+                        emoji.add(ex + bx as i32, ey + by as i32, alpha);
+                    }
+                    bitmaps.push((ex, ey, bitmap));
+                }
+            }
+        }
+    }
+
+    let mut decoration = coverage.clone();
+    for (value, emoji_value) in decoration.values.iter_mut().zip(&emoji.values) {
+        *value = value.max(*emoji_value);
+    }
+    let outlined = options
+        .outline
+        .map(|(width, colour)| (decoration.dilate(width), colour));
+
+    if let Some(shadow) = options.shadow {
+        let base = outlined.as_ref().map_or(&decoration, |(mask, _)| mask);
+        base.dilate(shadow.spread).blur(shadow.blur).composite(
+            photon_img,
+            shadow.colour,
+            shadow.offset_x,
+            shadow.offset_y,
+        );
+    }
+    if let Some((mask, colour)) = &outlined {
+        mask.composite(photon_img, *colour, 0, 0);
+    }
+    coverage.composite(photon_img, options.colour, 0, 0);
+    for (ex, ey, bitmap) in bitmaps {
+        draw_bitmap(photon_img, &bitmap, ex, ey);
+    }
+}
+
+/// Break text into lines no wider than `max_width`, wrapping at spaces where possible and
+/// splitting words which are too long to fit on a line of their own.
+fn wrap_lines(text: &str, shaper: &Shaper, scale: Scale, max_width: f32) -> Vec<String> {
+    let mut lines = vec![];
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if text_width(&candidate, shaper, scale) <= max_width {
+                line = candidate;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(line);
+            }
+            // The word doesn't fit on a line of its own, so break it between characters.
+            line = String::new();
+            for ch in word.chars() {
+                line.push(ch);
+                if text_width(&line, shaper, scale) > max_width
+                    && line.chars().count() > 1
+                {
+                    line.pop();
+                    lines.push(line);
+                    line = ch.to_string();
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// The horizontal advance of a single line of text, including kerning.
+fn text_width(text: &str, shaper: &Shaper, scale: Scale) -> f32 {
+    layout_line(text, shaper, scale).1
+}
+
+/// A font prepared for laying out text. With shaping, the font's OpenType tables are
+/// parsed here, once for each call which draws or measures text, rather than for every run.
+struct Shaper<'a> {
+    font: &'a Font,
+    #[cfg(feature = "shaping")]
+    face: Option<rustybuzz::Face<'a>>,
+}
+
+impl<'a> Shaper<'a> {
+    fn new(font: &'a Font) -> Shaper<'a> {
+        Shaper {
+            font,
+            #[cfg(feature = "shaping")]
+            face: rustybuzz::Face::from_slice(&font.data, 0),
+        }
+    }
+}
+
+/// A piece of a line of text, which is either drawn with the font or as an emoji image.
+enum Run<'a> {
+    Text { text: &'a str, rtl: bool },
+    Emoji(&'a PhotonImage),
+}
+
+/// Split a line of text into runs of glyphs and emoji in visual (left-to-right) order,
+/// returning the x-offset of each run from the start of the line, along with the width of
+/// the whole line.
+fn layout_line<'a>(
+    text: &'a str,
+    shaper: &Shaper<'a>,
+    scale: Scale,
+) -> (Vec<(f32, Run<'a>)>, f32) {
+    let mut runs = vec![];
+    let mut x = 0.0;
+    for (segment, rtl) in direction_runs(text) {
+        let mut pieces = split_emoji(segment, rtl, shaper.font.emoji.as_ref());
+        if rtl {
+            pieces.reverse();
+        }
+        for piece in pieces {
+            let width = match piece {
+                Run::Text { text, rtl } => {
+                    shape_run(text, rtl, shaper, scale, 0.0, 0.0).1
+                }
+                Run::Emoji(image) => emoji_width(image, scale),
+            };
+            runs.push((x, piece));
+            x += width;
+        }
+    }
+    (runs, x)
+}
+
+/// Split text of a single direction into runs of glyphs and emoji, in logical order.
+fn split_emoji<'a>(
+    text: &'a str,
+    rtl: bool,
+    atlas: Option<&'a EmojiAtlas>,
+) -> Vec<Run<'a>> {
+    let mut runs = vec![];
+    let push_text = |runs: &mut Vec<Run<'a>>, text: &'a str| {
+        if !text.is_empty() {
+            runs.push(Run::Text { text, rtl });
+        }
+    };
+
+    let atlas = match atlas {
+        Some(atlas) if !atlas.is_empty() => atlas,
+        _ => {
+            push_text(&mut runs, text);
+            return runs;
+        }
+    };
+
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if let Some((image, len)) = atlas.match_prefix(&text[i..]) {
+            push_text(&mut runs, &text[start..i]);
+            runs.push(Run::Emoji(image));
+            i += len;
+            start = i;
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    push_text(&mut runs, &text[start..]);
+    runs
+}
+
+/// Split a line of text into runs of a single direction, in visual order, flagging the
+/// right-to-left runs.
+#[cfg(feature = "shaping")]
+fn direction_runs(text: &str) -> Vec<(&str, bool)> {
+    if text.is_empty() {
+        return vec![];
+    }
+    let bidi = ParagraphBidiInfo::new(text, None);
+    let (levels, runs) = bidi.visual_runs(0..text.len());
+    runs.into_iter()
+        .map(|run| (&text[run.start..run.end], levels[run.start].is_rtl()))
+        .collect()
+}
+
+/// Without shaping, text is always laid out left-to-right.
+#[cfg(not(feature = "shaping"))]
+fn direction_runs(text: &str) -> Vec<(&str, bool)> {
+    vec![(text, false)]
+}
+
+/// Position the glyphs of a run of text on a baseline starting at `(x, baseline)`,
+/// returning them along with the horizontal advance of the run.
+#[cfg(feature = "shaping")]
+fn shape_run(
+    text: &str,
+    rtl: bool,
+    shaper: &Shaper,
+    scale: Scale,
+    x: f32,
+    baseline: f32,
+) -> (Vec<PositionedGlyph<'static>>, f32) {
+    let font = shaper.font;
+    let face = match &shaper.face {
+        Some(face) => face,
+        None => return layout_run(text, font, scale, x, baseline),
+    };
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(if rtl {
+        rustybuzz::Direction::RightToLeft
+    } else {
+        rustybuzz::Direction::LeftToRight
+    });
+    let shaped = rustybuzz::shape(face, &[], buffer);
+
+    // rusttype scales fonts so that the distance from ascent to descent is `scale` pixels.
+    let v_metrics = font.font.v_metrics_unscaled();
+    let units = v_metrics.ascent - v_metrics.descent;
+    let (sx, sy) = (scale.x / units, scale.y / units);
+
+    let mut pen = x;
+    let glyphs = shaped
+        .glyph_infos()
+        .iter()
+        .zip(shaped.glyph_positions())
+        .map(|(info, pos)| {
+            let position = point(
+                pen + pos.x_offset as f32 * sx,
+                baseline - pos.y_offset as f32 * sy,
+            );
+            pen += pos.x_advance as f32 * sx;
+            font.font
+                .glyph(GlyphId(info.glyph_id))
+                .scaled(scale)
+                .positioned(position)
+        })
+        .collect();
+    (glyphs, pen - x)
+}
+
+/// Position the glyphs of a run of text on a baseline starting at `(x, baseline)`,
+/// returning them along with the horizontal advance of the run.
+#[cfg(not(feature = "shaping"))]
+fn shape_run(
+    text: &str,
+    _rtl: bool,
+    shaper: &Shaper,
+    scale: Scale,
+    x: f32,
+    baseline: f32,
+) -> (Vec<PositionedGlyph<'static>>, f32) {
+    layout_run(text, shaper.font, scale, x, baseline)
+}
+
+/// Lay out a run of text with rusttype, which applies kerning but no other shaping.
+fn layout_run(
+    text: &str,
+    font: &Font,
+    scale: Scale,
+    x: f32,
+    baseline: f32,
+) -> (Vec<PositionedGlyph<'static>>, f32) {
+    let glyphs: Vec<_> = font.font.layout(text, scale, point(x, baseline)).collect();
+    let width = glyphs
+        .last()
+        .map(|glyph| {
+            glyph.position().x + glyph.unpositioned().h_metrics().advance_width - x
+        })
+        .unwrap_or(0.0);
+    (glyphs, width)
+}
+
+/// The width an emoji image is drawn at, when scaled to the height of the font size.
+fn emoji_width(image: &PhotonImage, scale: Scale) -> f32 {
+    scale.y * image.width as f32 / image.height.max(1) as f32
+}
+
+/// The y-coordinate of the top of an emoji, centring it on a line whose top is at `y`.
+fn emoji_top(font: &Font, scale: Scale, y: f32) -> i32 {
+    let v_metrics = font.font.v_metrics(scale);
+    (y + (v_metrics.ascent - v_metrics.descent - scale.y) / 2.0).round() as i32
+}
+
+/// Resize an emoji image to the height of the font size.
+fn scale_emoji(image: &PhotonImage, scale: Scale) -> RgbaImage {
+    let width = emoji_width(image, scale).round().max(1.0) as u32;
+    let height = scale.y.round().max(1.0) as u32;
+    let dyn_img = helpers::dyn_image_from_raw(image);
+    imageops::resize(&dyn_img, width, height, FilterType::Triangle)
+}
+
+/// Alpha-blend an RGBA bitmap onto the image with its top-left corner at `(x, y)`.
+fn draw_bitmap(photon_img: &mut PhotonImage, bitmap: &RgbaImage, x: i32, y: i32) {
+    let (width, height) = (photon_img.width as i32, photon_img.height as i32);
+    for (bx, by, px) in bitmap.enumerate_pixels() {
+        let (px_x, px_y) = (x + bx as i32, y + by as i32);
+        if px_x >= 0 && px_y >= 0 && px_x < width && px_y < height {
+            let idx = (px_y * width + px_x) as usize * 4;
+            blend_pixel(&mut photon_img.raw_pixels[idx..idx + 4], px.data, 1.0);
+        }
+    }
+}
+
+/// Rasterize a single line of text onto the image, blending each glyph's coverage with `colour`.
+/// `(x, y)` is the top-left of the line, so the baseline sits at `y + ascent`.
+fn draw_glyphs(
+    photon_img: &mut PhotonImage,
+    text: &str,
+    shaper: &Shaper,
+    font_size: f32,
+    x: f32,
+    y: f32,
+    colour: [u8; 4],
+) {
+    let scale = Scale::uniform(font_size);
+    let (width, height) = (photon_img.width as i32, photon_img.height as i32);
+
+    for (run_x, run) in layout_line(text, shaper, scale).0 {
+        match run {
+            Run::Text { text, rtl } => {
+                for_each_glyph_pixel(
+                    text,
+                    rtl,
+                    shaper,
+                    scale,
+                    x + run_x,
+                    y,
+                    |px, py, value| {
+                        if px >= 0 && py >= 0 && px < width && py < height {
+                            let idx = (py * width + px) as usize * 4;
+                            blend_pixel(
+                                &mut photon_img.raw_pixels[idx..idx + 4],
+                                colour,
+                                value,
+                            );
+                        }
+                    },
+                );
+            }
+            Run::Emoji(image) => {
+                let bitmap = scale_emoji(image, scale);
+                let ex = (x + run_x).round() as i32;
+                let ey = emoji_top(shaper.font, scale, y);
+                draw_bitmap(photon_img, &bitmap, ex, ey);
+            }
+        }
+    }
+}
+
+/// Call `f(x, y, coverage)` for every pixel covered by a run of text whose top-left is at
+/// `(x, y)`. Pixels may lie outside of the image.
+fn for_each_glyph_pixel<F: FnMut(i32, i32, f32)>(
+    text: &str,
+    rtl: bool,
+    shaper: &Shaper,
+    scale: Scale,
+    x: f32,
+    y: f32,
+    mut f: F,
+) {
+    let ascent = shaper.font.font.v_metrics(scale).ascent;
+    for glyph in shape_run(text, rtl, shaper, scale, x, y + ascent).0 {
+        if let Some(bb) = glyph.pixel_bounding_box() {
+            glyph.draw(|gx, gy, coverage| {
+                f(gx as i32 + bb.min.x, gy as i32 + bb.min.y, coverage)
+            });
+        }
+    }
+}
+
+/// Blend `colour` over every pixel of the image within `x0..x1` and `y0..y1`.
+fn fill_rect(
+    photon_img: &mut PhotonImage,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    colour: [u8; 4],
+) {
+    let (width, height) = (photon_img.width as i32, photon_img.height as i32);
+    for y in y0.max(0)..y1.min(height) {
+        for x in x0.max(0)..x1.min(width) {
+            let idx = (y * width + x) as usize * 4;
+            blend_pixel(&mut photon_img.raw_pixels[idx..idx + 4], colour, 1.0);
+        }
+    }
+}
+
+/// A rectangular mask of glyph coverage values between 0 and 1, in image coordinates.
+#[derive(Clone)]
+struct Coverage {
+    left: i32,
+    top: i32,
+    width: i32,
+    height: i32,
+    values: Vec<f32>,
+}
+
+impl Coverage {
+    fn new(x0: i32, y0: i32, x1: i32, y1: i32) -> Coverage {
+        let (width, height) = ((x1 - x0).max(0), (y1 - y0).max(0));
+        Coverage {
+            left: x0,
+            top: y0,
+            width,
+            height,
+            values: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let (x, y) = (x - self.left, y - self.top);
+        if x >= 0 && y >= 0 && x < self.width && y < self.height {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn add(&mut self, x: i32, y: i32, value: f32) {
+        if let Some(i) = self.index(x, y) {
+            self.values[i] = (self.values[i] + value).min(1.0);
+        }
+    }
+
+    /// Grow the mask by `radius` pixels in every direction, taking the maximum coverage
+    /// within a disc around each pixel.
+    fn dilate(&self, radius: u32) -> Coverage {
+        let r = radius as i32;
+        let offsets: Vec<(i32, i32)> = (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|(dx, dy)| dx * dx + dy * dy <= r * r)
+            .collect();
+
+        let mut out = self.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = (y * self.width + x) as usize;
+                if self.values[i] <= 0.0 {
+                    continue;
+                }
+                for (dx, dy) in &offsets {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx >= 0 && ny >= 0 && nx < self.width && ny < self.height {
+                        let j = (ny * self.width + nx) as usize;
+                        out.values[j] = out.values[j].max(self.values[i]);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Approximate a gaussian blur with three passes of a box blur.
+    fn blur(&self, radius: u32) -> Coverage {
+        let mut out = self.clone();
+        if radius == 0 {
+            return out;
+        }
+        let r = radius as i32;
+        let (w, h) = (self.width, self.height);
+        let mut tmp = vec![0.0; out.values.len()];
+        for _ in 0..3 {
+            box_blur_1d(&out.values, &mut tmp, w, h, r, 1, w);
+            box_blur_1d(&tmp, &mut out.values, h, w, r, w, 1);
+        }
+        out
+    }
+
+    /// Blend `colour` over the image wherever the mask has coverage, shifted by `(dx, dy)`.
+    fn composite(
+        &self,
+        photon_img: &mut PhotonImage,
+        colour: [u8; 4],
+        dx: i32,
+        dy: i32,
+    ) {
+        let (width, height) = (photon_img.width as i32, photon_img.height as i32);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let value = self.values[(y * self.width + x) as usize];
+                let px = x + self.left + dx;
+                let py = y + self.top + dy;
+                if value > 0.0 && px >= 0 && py >= 0 && px < width && py < height {
+                    let idx = (py * width + px) as usize * 4;
+                    blend_pixel(&mut photon_img.raw_pixels[idx..idx + 4], colour, value);
+                }
+            }
+        }
+    }
+}
+
+/// Box blur `len`-long runs of `src` into `dst`, treating values outside the mask as 0.
+/// Consecutive elements of a run are `step` apart, and consecutive runs are `stride` apart.
+fn box_blur_1d(
+    src: &[f32],
+    dst: &mut [f32],
+    len: i32,
+    runs: i32,
+    radius: i32,
+    step: i32,
+    stride: i32,
+) {
+    let norm = 1.0 / (2 * radius + 1) as f32;
+    for run in 0..runs {
+        let at = |i: i32| (run * stride + i * step) as usize;
+        let mut sum: f32 = (0..radius.min(len)).map(|i| src[at(i)]).sum();
+        for i in 0..len {
+            if i + radius < len {
+                sum += src[at(i + radius)];
+            }
+            if i - radius > 0 {
+                sum -= src[at(i - radius - 1)];
+            }
+            dst[at(i)] = sum * norm;
+        }
+    }
+}
+
+/// Composite `colour` over an RGBA pixel, weighted by a glyph's coverage of that pixel.
+fn blend_pixel(px: &mut [u8], colour: [u8; 4], coverage: f32) {
+    let alpha = num::clamp(coverage, 0.0, 1.0) * colour[3] as f32 / 255.0;
+    for c in 0..3 {
+        px[c] = (colour[c] as f32 * alpha + px[c] as f32 * (1.0 - alpha)).round() as u8;
+    }
+    px[3] = (255.0 * alpha + px[3] as f32 * (1.0 - alpha)).round() as u8;
 }
@@ -0,0 +1,184 @@
+//! Upload images as WebGL textures, or lay them out for WebGPU, so they can be used in
+//! shaders without drawing them to a canvas first.
+//!
+//! Textures are created from the image's pixels in wasm memory, with `texImage2D`, as
+//! straight (not premultiplied) RGBA, and with the first row of the image at texture
+//! coordinate `v = 0`. They're set up to be sampled without mipmaps, and clamped at the
+//! edges, so images of any size can be used in WebGL 1.
+//!
+//! `web-sys`'s bindings to WebGPU are unstable, so for WebGPU, the pixels are returned
+//! for `GPUQueue.writeTexture`, or `GPUCommandEncoder.copyBufferToTexture`, to upload.
+//!
+//! # Example
+//! ```js
+//! const gl = canvas.getContext("webgl2");
+//! const texture = photon.create_texture_webgl2(gl, img);
+//! // After editing the image, eg: once per frame:
+//! photon.write_texture_webgl2(gl, texture, img);
+//!
+//! // With WebGPU:
+//! const gpuTexture = device.createTexture({
+//!   size: [img.get_width(), img.get_height()],
+//!   format: "rgba8unorm",
+//!   usage: GPUTextureUsage.TEXTURE_BINDING | GPUTextureUsage.COPY_DST,
+//! });
+//! device.queue.writeTexture({ texture: gpuTexture }, img.get_raw_pixels_view(),
+//!   { bytesPerRow: img.get_width() * 4 }, [img.get_width(), img.get_height()]);
+//! ```
+
+use crate::framebuffer::{self, ByteLayout};
+use crate::PhotonImage;
+use wasm_bindgen::prelude::*;
+use web_sys::WebGl2RenderingContext as Gl2;
+use web_sys::WebGlRenderingContext as Gl;
+use web_sys::WebGlTexture;
+
+/// The multiple of 256 bytes which WebGPU requires the rows of a buffer copied to a
+/// texture to be padded to.
+const GPU_ROW_ALIGNMENT: u32 = 256;
+
+/// Create a WebGL texture from an image.
+///
+/// # Arguments
+/// * `gl` - A WebGL 1 context.
+/// * `img` - A PhotonImage.
+/// # Errors
+/// Returns an error if the texture can't be created, eg: because the context was lost,
+/// or is too large for the GPU.
+#[wasm_bindgen]
+pub fn create_texture(
+    gl: &Gl,
+    photon_image: &PhotonImage,
+) -> Result<WebGlTexture, JsValue> {
+    let texture = gl
+        .create_texture()
+        .ok_or_else(|| JsValue::from_str("couldn't create a texture"))?;
+    gl.bind_texture(Gl::TEXTURE_2D, Some(&texture));
+    for (name, value) in [
+        (Gl::TEXTURE_MIN_FILTER, Gl::LINEAR),
+        (Gl::TEXTURE_MAG_FILTER, Gl::LINEAR),
+        (Gl::TEXTURE_WRAP_S, Gl::CLAMP_TO_EDGE),
+        (Gl::TEXTURE_WRAP_T, Gl::CLAMP_TO_EDGE),
+    ] {
+        gl.tex_parameteri(Gl::TEXTURE_2D, name, value as i32);
+    }
+    write_texture(gl, &texture, photon_image)?;
+    Ok(texture)
+}
+
+/// Replace the contents of a WebGL texture with an image, which may be of a different
+/// size, eg: each time the image is edited.
+///
+/// # Arguments
+/// * `gl` - A WebGL 1 context.
+/// * `texture` - A texture, eg: from `create_texture`.
+/// * `img` - A PhotonImage.
+/// # Errors
+/// Returns an error if the image can't be uploaded, eg: because it's too large for the
+/// GPU.
+#[wasm_bindgen]
+pub fn write_texture(
+    gl: &Gl,
+    texture: &WebGlTexture,
+    photon_image: &PhotonImage,
+) -> Result<(), JsValue> {
+    gl.bind_texture(Gl::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        Gl::TEXTURE_2D,
+        0,
+        Gl::RGBA as i32,
+        photon_image.width as i32,
+        photon_image.height as i32,
+        0,
+        Gl::RGBA,
+        Gl::UNSIGNED_BYTE,
+        Some(&photon_image.raw_pixels),
+    )
+}
+
+/// Create a WebGL 2 texture from an image.
+///
+/// # Arguments
+/// * `gl` - A WebGL 2 context.
+/// * `img` - A PhotonImage.
+/// # Errors
+/// Returns an error if the texture can't be created, eg: because the context was lost,
+/// or is too large for the GPU.
+#[wasm_bindgen]
+pub fn create_texture_webgl2(
+    gl: &Gl2,
+    photon_image: &PhotonImage,
+) -> Result<WebGlTexture, JsValue> {
+    let texture = gl
+        .create_texture()
+        .ok_or_else(|| JsValue::from_str("couldn't create a texture"))?;
+    gl.bind_texture(Gl2::TEXTURE_2D, Some(&texture));
+    for (name, value) in [
+        (Gl2::TEXTURE_MIN_FILTER, Gl2::LINEAR),
+        (Gl2::TEXTURE_MAG_FILTER, Gl2::LINEAR),
+        (Gl2::TEXTURE_WRAP_S, Gl2::CLAMP_TO_EDGE),
+        (Gl2::TEXTURE_WRAP_T, Gl2::CLAMP_TO_EDGE),
+    ] {
+        gl.tex_parameteri(Gl2::TEXTURE_2D, name, value as i32);
+    }
+    write_texture_webgl2(gl, &texture, photon_image)?;
+    Ok(texture)
+}
+
+/// Replace the contents of a WebGL 2 texture with an image, which may be of a different
+/// size, eg: each time the image is edited.
+///
+/// # Arguments
+/// * `gl` - A WebGL 2 context.
+/// * `texture` - A texture, eg: from `create_texture_webgl2`.
+/// * `img` - A PhotonImage.
+/// # Errors
+/// Returns an error if the image can't be uploaded, eg: because it's too large for the
+/// GPU.
+#[wasm_bindgen]
+pub fn write_texture_webgl2(
+    gl: &Gl2,
+    texture: &WebGlTexture,
+    photon_image: &PhotonImage,
+) -> Result<(), JsValue> {
+    gl.bind_texture(Gl2::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        Gl2::TEXTURE_2D,
+        0,
+        Gl2::RGBA8 as i32,
+        photon_image.width as i32,
+        photon_image.height as i32,
+        0,
+        Gl2::RGBA,
+        Gl2::UNSIGNED_BYTE,
+        Some(&photon_image.raw_pixels),
+    )
+}
+
+/// The number of bytes from the start of one row to the start of the next in
+/// `gpu_buffer_data`, which is the `bytesPerRow` to copy it to a texture with.
+///
+/// # Arguments
+/// * `width` - The width of the image, in pixels.
+#[wasm_bindgen]
+pub fn gpu_bytes_per_row(width: u32) -> u32 {
+    (width * 4).div_ceil(GPU_ROW_ALIGNMENT) * GPU_ROW_ALIGNMENT
+}
+
+/// Get an image's pixels with each row padded to a multiple of 256 bytes, as WebGPU
+/// requires for `GPUCommandEncoder.copyBufferToTexture`, eg: to fill a mapped staging
+/// buffer. `GPUQueue.writeTexture` doesn't need the padding, so can be given the image's
+/// raw pixels directly.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+#[wasm_bindgen]
+pub fn gpu_buffer_data(photon_image: &PhotonImage) -> Vec<u8> {
+    if photon_image.width == 0 {
+        return vec![];
+    }
+    let stride = gpu_bytes_per_row(photon_image.width) as usize;
+    let mut out = vec![0; stride * photon_image.height as usize];
+    framebuffer::write_bytes(photon_image, &mut out, stride, ByteLayout::Rgba);
+    out
+}
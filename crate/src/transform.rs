@@ -3,8 +3,10 @@
 extern crate image;
 use image::{GenericImageView, ImageBuffer};
 extern crate wasm_bindgen;
-use crate::helpers;
-use crate::PhotonImage;
+#[cfg(feature = "analysis")]
+use crate::analysis::saliency_map;
+use crate::{alpha, gamma, helpers};
+use crate::{PhotonImage, Rect};
 use image::RgbaImage;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
@@ -59,6 +61,20 @@ pub fn crop(
     }
 }
 
+/// Copy the part of an image which lies within `region`.
+pub(crate) fn crop_rect(photon_image: &PhotonImage, region: &Rect) -> PhotonImage {
+    let (x0, y0, x1, y1) = region.clip(photon_image.width, photon_image.height);
+    let row_start = |y: u32| ((y * photon_image.width + x0) * 4) as usize;
+    let row_len = ((x1 - x0) * 4) as usize;
+    let mut raw_pixels = Vec::with_capacity(row_len * (y1 - y0) as usize);
+    for y in y0..y1 {
+        raw_pixels.extend_from_slice(
+            &photon_image.raw_pixels[row_start(y)..row_start(y) + row_len],
+        );
+    }
+    PhotonImage::new(raw_pixels, x1 - x0, y1 - y0)
+}
+
 // #[cfg(target_arch = "wasm32")]
 // #[wasm_bindgen]
 // pub fn crop(canv: HtmlCanvasElement, width: u32, height: u32, sampling_filter: SamplingFilter) -> HtmlCanvasElement {
@@ -193,14 +209,7 @@ pub fn resize_img_browser(
     height: u32,
     sampling_filter: SamplingFilter,
 ) -> HtmlCanvasElement {
-    let sampling_filter = filter_type_from_sampling_filter(sampling_filter);
-    let dyn_img = helpers::dyn_image_from_raw(&photon_img);
-    let resized_img = image::ImageRgba8(image::imageops::resize(
-        &dyn_img,
-        width,
-        height,
-        sampling_filter,
-    ));
+    let resized_img = resize(photon_img, width, height, sampling_filter);
 
     // TODO Check if in browser or Node.JS
     let document = web_sys::window().unwrap().document().unwrap();
@@ -210,11 +219,11 @@ pub fn resize_img_browser(
         .dyn_into::<web_sys::HtmlCanvasElement>()
         .unwrap();
 
-    canvas.set_width(resized_img.width());
-    canvas.set_height(resized_img.height());
+    canvas.set_width(resized_img.get_width());
+    canvas.set_height(resized_img.get_height());
 
     let new_img_data = ImageData::new_with_u8_clamped_array_and_sh(
-        Clamped(&mut resized_img.raw_pixels()),
+        Clamped(&mut resized_img.get_raw_pixels()),
         canvas.width(),
         canvas.height(),
     );
@@ -239,7 +248,9 @@ pub fn resize_img_browser(
 /// * `width` - New width.
 /// * `height` - New height.
 /// * `sampling_filter` - Nearest = 1, Triangle = 2, CatmullRom = 3, Gaussian = 4, Lanczos3 = 5
-#[cfg(not(target_arch = "wasm32"))]
+///
+/// Transparent images are resized with premultiplied alpha, so that the colour of fully
+/// transparent pixels doesn't bleed into the visible ones.
 pub fn resize(
     photon_img: &PhotonImage,
     width: u32,
@@ -247,18 +258,757 @@ pub fn resize(
     sampling_filter: SamplingFilter,
 ) -> PhotonImage {
     let sampling_filter = filter_type_from_sampling_filter(sampling_filter);
+    let (src_width, src_height) = (photon_img.width, photon_img.height);
+
+    let mut raw_pixels = photon_img.raw_pixels.clone();
+    if gamma::get_linear_light() {
+        let _: Result<(), ()> = gamma::filter_linear(&mut raw_pixels, |pixels| {
+            let buffer: ImageBuffer<image::Rgba<u16>, Vec<u16>> =
+                ImageBuffer::from_raw(src_width, src_height, std::mem::take(pixels))
+                    .unwrap();
+            *pixels = image::imageops::resize(&buffer, width, height, sampling_filter)
+                .into_raw();
+            Ok(())
+        });
+    } else {
+        let _: Result<(), ()> = alpha::filter_premultiplied(&mut raw_pixels, |pixels| {
+            let img = PhotonImage::new(pixels.clone(), src_width, src_height);
+            let dyn_img = helpers::dyn_image_from_raw(&img);
+            let resized_img = image::ImageRgba8(image::imageops::resize(
+                &dyn_img,
+                width,
+                height,
+                sampling_filter,
+            ));
+            *pixels = resized_img.raw_pixels();
+            Ok(())
+        });
+    }
+
+    PhotonImage {
+        raw_pixels,
+        width,
+        height,
+    }
+}
+
+/// Shrink an image to a smaller size, quickly, by first halving it with a 2x2 box filter
+/// as many times as it can without becoming smaller than that size, and then resizing
+/// the rest of the way with a triangle filter. Used by `PhotonImage::thumbnail`.
+///
+/// Like `resize`, transparent images are filtered with premultiplied alpha, and in
+/// linear light when it's enabled.
+pub(crate) fn downscale(
+    photon_img: &PhotonImage,
+    width: u32,
+    height: u32,
+) -> PhotonImage {
+    let (src_width, src_height) = (photon_img.width, photon_img.height);
+    let mut halvings = 0;
+    let (mut w, mut h) = (src_width, src_height);
+    while w.div_ceil(2) >= width && h.div_ceil(2) >= height && (w, h) != (1, 1) {
+        w = w.div_ceil(2);
+        h = h.div_ceil(2);
+        halvings += 1;
+    }
+
+    let mut raw_pixels = photon_img.raw_pixels.clone();
+    if gamma::get_linear_light() {
+        let _: Result<(), ()> = gamma::filter_linear(&mut raw_pixels, |pixels| {
+            let (mut w, mut h) = (src_width, src_height);
+            for _ in 0..halvings {
+                *pixels = halve_box(pixels, w, h, |v| v as u16);
+                w = w.div_ceil(2);
+                h = h.div_ceil(2);
+            }
+            if (w, h) != (width, height) {
+                let buffer: ImageBuffer<image::Rgba<u16>, Vec<u16>> =
+                    ImageBuffer::from_raw(w, h, std::mem::take(pixels)).unwrap();
+                *pixels = image::imageops::resize(
+                    &buffer,
+                    width,
+                    height,
+                    image::FilterType::Triangle,
+                )
+                .into_raw();
+            }
+            Ok(())
+        });
+    } else {
+        let _: Result<(), ()> = alpha::filter_premultiplied(&mut raw_pixels, |pixels| {
+            let (mut w, mut h) = (src_width, src_height);
+            for _ in 0..halvings {
+                *pixels = halve_box(pixels, w, h, |v| v as u8);
+                w = w.div_ceil(2);
+                h = h.div_ceil(2);
+            }
+            if (w, h) != (width, height) {
+                // Resize 16-bit values, as `image` truncates each pass's results, which
+                // would darken 8-bit values by a level or two.
+                let wide = pixels.iter().map(|&v| v as u16 * 257).collect();
+                let buffer: ImageBuffer<image::Rgba<u16>, Vec<u16>> =
+                    ImageBuffer::from_raw(w, h, wide).unwrap();
+                let resized = image::imageops::resize(
+                    &buffer,
+                    width,
+                    height,
+                    image::FilterType::Triangle,
+                );
+                *pixels = resized
+                    .iter()
+                    .map(|&v| ((v as u32 + 128) / 257) as u8)
+                    .collect();
+            }
+            Ok(())
+        });
+    }
+
+    PhotonImage {
+        raw_pixels,
+        width,
+        height,
+    }
+}
+
+/// Halve the width and height of RGBA values, rounding up, by averaging each 2x2 block.
+/// The blocks on the right and bottom edges of an odd-sized image are narrower.
+fn halve_box<T: Copy + Into<u32>>(
+    values: &[T],
+    width: u32,
+    height: u32,
+    from: fn(u32) -> T,
+) -> Vec<T> {
+    let (width, height) = (width as usize, height as usize);
+    let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut out = Vec::with_capacity(half_width * half_height * 4);
+    for y in 0..half_height {
+        let rows = 2 * y..(2 * y + 2).min(height);
+        for x in 0..half_width {
+            let columns = 2 * x..(2 * x + 2).min(width);
+            let count = (rows.len() * columns.len()) as u32;
+            for c in 0..4 {
+                let mut sum = 0;
+                for sy in rows.clone() {
+                    for sx in columns.clone() {
+                        sum += values[(sy * width + sx) * 4 + c].into();
+                    }
+                }
+                out.push(from((sum + count / 2) / count));
+            }
+        }
+    }
+    out
+}
+
+/// The interpolation used by `upscale_2x`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpscaleMethod {
+    /// Repeat each pixel, keeping hard pixel edges, eg: for pixel art.
+    Nearest = 1,
+    /// Bicubic (Catmull-Rom) interpolation, which is smooth, but blurs and jags diagonal
+    /// edges.
+    Bicubic = 2,
+    /// Interpolate along edges rather than across them, which keeps lines and text in line
+    /// art and screenshots crisp, without the staircasing of bicubic interpolation.
+    EdgeDirected = 3,
+}
+
+/// Double the width and height of an image.
+///
+/// The edge-directed method is based on directional cubic convolution interpolation
+/// (DCCI): each new pixel is interpolated along whichever direction has the smaller
+/// gradient around it, or from a blend of both directions where there is no clear edge.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `method` - How the new pixels are interpolated.
+///
+/// ## Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::transform::{upscale_2x, UpscaleMethod};
+///
+/// let img = open_image("screenshot.png");
+/// let upscaled = upscale_2x(&img, UpscaleMethod::EdgeDirected);
+/// ```
+#[wasm_bindgen]
+pub fn upscale_2x(photon_image: &PhotonImage, method: UpscaleMethod) -> PhotonImage {
+    let (width, height) = (photon_image.width, photon_image.height);
+    match method {
+        UpscaleMethod::Nearest => {
+            resize(photon_image, width * 2, height * 2, SamplingFilter::Nearest)
+        }
+        UpscaleMethod::Bicubic => resize(
+            photon_image,
+            width * 2,
+            height * 2,
+            SamplingFilter::CatmullRom,
+        ),
+        UpscaleMethod::EdgeDirected => {
+            let mut raw_pixels = photon_image.raw_pixels.clone();
+            let _: Result<(), ()> =
+                alpha::filter_premultiplied(&mut raw_pixels, |pixels| {
+                    *pixels =
+                        upscale_edge_directed(pixels, width as usize, height as usize);
+                    Ok(())
+                });
+            PhotonImage {
+                raw_pixels,
+                width: width * 2,
+                height: height * 2,
+            }
+        }
+    }
+}
+
+/// Double the size of RGBA pixels with directional cubic convolution interpolation.
+///
+/// Each pixel of the source is copied to the even positions of the result. The pixels at
+/// odd positions in both directions are interpolated diagonally from the source pixels
+/// first, then the rest are interpolated horizontally or vertically from both.
+fn upscale_edge_directed(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let (out_width, out_height) = (width * 2, height * 2);
+    let mut rgb = vec![[0.0f32; 3]; out_width * out_height];
+    let mut luma = vec![0.0f32; out_width * out_height];
+    for (i, px) in pixels.chunks_exact(4).enumerate() {
+        let o = (i / width) * 2 * out_width + (i % width) * 2;
+        rgb[o] = [px[0] as f32, px[1] as f32, px[2] as f32];
+        luma[o] = helpers::luma(px) as f32;
+    }
 
-    let dyn_img = helpers::dyn_image_from_raw(&photon_img);
-    let resized_img = image::ImageRgba8(image::imageops::resize(
-        &dyn_img,
+    // Clamp a position into the image, keeping it on a pixel of the same parity, which
+    // has already been filled.
+    let clamp = |v: isize, len: usize| {
+        let parity = v.rem_euclid(2);
+        let last = len as isize - 1;
+        let last = if last % 2 == parity { last } else { last - 1 };
+        v.max(parity).min(last) as usize
+    };
+
+    // The weights of the pixels 3 and 1 steps behind and ahead along a direction.
+    const CUBIC_TAPS: [(f32, isize); 4] = [(-1.0, -3), (9.0, -1), (9.0, 1), (-1.0, 3)];
+    let diagonals: [(isize, isize); 2] = [(1, 1), (1, -1)];
+    let diagonal_window: Vec<(isize, isize)> = [-3, -1, 1, 3]
+        .iter()
+        .flat_map(|&j| [-3, -1, 1, 3].iter().map(move |&i| (i, j)))
+        .collect();
+    let axes: [(isize, isize); 2] = [(1, 0), (0, 1)];
+    let axis_window: Vec<(isize, isize)> = (-3..=3)
+        .flat_map(|j: isize| (-3..=3).map(move |i: isize| (i, j)))
+        .filter(|(i, j)| (i + j) % 2 != 0 && i.abs() + j.abs() <= 3)
+        .collect();
+
+    for pass in 0..2 {
+        for y in 0..out_height {
+            for x in 0..out_width {
+                let (directions, window) = match (pass, x % 2, y % 2) {
+                    (0, 1, 1) => (&diagonals, &diagonal_window),
+                    (1, 1, 0) | (1, 0, 1) => (&axes, &axis_window),
+                    _ => continue,
+                };
+                let at = |dx: isize, dy: isize| {
+                    clamp(y as isize + dy, out_height) * out_width
+                        + clamp(x as isize + dx, out_width)
+                };
+
+                // The sum of the differences between neighbouring pixels in the window,
+                // along each direction.
+                let mut gradients = [0.0f32; 2];
+                for (gradient, &(dx, dy)) in gradients.iter_mut().zip(directions) {
+                    for &(i, j) in window.iter() {
+                        if window.contains(&(i + 2 * dx, j + 2 * dy)) {
+                            *gradient += (luma[at(i, j)]
+                                - luma[at(i + 2 * dx, j + 2 * dy)])
+                            .abs();
+                        }
+                    }
+                }
+
+                // Cubic convolution of the four pixels along each direction.
+                let mut interpolated = [[0.0f32; 3]; 2];
+                for (values, &(dx, dy)) in interpolated.iter_mut().zip(directions) {
+                    for &(weight, k) in CUBIC_TAPS.iter() {
+                        let px = rgb[at(k * dx, k * dy)];
+                        for (value, channel) in values.iter_mut().zip(px.iter()) {
+                            *value += weight / 16.0 * channel;
+                        }
+                    }
+                }
+
+                let [g0, g1] = gradients;
+                let value = if (1.0 + g1) / (1.0 + g0) > 1.15 {
+                    interpolated[0]
+                } else if (1.0 + g0) / (1.0 + g1) > 1.15 {
+                    interpolated[1]
+                } else {
+                    let w0 = 1.0 / (1.0 + g0.powi(5));
+                    let w1 = 1.0 / (1.0 + g1.powi(5));
+                    let [a, b] = interpolated;
+                    [
+                        (w0 * a[0] + w1 * b[0]) / (w0 + w1),
+                        (w0 * a[1] + w1 * b[1]) / (w0 + w1),
+                        (w0 * a[2] + w1 * b[2]) / (w0 + w1),
+                    ]
+                };
+                let i = y * out_width + x;
+                rgb[i] = value;
+                luma[i] = 0.299 * value[0] + 0.587 * value[1] + 0.114 * value[2];
+            }
+        }
+    }
+
+    let mut out = vec![255; out_width * out_height * 4];
+    for (px, value) in out.chunks_exact_mut(4).zip(&rgb) {
+        for (channel, value) in px.iter_mut().zip(value.iter()) {
+            *channel = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    out
+}
+
+/// Correct lens distortion, such as the barrel distortion of wide-angle and action
+/// cameras, with the Brown–Conrady model.
+///
+/// The model maps each point of the corrected image to the point of the distorted image
+/// which it was captured at, which is sampled with bilinear interpolation. Positions are
+/// measured from the centre of the image, in units of half of its diagonal, so the
+/// coefficients don't depend on its resolution. Pixels which map to outside of the image
+/// are made transparent.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `k1` - The first radial distortion coefficient: negative values correct barrel
+/// distortion, and positive values correct pincushion distortion.
+/// * `k2` - The second radial distortion coefficient, which mostly affects the corners.
+/// * `p1` - The vertical tangential distortion coefficient, for lenses which aren't
+/// parallel to the sensor.
+/// * `p2` - The horizontal tangential distortion coefficient.
+///
+/// ## Example
+///
+/// ```
+/// // For example, to straighten the lines in an action camera's frame:
+/// use photon_rs::native::open_image;
+/// use photon_rs::transform::undistort;
+///
+/// let img = open_image("img.jpg");
+/// let corrected = undistort(&img, -0.25, 0.05, 0.0, 0.0);
+/// ```
+#[wasm_bindgen]
+pub fn undistort(
+    photon_image: &PhotonImage,
+    k1: f32,
+    k2: f32,
+    p1: f32,
+    p2: f32,
+) -> PhotonImage {
+    let (width, height) = (photon_image.width, photon_image.height);
+    let (k1, k2, p1, p2) = (k1 as f64, k2 as f64, p1 as f64, p2 as f64);
+    let (cx, cy) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+    let scale = ((width as f64).powi(2) + (height as f64).powi(2)).sqrt() / 2.0;
+
+    let mut raw_pixels = vec![0; photon_image.raw_pixels.len()];
+    for (i, px) in raw_pixels.chunks_exact_mut(4).enumerate() {
+        let x = ((i % width as usize) as f64 - cx) / scale;
+        let y = ((i / width as usize) as f64 - cy) / scale;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + k1 * r2 + k2 * r2 * r2;
+        let xd = x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+        let yd = y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+
+        let (sx, sy) = (xd * scale + cx, yd * scale + cy);
+        if sx >= -0.5
+            && sy >= -0.5
+            && sx <= width as f64 - 0.5
+            && sy <= height as f64 - 0.5
+        {
+            let (sx, sy) = (
+                sx.max(0.0).min(width as f64 - 1.0),
+                sy.max(0.0).min(height as f64 - 1.0),
+            );
+            px.copy_from_slice(&helpers::sample_bilinear(photon_image, sx, sy));
+        }
+    }
+    PhotonImage {
+        raw_pixels,
         width,
         height,
-        sampling_filter,
-    ));
+    }
+}
+
+/// The steepest tilt, in degrees, which `skew_angle` and `auto_straighten` look for.
+const MAX_SKEW_DEGREES: f32 = 20.0;
+
+/// The most pixels whose edges `skew_angle` looks at.
+const MAX_SKEW_SAMPLES: usize = 1 << 17;
+
+/// Find the angle, in degrees, at which the dominant near-horizontal lines of an image,
+/// such as a horizon or the lines of text in a scanned document, are tilted.
+///
+/// The image's edges are found from the gradient of its luma, keeping those which run
+/// closer to horizontal than vertical. They're then projected along lines at each angle,
+/// Hough-style, first in steps of half a degree, then in finer steps around the best of
+/// those, and the angle which lines them up most sharply is kept. Only tilts of up to 20
+/// degrees are looked for.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+///
+/// Returns the tilt, which is positive when the lines fall towards the right of the
+/// image, or 0 if there are no edges.
+///
+/// ## Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::transform::skew_angle;
+///
+/// let img = open_image("scan.jpg");
+/// let degrees = skew_angle(&img);
+/// ```
+#[wasm_bindgen]
+pub fn skew_angle(photon_image: &PhotonImage) -> f32 {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let luma: Vec<f32> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f32)
+        .collect();
+
+    // Sample the pixels evenly, so that large images don't take too long.
+    let step = ((width - 2) * (height - 2))
+        .div_ceil(MAX_SKEW_SAMPLES)
+        .max(1);
+    let mut edges = vec![];
+    for i in (0..(width - 2) * (height - 2)).step_by(step) {
+        let (x, y) = (i % (width - 2) + 1, i / (width - 2) + 1);
+        let (gx, gy) = luma_gradient(&luma, width, x, y);
+        // An edge runs across its gradient, so is near-horizontal when the gradient is
+        // near-vertical.
+        if gy.abs() > gx.abs() {
+            edges.push((x as f32, y as f32, (gx * gx + gy * gy).sqrt()));
+        }
+    }
+    if edges.is_empty() {
+        return 0.0;
+    }
+
+    // How sharply the edges line up along lines at `degrees`, from the sum of the
+    // squares of their total strength along each line.
+    let sharpness = |degrees: f32| {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let offset = width as f32 * sin.max(0.0);
+        let last = width + height + 1;
+        let mut sums = vec![0.0f32; last + 1];
+        for &(x, y, strength) in &edges {
+            let rho = y * cos - x * sin + offset;
+            sums[(rho.round() as usize).min(last)] += strength;
+        }
+        sums.iter().map(|s| s * s).sum::<f32>()
+    };
+    let sharpest = |angles: Vec<f32>| {
+        angles
+            .into_iter()
+            .filter(|degrees| degrees.abs() <= MAX_SKEW_DEGREES)
+            .map(|degrees| (degrees, sharpness(degrees)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map_or(0.0, |(degrees, _)| degrees)
+    };
+    let steps = (MAX_SKEW_DEGREES * 2.0) as i32;
+    let rough = sharpest((-steps..=steps).map(|step| step as f32 * 0.5).collect());
+    sharpest((-10..=10).map(|step| rough + step as f32 * 0.05).collect())
+}
 
+/// Level the tilted lines of an image, such as a horizon or the text of a scanned
+/// document, by rotating it by the angle found by `skew_angle`.
+///
+/// The rotated image is cropped to the largest rectangle of the same aspect ratio which
+/// lies within it, so that there are no empty wedges at its corners. Rotated pixels are
+/// sampled with bilinear interpolation.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+///
+/// ## Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::transform::auto_straighten;
+///
+/// let img = open_image("horizon.jpg");
+/// let level = auto_straighten(&img);
+/// ```
+#[wasm_bindgen]
+pub fn auto_straighten(photon_image: &PhotonImage) -> PhotonImage {
+    let degrees = skew_angle(photon_image);
+    if degrees == 0.0 {
+        return photon_image.clone();
+    }
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    let (w, h) = (photon_image.width as f64, photon_image.height as f64);
+    let scale = (w / (w * cos + h * sin.abs())).min(h / (w * sin.abs() + h * cos));
+    let width = ((w * scale).floor() as u32).max(1);
+    let height = ((h * scale).floor() as u32).max(1);
+
+    let (cx, cy) = ((w - 1.0) / 2.0, (h - 1.0) / 2.0);
+    let (ox, oy) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+    let mut raw_pixels = vec![0; (width * height * 4) as usize];
+    for (i, px) in raw_pixels.chunks_exact_mut(4).enumerate() {
+        let dx = (i % width as usize) as f64 - ox;
+        let dy = (i / width as usize) as f64 - oy;
+        let sx = (cx + dx * cos - dy * sin).max(0.0).min(w - 1.0);
+        let sy = (cy + dx * sin + dy * cos).max(0.0).min(h - 1.0);
+        px.copy_from_slice(&helpers::sample_bilinear(photon_image, sx, sy));
+    }
     PhotonImage {
-        raw_pixels: resized_img.raw_pixels(),
-        width: resized_img.width(),
-        height: resized_img.height(),
+        raw_pixels,
+        width,
+        height,
+    }
+}
+
+/// The fraction of the edges' total strength which `subject_rect` leaves outside of the
+/// subject on each side, so that stray edges in the background don't widen it.
+#[cfg(feature = "analysis")]
+const SUBJECT_TRIM: f64 = 0.01;
+
+/// Find the rectangle around the main subject of an image, eg: a product photographed
+/// against a plain background.
+///
+/// Each pixel is weighted by the strength of the edge through it, from the gradient of
+/// its luma, and by how salient it is, from `analysis::saliency_map`, so that the
+/// subject's outline and details count for the most, and texture in the background for
+/// little. The rectangle is the smallest which leaves no more than 1% of the total
+/// weight on each side of it, and is then grown by `padding` on every side, within the
+/// image.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `padding` - The margin to leave around the subject, as a fraction of its longer
+/// side, eg: 0.1 for 10%.
+///
+/// Returns the whole image if it has no edges.
+///
+/// ## Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::transform::subject_rect;
+///
+/// let img = open_image("product.jpg");
+/// let subject = subject_rect(&img, 0.0);
+/// let (x, y) = (subject.get_x(), subject.get_y());
+/// ```
+#[cfg(feature = "analysis")]
+#[wasm_bindgen]
+pub fn subject_rect(photon_image: &PhotonImage, padding: f32) -> Rect {
+    let (width, height) = (photon_image.width as usize, photon_image.height as usize);
+    let whole = Rect::new(0, 0, photon_image.width, photon_image.height);
+    if width < 3 || height < 3 {
+        return whole;
+    }
+    let saliency = saliency_map(photon_image);
+    let luma: Vec<f32> = photon_image
+        .raw_pixels
+        .chunks_exact(4)
+        .map(|px| helpers::luma(px) as f32)
+        .collect();
+
+    let weights: Vec<f64> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                return 0.0;
+            }
+            let (gx, gy) = luma_gradient(&luma, width, x, y);
+            let salient = saliency.raw_pixels[i * 4] as f64 / 255.0;
+            (gx * gx + gy * gy).sqrt() as f64 * salient
+        })
+        .collect();
+    let columns: Vec<f64> = (0..width)
+        .map(|x| weights.iter().skip(x).step_by(width).sum())
+        .collect();
+    let rows: Vec<f64> = weights
+        .chunks_exact(width)
+        .map(|row| row.iter().sum())
+        .collect();
+    let total: f64 = rows.iter().sum();
+    if total == 0.0 {
+        return whole;
+    }
+
+    // The range of indices which leaves no more than the trimmed weight on either side.
+    let trim = total * SUBJECT_TRIM;
+    let within_trim = |sums: &mut dyn Iterator<Item = &f64>| {
+        sums.scan(0.0, |seen, s| {
+            *seen += s;
+            Some(*seen)
+        })
+        .take_while(|&seen| seen <= trim)
+        .count()
+    };
+    let span = |sums: &[f64]| {
+        let start = within_trim(&mut sums.iter());
+        let end = sums.len() - within_trim(&mut sums.iter().rev());
+        (start, end.max(start + 1))
+    };
+    let (x0, x1) = span(&columns);
+    let (y0, y1) = span(&rows);
+    let margin = (padding.max(0.0) * (x1 - x0).max(y1 - y0) as f32).round() as usize;
+    let (x0, y0) = (x0.saturating_sub(margin), y0.saturating_sub(margin));
+    let (x1, y1) = ((x1 + margin).min(width), (y1 + margin).min(height));
+    Rect::new(x0 as u32, y0 as u32, (x1 - x0) as u32, (y1 - y0) as u32)
+}
+
+/// Crop an image to its main subject, as found by `subject_rect`, with a margin around
+/// it, eg: to frame a set of product photos consistently.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `padding` - The margin to leave around the subject, as a fraction of its longer
+/// side, eg: 0.1 for 10%.
+///
+/// ## Example
+///
+/// ```
+/// use photon_rs::native::open_image;
+/// use photon_rs::transform::crop_to_subject;
+///
+/// let img = open_image("product.jpg");
+/// let framed = crop_to_subject(&img, 0.1);
+/// ```
+#[cfg(feature = "analysis")]
+#[wasm_bindgen]
+pub fn crop_to_subject(photon_image: &PhotonImage, padding: f32) -> PhotonImage {
+    crop_rect(photon_image, &subject_rect(photon_image, padding))
+}
+
+/// The horizontal and vertical Sobel gradients of the luma of an image at `(x, y)`,
+/// which must not be on its edge.
+fn luma_gradient(luma: &[f32], width: usize, x: usize, y: usize) -> (f32, f32) {
+    let at = |dx: usize, dy: usize| luma[(y + dy - 1) * width + x + dx - 1];
+    let gx = at(2, 0) + 2.0 * at(2, 1) + at(2, 2) - at(0, 0) - 2.0 * at(0, 1) - at(0, 2);
+    let gy = at(0, 2) + 2.0 * at(1, 2) + at(2, 2) - at(0, 0) - 2.0 * at(1, 0) - at(2, 0);
+    (gx, gy)
+}
+
+/// Round the corners of an image, making them transparent.
+/// The edges of the corners are anti-aliased.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+/// * `radius` - The radius of each corner, in pixels. This is limited to half of the
+/// image's shortest side.
+///
+/// ## Example
+///
+/// ```
+/// // For example, to round the corners of an image by 20 pixels:
+/// use photon_rs::native::open_image;
+/// use photon_rs::transform::round_corners;
+///
+/// let mut img = open_image("img.jpg");
+/// round_corners(&mut img, 20_u32);
+/// ```
+#[wasm_bindgen]
+pub fn round_corners(photon_image: &mut PhotonImage, radius: u32) {
+    let width = photon_image.width;
+    let height = photon_image.height;
+    let radius = radius.min(width.min(height) / 2) as f32;
+    if radius <= 0.0 {
+        return;
+    }
+
+    // The centres of the corner circles.
+    let left = radius;
+    let right = width as f32 - radius;
+    let top = radius;
+    let bottom = height as f32 - radius;
+
+    for y in 0..height {
+        let py = y as f32 + 0.5;
+        let cy = if py < top {
+            top
+        } else if py > bottom {
+            bottom
+        } else {
+            continue;
+        };
+        for x in 0..width {
+            let px = x as f32 + 0.5;
+            let cx = if px < left {
+                left
+            } else if px > right {
+                right
+            } else {
+                continue;
+            };
+            let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+            scale_alpha(photon_image, x, y, radius - distance + 0.5);
+        }
+    }
+}
+
+/// Crop the largest centred circle out of an image, for use as an avatar.
+/// The returned image is square, and transparent outside of the circle, whose edge is
+/// anti-aliased.
+///
+/// # Arguments
+/// * `img` - A PhotonImage.
+///
+/// ## Example
+///
+/// ```
+/// use photon_rs::native::{open_image, save_image};
+/// use photon_rs::transform::circle_crop;
+///
+/// let img = open_image("img.jpg");
+/// let avatar = circle_crop(&img);
+/// // Save as a PNG, to keep the transparent corners.
+/// save_image(avatar, "avatar.png");
+/// ```
+#[wasm_bindgen]
+pub fn circle_crop(photon_image: &PhotonImage) -> PhotonImage {
+    let size = photon_image.width.min(photon_image.height);
+    let x0 = (photon_image.width - size) / 2;
+    let y0 = (photon_image.height - size) / 2;
+
+    let mut raw_pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in y0..y0 + size {
+        let start = ((y * photon_image.width + x0) * 4) as usize;
+        raw_pixels.extend_from_slice(
+            &photon_image.raw_pixels[start..start + size as usize * 4],
+        );
+    }
+    let mut cropped = PhotonImage {
+        raw_pixels,
+        width: size,
+        height: size,
+    };
+
+    let radius = size as f32 / 2.0;
+    for y in 0..size {
+        for x in 0..size {
+            let dx = x as f32 + 0.5 - radius;
+            let dy = y as f32 + 0.5 - radius;
+            let distance = (dx * dx + dy * dy).sqrt();
+            scale_alpha(&mut cropped, x, y, radius - distance + 0.5);
+        }
+    }
+    cropped
+}
+
+/// Multiply the alpha of a pixel by `coverage`, clamped between 0 and 1.
+fn scale_alpha(photon_image: &mut PhotonImage, x: u32, y: u32, coverage: f32) {
+    let coverage = num::clamp(coverage, 0.0, 1.0);
+    if coverage < 1.0 {
+        let idx = ((y * photon_image.width + x) * 4 + 3) as usize;
+        let alpha = photon_image.raw_pixels[idx] as f32 * coverage;
+        photon_image.raw_pixels[idx] = alpha.round() as u8;
     }
 }
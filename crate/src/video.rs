@@ -0,0 +1,184 @@
+//! Decode the raw frames of the WebCodecs API, so effects and pipelines can be run on live
+//! video in the browser.
+//!
+//! `web-sys`'s bindings to `VideoFrame` are unstable, so frames are passed as the bytes
+//! copied out of them with `VideoFrame.copyTo`, along with their format. Without a
+//! `layout` option, `copyTo` packs the planes one after another, with each row of the
+//! first plane `stride` bytes long, which is what `decode_frame` expects. The result is
+//! RGBA, which can be drawn to a canvas with `putImageData`, or wrapped in a new
+//! `VideoFrame` for a `VideoEncoder`.
+//!
+//! YUV frames are assumed to use BT.601 coefficients with limited range, as most webcams
+//! and SD video do.
+//!
+//! # Example
+//! ```js
+//! const processor = new MediaStreamTrackProcessor({ track });
+//! const generator = new MediaStreamTrackGenerator({ kind: "video" });
+//! const pipeline = photon.Pipeline.from_json('[{"op": "sepia"}]');
+//! const transform = new TransformStream({
+//!   async transform(frame, controller) {
+//!     const data = new Uint8Array(frame.allocationSize());
+//!     await frame.copyTo(data);
+//!     const img = photon.process_frame(data, frame.codedWidth, frame.codedHeight,
+//!       photon.FrameFormat[frame.format], frame.codedWidth, pipeline);
+//!     controller.enqueue(new VideoFrame(img.get_raw_pixels(), {
+//!       format: "RGBA", codedWidth: img.get_width(), codedHeight: img.get_height(),
+//!       timestamp: frame.timestamp,
+//!     }));
+//!     frame.close();
+//!   },
+//! });
+//! processor.readable.pipeThrough(transform).pipeTo(generator.writable);
+//! ```
+
+use crate::framebuffer::{from_bytes, ByteLayout};
+use crate::PhotonImage;
+#[cfg(feature = "pipeline")]
+use crate::{error::Result, pipeline::Pipeline};
+use wasm_bindgen::prelude::*;
+
+/// The pixel formats of `VideoFrame`, named as in WebCodecs, eg: `"I420"` and `"RGBA"`.
+#[wasm_bindgen]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// 8-bit YUV, with a full-size Y plane followed by quarter-size U and V planes.
+    I420,
+    /// 8-bit YUV, with a full-size Y plane followed by a quarter-size plane of interleaved
+    /// U and V samples.
+    NV12,
+    RGBA,
+    /// RGBA whose alpha bytes are ignored.
+    RGBX,
+    BGRA,
+    /// BGRA whose alpha bytes are ignored.
+    BGRX,
+}
+
+/// Decode a raw video frame to a PhotonImage.
+///
+/// # Arguments
+/// * `data` - The frame's bytes.
+/// * `width` - The width of the frame, in pixels.
+/// * `height` - The height of the frame, in pixels.
+/// * `format` - The pixel format of the frame.
+/// * `stride` - The number of bytes from the start of one row to the start of the next,
+/// in the first plane. For YUV frames, the rows of the chroma planes are assumed to be
+/// half as long, or as long for `NV12`'s interleaved plane.
+/// # Panics
+/// Panics if `data` is too small for the frame's size, format and stride.
+#[wasm_bindgen]
+pub fn decode_frame(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: FrameFormat,
+    stride: usize,
+) -> PhotonImage {
+    match format {
+        FrameFormat::I420 | FrameFormat::NV12 => {
+            decode_yuv(data, width, height, format, stride)
+        }
+        FrameFormat::RGBA
+        | FrameFormat::RGBX
+        | FrameFormat::BGRA
+        | FrameFormat::BGRX => {
+            let layout = match format {
+                FrameFormat::BGRA | FrameFormat::BGRX => ByteLayout::Bgra,
+                _ => ByteLayout::Rgba,
+            };
+            let mut photon_image = from_bytes(data, width, height, stride, layout);
+            if let FrameFormat::RGBX | FrameFormat::BGRX = format {
+                for px in photon_image.raw_pixels.chunks_exact_mut(4) {
+                    px[3] = 255;
+                }
+            }
+            photon_image
+        }
+    }
+}
+
+/// Decode a raw video frame, apply a pipeline to it, and return the result as an RGBA
+/// image.
+///
+/// # Arguments
+/// * `data` - The frame's bytes.
+/// * `width` - The width of the frame, in pixels.
+/// * `height` - The height of the frame, in pixels.
+/// * `format` - The pixel format of the frame.
+/// * `stride` - The number of bytes from the start of one row to the start of the next,
+/// in the first plane.
+/// * `pipeline` - The operations to apply to the frame.
+/// # Errors
+/// Returns `PhotonError::InvalidPipeline` if any of the pipeline's operations is invalid.
+/// # Panics
+/// Panics if `data` is too small for the frame's size, format and stride.
+#[cfg(feature = "pipeline")]
+#[wasm_bindgen]
+pub fn process_frame(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: FrameFormat,
+    stride: usize,
+    pipeline: &Pipeline,
+) -> Result<PhotonImage> {
+    let mut photon_image = decode_frame(data, width, height, format, stride);
+    pipeline.apply(&mut photon_image)?;
+    Ok(photon_image)
+}
+
+/// Decode an `I420` or `NV12` frame.
+fn decode_yuv(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: FrameFormat,
+    stride: usize,
+) -> PhotonImage {
+    let (width, height) = (width as usize, height as usize);
+    let chroma_height = height.div_ceil(2);
+    if stride < width {
+        panic!(
+            "Stride {} is smaller than a row of {} pixels.",
+            stride, width
+        );
+    }
+
+    let luma_len = stride * height;
+    let (chroma_stride, chroma_step, v_start, needed) = match format {
+        FrameFormat::NV12 => {
+            (stride, 2, luma_len + 1, luma_len + stride * chroma_height)
+        }
+        _ => {
+            let chroma_stride = stride.div_ceil(2);
+            let chroma_len = chroma_stride * chroma_height;
+            (
+                chroma_stride,
+                1,
+                luma_len + chroma_len,
+                luma_len + 2 * chroma_len,
+            )
+        }
+    };
+    if data.len() < needed {
+        panic!("Frame of {} bytes is too small for its size.", data.len());
+    }
+
+    let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+    let mut raw_pixels = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let chroma = (y / 2) * chroma_stride + (x / 2) * chroma_step;
+            let c = data[y * stride + x] as f32 - 16.0;
+            let d = data[luma_len + chroma] as f32 - 128.0;
+            let e = data[v_start + chroma] as f32 - 128.0;
+            let r = 1.164 * c + 1.596 * e;
+            let g = 1.164 * c - 0.392 * d - 0.813 * e;
+            let b = 1.164 * c + 2.017 * d;
+            raw_pixels.extend_from_slice(&[clamp(r), clamp(g), clamp(b), 255]);
+        }
+    }
+    PhotonImage::new(raw_pixels, width as u32, height as u32)
+}
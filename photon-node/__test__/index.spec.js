@@ -0,0 +1,68 @@
+const test = require("node:test");
+const assert = require("node:assert");
+
+const { Image, process, processInPlace } = require("../index.js");
+
+// A 4x2 image, its left half red and its right half blue.
+function pixels() {
+  const buf = Buffer.alloc(4 * 2 * 4);
+  for (let i = 0; i < 8; i++) {
+    const red = i % 4 < 2;
+    buf.set([red ? 255 : 0, 0, red ? 0 : 255, 255], i * 4);
+  }
+  return buf;
+}
+
+test("fromRaw rejects a Buffer of the wrong length", () => {
+  assert.throws(() => Image.fromRaw(Buffer.alloc(3), 4, 2));
+});
+
+test("methods apply pipeline operations", () => {
+  const img = Image.fromRaw(pixels(), 4, 2);
+  img.fliph();
+  assert.deepStrictEqual([...img.rawPixels().subarray(0, 4)], [0, 0, 255, 255]);
+
+  img.grayscale();
+  const px = img.rawPixels();
+  assert.strictEqual(px[0], px[1]);
+  assert.strictEqual(px[1], px[2]);
+
+  img.resize(2);
+  assert.strictEqual(img.width, 2);
+  assert.strictEqual(img.height, 1);
+});
+
+test("invalid parameters throw and leave the image unmodified", () => {
+  const img = Image.fromRaw(pixels(), 4, 2);
+  assert.throws(() => img.crop(2, 0, 4, 2));
+  assert.throws(() => img.filter("no_such_filter"));
+  assert.deepStrictEqual(img.rawPixels(), pixels());
+});
+
+test("apply matches the equivalent method calls", () => {
+  const a = Image.fromRaw(pixels(), 4, 2);
+  a.apply('[{"op": "invert"}, {"op": "flipv"}]');
+
+  const b = Image.fromRaw(pixels(), 4, 2);
+  b.invert();
+  b.flipv();
+
+  assert.deepStrictEqual(a.rawPixels(), b.rawPixels());
+});
+
+test("process leaves its input alone, processInPlace overwrites it", () => {
+  const input = pixels();
+  const out = process(input, 4, 2, '[{"op": "invert"}]');
+  assert.deepStrictEqual(input, pixels());
+  assert.deepStrictEqual([...out.subarray(0, 4)], [0, 255, 255, 255]);
+
+  processInPlace(input, 4, 2, '[{"op": "invert"}]');
+  assert.deepStrictEqual(input, out);
+});
+
+test("encoded images decode to the same pixels", () => {
+  const img = Image.fromRaw(pixels(), 4, 2);
+  const decoded = Image.decode(img.encodePng());
+  assert.strictEqual(decoded.width, 4);
+  assert.deepStrictEqual(decoded.rawPixels(), img.rawPixels());
+});
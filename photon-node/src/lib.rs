@@ -0,0 +1,353 @@
+//! A native Node.js addon for Photon, built with napi-rs.
+//!
+//! Server-side Node users get considerably better throughput from a native addon than
+//! from the WebAssembly build, as the pixel loops run as native (and SIMD-capable) code.
+//! Pixels are exchanged as Node `Buffer`s. Photon's images own their pixels, so a Buffer
+//! passed in is copied once into native memory, but results are handed to Node without
+//! being copied again.
+//!
+//! Only the operations of Photon's `pipeline` module are exposed, rather than all of its
+//! API. Each of them is a method of `Image`, and a whole pipeline can be applied at once
+//! from a JSON spec: an array of objects, each naming an operation in its `"op"` field
+//! along with its parameters, as listed in the README and `photon_rs::pipeline::Operation`.
+//! For example:
+//!
+//! ```js
+//! const { Image } = require("@silvia-odwyer/photon-native");
+//!
+//! const img = Image.decode(fs.readFileSync("beach.jpg"));
+//! img.resize(800);
+//! img.apply('[{"op": "sharpen"}, {"op": "filter", "name": "vintage"}]');
+//! fs.writeFileSync("beach.png", img.encodePng());
+//! ```
+
+#[macro_use]
+extern crate napi_derive;
+
+use image::{GenericImageView, ImageBuffer, ImageOutputFormat};
+use napi::bindgen_prelude::{Buffer, Error, Result, Status};
+use photon_rs::error::PhotonError;
+use photon_rs::pipeline::{Operation, Pipeline};
+use photon_rs::PhotonImage;
+
+/// An image, with its pixels held in native memory.
+#[napi]
+pub struct Image {
+    inner: PhotonImage,
+}
+
+#[napi]
+impl Image {
+    /// Create an image from a Buffer of raw RGBA pixels, which are copied.
+    #[napi(factory)]
+    pub fn from_raw(pixels: Buffer, width: u32, height: u32) -> Result<Image> {
+        check_len(&pixels, width, height)?;
+        Ok(Image {
+            inner: PhotonImage::new(pixels.to_vec(), width, height),
+        })
+    }
+
+    /// Decode an image from a Buffer holding an encoded file, eg: a JPEG or PNG.
+    #[napi(factory)]
+    pub fn decode(bytes: Buffer) -> Result<Image> {
+        let img = image::load_from_memory(&bytes)
+            .map_err(|err| Error::new(Status::InvalidArg, err.to_string()))?;
+        let (width, height) = img.dimensions();
+        Ok(Image {
+            inner: PhotonImage::new(img.to_rgba().to_vec(), width, height),
+        })
+    }
+
+    /// The width of the image.
+    #[napi(getter)]
+    pub fn width(&self) -> u32 {
+        self.inner.get_width()
+    }
+
+    /// The height of the image.
+    #[napi(getter)]
+    pub fn height(&self) -> u32 {
+        self.inner.get_height()
+    }
+
+    /// Get a copy of the image's raw RGBA pixels.
+    #[napi]
+    pub fn raw_pixels(&self) -> Buffer {
+        Buffer::from(self.inner.get_raw_pixels())
+    }
+
+    /// Apply a JSON array of pipeline operations, eg: `[{"op": "sharpen"}]`.
+    ///
+    /// If any operation is invalid, an error is thrown and the image is left unmodified.
+    #[napi]
+    pub fn apply(&mut self, spec: String) -> Result<()> {
+        parse(&spec)?.apply(&mut self.inner).map_err(to_napi)
+    }
+
+    /// Resize the image to `width` by `height` pixels. If only one of them is given, the
+    /// other is chosen to keep the aspect ratio.
+    #[napi]
+    pub fn resize(&mut self, width: Option<u32>, height: Option<u32>) -> Result<()> {
+        self.run(Operation::Resize {
+            w: width,
+            h: height,
+        })
+    }
+
+    /// Shrink the image to fit within `max_dim` pixels on each side, keeping its aspect
+    /// ratio, returning a new image. Much faster than `resize` for large reductions.
+    #[napi]
+    pub fn thumbnail(&self, max_dim: u32) -> Image {
+        Image {
+            inner: self.inner.thumbnail(max_dim),
+        }
+    }
+
+    /// Crop the image to the `width` by `height` rectangle whose top-left corner is at
+    /// (`x`, `y`).
+    #[napi]
+    pub fn crop(&mut self, x: u32, y: u32, width: u32, height: u32) -> Result<()> {
+        self.run(Operation::Crop {
+            x,
+            y,
+            w: width,
+            h: height,
+        })
+    }
+
+    /// Flip the image horizontally.
+    #[napi]
+    pub fn fliph(&mut self) -> Result<()> {
+        self.run(Operation::FlipH)
+    }
+
+    /// Flip the image vertically.
+    #[napi]
+    pub fn flipv(&mut self) -> Result<()> {
+        self.run(Operation::FlipV)
+    }
+
+    /// Blur the image, with a `sigma` between 0 and 100.
+    #[napi]
+    pub fn gaussian_blur(&mut self, sigma: f64) -> Result<()> {
+        self.run(Operation::GaussianBlur {
+            sigma: sigma as f32,
+        })
+    }
+
+    /// Blur the image with a 3x3 box blur.
+    #[napi]
+    pub fn box_blur(&mut self) -> Result<()> {
+        self.run(Operation::BoxBlur)
+    }
+
+    /// Sharpen the image.
+    #[napi]
+    pub fn sharpen(&mut self) -> Result<()> {
+        self.run(Operation::Sharpen)
+    }
+
+    /// Apply a Kuwahara filter, which gives a painterly look, with a `radius` between 0 and
+    /// 32.
+    #[napi]
+    pub fn kuwahara(&mut self, radius: u32) -> Result<()> {
+        self.run(Operation::Kuwahara { radius })
+    }
+
+    /// Reduce noise with non-local means, with a `strength` between 0 and 1.
+    #[napi]
+    pub fn nl_means(&mut self, strength: f64) -> Result<()> {
+        self.run(Operation::NlMeans {
+            strength: strength as f32,
+        })
+    }
+
+    /// Brighten (positive) or darken (negative) the image, by an `amount` between -255 and
+    /// 255.
+    #[napi]
+    pub fn brightness(&mut self, amount: i32) -> Result<()> {
+        let amount = amount.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        self.run(Operation::Brightness { amount })
+    }
+
+    /// Adjust the contrast of the image by an `amount` between -255 and 255.
+    #[napi]
+    pub fn contrast(&mut self, amount: f64) -> Result<()> {
+        self.run(Operation::Contrast {
+            amount: amount as f32,
+        })
+    }
+
+    /// Saturate (positive) or desaturate (negative) the image, by an `amount` between -1
+    /// and 1.
+    #[napi]
+    pub fn saturate(&mut self, amount: f64) -> Result<()> {
+        self.run(Operation::Saturate {
+            amount: amount as f32,
+        })
+    }
+
+    /// Rotate the hue of every pixel by `degrees`, between -360 and 360.
+    #[napi]
+    pub fn hue_rotate(&mut self, degrees: f64) -> Result<()> {
+        self.run(Operation::HueRotate {
+            degrees: degrees as f32,
+        })
+    }
+
+    /// Convert the image to grayscale.
+    #[napi]
+    pub fn grayscale(&mut self) -> Result<()> {
+        self.run(Operation::Grayscale)
+    }
+
+    /// Give the image a sepia tone.
+    #[napi]
+    pub fn sepia(&mut self) -> Result<()> {
+        self.run(Operation::Sepia)
+    }
+
+    /// Invert the colours of the image.
+    #[napi]
+    pub fn invert(&mut self) -> Result<()> {
+        self.run(Operation::Invert)
+    }
+
+    /// Solarize the image.
+    #[napi]
+    pub fn solarize(&mut self) -> Result<()> {
+        self.run(Operation::Solarize)
+    }
+
+    /// Add signed offsets, between -255 and 255, to the red, green and blue channels.
+    #[napi]
+    pub fn tint(&mut self, r: i32, g: i32, b: i32) -> Result<()> {
+        self.run(Operation::Tint { r, g, b })
+    }
+
+    /// Stretch the contrast of the image, clipping `clipPercent` percent (default 0.5) of
+    /// pixels at each end.
+    #[napi]
+    pub fn auto_contrast(&mut self, clip_percent: Option<f64>) -> Result<()> {
+        self.run(Operation::AutoContrast {
+            clip_percent: clip_percent.unwrap_or(0.5) as f32,
+        })
+    }
+
+    /// Automatically correct the colour balance, contrast, vibrance and sharpness.
+    #[napi]
+    pub fn auto_enhance(&mut self) -> Result<()> {
+        self.run(Operation::AutoEnhance)
+    }
+
+    /// Apply one of the preset filters, by name, eg: "vintage".
+    #[napi]
+    pub fn filter(&mut self, name: String) -> Result<()> {
+        self.run(Operation::Filter { name })
+    }
+
+    /// Encode the image as a PNG file.
+    #[napi]
+    pub fn encode_png(&self) -> Result<Buffer> {
+        let buffer = ImageBuffer::from_vec(
+            self.inner.get_width(),
+            self.inner.get_height(),
+            self.inner.get_raw_pixels(),
+        )
+        .expect("raw pixels match the dimensions");
+        let mut out = vec![];
+        image::ImageRgba8(buffer)
+            .write_to(&mut out, ImageOutputFormat::PNG)
+            .map_err(|err| Error::from_reason(err.to_string()))?;
+        Ok(Buffer::from(out))
+    }
+
+    /// Encode the image as a JPEG file, with a `quality` from 1 to 100.
+    #[napi]
+    pub fn encode_jpeg(&self, quality: u32) -> Result<Buffer> {
+        let rgba = ImageBuffer::from_vec(
+            self.inner.get_width(),
+            self.inner.get_height(),
+            self.inner.get_raw_pixels(),
+        )
+        .expect("raw pixels match the dimensions");
+        let mut out = vec![];
+        image::ImageRgba8(rgba)
+            .write_to(
+                &mut out,
+                ImageOutputFormat::JPEG(quality.clamp(1, 100) as u8),
+            )
+            .map_err(|err| Error::from_reason(err.to_string()))?;
+        Ok(Buffer::from(out))
+    }
+}
+
+impl Image {
+    /// Validate an operation's parameters, then apply it. If they're invalid, an error is
+    /// thrown and the image is left unmodified.
+    fn run(&mut self, operation: Operation) -> Result<()> {
+        operation.apply(&mut self.inner).map_err(to_napi)
+    }
+}
+
+/// Apply a JSON array of pipeline operations to a Buffer of raw RGBA pixels, returning the
+/// result as a new Buffer, which is handed to Node without being copied.
+///
+/// Use this when the operations may change the size of the image, eg: `resize` or `crop`.
+/// The new size can be worked out from the length of the result, or by using `Image`.
+#[napi]
+pub fn process(pixels: Buffer, width: u32, height: u32, spec: String) -> Result<Buffer> {
+    check_len(&pixels, width, height)?;
+    let mut img = PhotonImage::new(pixels.to_vec(), width, height);
+    parse(&spec)?.apply(&mut img).map_err(to_napi)?;
+    Ok(Buffer::from(img.into_raw_pixels()))
+}
+
+/// Apply a JSON array of pipeline operations to a Buffer of raw RGBA pixels, copying the
+/// result back into the same Buffer, eg: a frame which is reused for every frame of a video,
+/// so that no new Buffer is allocated.
+///
+/// The operations must not change the size of the image; if they do, an error is thrown
+/// and the Buffer is left unmodified.
+#[napi]
+pub fn process_in_place(
+    mut pixels: Buffer,
+    width: u32,
+    height: u32,
+    spec: String,
+) -> Result<()> {
+    check_len(&pixels, width, height)?;
+    let mut img = PhotonImage::new(pixels.to_vec(), width, height);
+    parse(&spec)?.apply(&mut img).map_err(to_napi)?;
+    if img.get_width() != width || img.get_height() != height {
+        return Err(Error::new(
+            Status::InvalidArg,
+            "operations which change the size of the image can't be applied in place"
+                .to_string(),
+        ));
+    }
+    pixels.copy_from_slice(&img.into_raw_pixels());
+    Ok(())
+}
+
+fn parse(spec: &str) -> Result<Pipeline> {
+    Pipeline::from_json(spec).map_err(to_napi)
+}
+
+fn check_len(pixels: &[u8], width: u32, height: u32) -> Result<()> {
+    let expected = width as u64 * height as u64 * 4;
+    if pixels.len() as u64 != expected {
+        return Err(Error::new(
+            Status::InvalidArg,
+            format!(
+                "expected {} bytes of pixels for a {}x{} image",
+                expected, width, height
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn to_napi(err: PhotonError) -> Error {
+    Error::new(Status::InvalidArg, err.to_string())
+}